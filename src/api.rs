@@ -0,0 +1,334 @@
+//! HTTP API for systems without access to Elasticsearch, such as bridges,
+//! scrapers and dashboards. Requests authenticate with the configured bearer
+//! token. Messages pushed go through the bot's own [`BatchIndexer`], and
+//! searches are structured [`SearchParams`] run like the bot's `/s`.
+
+use axum::extract::{DefaultBodyLimit, Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::bot::query::{validate_keyword, QueryError};
+use crate::config::ApiConfig;
+use crate::error::AppError;
+use crate::es::audit::{AuditEntry, AuditLog};
+use crate::es::indexer::BatchIndexer;
+use crate::es::search::{SearchClient, SearchParams, HIGHLIGHT_END, HIGHLIGHT_START};
+use crate::models::message::ChatMessage;
+
+/// Line errors reported back per request; the rest are only counted.
+const MAX_REPORTED_ERRORS: usize = 20;
+/// Largest `page_size` a search may ask for.
+const MAX_PAGE_SIZE: usize = 100;
+
+struct ApiState {
+    token: String,
+    indexer: Arc<BatchIndexer>,
+    search: Arc<SearchClient>,
+    audit: Arc<AuditLog>,
+    /// Page size of searches that don't set one
+    default_page_size: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct BulkResponse {
+    accepted: usize,
+    rejected: usize,
+    errors: Vec<LineError>,
+}
+
+#[derive(Debug, Serialize)]
+struct LineError {
+    /// 1-based line number in the request body
+    line: usize,
+    error: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SearchResponse {
+    total: u64,
+    page: usize,
+    total_pages: usize,
+    hits: Vec<Hit>,
+}
+
+#[derive(Debug, Serialize)]
+struct Hit {
+    message: ChatMessage,
+    /// Highlighted fragments as plain text
+    snippet: String,
+}
+
+/// Serve the API until the listener fails.
+pub async fn serve(
+    config: ApiConfig,
+    indexer: Arc<BatchIndexer>,
+    search: Arc<SearchClient>,
+    audit: Arc<AuditLog>,
+    default_page_size: usize,
+) -> anyhow::Result<()> {
+    let addr = format!("{}:{}", config.listen_addr, config.port);
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    tracing::info!("HTTP API listening on {addr}");
+    let state = Arc::new(ApiState {
+        token: config.token.clone(),
+        indexer,
+        search,
+        audit,
+        default_page_size,
+    });
+    axum::serve(listener, router(&config, state)).await?;
+    Ok(())
+}
+
+fn router(config: &ApiConfig, state: Arc<ApiState>) -> Router {
+    Router::new()
+        .route("/api/v1/chats/{chat_id}/messages:bulk", post(bulk_messages))
+        .route("/api/v1/chats/{chat_id}/search", post(search))
+        .route("/api/v1/chats/{chat_id}/search:translate", post(translate))
+        .layer(DefaultBodyLimit::max(config.max_body_bytes))
+        .with_state(state)
+}
+
+/// `POST /api/v1/chats/{chat_id}/messages:bulk`: index a JSONL body of
+/// [`ChatMessage`]s of one chat. Valid lines are queued even when others are
+/// rejected; the response counts both and describes the first errors.
+async fn bulk_messages(
+    State(state): State<Arc<ApiState>>,
+    Path(chat_id): Path<i64>,
+    headers: HeaderMap,
+    body: String,
+) -> (StatusCode, Json<BulkResponse>) {
+    if !authorized(&headers, &state.token) {
+        return (StatusCode::UNAUTHORIZED, Json(BulkResponse::default()));
+    }
+
+    let (messages, response) = parse_lines(chat_id, &body);
+    for message in messages {
+        state.indexer.index(message).await;
+    }
+    tracing::info!(
+        "Bulk API: {} messages queued for chat {chat_id}, {} rejected",
+        response.accepted,
+        response.rejected
+    );
+    let status = if response.accepted == 0 && response.rejected > 0 {
+        StatusCode::BAD_REQUEST
+    } else {
+        StatusCode::OK
+    };
+    (status, Json(response))
+}
+
+/// `POST /api/v1/chats/{chat_id}/search`: run a search given as
+/// [`SearchParams`] JSON in the chat, e.g.
+/// `{"keyword": "部署", "from": "alice", "has": ["link"], "sort": "views"}`.
+async fn search(
+    State(state): State<Arc<ApiState>>,
+    Path(chat_id): Path<i64>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    if !authorized(&headers, &state.token) {
+        return error_response(StatusCode::UNAUTHORIZED, "invalid token");
+    }
+    let params = match parse_search(chat_id, &body, state.default_page_size) {
+        Ok(params) => params,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+
+    match state.search.search(&params).await {
+        Ok(result) => {
+            // API searches have no Telegram user to attribute them to
+            state.audit.record(AuditEntry {
+                chat_id,
+                user_id: None,
+                query: params.keyword.clone().unwrap_or_default(),
+                result_count: result.total,
+                date: chrono::Utc::now().timestamp(),
+            });
+            Json(SearchResponse {
+                total: result.total,
+                page: result.page,
+                total_pages: result.total_pages,
+                hits: result
+                    .messages
+                    .into_iter()
+                    .map(|hit| Hit {
+                        message: hit.message,
+                        snippet: hit.snippet.replace([HIGHLIGHT_START, HIGHLIGHT_END], ""),
+                    })
+                    .collect(),
+            })
+            .into_response()
+        }
+        Err(e) => {
+            let status = match e {
+                AppError::InvalidQuery(_) => StatusCode::BAD_REQUEST,
+                AppError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+                AppError::SearchTimeout => StatusCode::GATEWAY_TIMEOUT,
+                _ => StatusCode::BAD_GATEWAY,
+            };
+            tracing::warn!("API search in chat {chat_id} failed: {e}");
+            error_response(status, &e.to_string())
+        }
+    }
+}
+
+/// `POST /api/v1/chats/{chat_id}/search:translate`: the Elasticsearch request
+/// body the search endpoint would send for the same JSON, without running it.
+async fn translate(
+    State(state): State<Arc<ApiState>>,
+    Path(chat_id): Path<i64>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    if !authorized(&headers, &state.token) {
+        return error_response(StatusCode::UNAUTHORIZED, "invalid token");
+    }
+    match parse_search(chat_id, &body, state.default_page_size) {
+        Ok(params) => {
+            let from = params.offset().unwrap_or_default();
+            let params = state.search.resolve_senders(&params).await;
+            let mut query = state.search.search_body(&params);
+            query["from"] = json!(from);
+            query["size"] = json!(params.page_size);
+            Json(query).into_response()
+        }
+        Err(e) => error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+    }
+}
+
+/// Read a search of the chat in the path; an empty body searches everything.
+/// The keyword is validated and normalized like the bot's `/s` queries.
+fn parse_search(
+    chat_id: i64,
+    body: &str,
+    default_page_size: usize,
+) -> Result<SearchParams, AppError> {
+    let mut params: SearchParams = if body.trim().is_empty() {
+        SearchParams::default()
+    } else {
+        serde_json::from_str(body)?
+    };
+    params.chat_id = chat_id;
+    params.page_size = match params.page_size {
+        0 => default_page_size,
+        size => size.min(MAX_PAGE_SIZE),
+    };
+    if params.offset().is_none() {
+        return Err(QueryError::PageTooDeep.into());
+    }
+    if let Some(keyword) = &params.keyword {
+        params.keyword = Some(validate_keyword(keyword)?);
+    }
+    Ok(params)
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+/// Parse one message per non-blank line, rejecting lines of other chats.
+fn parse_lines(chat_id: i64, body: &str) -> (Vec<ChatMessage>, BulkResponse) {
+    let mut messages = Vec::new();
+    let mut response = BulkResponse::default();
+    for (i, line) in body.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let error = match serde_json::from_str::<ChatMessage>(line) {
+            Ok(message) if message.chat_id == chat_id => {
+                messages.push(message);
+                continue;
+            }
+            Ok(message) => format!(
+                "chat_id {} does not match the chat {chat_id} in the path",
+                message.chat_id
+            ),
+            Err(e) => e.to_string(),
+        };
+        response.rejected += 1;
+        if response.errors.len() < MAX_REPORTED_ERRORS {
+            response.errors.push(LineError { line: i + 1, error });
+        }
+    }
+    response.accepted = messages.len();
+    (messages, response)
+}
+
+fn authorized(headers: &HeaderMap, expected: &str) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|token| token_matches(token, expected))
+}
+
+/// Compare tokens without returning early, so response times don't reveal
+/// how much of a guess was right.
+fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::es::search::{Attachment, SearchSort};
+
+    #[test]
+    fn lines_of_other_chats_are_rejected() {
+        let body = concat!(
+            r#"{"message_id":1,"chat_id":-100,"text":"a","date":1700000000,"message_type":"text"}"#,
+            "\n\n",
+            r#"{"message_id":2,"chat_id":-200,"text":"b","date":1700000000,"message_type":"text"}"#,
+            "\n",
+            "not json\n",
+        );
+        let (messages, response) = parse_lines(-100, body);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].message_id, 1);
+        assert_eq!((response.accepted, response.rejected), (1, 2));
+        let lines: Vec<usize> = response.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [3, 4]);
+    }
+
+    #[test]
+    fn searches_are_scoped_to_the_chat_in_the_path() {
+        let body =
+            r#"{"chat_id":-200,"keyword":"部署","has":["link"],"sort":"views","page_size":500}"#;
+        let params = parse_search(-100, body, 10).unwrap();
+        assert_eq!(params.chat_id, -100);
+        assert_eq!(params.keyword.as_deref(), Some("部署"));
+        assert_eq!(params.has, [Attachment::Link]);
+        assert_eq!(params.sort, SearchSort::Views);
+        assert_eq!(params.page_size, MAX_PAGE_SIZE);
+
+        let params = parse_search(-100, "", 10).unwrap();
+        assert_eq!((params.page, params.page_size), (0, 10));
+        assert!(parse_search(-100, r#"{"has":["nope"]}"#, 10).is_err());
+
+        let params = parse_search(-100, r#"{"keyword":"ｄｏｃｋｅｒ部署"}"#, 10).unwrap();
+        assert_eq!(params.keyword.as_deref(), Some("docker 部署"));
+        assert!(parse_search(-100, r#"{"page":99}"#, 100).is_ok());
+        assert!(parse_search(-100, r#"{"page":100}"#, 100).is_err());
+        assert!(parse_search(-100, &format!(r#"{{"page":{}}}"#, usize::MAX), 10).is_err());
+    }
+
+    #[test]
+    fn tokens_must_match_exactly() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("secreT", "secret"));
+        assert!(!token_matches("secret2", "secret"));
+        assert!(!token_matches("", "secret"));
+    }
+}
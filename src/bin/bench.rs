@@ -0,0 +1,381 @@
+//! Load-testing tool for sizing the indexer and search on big groups.
+//!
+//! Generates synthetic messages across a number of chats and users, pumps
+//! them through `BatchIndexer` at a given rate, waits until they are all
+//! searchable, then runs a concurrent search workload and prints throughput
+//! and latency percentiles. Writes to its own indexes (`search_bench` by
+//! default), which are deleted afterwards unless `--keep` is given.
+//!
+//! Usage: bench [--es-url URL] [--index NAME] [--chats N] [--users N]
+//!              [--messages N] [--rate MSGS_PER_SEC] [--batch-size N]
+//!              [--flush-interval-ms MS] [--searches N] [--concurrency N] [--keep]
+
+use anyhow::{bail, Context, Result};
+use elasticsearch::indices::{IndicesDeleteParts, IndicesRefreshParts};
+use elasticsearch::{CountParts, Elasticsearch};
+use search_bot_rs::config::AppConfig;
+use search_bot_rs::es::breaker::CircuitBreaker;
+use search_bot_rs::es::client::create_client;
+use search_bot_rs::es::indexer::BatchIndexer;
+use search_bot_rs::es::search::{SearchClient, SearchParams};
+use search_bot_rs::es::users::UserStore;
+use search_bot_rs::models::message::{ChatMessage, MessageType};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+/// Words synthetic messages are made of; searches pick from the same list.
+const VOCABULARY: &[&str] = &[
+    "今天",
+    "天气",
+    "开会",
+    "周末",
+    "吃饭",
+    "项目",
+    "上线",
+    "测试",
+    "文档",
+    "部署",
+    "服务器",
+    "数据库",
+    "rust",
+    "docker",
+    "elasticsearch",
+    "telegram",
+    "bug",
+    "release",
+    "review",
+    "deadline",
+    "咖啡",
+    "电影",
+    "旅行",
+    "照片",
+    "链接",
+    "教程",
+    "问题",
+    "答案",
+    "新闻",
+    "讨论",
+];
+/// How long indexed messages may take to become searchable after the last
+/// one was queued.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(300);
+/// Synthetic chat ids start here, clear of real supergroup ids.
+const CHAT_ID_BASE: i64 = -2_000_000_000_000;
+
+// ── Options ────────────────────────────────────────────────────
+
+#[derive(Debug)]
+struct Options {
+    es_url: String,
+    index: String,
+    chats: i64,
+    users: i64,
+    messages: u64,
+    /// Messages queued per second (0 = as fast as the indexer accepts them)
+    rate: u64,
+    batch_size: usize,
+    flush_interval_ms: u64,
+    searches: usize,
+    concurrency: usize,
+    keep: bool,
+}
+
+impl Options {
+    fn parse() -> Result<Self> {
+        let mut options = Self {
+            es_url: std::env::var("ELASTICSEARCH_URL")
+                .unwrap_or_else(|_| "http://localhost:9200".into()),
+            index: "search_bench".into(),
+            chats: 10,
+            users: 200,
+            messages: 100_000,
+            rate: 0,
+            batch_size: 50,
+            flush_interval_ms: 5000,
+            searches: 1000,
+            concurrency: 8,
+            keep: false,
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(flag) = args.next() {
+            if flag == "--keep" {
+                options.keep = true;
+                continue;
+            }
+            let value = args
+                .next()
+                .with_context(|| format!("Missing value for {flag}"))?;
+            let number = || {
+                value
+                    .parse::<u64>()
+                    .with_context(|| format!("Invalid value for {flag}: {value}"))
+            };
+            match flag.as_str() {
+                "--es-url" => options.es_url = value.clone(),
+                "--index" => options.index = value.clone(),
+                "--chats" => options.chats = number()? as i64,
+                "--users" => options.users = number()? as i64,
+                "--messages" => options.messages = number()?,
+                "--rate" => options.rate = number()?,
+                "--batch-size" => options.batch_size = number()? as usize,
+                "--flush-interval-ms" => options.flush_interval_ms = number()?,
+                "--searches" => options.searches = number()? as usize,
+                "--concurrency" => options.concurrency = number()? as usize,
+                _ => bail!("Unknown option {flag}"),
+            }
+        }
+        if options.chats == 0 || options.users == 0 || options.batch_size == 0 {
+            bail!("--chats, --users and --batch-size must be positive");
+        }
+        options.concurrency = options.concurrency.max(1);
+        Ok(options)
+    }
+}
+
+// ── Synthetic data ─────────────────────────────────────────────
+
+/// Small deterministic xorshift generator, so runs are repeatable.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn word(&mut self) -> &'static str {
+        VOCABULARY[self.below(VOCABULARY.len() as u64) as usize]
+    }
+}
+
+fn synthetic_message(rng: &mut Rng, options: &Options, message_id: i64, now: i64) -> ChatMessage {
+    let words = 3 + rng.below(12);
+    let text = (0..words).map(|_| rng.word()).collect::<Vec<_>>().join(" ");
+    ChatMessage {
+        chat_id: CHAT_ID_BASE - rng.below(options.chats as u64) as i64,
+        message_id,
+        user_id: Some(1 + rng.below(options.users as u64) as i64),
+        text,
+        // Spread over the last year
+        date: now - rng.below(365 * 86400) as i64,
+        message_type: MessageType::Text,
+        ..Default::default()
+    }
+}
+
+// ── Reporting ──────────────────────────────────────────────────
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((sorted.len() as f64 * p).ceil() as usize).clamp(1, sorted.len());
+    sorted[rank - 1]
+}
+
+fn print_latencies(label: &str, latencies: &mut [Duration]) {
+    latencies.sort();
+    println!(
+        "  {label}: p50 {:.2?}  p90 {:.2?}  p99 {:.2?}  max {:.2?}",
+        percentile(latencies, 0.50),
+        percentile(latencies, 0.90),
+        percentile(latencies, 0.99),
+        latencies.last().copied().unwrap_or_default(),
+    );
+}
+
+// ── Phases ─────────────────────────────────────────────────────
+
+/// Queue every message at the configured rate, returning how long each
+/// enqueue waited on the indexer's channel.
+async fn pump(indexer: &BatchIndexer, options: &Options) -> Vec<Duration> {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let now = chrono::Utc::now().timestamp();
+    let mut waits = Vec::with_capacity(options.messages as usize);
+    let started = Instant::now();
+
+    for i in 0..options.messages {
+        if options.rate > 0 {
+            let due = started + Duration::from_secs_f64(i as f64 / options.rate as f64);
+            tokio::time::sleep_until(due.into()).await;
+        }
+        let message = synthetic_message(&mut rng, options, i as i64 + 1, now);
+        let queued = Instant::now();
+        indexer.index(message).await;
+        waits.push(queued.elapsed());
+    }
+    waits
+}
+
+/// Wait until the index holds `expected` documents.
+async fn drain(es: &Elasticsearch, index: &str, expected: u64) -> Result<()> {
+    let deadline = Instant::now() + DRAIN_TIMEOUT;
+    loop {
+        es.indices()
+            .refresh(IndicesRefreshParts::Index(&[index]))
+            .send()
+            .await?;
+        let body: serde_json::Value = es
+            .count(CountParts::Index(&[index]))
+            .send()
+            .await?
+            .json()
+            .await?;
+        let count = body["count"].as_u64().unwrap_or(0);
+        if count >= expected {
+            return Ok(());
+        }
+        if Instant::now() > deadline {
+            bail!("Only {count} of {expected} messages were indexed");
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+}
+
+/// Run the search workload, returning the latency of each search and how
+/// many failed.
+async fn search_workload(search: Arc<SearchClient>, options: &Options) -> (Vec<Duration>, usize) {
+    let mut tasks = JoinSet::new();
+    let per_worker = options.searches.div_ceil(options.concurrency);
+    for worker in 0..options.concurrency {
+        let search = search.clone();
+        let count = per_worker.min(options.searches.saturating_sub(worker * per_worker));
+        let (chats, seed) = (options.chats as u64, worker as u64 + 1);
+        tasks.spawn(async move {
+            let mut rng = Rng(seed.wrapping_mul(0x2545_f491_4f6c_dd1d));
+            let mut latencies = Vec::with_capacity(count);
+            let mut failures = 0;
+            for _ in 0..count {
+                let keyword = match rng.below(3) {
+                    0 => format!("{} {}", rng.word(), rng.word()),
+                    _ => rng.word().to_string(),
+                };
+                let params = SearchParams {
+                    chat_id: CHAT_ID_BASE - rng.below(chats) as i64,
+                    keyword: Some(keyword),
+                    page_size: 5,
+                    ..Default::default()
+                };
+                let started = Instant::now();
+                match search.search(&params).await {
+                    Ok(_) => latencies.push(started.elapsed()),
+                    Err(_) => failures += 1,
+                }
+            }
+            (latencies, failures)
+        });
+    }
+
+    let (mut latencies, mut failures) = (Vec::new(), 0);
+    while let Some(result) = tasks.join_next().await {
+        let (worker_latencies, worker_failures) = result.expect("search worker panicked");
+        latencies.extend(worker_latencies);
+        failures += worker_failures;
+    }
+    (latencies, failures)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::from_default_env()
+                .add_directive("search_bot_rs=warn".parse()?),
+        )
+        .init();
+
+    let options = Options::parse()?;
+    println!("{options:#?}");
+
+    let mut config = AppConfig::defaults();
+    config.elasticsearch.url = options.es_url.clone();
+    config.elasticsearch.index_name = options.index.clone();
+    config.indexer.batch_size = options.batch_size;
+    config.indexer.flush_interval_ms = options.flush_interval_ms;
+    config.indexer.spool_path = std::env::temp_dir()
+        .join(format!("{}_spool.jsonl", options.index))
+        .to_string_lossy()
+        .into_owned();
+    // Keep the bot's side indexes out of the target cluster
+    config.audit.enabled = false;
+    config.alerts.enabled = false;
+    config.settings.index_name = format!("{}_settings", options.index);
+    config.chats.index_name = format!("{}_chats", options.index);
+    config.bookmarks.index_name = format!("{}_bookmarks", options.index);
+    let es = create_client(&config).await?;
+    let breaker = Arc::new(CircuitBreaker::new(config.breaker.failure_threshold));
+
+    let indexer = BatchIndexer::new(
+        es.clone(),
+        options.index.clone(),
+        &config.indexer,
+        breaker.clone(),
+        None,
+    );
+
+    println!("\nIndexing {} messages…", options.messages);
+    let started = Instant::now();
+    let mut waits = pump(&indexer, &options).await;
+    let queued_in = started.elapsed();
+    drain(&es, &options.index, options.messages).await?;
+    let indexed_in = started.elapsed();
+    let snapshot = indexer.snapshot();
+
+    println!(
+        "  queued in {queued_in:.2?}, searchable after {indexed_in:.2?} ({:.0} msg/s)",
+        options.messages as f64 / indexed_in.as_secs_f64()
+    );
+    print_latencies("enqueue wait", &mut waits);
+    println!(
+        "  bulk requests in the last 5 min: {} ({} failed), last took {:.2?}, {} spooled",
+        snapshot.recent_bulks,
+        snapshot.recent_errors,
+        snapshot.last_flush.unwrap_or_default(),
+        snapshot.spooled
+    );
+
+    println!(
+        "\nRunning {} searches with {} workers…",
+        options.searches, options.concurrency
+    );
+    let search = Arc::new(SearchClient::new(
+        es.clone(),
+        options.index.clone(),
+        &config.search,
+        &config.indexer,
+        breaker,
+        Arc::new(UserStore::new(es.clone(), config.users.index_name.clone())),
+    ));
+    let started = Instant::now();
+    let (mut latencies, failures) = search_workload(search, &options).await;
+    let elapsed = started.elapsed();
+    println!(
+        "  {} ok, {failures} failed in {elapsed:.2?} ({:.0} searches/s)",
+        latencies.len(),
+        latencies.len() as f64 / elapsed.as_secs_f64()
+    );
+    print_latencies("search latency", &mut latencies);
+
+    if !options.keep {
+        let indexes = [
+            options.index.as_str(),
+            &config.settings.index_name,
+            &config.chats.index_name,
+            &config.bookmarks.index_name,
+        ];
+        es.indices()
+            .delete(IndicesDeleteParts::Index(&indexes))
+            .send()
+            .await?;
+        println!("\nDeleted indexes {}", indexes.join(", "));
+    }
+    Ok(())
+}
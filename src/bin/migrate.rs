@@ -12,6 +12,7 @@ use mongodb::{
     bson::{doc, Document},
     Client as MongoClient,
 };
+use search_bot_rs::config::IdStrategy;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::sync::Arc;
@@ -44,6 +45,9 @@ struct MigrationSettings {
     batch_size: usize,
     #[serde(default)]
     dry_run: bool,
+    /// Document ids of migrated messages; must match the bot's `indexer.id_strategy`
+    #[serde(default)]
+    id_strategy: IdStrategy,
 }
 
 // ── Data models ────────────────────────────────────────────────
@@ -136,7 +140,14 @@ async fn main() -> Result<()> {
                             if config.migration.dry_run {
                                 ok += batch.len();
                             } else {
-                                match bulk_index(&es, &config.elasticsearch.index_name, &batch).await {
+                                match bulk_index(
+                                    &es,
+                                    &config.elasticsearch.index_name,
+                                    config.migration.id_strategy,
+                                    &batch,
+                                )
+                                .await
+                                {
                                     Ok(n) => ok += n,
                                     Err(e) => {
                                         tracing::error!("  Bulk index error: {e}");
@@ -165,7 +176,14 @@ async fn main() -> Result<()> {
             if config.migration.dry_run {
                 ok += batch.len();
             } else {
-                match bulk_index(&es, &config.elasticsearch.index_name, &batch).await {
+                match bulk_index(
+                    &es,
+                    &config.elasticsearch.index_name,
+                    config.migration.id_strategy,
+                    &batch,
+                )
+                .await
+                {
                     Ok(n) => ok += n,
                     Err(e) => {
                         tracing::error!("  Bulk index error: {e}");
@@ -213,20 +231,24 @@ fn load_config() -> Result<Config> {
                     .ok()
                     .and_then(|s| s.parse().ok())
                     .unwrap_or(false),
+                id_strategy: IdStrategy::default(),
             },
         }
     };
 
     // Env vars always override file config
-    if let Ok(v) = std::env::var("MIGRATION_DRY_RUN") {
-        if let Ok(b) = v.parse::<bool>() {
-            config.migration.dry_run = b;
-        }
+    if let Ok(v) = std::env::var("MIGRATION_DRY_RUN")
+        && let Ok(b) = v.parse::<bool>()
+    {
+        config.migration.dry_run = b;
     }
-    if let Ok(v) = std::env::var("MIGRATION_BATCH_SIZE") {
-        if let Ok(n) = v.parse::<usize>() {
-            config.migration.batch_size = n;
-        }
+    if let Ok(v) = std::env::var("MIGRATION_BATCH_SIZE")
+        && let Ok(n) = v.parse::<usize>()
+    {
+        config.migration.batch_size = n;
+    }
+    if let Ok(v) = std::env::var("MIGRATION_ID_STRATEGY") {
+        config.migration.id_strategy = v.parse()?;
     }
 
     Ok(config)
@@ -334,14 +356,19 @@ fn parse_document(doc: Document, expected_chat_id: i64) -> Result<EsMessage> {
 
 // ── Bulk indexing ──────────────────────────────────────────────
 
-async fn bulk_index(es: &Elasticsearch, index: &str, messages: &[EsMessage]) -> Result<usize> {
+async fn bulk_index(
+    es: &Elasticsearch,
+    index: &str,
+    id_strategy: IdStrategy,
+    messages: &[EsMessage],
+) -> Result<usize> {
     if messages.is_empty() {
         return Ok(0);
     }
 
     let mut body: Vec<JsonBody<serde_json::Value>> = Vec::with_capacity(messages.len() * 2);
     for msg in messages {
-        let doc_id = format!("{}_{}", msg.chat_id, msg.message_id);
+        let doc_id = id_strategy.doc_id(msg.chat_id, msg.message_id, None);
         body.push(json!({ "index": { "_id": doc_id } }).into());
         body.push(serde_json::to_value(msg)?.into());
     }
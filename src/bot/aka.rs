@@ -0,0 +1,93 @@
+//! `/aka @user`: the usernames and display names a member of this chat has
+//! posted under, so mentions of a former name can still be placed.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::util::{format_timestamp, html_escape};
+use crate::es::users::{UserInfo, UserStore};
+
+const USAGE: &str = "用法: /aka @用户名、名字或用户 ID，或回复某人的消息发送 /aka\n\
+    曾用的用户名和名字也能查到。";
+/// Senders shown when a name matches several.
+const MAX_LISTED: usize = 5;
+
+pub async fn handle_aka(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    users: Arc<UserStore>,
+) -> anyhow::Result<()> {
+    if msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "请在群组中使用此命令。")
+            .await?;
+        return Ok(());
+    }
+    let chat_id = msg.chat.id.0;
+    let args = args.trim();
+    let replied = msg
+        .reply_to_message()
+        .and_then(|m| m.from.as_ref())
+        .map(|u| u.id.0 as i64);
+
+    let found = match (args, replied) {
+        ("", Some(user_id)) => users.get(chat_id, user_id).await?.into_iter().collect(),
+        ("", None) => {
+            bot.send_message(msg.chat.id, USAGE).await?;
+            return Ok(());
+        }
+        (arg, _) => match arg.parse::<i64>() {
+            Ok(user_id) => users.get(chat_id, user_id).await?.into_iter().collect(),
+            Err(_) => users.find(chat_id, arg).await?,
+        },
+    };
+
+    let text = if found.is_empty() {
+        "本群没有这个用户的记录。".to_string()
+    } else {
+        found
+            .iter()
+            .take(MAX_LISTED)
+            .map(format_user)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    };
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+fn format_user(info: &UserInfo) -> String {
+    let name = info.display_name.as_deref().unwrap_or("（无名称）");
+    let mut text = format!(
+        "<a href=\"tg://user?id={id}\">{}</a> <code>{id}</code>",
+        html_escape(name),
+        id = info.user_id
+    );
+    if let Some(ref username) = info.username {
+        text.push_str(&format!(" @{}", html_escape(username)));
+    }
+    text.push('\n');
+    if info.name_history.len() <= 1 {
+        text.push_str("没有改过名。");
+        return text;
+    }
+    text.push_str("<b>曾用名：</b>\n");
+    for change in info.name_history.iter().rev() {
+        let mut names = Vec::new();
+        if let Some(ref display_name) = change.display_name {
+            names.push(html_escape(display_name));
+        }
+        if let Some(ref username) = change.username {
+            names.push(format!("@{}", html_escape(username)));
+        }
+        text.push_str(&format!(
+            "{} 起 — {}\n",
+            format_timestamp(change.since),
+            names.join(" ")
+        ));
+    }
+    text
+}
@@ -0,0 +1,212 @@
+//! Keyword spike alerts: admins register keywords per chat, and a background
+//! task notifies them when a keyword's last-24h count jumps above its baseline.
+
+use std::sync::Arc;
+use std::time::Duration;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::shards::Shards;
+use crate::bot::telegram::TelegramSender;
+use crate::bot::util::html_escape;
+use crate::config::{AlertsConfig, AppConfig};
+use crate::es::alerts::{normalize_keyword, AlertStore, KeywordWatch};
+use crate::es::analytics::AnalyticsClient;
+
+const USAGE: &str = "用法:\n\
+    /alert add <关键词> — 监控关键词频率\n\
+    /alert del <关键词> — 取消监控\n\
+    /alert list — 查看本群监控的关键词";
+/// Messages percolated against the watches per request.
+const PERCOLATE_BATCH: usize = 500;
+
+/// Handle `/alert add|del|list`: manage the chat's watched keywords (admins).
+pub async fn handle_alert(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    alerts: Arc<AlertStore>,
+    config: Arc<AppConfig>,
+) -> anyhow::Result<()> {
+    if !config.alerts.enabled {
+        bot.send_message(msg.chat.id, "关键词提醒未启用。").await?;
+        return Ok(());
+    }
+    if msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "请在群组中使用此命令。")
+            .await?;
+        return Ok(());
+    }
+
+    let chat_id = msg.chat.id.0;
+    let (action, keyword) = args.trim().split_once(' ').unwrap_or((args.trim(), ""));
+    let keyword = normalize_keyword(keyword);
+
+    let reply = match (action, keyword.is_empty()) {
+        ("add", false) => {
+            alerts
+                .add(&KeywordWatch {
+                    chat_id,
+                    keyword: keyword.clone(),
+                    created_by: msg.from.as_ref().map_or(0, |u| u.id.0 as i64),
+                    created_at: chrono::Utc::now().timestamp(),
+                    last_alerted: None,
+                })
+                .await?;
+            format!(
+                "已开始监控「{}」，当日频率超过近 {} 天均值的 {} 倍时会通知管理员。",
+                html_escape(&keyword),
+                config.alerts.baseline_days,
+                config.alerts.factor
+            )
+        }
+        ("del", false) => {
+            if alerts.remove(chat_id, &keyword).await? {
+                format!("已取消监控「{}」。", html_escape(&keyword))
+            } else {
+                format!("本群没有监控「{}」。", html_escape(&keyword))
+            }
+        }
+        ("list", _) => {
+            let watches = alerts.list(chat_id).await?;
+            if watches.is_empty() {
+                "本群没有监控任何关键词。".to_string()
+            } else {
+                let mut text = "<b>本群监控的关键词：</b>\n".to_string();
+                for watch in &watches {
+                    text.push_str(&format!("• {}\n", html_escape(&watch.keyword)));
+                }
+                text
+            }
+        }
+        _ => USAGE.to_string(),
+    };
+
+    bot.send_message(msg.chat.id, reply)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+/// Spawn the background task that checks watches periodically.
+pub fn spawn_alert_monitor(
+    shards: Arc<Shards>,
+    sender: Arc<TelegramSender>,
+    alerts: Arc<AlertStore>,
+    analytics: Arc<AnalyticsClient>,
+    config: AlertsConfig,
+) {
+    tokio::spawn(async move {
+        match alerts.backfill_queries().await {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("Added percolator queries to {n} keyword watches"),
+            Err(e) => tracing::warn!("Failed to backfill keyword watch queries: {e}"),
+        }
+
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(config.check_interval_secs.max(60)));
+        // The first check covers the whole spike window
+        let mut since = chrono::Utc::now().timestamp() - 86400;
+        loop {
+            interval.tick().await;
+            let now = chrono::Utc::now().timestamp();
+            match check_alerts(&shards, &sender, &alerts, &analytics, &config, since, now).await {
+                Ok(()) => since = now,
+                Err(e) => tracing::warn!("Keyword alert check failed: {e}"),
+            }
+        }
+    });
+}
+
+/// Check the watches matched by messages dated in `[since, now)`: only a
+/// watch with new matches can have spiked since the last check.
+async fn check_alerts(
+    shards: &Shards,
+    sender: &TelegramSender,
+    alerts: &AlertStore,
+    analytics: &AnalyticsClient,
+    config: &AlertsConfig,
+    since: i64,
+    now: i64,
+) -> anyhow::Result<()> {
+    let mut matched: Vec<KeywordWatch> = Vec::new();
+    for batch in analytics
+        .messages_between(since, now)
+        .await?
+        .chunks(PERCOLATE_BATCH)
+    {
+        for watch in alerts.matching(batch).await? {
+            if !matched
+                .iter()
+                .any(|w| w.chat_id == watch.chat_id && w.keyword == watch.keyword)
+            {
+                matched.push(watch);
+            }
+        }
+    }
+
+    for watch in matched {
+        // One notification per keyword per day at most
+        if watch.last_alerted.is_some_and(|at| now - at < 86400) {
+            continue;
+        }
+
+        let counts = analytics
+            .keyword_daily_counts(watch.chat_id, &watch.keyword, config.baseline_days + 1)
+            .await?;
+        let Some((&today, history)) = counts.split_first() else {
+            continue;
+        };
+        let baseline = history.iter().sum::<u64>() as f64 / history.len().max(1) as f64;
+        if today < config.min_count || (today as f64) <= baseline.max(1.0) * config.factor {
+            continue;
+        }
+
+        notify_admins(shards, sender, &watch, today, baseline).await;
+        alerts.mark_alerted(&watch, now).await?;
+    }
+    Ok(())
+}
+
+/// Message each human admin privately; fall back to the group when none of
+/// them has started a chat with the bot.
+async fn notify_admins(
+    shards: &Shards,
+    sender: &TelegramSender,
+    watch: &KeywordWatch,
+    today: u64,
+    baseline: f64,
+) {
+    let chat_id = ChatId(watch.chat_id);
+    let text = format!(
+        "⚠️ <b>关键词异常提醒</b>\n\
+         群组 {} 中「{}」最近 24 小时出现 <b>{today}</b> 次，\
+         近期日均 {baseline:.1} 次。",
+        watch.chat_id,
+        html_escape(&watch.keyword)
+    );
+
+    let bot = shards.bot_for(chat_id);
+    let admins = match bot.get_chat_administrators(chat_id).await {
+        Ok(admins) => admins,
+        Err(e) => {
+            tracing::warn!("Failed to list admins of {}: {e}", watch.chat_id);
+            Vec::new()
+        }
+    };
+
+    let mut delivered = false;
+    for admin in admins.iter().filter(|m| !m.user.is_bot) {
+        if sender
+            .send_html_via(chat_id, admin.user.id, text.clone())
+            .await
+            .is_ok()
+        {
+            delivered = true;
+        }
+    }
+
+    if !delivered && let Err(e) = sender.send_html(chat_id, text).await {
+        tracing::warn!("Failed to send keyword alert to {}: {e}", watch.chat_id);
+    }
+}
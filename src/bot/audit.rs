@@ -0,0 +1,86 @@
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::util::{format_timestamp, html_escape, parse_period};
+use crate::es::audit::{AuditLog, AuditSummary};
+
+/// Handle `/audit [chat] [period]`: owner-only review of recorded searches.
+pub async fn handle_audit(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    audit: Arc<AuditLog>,
+) -> anyhow::Result<()> {
+    if !audit.is_enabled() {
+        bot.send_message(msg.chat.id, "搜索审计未启用。").await?;
+        return Ok(());
+    }
+
+    // In a group the current chat is the default scope; in private, all chats.
+    let mut chat_id = (!msg.chat.is_private()).then_some(msg.chat.id.0);
+    let mut period = None;
+    for token in args.split_whitespace() {
+        if let Some(secs) = parse_period(token) {
+            period = Some((token.to_string(), secs));
+        } else if let Ok(id) = token.parse::<i64>() {
+            chat_id = Some(id);
+        } else if token == "all" {
+            chat_id = None;
+        } else {
+            bot.send_message(
+                msg.chat.id,
+                "用法: /audit [chat_id|all] [时间段]\n\n示例:\n/audit\n/audit 7d\n/audit -1001234567890 30d",
+            )
+            .await?;
+            return Ok(());
+        }
+    }
+
+    let since = period
+        .as_ref()
+        .map(|(_, secs)| chrono::Utc::now().timestamp() - secs);
+    let summary = audit.summary(chat_id, since).await?;
+
+    let scope = chat_id.map_or("全部群组".to_string(), |id| format!("群组 {id}"));
+    let range = period.map_or("全部时间".to_string(), |(token, _)| format!("最近 {token}"));
+    bot.send_message(msg.chat.id, format_summary(&summary, &scope, &range))
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+fn format_summary(summary: &AuditSummary, scope: &str, range: &str) -> String {
+    let mut text = format!(
+        "<b>搜索审计</b>（{scope}，{range}）\n共 <b>{}</b> 次搜索\n",
+        summary.total
+    );
+    if summary.total == 0 {
+        return text;
+    }
+
+    text.push_str("\n<b>活跃用户：</b>\n");
+    for (user_id, count) in &summary.top_users {
+        text.push_str(&format!(
+            "<a href=\"tg://user?id={user_id}\">User {user_id}</a> — {count}\n"
+        ));
+    }
+
+    text.push_str("\n<b>热门查询：</b>\n");
+    for (query, count) in &summary.top_queries {
+        text.push_str(&format!("{} — {count}\n", html_escape(query)));
+    }
+
+    text.push_str("\n<b>最近搜索：</b>\n");
+    for entry in &summary.recent {
+        let user = entry.user_id.map_or("-".to_string(), |id| id.to_string());
+        text.push_str(&format!(
+            "<i>{}</i> [{}] {user}: {} ({})\n",
+            format_timestamp(entry.date),
+            entry.chat_id,
+            html_escape(&entry.query),
+            entry.result_count
+        ));
+    }
+    text
+}
@@ -0,0 +1,387 @@
+//! `/exportbookmarks [json|csv]` sends the sender's bookmarks to them as a
+//! file in a private chat, and `/importbookmarks`, as a reply to such a file,
+//! adds its bookmarks back, so they can move between bot instances.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use teloxide::net::Download;
+use teloxide::prelude::*;
+use teloxide::types::{InputFile, ReplyParameters};
+
+use crate::es::bookmarks::{Bookmark, BookmarkStore};
+
+/// Version written to and accepted from JSON exports.
+const FORMAT_VERSION: u32 = 1;
+/// Largest file `/importbookmarks` downloads.
+const MAX_FILE_BYTES: u32 = 1_000_000;
+/// Most bookmarks taken from one file.
+const MAX_IMPORTED: usize = 1000;
+/// Columns of CSV exports, in order.
+const CSV_COLUMNS: [&str; 6] = [
+    "chat_id",
+    "message_id",
+    "date",
+    "saved_at",
+    "display_name",
+    "preview",
+];
+
+/// A bookmark as exported; the owner is whoever imports it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Entry {
+    chat_id: i64,
+    message_id: i64,
+    #[serde(default)]
+    date: i64,
+    #[serde(default)]
+    saved_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    display_name: Option<String>,
+    #[serde(default)]
+    preview: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportFile {
+    version: u32,
+    bookmarks: Vec<Entry>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Csv,
+}
+
+/// Handle `/exportbookmarks [json|csv]`: every bookmark of the sender, sent to
+/// them privately so a group never sees another member's list.
+pub async fn handle_export_bookmarks(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    bookmarks: Arc<BookmarkStore>,
+) -> anyhow::Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    let format = match args.trim().to_lowercase().as_str() {
+        "" | "json" => Format::Json,
+        "csv" => Format::Csv,
+        _ => {
+            bot.send_message(msg.chat.id, "用法: /exportbookmarks [json|csv]")
+                .reply_parameters(ReplyParameters::new(msg.id))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let list = bookmarks.list(user.id.0 as i64, None).await?;
+    if list.is_empty() {
+        bot.send_message(msg.chat.id, "还没有收藏，没有可导出的内容。")
+            .reply_parameters(ReplyParameters::new(msg.id))
+            .await?;
+        return Ok(());
+    }
+    let entries: Vec<Entry> = list.into_iter().map(Entry::from).collect();
+    let (data, file_name) = match format {
+        Format::Json => (
+            serde_json::to_vec_pretty(&ExportFile {
+                version: FORMAT_VERSION,
+                bookmarks: entries.clone(),
+            })?,
+            "bookmarks.json",
+        ),
+        Format::Csv => (to_csv(&entries).into_bytes(), "bookmarks.csv"),
+    };
+
+    let sent = bot
+        .send_document(user.id, InputFile::memory(data).file_name(file_name))
+        .caption(format!(
+            "共 {} 条收藏。回复这个文件发送 /importbookmarks 即可导入。",
+            entries.len()
+        ))
+        .await;
+    if let Err(e) = sent {
+        tracing::warn!("Failed to send bookmark export to {}: {e}", user.id);
+        bot.send_message(
+            msg.chat.id,
+            "无法私聊发送文件，请先私聊机器人并发送 /start。",
+        )
+        .reply_parameters(ReplyParameters::new(msg.id))
+        .await?;
+    } else if !msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "已通过私聊发送导出文件。")
+            .reply_parameters(ReplyParameters::new(msg.id))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Handle `/importbookmarks` sent as a reply to an exported JSON or CSV file.
+pub async fn handle_import_bookmarks(
+    bot: Bot,
+    msg: Message,
+    bookmarks: Arc<BookmarkStore>,
+) -> anyhow::Result<()> {
+    let Some(user_id) = msg.from.as_ref().map(|u| u.id.0 as i64) else {
+        return Ok(());
+    };
+    let reply = |text: String| {
+        bot.send_message(msg.chat.id, text)
+            .reply_parameters(ReplyParameters::new(msg.id))
+    };
+    let Some(document) = msg.reply_to_message().and_then(|r| r.document()) else {
+        reply("请回复 /exportbookmarks 导出的 JSON 或 CSV 文件发送 /importbookmarks".into())
+            .await?;
+        return Ok(());
+    };
+    if document.file.size > MAX_FILE_BYTES {
+        reply(format!("文件过大，最多 {} KB", MAX_FILE_BYTES / 1000)).await?;
+        return Ok(());
+    }
+
+    let file = bot.get_file(document.file.id.clone()).await?;
+    let mut data = Vec::with_capacity(file.size as usize);
+    bot.download_file(&file.path, &mut data).await?;
+
+    let entries = match parse_file(&data) {
+        Ok(entries) => entries,
+        Err(e) => {
+            reply(format!("无法读取文件：{e}")).await?;
+            return Ok(());
+        }
+    };
+    let skipped = entries.len().saturating_sub(MAX_IMPORTED);
+    let now = chrono::Utc::now().timestamp();
+    let imported: Vec<Bookmark> = entries
+        .into_iter()
+        .take(MAX_IMPORTED)
+        .map(|entry| entry.into_bookmark(user_id, now))
+        .collect();
+    let stored = bookmarks.add_all(&imported).await?;
+
+    let mut text = format!("已导入 {stored} 条收藏");
+    if skipped > 0 {
+        text.push_str(&format!(
+            "，超出上限 {MAX_IMPORTED} 条的 {skipped} 条未导入"
+        ));
+    }
+    reply(text).await?;
+    Ok(())
+}
+
+impl From<Bookmark> for Entry {
+    fn from(bookmark: Bookmark) -> Self {
+        Self {
+            chat_id: bookmark.chat_id,
+            message_id: bookmark.message_id,
+            date: bookmark.date,
+            saved_at: Some(bookmark.saved_at),
+            display_name: bookmark.display_name,
+            preview: bookmark.preview,
+        }
+    }
+}
+
+impl Entry {
+    fn into_bookmark(self, user_id: i64, now: i64) -> Bookmark {
+        Bookmark {
+            user_id,
+            chat_id: self.chat_id,
+            message_id: self.message_id,
+            preview: self.preview,
+            display_name: self.display_name,
+            date: self.date,
+            saved_at: self.saved_at.unwrap_or(now),
+        }
+    }
+}
+
+/// Read an export in either format; JSON exports are objects, so anything
+/// else is taken as CSV.
+fn parse_file(data: &[u8]) -> Result<Vec<Entry>, String> {
+    let text = std::str::from_utf8(data).map_err(|_| "不是 UTF-8 文本".to_string())?;
+    // Spreadsheet programs often save CSV with a byte order mark
+    let text = text.trim_start_matches('\u{feff}');
+    if text.trim_start().starts_with('{') {
+        let file: ExportFile =
+            serde_json::from_str(text).map_err(|e| format!("JSON 格式错误（{e}）"))?;
+        if file.version > FORMAT_VERSION {
+            return Err(format!("不支持的版本 {}", file.version));
+        }
+        Ok(file.bookmarks)
+    } else {
+        from_csv(text)
+    }
+}
+
+fn to_csv(entries: &[Entry]) -> String {
+    let mut csv = CSV_COLUMNS.join(",");
+    csv.push_str("\r\n");
+    for entry in entries {
+        let fields = [
+            entry.chat_id.to_string(),
+            entry.message_id.to_string(),
+            entry.date.to_string(),
+            entry.saved_at.map(|t| t.to_string()).unwrap_or_default(),
+            entry.display_name.clone().unwrap_or_default(),
+            entry.preview.clone(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+/// Quote a field when it contains a separator, a quote or a line break.
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Parse CSV with a header row naming at least `chat_id` and `message_id`;
+/// the other columns of [`CSV_COLUMNS`] are optional and may be in any order.
+fn from_csv(text: &str) -> Result<Vec<Entry>, String> {
+    let mut rows = csv_records(text)?.into_iter();
+    let header = rows.next().ok_or("文件为空")?;
+    let column = |name: &str| header.iter().position(|h| h.trim() == name);
+    let (Some(chat_col), Some(message_col)) = (column("chat_id"), column("message_id")) else {
+        return Err("CSV 缺少 chat_id 或 message_id 列".into());
+    };
+    let [date_col, saved_col, name_col, preview_col] =
+        ["date", "saved_at", "display_name", "preview"].map(column);
+
+    let mut entries = Vec::new();
+    for (i, row) in rows.enumerate() {
+        if row.iter().all(|f| f.is_empty()) {
+            continue;
+        }
+        let field = |col: Option<usize>| col.and_then(|c| row.get(c)).map(String::as_str);
+        let number = |col: Option<usize>| -> Result<Option<i64>, String> {
+            match field(col).map(str::trim).filter(|f| !f.is_empty()) {
+                Some(f) => f
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| format!("第 {} 行数字无效：{f}", i + 2)),
+                None => Ok(None),
+            }
+        };
+        let (Some(chat_id), Some(message_id)) =
+            (number(Some(chat_col))?, number(Some(message_col))?)
+        else {
+            return Err(format!("第 {} 行缺少 chat_id 或 message_id", i + 2));
+        };
+        entries.push(Entry {
+            chat_id,
+            message_id,
+            date: number(date_col)?.unwrap_or_default(),
+            saved_at: number(saved_col)?,
+            display_name: field(name_col)
+                .filter(|f| !f.is_empty())
+                .map(str::to_string),
+            preview: field(preview_col).unwrap_or_default().to_string(),
+        });
+    }
+    Ok(entries)
+}
+
+/// Split CSV text into records of fields (RFC 4180: quoted fields may hold
+/// separators and line breaks, quotes inside them are doubled).
+fn csv_records(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("CSV 引号未闭合".into());
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(message_id: i64, display_name: Option<&str>, preview: &str) -> Entry {
+        Entry {
+            chat_id: -1001234567890,
+            message_id,
+            date: 1_700_000_000,
+            saved_at: Some(1_700_000_100),
+            display_name: display_name.map(str::to_string),
+            preview: preview.to_string(),
+        }
+    }
+
+    #[test]
+    fn csv_round_trips_quotes_and_line_breaks() {
+        let entries = vec![
+            entry(1, Some("Alice"), "plain"),
+            entry(2, None, "a, \"quoted\"\nsecond line"),
+            entry(3, Some("张三"), ""),
+        ];
+        let csv = to_csv(&entries);
+        assert_eq!(parse_file(csv.as_bytes()), Ok(entries));
+    }
+
+    #[test]
+    fn csv_columns_are_found_by_name() {
+        let csv = "\u{feff}message_id,preview,chat_id\n42,hello,-100\n\n";
+        let entries = parse_file(csv.as_bytes()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].chat_id, entries[0].message_id), (-100, 42));
+        assert_eq!(entries[0].preview, "hello");
+        assert_eq!(entries[0].saved_at, None);
+    }
+
+    #[test]
+    fn json_export_is_read_back() {
+        let entries = vec![entry(7, Some("Bob"), "hi")];
+        let json = serde_json::to_vec(&ExportFile {
+            version: FORMAT_VERSION,
+            bookmarks: entries.clone(),
+        })
+        .unwrap();
+        assert_eq!(parse_file(&json), Ok(entries));
+        assert!(parse_file(br#"{"version":99,"bookmarks":[]}"#).is_err());
+    }
+
+    #[test]
+    fn malformed_csv_is_rejected() {
+        assert!(parse_file(b"chat_id,message_id\n1,\"2\n").is_err());
+        assert!(parse_file(b"chat_id,preview\n1,x\n").is_err());
+        assert!(parse_file(b"chat_id,message_id\n1,abc\n").is_err());
+    }
+}
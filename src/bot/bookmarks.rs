@@ -0,0 +1,204 @@
+//! Personal bookmarks: the 收藏 button of a search hit saves the message to
+//! the presser's list, and `/bookmarks` browses and removes them.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::{
+    InlineKeyboardButton, InlineKeyboardMarkup, MaybeInaccessibleMessage, ParseMode,
+    ReplyParameters,
+};
+
+use crate::bot::session::SessionStore;
+use crate::bot::util::{format_message_link, format_timestamp, html_escape, no_link_preview};
+use crate::error::AppError;
+use crate::es::bookmarks::{Bookmark, BookmarkStore};
+use crate::es::search::SearchClient;
+
+/// Callback data prefix of remove buttons: `bm:del:<chat_id>:<message_id>`.
+pub const CALLBACK_PREFIX: &str = "bm:";
+/// Message text kept with a bookmark for the list.
+const PREVIEW_CHARS: usize = 80;
+/// Bookmarks listed, newest first.
+const MAX_LISTED: usize = 20;
+/// Remove buttons per keyboard row.
+const BUTTONS_PER_ROW: usize = 5;
+
+pub fn is_bookmarks_callback(q: &CallbackQuery) -> bool {
+    q.data
+        .as_deref()
+        .is_some_and(|d| d.starts_with(CALLBACK_PREFIX))
+}
+
+/// Save the hit at `index` on the results message `msg` to the presser's
+/// bookmarks, answering the press with the outcome.
+pub(crate) async fn save_bookmark(
+    bot: &Bot,
+    q: &CallbackQuery,
+    msg: &Message,
+    index: u8,
+    search_client: &SearchClient,
+    sessions: &SessionStore,
+    bookmarks: &BookmarkStore,
+) -> Result<(), AppError> {
+    let chat_id = msg.chat.id.0;
+    let saved = async {
+        let message_id = sessions
+            .hit(chat_id, msg.id.0, usize::from(index))
+            .ok_or(AppError::SessionExpired)?;
+        let message = search_client
+            .get_message(chat_id, message_id)
+            .await?
+            .unwrap_or_default();
+        bookmarks
+            .add(&Bookmark {
+                user_id: q.from.id.0 as i64,
+                chat_id,
+                message_id,
+                preview: message.text.chars().take(PREVIEW_CHARS).collect(),
+                display_name: message.display_name,
+                date: message.date,
+                saved_at: chrono::Utc::now().timestamp(),
+            })
+            .await?;
+        Ok::<_, AppError>(())
+    }
+    .await;
+
+    let Err(e) = saved else {
+        bot.answer_callback_query(q.id.clone())
+            .text("已收藏，发送 /bookmarks 查看")
+            .await?;
+        return Ok(());
+    };
+    bot.answer_callback_query(q.id.clone())
+        .text(e.alert_message())
+        .show_alert(true)
+        .await?;
+    match e.user_message() {
+        Some(_) => Ok(()),
+        None => Err(e),
+    }
+}
+
+/// Handle `/bookmarks`: the sender's bookmarks from this chat, or from every
+/// chat in a private chat with the bot.
+pub async fn handle_bookmarks(
+    bot: Bot,
+    msg: Message,
+    bookmarks: Arc<BookmarkStore>,
+) -> anyhow::Result<()> {
+    let Some(user_id) = msg.from.as_ref().map(|u| u.id.0 as i64) else {
+        return Ok(());
+    };
+    let (text, markup) = render_list(&bookmarks, user_id, &msg.chat).await?;
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .link_preview_options(no_link_preview())
+        .reply_markup(markup)
+        .reply_parameters(ReplyParameters::new(msg.id))
+        .await?;
+    Ok(())
+}
+
+/// Remove a bookmark from a `/bookmarks` reply, for the user who asked for it.
+pub async fn handle_bookmarks_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    bookmarks: Arc<BookmarkStore>,
+) -> anyhow::Result<()> {
+    let Some(MaybeInaccessibleMessage::Regular(msg)) = q.message.as_ref() else {
+        bot.answer_callback_query(q.id).await?;
+        return Ok(());
+    };
+    let presser = q.from.id.0 as i64;
+    let owner = msg
+        .reply_to_message()
+        .and_then(|r| r.from.as_ref())
+        .map(|u| u.id.0 as i64);
+    if owner != Some(presser) {
+        bot.answer_callback_query(q.id)
+            .text("这不是你的收藏")
+            .await?;
+        return Ok(());
+    }
+    let Some((chat_id, message_id)) = q
+        .data
+        .as_deref()
+        .and_then(|d| d.strip_prefix(CALLBACK_PREFIX))
+        .and_then(|d| d.strip_prefix("del:"))
+        .and_then(|d| d.split_once(':'))
+        .and_then(|(chat, message)| Some((chat.parse().ok()?, message.parse().ok()?)))
+    else {
+        bot.answer_callback_query(q.id).await?;
+        return Ok(());
+    };
+
+    bookmarks.remove(presser, chat_id, message_id).await?;
+    bot.answer_callback_query(q.id).text("已取消收藏").await?;
+
+    let (text, markup) = render_list(&bookmarks, presser, &msg.chat).await?;
+    match bot
+        .edit_message_text(msg.chat.id, msg.id, text)
+        .parse_mode(ParseMode::Html)
+        .link_preview_options(no_link_preview())
+        .reply_markup(markup)
+        .await
+    {
+        Ok(_) => {}
+        Err(e) if e.to_string().contains("message is not modified") => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+async fn render_list(
+    bookmarks: &BookmarkStore,
+    user_id: i64,
+    chat: &teloxide::types::Chat,
+) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let chat_filter = (!chat.is_private()).then_some(chat.id.0);
+    let list = bookmarks.list(user_id, chat_filter).await?;
+    if list.is_empty() {
+        let text = "还没有收藏。搜索结果里每条消息旁的「收藏」按钮可以把它加入收藏。";
+        return Ok((text.to_string(), InlineKeyboardMarkup::default()));
+    }
+
+    let mut text = format!("<b>我的收藏（{}）</b>\n", list.len());
+    for (i, bookmark) in list.iter().take(MAX_LISTED).enumerate() {
+        let date = format!(
+            "<a href=\"{}\">{}</a>",
+            format_message_link(bookmark.chat_id, bookmark.message_id),
+            format_timestamp(bookmark.date)
+        );
+        let name = bookmark
+            .display_name
+            .as_deref()
+            .map(|name| format!(" {}", html_escape(name)))
+            .unwrap_or_default();
+        text.push_str(&format!(
+            "\n{}. {date}{name}\n{}\n",
+            i + 1,
+            html_escape(&bookmark.preview)
+        ));
+    }
+    if list.len() > MAX_LISTED {
+        text.push_str(&format!("\n仅显示最近 {MAX_LISTED} 条"));
+    }
+
+    let buttons: Vec<InlineKeyboardButton> = list
+        .iter()
+        .take(MAX_LISTED)
+        .enumerate()
+        .map(|(i, bookmark)| {
+            InlineKeyboardButton::callback(
+                format!("✕ {}", i + 1),
+                format!(
+                    "{CALLBACK_PREFIX}del:{}:{}",
+                    bookmark.chat_id, bookmark.message_id
+                ),
+            )
+        })
+        .collect();
+    let rows = buttons.chunks(BUTTONS_PER_ROW).map(<[_]>::to_vec);
+    Ok((text, InlineKeyboardMarkup::new(rows)))
+}
@@ -0,0 +1,90 @@
+//! Bridge attribution rules: for bots relaying IRC/Matrix users, a regex
+//! pulls the real author out of the relayed text so `from:` still works.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::util::html_escape;
+use crate::es::settings::{BridgeRule, SettingsStore};
+
+const USAGE: &str = "用法:\n\
+    /bridge add @桥接机器人 正则 — 正则需包含 (?P&lt;name&gt;...) 分组，例如 \
+    <code>^&lt;(?P&lt;name&gt;[^&gt;]+)&gt; </code>\n\
+    /bridge del @桥接机器人 — 删除规则\n\
+    /bridge list — 查看本群的规则";
+
+/// Handle `/bridge add|del|list`: manage the chat's bridge rules (admins).
+pub async fn handle_bridge(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    settings: Arc<SettingsStore>,
+) -> anyhow::Result<()> {
+    if msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "请在群组中使用此命令。")
+            .await?;
+        return Ok(());
+    }
+
+    let chat_id = msg.chat.id.0;
+    let mut parts = args.trim().splitn(3, ' ');
+    let action = parts.next().unwrap_or_default();
+    let bot_name = parts.next().and_then(|s| s.strip_prefix('@'));
+    let pattern = parts.next().map(str::trim).unwrap_or_default();
+
+    let reply = match (action, bot_name) {
+        ("add", Some(name)) if !pattern.is_empty() => match BridgeRule::new(name, pattern) {
+            Ok(rule) => {
+                let label = format!("@{}", html_escape(&rule.bot_username));
+                settings
+                    .update(chat_id, |s| {
+                        s.bridge_rules
+                            .retain(|r| r.bot_username != rule.bot_username);
+                        s.bridge_rules.push(rule);
+                    })
+                    .await?;
+                format!("已保存 {label} 的桥接规则，之后转发的消息会记录原作者。")
+            }
+            Err(e) => format!("规则无效：{}", html_escape(&e.to_string())),
+        },
+        ("del", Some(name)) => {
+            let name = name.to_lowercase();
+            let mut removed = false;
+            settings
+                .update(chat_id, |s| {
+                    let before = s.bridge_rules.len();
+                    s.bridge_rules.retain(|r| r.bot_username != name);
+                    removed = s.bridge_rules.len() != before;
+                })
+                .await?;
+            if removed {
+                format!("已删除 @{} 的桥接规则。", html_escape(&name))
+            } else {
+                format!("@{} 没有桥接规则。", html_escape(&name))
+            }
+        }
+        ("list", _) => {
+            let chat_settings = settings.get(chat_id).await?;
+            if chat_settings.bridge_rules.is_empty() {
+                "本群没有桥接规则。".to_string()
+            } else {
+                let mut text = "<b>本群的桥接规则：</b>\n".to_string();
+                for rule in &chat_settings.bridge_rules {
+                    text.push_str(&format!(
+                        "• @{} <code>{}</code>\n",
+                        html_escape(&rule.bot_username),
+                        html_escape(&rule.pattern)
+                    ));
+                }
+                text
+            }
+        }
+        _ => USAGE.to_string(),
+    };
+
+    bot.send_message(msg.chat.id, reply)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
@@ -1,158 +1,203 @@
+use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 use teloxide::prelude::*;
 use teloxide::types::{
-    InlineKeyboardButton, InlineKeyboardMarkup, MaybeInaccessibleMessage, ParseMode,
+    InlineKeyboardButton, InlineKeyboardMarkup, LinkPreviewOptions, MaybeInaccessibleMessage,
     ReplyParameters,
 };
 
-use crate::es::search::{SearchClient, SearchParams, SearchResult};
+use crate::bot::bookmarks::save_bookmark;
+use crate::bot::help::cheat_sheet_button;
+use crate::bot::hits::show_hit_view;
+use crate::bot::inline;
+use crate::bot::payload::{self, SearchAction};
+use crate::bot::query::{parse_query, validate_query, ParsedQuery};
+use crate::bot::session::SessionStore;
+use crate::bot::util::{format_message_link, format_timestamp, link_preview, no_link_preview};
+use crate::config::OutputFormat;
+use crate::error::AppError;
+use crate::es::audit::{AuditEntry, AuditLog};
+use crate::es::bookmarks::BookmarkStore;
+use crate::es::search::{FacetRequest, SearchBackend, SearchClient, SearchParams, SearchResult};
+use crate::es::settings::{LinkPreviewMode, SettingsStore};
+
+/// Upper bound on handling a keyboard press, keeping the callback answer well
+/// inside the window in which Telegram still accepts it.
+const CALLBACK_DEADLINE: Duration = Duration::from_secs(10);
 
 /// Compact search state for encoding in callback data
 #[derive(Debug, Clone)]
-struct SearchState {
-    page: usize,
-    message_type: Option<String>,
-    date_range: Option<&'static str>, // "7d", "30d", "90d"
-    user_id: Option<i64>,
+pub(crate) struct SearchState {
+    pub(crate) page: usize,
+    pub(crate) message_type: Option<String>,
+    pub(crate) date_range: Option<&'static str>, // "7d", "30d", "90d"
+    pub(crate) user_id: Option<i64>,
 }
 
 impl SearchState {
-    /// Encode state as a compact string: {page}|{type}|{date}|{user_id}
+    /// Callback data of a button showing this state, see [`payload`].
     fn encode(&self) -> String {
-        let type_char = match self.message_type.as_deref() {
-            Some("text") => "t",
-            Some("photo") => "p",
-            Some("video") => "v",
-            Some("document") => "d",
-            _ => "-",
-        };
-        let date_char = match self.date_range {
-            Some("7d") => "7",
-            Some("30d") => "3",
-            Some("90d") => "9",
-            _ => "-",
-        };
-        let user_str = self.user_id.map_or("-".to_string(), |id| id.to_string());
-        format!("{}|{}|{}|{}", self.page, type_char, date_char, user_str)
+        payload::encode(SearchAction::Show, self)
     }
 
-    /// Decode state from compact string
-    fn decode(s: &str) -> anyhow::Result<Self> {
-        let parts: Vec<&str> = s.split('|').collect();
-        if parts.len() != 4 {
-            anyhow::bail!("Invalid state format: {}", s);
-        }
-
-        let page = parts[0].parse::<usize>()?;
-
-        let message_type = match parts[1] {
-            "t" => Some("text".to_string()),
-            "p" => Some("photo".to_string()),
-            "v" => Some("video".to_string()),
-            "d" => Some("document".to_string()),
-            "-" => None,
-            _ => anyhow::bail!("Invalid message type: {}", parts[1]),
-        };
-
-        let date_range = match parts[2] {
-            "7" => Some("7d"),
-            "3" => Some("30d"),
-            "9" => Some("90d"),
-            "-" => None,
-            _ => anyhow::bail!("Invalid date range: {}", parts[2]),
-        };
-
-        let user_id = if parts[3] == "-" {
-            None
-        } else {
-            Some(parts[3].parse::<i64>()?)
-        };
-
-        Ok(Self {
-            page,
-            message_type,
-            date_range,
-            user_id,
-        })
+    fn to_date_from(&self) -> Option<i64> {
+        self.date_range.and_then(date_from)
     }
+}
 
-    fn to_date_from(&self) -> Option<i64> {
-        let now = chrono::Utc::now().timestamp();
-        match self.date_range {
-            Some("7d") => Some(now - 7 * 86400),
-            Some("30d") => Some(now - 30 * 86400),
-            Some("90d") => Some(now - 90 * 86400),
-            _ => None,
-        }
+/// Date filter buttons: state key and label. `all` clears the filter.
+const DATE_FILTERS: [(&str, &str); 4] = [
+    ("7d", "7天内"),
+    ("30d", "30天内"),
+    ("90d", "90天内"),
+    ("all", "全部"),
+];
+/// Message type filter buttons: `message_type` value and label.
+pub(crate) const TYPE_FILTERS: [(&str, &str); 7] = [
+    ("text", "文字"),
+    ("photo", "图片"),
+    ("video", "视频"),
+    ("document", "文件"),
+    ("voice", "语音"),
+    ("audio", "音频"),
+    ("video_note", "视频消息"),
+];
+/// Type filter buttons per keyboard row.
+pub(crate) const TYPE_FILTERS_PER_ROW: usize = 4;
+
+/// Lower date bound of a date filter key.
+fn date_from(key: &str) -> Option<i64> {
+    let now = chrono::Utc::now().timestamp();
+    match key {
+        "7d" => Some(now - 7 * 86400),
+        "30d" => Some(now - 30 * 86400),
+        "90d" => Some(now - 90 * 86400),
+        _ => None,
     }
 }
 
+/// Label of the button repeating a press that failed.
+const RETRY_LABEL: &str = "🔄 重试";
+
+/// Callback data prefix of recent-query buttons shown for an empty `/s`.
+const HISTORY_PREFIX: &str = "hist:";
+
 /// Handle the /search command: perform initial search and show results with keyboard.
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_search(
     bot: Bot,
     msg: Message,
     query: String,
     search_client: Arc<SearchClient>,
+    audit: Arc<AuditLog>,
+    sessions: Arc<SessionStore>,
+    settings: Arc<SettingsStore>,
     default_page_size: usize,
-) -> anyhow::Result<()> {
+    format: OutputFormat,
+) -> Result<(), AppError> {
     let chat_id = msg.chat.id;
+    let sender_id = msg.from.as_ref().map(|u| u.id.0 as i64);
 
     if query.trim().is_empty() {
+        let history = sender_id
+            .map(|uid| sessions.history(chat_id.0, uid))
+            .unwrap_or_default();
         bot.send_message(
             chat_id,
             "用法: /s <关键词>\n\n\
              示例:\n\
              /s 你好\n\
-             /s id:123456 关键词\n\n\
+             /s id:123456 关键词\n\
+             /s @username 关键词\n\
+             /s 关键词 type:photo\n\
+             /s 😂 type:text\n\
+             /s 教程 has:link\n\
+             /s 报告 ext:pdf\n\
+             /s 咖啡 near:31.23,121.47,2km\n\n\
              也可以回复某人的消息后发送 /s 关键词，自动过滤该用户",
         )
+        .reply_markup(usage_keyboard(&history))
+        .reply_parameters(ReplyParameters::new(msg.id))
         .await?;
         return Ok(());
     }
 
+    let query = match validate_query(&query) {
+        Ok(query) => query,
+        Err(e) => return reply_error(&bot, &msg, e.into()).await,
+    };
+
+    if let Some(uid) = sender_id {
+        sessions.push_history(chat_id.0, uid, &query);
+    }
+
     let reply_user_id = msg
         .reply_to_message()
         .and_then(|r| r.from.as_ref())
         .map(|u| u.id.0 as i64);
 
-    let (keyword, user_id_filter) = parse_search_query(&query, reply_user_id);
-
-    let params = SearchParams {
-        chat_id: chat_id.0,
-        keyword: Some(keyword.clone()),
-        user_id: user_id_filter,
-        page_size: default_page_size,
-        ..Default::default()
-    };
-
-    let result = search_client.search(&params).await?;
+    let mut parsed = parse_query(&query, reply_user_id);
+    parsed.boost_users = personal_boost(&settings, &search_client, chat_id.0, sender_id).await;
 
     let state = SearchState {
         page: 0,
-        message_type: None,
+        message_type: parsed.message_type.clone(),
         date_range: None,
-        user_id: user_id_filter,
+        user_id: parsed.user_id,
     };
 
-    let text = format_results(&result, chat_id.0);
-    let keyboard = build_keyboard(&result, &state, user_id_filter.is_some());
+    let (result, text, keyboard) = match search_page(
+        search_client.as_ref(),
+        chat_id.0,
+        query.trim(),
+        parsed,
+        &state,
+        default_page_size,
+        format,
+    )
+    .await
+    {
+        Ok(page) => page,
+        Err(e) => return reply_error(&bot, &msg, e).await,
+    };
+
+    audit.record(AuditEntry {
+        chat_id: chat_id.0,
+        user_id: sender_id,
+        query: query.trim().to_string(),
+        result_count: result.total,
+        date: msg.date.timestamp(),
+    });
 
-    bot.send_message(chat_id, text)
-        .parse_mode(ParseMode::Html)
+    let preview = results_preview(&settings, chat_id.0, &result).await;
+
+    let sent = bot
+        .send_message(chat_id, text)
+        .parse_mode(format.parse_mode())
+        .link_preview_options(preview)
         .reply_markup(keyboard)
         .reply_parameters(ReplyParameters::new(msg.id))
         .await?;
+    sessions.set_hits(chat_id.0, sent.id.0, hit_ids(&result));
 
     Ok(())
 }
 
 /// Handle inline keyboard callback queries for pagination and filters.
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_callback(
     bot: Bot,
     q: CallbackQuery,
     search_client: Arc<SearchClient>,
+    audit: Arc<AuditLog>,
+    sessions: Arc<SessionStore>,
+    settings: Arc<SettingsStore>,
+    bookmarks: Arc<BookmarkStore>,
     default_page_size: usize,
-) -> anyhow::Result<()> {
+    format: OutputFormat,
+) -> Result<(), AppError> {
     let data = match q.data {
         Some(ref d) => d.clone(),
         None => return Ok(()),
@@ -164,58 +209,319 @@ pub async fn handle_callback(
         return Ok(());
     }
 
-    bot.answer_callback_query(q.id.clone()).await?;
-
     let msg = match q.message {
         Some(MaybeInaccessibleMessage::Regular(ref m)) => m.clone(),
-        _ => return Ok(()),
+        _ => {
+            bot.answer_callback_query(q.id).await?;
+            return Ok(());
+        }
     };
 
-    // Decode the state from callback data
-    let state = SearchState::decode(&data)?;
+    if let Some(index) = data.strip_prefix(HISTORY_PREFIX) {
+        return handle_history_callback(
+            bot,
+            &q,
+            &msg,
+            index,
+            search_client,
+            audit,
+            sessions,
+            settings,
+            default_page_size,
+            format,
+        )
+        .await;
+    }
+
+    // Saving answers with its own toast instead of editing the results
+    if let Ok((SearchAction::Bookmark(index), _)) = payload::decode(&data) {
+        return save_bookmark(&bot, &q, &msg, index, &search_client, &sessions, &bookmarks).await;
+    }
+
+    answer_after(&bot, &q, async {
+        let (action, state) = payload::decode(&data).map_err(|e| {
+            tracing::debug!("Stale keyboard button {data:?}: {e}");
+            AppError::StaleKeyboard(data.clone())
+        })?;
+        if action != SearchAction::Show {
+            return show_hit_view(
+                &bot,
+                &msg,
+                action,
+                &state,
+                &search_client,
+                &sessions,
+                format,
+            )
+            .await;
+        }
+
+        // Results opened from a history button don't reply to the query, so their
+        // query lives in the session store; otherwise use the original command.
+        let query = match sessions.query(msg.chat.id.0, msg.id.0) {
+            Some(query) => query,
+            None => {
+                let original_msg = msg.reply_to_message().ok_or(AppError::SessionExpired)?;
+                extract_search_query(original_msg).map_err(|_| AppError::SessionExpired)?
+            }
+        };
+
+        // user_id_filter is now stored in state, no need to get from reply_to_message
+        let (query, mut parsed) = reparse_query(&query)?;
+        // Pages keep the ranking of whoever searched, not of the presser
+        let searcher = msg
+            .reply_to_message()
+            .and_then(|r| r.from.as_ref())
+            .map(|u| u.id.0 as i64);
+        parsed.boost_users =
+            personal_boost(&settings, &search_client, msg.chat.id.0, searcher).await;
+
+        let (result, text, keyboard) = search_page(
+            search_client.as_ref(),
+            msg.chat.id.0,
+            &query,
+            parsed,
+            &state,
+            default_page_size,
+            format,
+        )
+        .await?;
+        let preview = results_preview(&settings, msg.chat.id.0, &result).await;
+
+        // Update message
+        match bot
+            .edit_message_text(msg.chat.id, msg.id, text)
+            .parse_mode(format.parse_mode())
+            .link_preview_options(preview)
+            .reply_markup(keyboard)
+            .await
+        {
+            Ok(_) => {}
+            Err(e) if e.to_string().contains("message is not modified") => {}
+            Err(e) => return Err(e.into()),
+        }
+        sessions.set_hits(msg.chat.id.0, msg.id.0, hit_ids(&result));
+        Ok(())
+    })
+    .await
+}
 
-    // Get the original search command from reply_to_message
-    let original_msg = msg
+/// Re-run one of the user's recent queries, turning the usage message into results.
+#[allow(clippy::too_many_arguments)]
+async fn handle_history_callback(
+    bot: Bot,
+    q: &CallbackQuery,
+    msg: &Message,
+    index: &str,
+    search_client: Arc<SearchClient>,
+    audit: Arc<AuditLog>,
+    sessions: Arc<SessionStore>,
+    settings: Arc<SettingsStore>,
+    default_page_size: usize,
+    format: OutputFormat,
+) -> Result<(), AppError> {
+    let chat_id = msg.chat.id.0;
+    let presser = q.from.id.0 as i64;
+
+    let owner = msg
         .reply_to_message()
-        .ok_or_else(|| anyhow::anyhow!("No reply_to_message found"))?;
+        .and_then(|r| r.from.as_ref())
+        .map(|u| u.id.0 as i64);
+    if owner != Some(presser) {
+        bot.answer_callback_query(q.id.clone())
+            .text("这不是你的搜索记录")
+            .await?;
+        return Ok(());
+    }
+
+    let history = sessions.history(chat_id, presser);
+    let Some((query, mut parsed)) = index
+        .parse::<usize>()
+        .ok()
+        .and_then(|i| history.get(i))
+        .and_then(|query| reparse_query(query).ok())
+    else {
+        bot.answer_callback_query(q.id.clone())
+            .text("搜索记录已过期")
+            .await?;
+        return Ok(());
+    };
 
-    let query = extract_search_query(&original_msg)?;
+    answer_after(&bot, q, async {
+        parsed.boost_users =
+            personal_boost(&settings, &search_client, chat_id, Some(presser)).await;
+        let state = SearchState {
+            page: 0,
+            message_type: parsed.message_type.clone(),
+            date_range: None,
+            user_id: parsed.user_id,
+        };
+        let (result, text, keyboard) = search_page(
+            search_client.as_ref(),
+            chat_id,
+            &query,
+            parsed,
+            &state,
+            default_page_size,
+            format,
+        )
+        .await?;
 
-    // user_id_filter is now stored in state, no need to get from reply_to_message
-    let (keyword, _) = parse_search_query(&query, None);
+        audit.record(AuditEntry {
+            chat_id,
+            user_id: Some(presser),
+            query: query.clone(),
+            result_count: result.total,
+            date: chrono::Utc::now().timestamp(),
+        });
+        sessions.push_history(chat_id, presser, &query);
+        sessions.set_query(chat_id, msg.id.0, &query);
+        sessions.set_hits(chat_id, msg.id.0, hit_ids(&result));
 
-    // Build search params from state and original query
-    let params = SearchParams {
-        chat_id: msg.chat.id.0,
-        keyword: Some(keyword),
-        user_id: state.user_id,
-        page: state.page,
-        page_size: default_page_size,
-        message_type: state.message_type.clone(),
-        date_from: state.to_date_from(),
-        date_to: None,
+        let preview = results_preview(&settings, chat_id, &result).await;
+        bot.edit_message_text(msg.chat.id, msg.id, text)
+            .parse_mode(format.parse_mode())
+            .link_preview_options(preview)
+            .reply_markup(keyboard)
+            .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Message ids of the hits on a results page, which its per-hit buttons
+/// refer to by position.
+pub(crate) fn hit_ids(result: &SearchResult) -> Vec<i64> {
+    result
+        .messages
+        .iter()
+        .map(|hit| hit.message.message_id)
+        .collect()
+}
+
+/// Senders ranked higher for `searcher`, when the chat personalizes results.
+async fn personal_boost(
+    settings: &SettingsStore,
+    search_client: &SearchClient,
+    chat_id: i64,
+    searcher: Option<i64>,
+) -> Vec<i64> {
+    let Some(searcher) = searcher else {
+        return Vec::new();
+    };
+    match settings.get(chat_id).await {
+        Ok(s) if s.personalize => search_client
+            .interlocutors(chat_id, searcher)
+            .await
+            .to_vec(),
+        Ok(_) => Vec::new(),
+        Err(e) => {
+            tracing::warn!("Failed to load settings of {chat_id}: {e}");
+            Vec::new()
+        }
+    }
+}
+
+/// Link preview of a results message under the chat's preview mode.
+async fn results_preview(
+    settings: &SettingsStore,
+    chat_id: i64,
+    result: &SearchResult,
+) -> LinkPreviewOptions {
+    let mode = match settings.get(chat_id).await {
+        Ok(s) => s.link_preview,
+        Err(e) => {
+            tracing::warn!("Failed to load settings of {chat_id}: {e}");
+            LinkPreviewMode::default()
+        }
     };
+    match mode {
+        LinkPreviewMode::Auto => link_preview(None),
+        LinkPreviewMode::Off => no_link_preview(),
+        LinkPreviewMode::TopHit => match result.messages.first() {
+            Some(hit) => link_preview(Some(format_message_link(chat_id, hit.message.message_id))),
+            None => no_link_preview(),
+        },
+    }
+}
 
-    // Perform search
-    let result = search_client.search(&params).await?;
-    let text = format_results(&result, msg.chat.id.0);
-    let keyboard = build_keyboard(&result, &state, state.user_id.is_some());
+/// Answer a callback query once `work` finishes, so failures can be shown as
+/// an alert naming what went wrong. Work running past [`CALLBACK_DEADLINE`]
+/// is abandoned. Failures worth retrying also put a retry button above the
+/// keyboard; errors without a user-facing text are still passed on to be
+/// logged.
+async fn answer_after(
+    bot: &Bot,
+    q: &CallbackQuery,
+    work: impl Future<Output = Result<(), AppError>>,
+) -> Result<(), AppError> {
+    let outcome = tokio::time::timeout(CALLBACK_DEADLINE, work)
+        .await
+        .unwrap_or(Err(AppError::SearchTimeout));
+    let Err(e) = outcome else {
+        bot.answer_callback_query(q.id.clone()).await?;
+        return Ok(());
+    };
 
-    // Update message
-    match bot
-        .edit_message_text(msg.chat.id, msg.id, text)
-        .parse_mode(ParseMode::Html)
-        .reply_markup(keyboard)
+    bot.answer_callback_query(q.id.clone())
+        .text(e.alert_message())
+        .show_alert(true)
+        .await?;
+    if e.is_retryable() {
+        offer_retry(bot, q).await;
+    }
+    match e.user_message() {
+        Some(_) => Ok(()),
+        None => Err(e),
+    }
+}
+
+/// Add a button repeating the failed press above the message's keyboard.
+async fn offer_retry(bot: &Bot, q: &CallbackQuery) {
+    let (Some(MaybeInaccessibleMessage::Regular(msg)), Some(data)) = (&q.message, &q.data) else {
+        return;
+    };
+    let retry = InlineKeyboardButton::callback(RETRY_LABEL, data.clone());
+    let mut rows = vec![vec![retry]];
+    if let Some(markup) = msg.reply_markup() {
+        rows.extend(
+            markup
+                .inline_keyboard
+                .iter()
+                .filter(|row| !row.iter().any(|button| button.text == RETRY_LABEL))
+                .cloned(),
+        );
+    }
+    if let Err(e) = bot
+        .edit_message_reply_markup(msg.chat.id, msg.id)
+        .reply_markup(InlineKeyboardMarkup::new(rows))
         .await
+        && !e.to_string().contains("message is not modified")
     {
-        Ok(_) => {}
-        Err(e) if e.to_string().contains("message is not modified") => {}
-        Err(e) => return Err(e.into()),
+        tracing::warn!("Failed to add retry button: {e}");
     }
+}
 
+/// Reply to `msg` with the user-facing text of `e`, or pass `e` on when it
+/// has none.
+pub(crate) async fn reply_error(bot: &Bot, msg: &Message, e: AppError) -> Result<(), AppError> {
+    let Some(text) = e.user_message() else {
+        return Err(e);
+    };
+    bot.send_message(msg.chat.id, text)
+        .reply_parameters(ReplyParameters::new(msg.id))
+        .await?;
     Ok(())
 }
 
+/// Validate and parse a query read back for a callback, the way `/s`
+/// treated it when it was sent.
+fn reparse_query(raw: &str) -> Result<(String, ParsedQuery), AppError> {
+    let query = validate_query(raw).map_err(|_| AppError::SessionExpired)?;
+    let parsed = parse_query(&query, None);
+    Ok((query, parsed))
+}
+
 /// Extract search query from a message (either from /s command or message text)
 fn extract_search_query(msg: &Message) -> anyhow::Result<String> {
     let text = msg
@@ -236,93 +542,204 @@ fn extract_search_query(msg: &Message) -> anyhow::Result<String> {
 
 // ── Helpers ────────────────────────────────────────────────────
 
-fn parse_search_query(query: &str, reply_user_id: Option<i64>) -> (String, Option<i64>) {
-    let parts: Vec<&str> = query.splitn(2, ' ').collect();
-    if parts.len() == 2 {
-        if let Some(uid) = try_parse_id_prefix(parts[0]) {
-            return (parts[1].to_string(), Some(uid));
-        }
-        if let Some(uid) = try_parse_id_prefix(parts[1]) {
-            return (parts[0].to_string(), Some(uid));
-        }
+/// Combine the parsed query with keyboard state. Keyboard filters take
+/// precedence; a `type:` the keyboard can't encode falls back to the query.
+pub(crate) fn search_params(
+    chat_id: i64,
+    parsed: ParsedQuery,
+    state: &SearchState,
+    page_size: usize,
+) -> SearchParams {
+    let params = parsed.into_params(chat_id);
+    SearchParams {
+        user_id: state.user_id,
+        page: state.page,
+        page_size,
+        message_type: state.message_type.clone().or(params.message_type),
+        date_from: state.to_date_from(),
+        facets: Some(FacetRequest {
+            types: TYPE_FILTERS.map(|(key, _)| key.to_string()).to_vec(),
+            dates: DATE_FILTERS
+                .map(|(key, _)| (key.to_string(), date_from(key)))
+                .to_vec(),
+        }),
+        ..params
     }
-    (query.to_string(), reply_user_id)
 }
 
-fn try_parse_id_prefix(token: &str) -> Option<i64> {
-    token.strip_prefix("id:").and_then(|s| s.parse().ok())
+/// Run the search for `query` under the keyboard `state` and render the
+/// results page, without touching Telegram.
+pub(crate) async fn search_page(
+    backend: &dyn SearchBackend,
+    chat_id: i64,
+    query: &str,
+    parsed: ParsedQuery,
+    state: &SearchState,
+    page_size: usize,
+    format: OutputFormat,
+) -> Result<(SearchResult, String, InlineKeyboardMarkup), AppError> {
+    let params = search_params(chat_id, parsed, state, page_size);
+    let result = backend.search(&params).await?;
+    let (text, keyboard) = render_page(&result, state, chat_id, query, format);
+    Ok((result, text, keyboard))
+}
+
+/// Render a results page and its pagination/filter keyboard.
+pub(crate) fn render_page(
+    result: &SearchResult,
+    state: &SearchState,
+    chat_id: i64,
+    query: &str,
+    format: OutputFormat,
+) -> (String, InlineKeyboardMarkup) {
+    let text = format_results(result, format);
+    let keyboard = build_keyboard(result, state, chat_id, query);
+    (text, keyboard)
 }
 
-fn format_results(result: &SearchResult, chat_id: i64) -> String {
+fn format_results(result: &SearchResult, format: OutputFormat) -> String {
     if result.total == 0 {
-        return "未找到相关消息。".to_string();
+        return format.escape("未找到相关消息。");
     }
 
     let mut text = format!(
-        "共找到 <b>{}</b> 条结果（第 {}/{} 页）：\n\n",
-        result.total,
-        result.page + 1,
-        result.total_pages
+        "共找到 {} 条结果{}\n\n",
+        format.bold(&result.total.to_string()),
+        format.escape(&format!(
+            "（第 {}/{} 页）：",
+            result.page + 1,
+            result.total_pages
+        ))
     );
 
     for (i, hit) in result.messages.iter().enumerate() {
-        let num = result.page * 5 + i + 1;
-        let date = chrono::DateTime::from_timestamp(hit.message.date, 0)
-            .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
-            .unwrap_or_default();
+        let num = result.page * result.page_size + i + 1;
+        let date = format.italic(&format.escape(&format_timestamp(hit.message.date)));
 
         // Format user info with tg://user?id=xxx link
-        let user_info = if let Some(user_id) = hit.message.user_id {
-            format!(" | <a href=\"tg://user?id={}\">User {}</a>", user_id, user_id)
-        } else {
-            String::new()
+        let user_info = match (hit.message.user_id, hit.message.display_name.as_deref()) {
+            (Some(user_id), Some(name)) => format!(
+                " {} {}",
+                format.escape("|"),
+                format.link(&format.escape(name), &format!("tg://user?id={user_id}"))
+            ),
+            (Some(user_id), None) => format!(
+                " {} {}",
+                format.escape("|"),
+                format.link(
+                    &format.escape(&format!("User {user_id}")),
+                    &format!("tg://user?id={user_id}")
+                )
+            ),
+            (None, Some(name)) => format!(" {} {}", format.escape("|"), format.escape(name)),
+            (None, None) => String::new(),
         };
 
-        let snippet = hit
-            .highlight
-            .as_deref()
-            .map(String::from)
-            .unwrap_or_else(|| truncate_html(&hit.message.text, 80));
+        let mut snippet = format.highlight(&hit.snippet);
+        if let Some(ref quote) = hit.quote_highlight {
+            snippet = prepend_line(&snippet, &format!("❝ {}", format.highlight(quote)));
+        }
+        if let Some(loc) = hit.message.location {
+            let loc_line = format!(
+                "📍 {}",
+                format.link(
+                    &format.escape(&format!("{:.5}, {:.5}", loc.lat, loc.lon)),
+                    &format!("https://maps.google.com/?q={},{}", loc.lat, loc.lon)
+                )
+            );
+            snippet = prepend_line(&snippet, &loc_line);
+        }
+        if let Some(ref name) = hit.message.file_name {
+            snippet = prepend_line(&snippet, &format!("📎 {}", format.escape(name)));
+        }
 
-        let link = format_message_link(chat_id, hit.message.message_id);
         text.push_str(&format!(
-            "{num}. <i>{date}</i>{user_info}\n{snippet}\n<a href=\"{link}\">跳转到消息</a>\n\n"
+            "{}{date}{user_info}\n{snippet}\n\n",
+            format.escape(&format!("{num}. "))
         ));
     }
     text
 }
 
-fn truncate_html(s: &str, max_chars: usize) -> String {
-    if s.chars().count() > max_chars {
-        let truncated: String = s.chars().take(max_chars).collect();
-        format!("{}...", html_escape(&truncated))
+fn prepend_line(snippet: &str, line: &str) -> String {
+    if snippet.is_empty() {
+        line.to_string()
     } else {
-        html_escape(s)
+        format!("{line}\n{snippet}")
     }
 }
 
-fn html_escape(s: &str) -> String {
-    s.replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
+/// Button label with its hit count, e.g. `图片 (12)`, when counts are known.
+fn facet_label(label: &str, counts: Option<&HashMap<String, u64>>, key: &str) -> String {
+    match counts.and_then(|c| c.get(key)) {
+        Some(count) => format!("{label} ({count})"),
+        None => label.to_string(),
+    }
 }
 
-fn format_message_link(chat_id: i64, message_id: i64) -> String {
-    let abs_id = chat_id.unsigned_abs();
-    let channel_id = if abs_id > 1_000_000_000_000 {
-        abs_id - 1_000_000_000_000
-    } else {
-        abs_id
-    };
-    format!("https://t.me/c/{channel_id}/{message_id}")
+/// Recent queries of the sender, one per row, then the syntax help button.
+fn usage_keyboard(history: &[String]) -> InlineKeyboardMarkup {
+    let mut rows = history
+        .iter()
+        .enumerate()
+        .map(|(i, query)| {
+            let label: String = query.chars().take(30).collect();
+            vec![InlineKeyboardButton::callback(
+                format!("🕘 {label}"),
+                format!("{HISTORY_PREFIX}{i}"),
+            )]
+        })
+        .collect::<Vec<_>>();
+    rows.push(vec![cheat_sheet_button()]);
+    InlineKeyboardMarkup::new(rows)
+}
+
+/// A numbered row of buttons per hit: jump to the message, show the
+/// messages around it or similar ones, bookmark it and share its link.
+fn hit_rows(
+    result: &SearchResult,
+    state: &SearchState,
+    chat_id: i64,
+) -> Vec<Vec<InlineKeyboardButton>> {
+    result
+        .messages
+        .iter()
+        .enumerate()
+        .filter_map(|(i, hit)| {
+            let index = u8::try_from(i).ok()?;
+            let num = result.page * result.page_size + i + 1;
+            let link =
+                url::Url::parse(&format_message_link(chat_id, hit.message.message_id)).ok()?;
+            let share =
+                url::Url::parse_with_params("https://t.me/share/url", [("url", link.as_str())])
+                    .ok()?;
+            Some(vec![
+                InlineKeyboardButton::url(format!("{num}. 跳转"), link),
+                InlineKeyboardButton::callback(
+                    "上下文",
+                    payload::encode(SearchAction::Context(index), state),
+                ),
+                InlineKeyboardButton::callback(
+                    "相似",
+                    payload::encode(SearchAction::Similar(index), state),
+                ),
+                InlineKeyboardButton::callback(
+                    "收藏",
+                    payload::encode(SearchAction::Bookmark(index), state),
+                ),
+                InlineKeyboardButton::url("转发", share),
+            ])
+        })
+        .collect()
 }
 
 fn build_keyboard(
     result: &SearchResult,
     state: &SearchState,
-    has_user_filter: bool,
+    chat_id: i64,
+    query: &str,
 ) -> InlineKeyboardMarkup {
-    let mut rows: Vec<Vec<InlineKeyboardButton>> = vec![];
+    let mut rows = hit_rows(result, state, chat_id);
 
     // Navigation
     if result.total_pages > 1 {
@@ -356,15 +773,15 @@ fn build_keyboard(
 
     // Date filter
     rows.push(
-        [("7d", "7天内"), ("30d", "30天内"), ("90d", "90天内"), (
-            "all", "全部",
-        )]
+        DATE_FILTERS
             .map(|(key, label)| {
-                let active = state.date_range == Some(key) || (key == "all" && state.date_range.is_none());
+                let active =
+                    state.date_range == Some(key) || (key == "all" && state.date_range.is_none());
+                let label = facet_label(label, result.facets.as_ref().map(|f| &f.dates), key);
                 let text = if active {
                     format!("✓ {label}")
                 } else {
-                    label.to_string()
+                    label
                 };
                 let new_state = SearchState {
                     page: 0,
@@ -378,32 +795,331 @@ fn build_keyboard(
     );
 
     // Message type filter (only show if not filtered by user)
-    if !has_user_filter {
-        rows.push(
+    if state.user_id.is_none() {
+        let buttons = TYPE_FILTERS.map(|(key, label)| {
+            let active = state.message_type.as_deref() == Some(key);
+            let label = facet_label(label, result.facets.as_ref().map(|f| &f.types), key);
+            let text = if active {
+                format!("✓ {label}")
+            } else {
+                label
+            };
+            let new_state = SearchState {
+                page: 0,
+                message_type: if active { None } else { Some(key.to_string()) },
+                date_range: state.date_range,
+                user_id: state.user_id,
+            };
+            InlineKeyboardButton::callback(text, new_state.encode())
+        });
+        rows.extend(buttons.chunks(TYPE_FILTERS_PER_ROW).map(<[_]>::to_vec));
+    }
+
+    // Share: prefill `@bot <query>` in a chat the user picks
+    let mut last_row = vec![InlineKeyboardButton::switch_inline_query(
+        "分享",
+        inline::share_query(query),
+    )];
+    if result.total == 0 {
+        last_row.push(cheat_sheet_button());
+    }
+    rows.push(last_row);
+
+    InlineKeyboardMarkup::new(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::es::memory::MemoryBackend;
+    use crate::es::search::SearchHit;
+    use crate::models::message::{ChatMessage, MessageType};
+    use teloxide::types::InlineKeyboardButtonKind;
+
+    fn result(page: usize, page_size: usize, hits: usize) -> SearchResult {
+        SearchResult {
+            total: 100,
+            messages: (0..hits)
+                .map(|i| SearchHit {
+                    message: ChatMessage {
+                        message_id: i as i64,
+                        text: format!("hit {i}"),
+                        ..Default::default()
+                    },
+                    snippet: format!("hit {i}"),
+                    quote_highlight: None,
+                })
+                .collect(),
+            page,
+            page_size,
+            total_pages: 100usize.div_ceil(page_size),
+            facets: None,
+        }
+    }
+
+    fn numbers(text: &str) -> Vec<usize> {
+        text.lines()
+            .filter_map(|line| line.split_once(". ")?.0.parse().ok())
+            .collect()
+    }
+
+    #[test]
+    fn numbers_first_page_from_one() {
+        let text = format_results(&result(0, 10, 3), OutputFormat::Html);
+        assert_eq!(numbers(&text), [1, 2, 3]);
+    }
+
+    #[test]
+    fn numbers_follow_page_size() {
+        let text = format_results(&result(2, 10, 10), OutputFormat::Html);
+        assert_eq!(numbers(&text), (21..=30).collect::<Vec<_>>());
+
+        let text = format_results(&result(3, 3, 2), OutputFormat::Html);
+        assert_eq!(numbers(&text), [10, 11]);
+    }
+
+    #[test]
+    fn numbers_escape_in_markdown() {
+        let text = format_results(&result(1, 7, 1), OutputFormat::MarkdownV2);
+        assert!(text.lines().any(|line| line.starts_with("8\\. ")));
+    }
+
+    const CHAT: i64 = -1001234567890;
+    /// 2023-11-14 22:13 UTC, old enough that no date facet but `all` counts it
+    const DATE: i64 = 1_700_000_000;
+
+    fn backend() -> MemoryBackend {
+        let message = |message_id, user_id, text: &str, message_type| ChatMessage {
+            chat_id: CHAT,
+            message_id,
+            user_id: Some(user_id),
+            display_name: Some(format!("User<{user_id}>")),
+            text: text.to_string(),
+            date: DATE + message_id * 60,
+            message_type,
+            ..Default::default()
+        };
+        MemoryBackend::new(vec![
+            message(1, 7, "Rust 1.0 发布了", MessageType::Text),
+            message(2, 8, "学 rust 的第一天 & 第二天", MessageType::Text),
+            message(3, 7, "rust 吉祥物照片", MessageType::Photo),
+            message(4, 8, "今天吃什么", MessageType::Text),
+            message(6, 7, "docker 部署笔记", MessageType::Text),
+            message(7, 8, "用 Docker 部署 bot", MessageType::Text),
+            message(8, 7, "docker 部署又失败了", MessageType::Text),
+            ChatMessage {
+                chat_id: -1009999,
+                ..message(5, 7, "别的群也聊 rust", MessageType::Text)
+            },
+        ])
+    }
+
+    /// A page as the callback handler renders it for the query `raw`.
+    async fn page(raw: &str, state: &SearchState) -> (SearchResult, String, InlineKeyboardMarkup) {
+        let (query, parsed) = reparse_query(raw).expect("invalid query");
+        search_page(
+            &backend(),
+            CHAT,
+            &query,
+            parsed,
+            state,
+            2,
+            OutputFormat::Html,
+        )
+        .await
+        .expect("search failed")
+    }
+
+    fn first_page(raw: &str) -> SearchState {
+        let (_, parsed) = reparse_query(raw).expect("invalid query");
+        SearchState {
+            page: 0,
+            message_type: parsed.message_type,
+            date_range: None,
+            user_id: parsed.user_id,
+        }
+    }
+
+    /// Keyboard as rows of `label => action`.
+    fn layout(keyboard: &InlineKeyboardMarkup) -> Vec<Vec<String>> {
+        keyboard
+            .inline_keyboard
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|button| match &button.kind {
+                        InlineKeyboardButtonKind::CallbackData(data) => {
+                            format!("{} => {}", button.text, readable(data))
+                        }
+                        InlineKeyboardButtonKind::SwitchInlineQuery(query) => {
+                            format!("{} => inline:{query}", button.text)
+                        }
+                        InlineKeyboardButtonKind::Url(url) => format!("{} => {url}", button.text),
+                        other => format!("{} => {other:?}", button.text),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Decoded search payload as `{page}|{type}|{date}|{user}`, prefixed
+    /// with the action and hit position for per-hit buttons.
+    fn readable(data: &str) -> String {
+        let Ok((action, state)) = payload::decode(data) else {
+            return data.to_string();
+        };
+        let or_dash = |v: Option<String>| v.unwrap_or_else(|| "-".into());
+        let action = match action {
+            SearchAction::Show => String::new(),
+            SearchAction::Context(hit) => format!("context:{hit} "),
+            SearchAction::Similar(hit) => format!("similar:{hit} "),
+            SearchAction::Bookmark(hit) => format!("bookmark:{hit} "),
+        };
+        format!(
+            "{action}{}|{}|{}|{}",
+            state.page,
+            or_dash(state.message_type),
+            or_dash(state.date_range.map(String::from)),
+            or_dash(state.user_id.map(|id| id.to_string())),
+        )
+    }
+
+    /// The state a press of the button labelled `label` decodes to.
+    fn press(keyboard: &InlineKeyboardMarkup, label: &str) -> SearchState {
+        let data = keyboard
+            .inline_keyboard
+            .iter()
+            .flatten()
+            .find_map(|button| match &button.kind {
+                InlineKeyboardButtonKind::CallbackData(data) if button.text == label => Some(data),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("no button {label}"));
+        payload::decode(data).expect("undecodable callback data").1
+    }
+
+    #[tokio::test]
+    async fn golden_first_page() {
+        let (result, text, keyboard) = page("rust", &first_page("rust")).await;
+        assert_eq!(result.total, 3);
+        assert_eq!(
+            text,
+            "共找到 <b>3</b> 条结果（第 1/2 页）：\n\n\
+             1. <i>2023-11-14 22:16</i> | <a href=\"tg://user?id=7\">User&lt;7&gt;</a>\n\
+             <b>rust</b> 吉祥物照片\n\n\
+             2. <i>2023-11-14 22:15</i> | <a href=\"tg://user?id=8\">User&lt;8&gt;</a>\n\
+             学 <b>rust</b> 的第一天 &amp; 第二天\n\n"
+        );
+        assert_eq!(
+            layout(&keyboard),
             [
-                ("text", "文字"),
-                ("photo", "图片"),
-                ("video", "视频"),
-                ("document", "文件"),
+                vec![
+                    "1. 跳转 => https://t.me/c/1234567890/3",
+                    "上下文 => context:0 0|-|-|-",
+                    "相似 => similar:0 0|-|-|-",
+                    "收藏 => bookmark:0 0|-|-|-",
+                    "转发 => https://t.me/share/url?url=https%3A%2F%2Ft.me%2Fc%2F1234567890%2F3",
+                ],
+                vec![
+                    "2. 跳转 => https://t.me/c/1234567890/2",
+                    "上下文 => context:1 0|-|-|-",
+                    "相似 => similar:1 0|-|-|-",
+                    "收藏 => bookmark:1 0|-|-|-",
+                    "转发 => https://t.me/share/url?url=https%3A%2F%2Ft.me%2Fc%2F1234567890%2F2",
+                ],
+                vec!["1/2 => noop", "下一页 ➡ => 1|-|-|-"],
+                vec![
+                    "7天内 (0) => 0|-|7d|-",
+                    "30天内 (0) => 0|-|30d|-",
+                    "90天内 (0) => 0|-|90d|-",
+                    "✓ 全部 (3) => 0|-|-|-",
+                ],
+                vec![
+                    "文字 (2) => 0|text|-|-",
+                    "图片 (1) => 0|photo|-|-",
+                    "视频 (0) => 0|video|-|-",
+                    "文件 (0) => 0|document|-|-",
+                ],
+                vec![
+                    "语音 (0) => 0|voice|-|-",
+                    "音频 (0) => 0|audio|-|-",
+                    "视频消息 (0) => 0|video_note|-|-",
+                ],
+                vec!["分享 => inline:rust"],
             ]
-            .map(|(key, label)| {
-                let active = state.message_type.as_deref() == Some(key);
-                let text = if active {
-                    format!("✓ {label}")
-                } else {
-                    label.to_string()
-                };
-                let new_state = SearchState {
-                    page: 0,
-                    message_type: if active { None } else { Some(key.to_string()) },
-                    date_range: state.date_range,
-                    user_id: state.user_id,
-                };
-                InlineKeyboardButton::callback(text, new_state.encode())
-            })
-            .to_vec(),
         );
     }
 
-    InlineKeyboardMarkup::new(rows)
+    #[tokio::test]
+    async fn next_page_continues_numbering() {
+        let (_, _, keyboard) = page("rust", &first_page("rust")).await;
+        let (result, text, keyboard) = page("rust", &press(&keyboard, "下一页 ➡")).await;
+        assert_eq!(result.page, 1);
+        assert_eq!(numbers(&text), [3]);
+        assert!(text.contains("<b>Rust</b> 1.0 发布了"));
+        let rows = layout(&keyboard);
+        assert_eq!(rows[0][0], "3. 跳转 => https://t.me/c/1234567890/1");
+        assert_eq!(rows[0][1], "上下文 => context:0 1|-|-|-");
+        assert_eq!(rows[1], ["⬅ 上一页 => 0|-|-|-", "2/2 => noop"]);
+    }
+
+    #[tokio::test]
+    async fn full_width_query_pages_normalized() {
+        let query = "ｄｏｃｋｅｒ部署";
+        let (_, _, keyboard) = page(query, &first_page(query)).await;
+        let (result, text, keyboard) = page(query, &press(&keyboard, "下一页 ➡")).await;
+        assert_eq!(result.total, 3);
+        assert_eq!(
+            text,
+            "共找到 <b>3</b> 条结果（第 2/2 页）：\n\n\
+             3. <i>2023-11-14 22:19</i> | <a href=\"tg://user?id=7\">User&lt;7&gt;</a>\n\
+             <b>docker</b> <b>部署</b>笔记\n\n"
+        );
+        assert_eq!(
+            layout(&keyboard).last().unwrap(),
+            &["分享 => inline:docker部署"]
+        );
+    }
+
+    #[tokio::test]
+    async fn type_button_toggles_filter() {
+        let (_, _, keyboard) = page("rust", &first_page("rust")).await;
+        let photos = press(&keyboard, "图片 (1)");
+        let (result, _, keyboard) = page("rust", &photos).await;
+        assert_eq!(result.total, 1);
+        assert_eq!(result.messages[0].message.message_id, 3);
+        // Single page: no navigation row, the pressed filter is ticked
+        assert_eq!(
+            layout(&keyboard)[2],
+            [
+                "文字 (2) => 0|text|-|-",
+                "✓ 图片 (1) => 0|-|-|-",
+                "视频 (0) => 0|video|-|-",
+                "文件 (0) => 0|document|-|-",
+            ]
+        );
+
+        let (result, _, _) = page("rust", &press(&keyboard, "✓ 图片 (1)")).await;
+        assert_eq!(result.total, 3);
+    }
+
+    #[tokio::test]
+    async fn user_filter_hides_type_buttons() {
+        let query = "rust id:8";
+        let (result, _, keyboard) = page(query, &first_page(query)).await;
+        assert_eq!(result.total, 1);
+        let rows = layout(&keyboard);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1][3], "✓ 全部 (1) => 0|-|-|8");
+    }
+
+    #[tokio::test]
+    async fn golden_no_results() {
+        let (_, text, keyboard) = page("golang", &first_page("golang")).await;
+        assert_eq!(text, "未找到相关消息。");
+        assert_eq!(
+            layout(&keyboard).last().unwrap(),
+            &["分享 => inline:golang", "语法帮助 => help:cheat"]
+        );
+    }
 }
@@ -0,0 +1,107 @@
+//! `/chats [chat_id|here]`: owner-only overview of the chats the bot indexes,
+//! or the metadata and title history of one of them.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::util::{format_timestamp, html_escape};
+use crate::es::chats::{ChatInfo, ChatStore};
+
+/// Chats listed before the list is cut off, within Telegram's message limit.
+const MAX_LISTED: usize = 30;
+
+pub async fn handle_chats(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    chats: Arc<ChatStore>,
+) -> anyhow::Result<()> {
+    let text = match args.trim() {
+        "" => format_list(&chats.all().await?),
+        arg => {
+            let chat_id = if arg == "here" {
+                msg.chat.id.0
+            } else if let Ok(id) = arg.parse() {
+                id
+            } else {
+                bot.send_message(msg.chat.id, "用法: /chats [群组 ID|here]")
+                    .await?;
+                return Ok(());
+            };
+            match chats.get(chat_id).await? {
+                Some(info) => format_details(&info),
+                None => "没有这个群组的记录。".to_string(),
+            }
+        }
+    };
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+fn format_list(chats: &[ChatInfo]) -> String {
+    if chats.is_empty() {
+        return "还没有收录任何群组。".to_string();
+    }
+    let mut text = format!("<b>已收录的群组（{}）</b>\n", chats.len());
+    for info in chats.iter().take(MAX_LISTED) {
+        text.push_str(&format!(
+            "\n• {}\n  <code>{}</code> · {}\n",
+            name(info),
+            info.chat_id,
+            date_range(info)
+        ));
+    }
+    if chats.len() > MAX_LISTED {
+        text.push_str(&format!("\n…另有 {} 个群组", chats.len() - MAX_LISTED));
+    }
+    text
+}
+
+fn format_details(info: &ChatInfo) -> String {
+    let mut text = format!(
+        "{}\nID: <code>{}</code>（{}）\n消息: {}\n",
+        name(info),
+        info.chat_id,
+        info.chat_type,
+        date_range(info)
+    );
+    if let Some(latest) = info.member_counts.last() {
+        text.push_str(&format!(
+            "成员: {}（{}）\n",
+            latest.count,
+            format_timestamp(latest.date)
+        ));
+    }
+    if info.title_history.len() > 1 {
+        text.push_str("\n<b>曾用名：</b>\n");
+        for change in info.title_history.iter().rev() {
+            text.push_str(&format!(
+                "{} 起 — {}\n",
+                format_timestamp(change.since),
+                html_escape(&change.title)
+            ));
+        }
+    }
+    text
+}
+
+fn name(info: &ChatInfo) -> String {
+    let title = info.title.as_deref().unwrap_or("（无标题）");
+    let mut name = format!("<b>{}</b>", html_escape(title));
+    if let Some(ref username) = info.username {
+        name.push_str(&format!(" @{}", html_escape(username)));
+    }
+    name
+}
+
+fn date_range(info: &ChatInfo) -> String {
+    match (info.first_message_date, info.last_message_date) {
+        (Some(first), Some(last)) => {
+            format!("{} ~ {}", format_timestamp(first), format_timestamp(last))
+        }
+        _ => "无".to_string(),
+    }
+}
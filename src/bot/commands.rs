@@ -6,6 +6,216 @@ pub enum Command {
     #[command(description = "搜索群组消息：/s <关键词>", aliases = ["s"])]
     Search(String),
 
+    #[command(description = "分步搜索向导")]
+    FindWizard,
+
     #[command(description = "显示帮助信息", aliases = ["h"])]
     Help,
+
+    #[command(description = "查看完整的搜索语法：/syntax [zh|en]")]
+    Syntax(String),
+
+    #[command(description = "通过消息链接查看索引中保存的内容：/get <链接>")]
+    Get(String),
+
+    #[command(description = "查看消息的编辑历史：/history <链接>，或回复该消息")]
+    History(String),
+
+    #[command(description = "查看和管理我收藏的消息")]
+    Bookmarks,
+
+    #[command(description = "私聊导出我的收藏：/exportbookmarks [json|csv]")]
+    ExportBookmarks(String),
+
+    #[command(description = "回复导出的文件以导入收藏")]
+    ImportBookmarks,
+
+    #[command(description = "查看群成员用过的用户名和名字：/aka @用户名，或回复其消息")]
+    Aka(String),
+
+    #[command(description = "查看置顶历史：/pins [关键词]")]
+    Pins(String),
+
+    #[command(description = "分享最多的网站和最近的链接：/links [时间段]")]
+    Links(String),
+
+    #[command(description = "对比关键词出现次数：/compare 关键词1 vs 关键词2 [时间段]")]
+    Compare(String),
+
+    #[command(description = "提及最多的人物、组织和地点：/entities [时间段]")]
+    Entities(String),
+
+    #[command(description = "查看本群索引统计：/stats [replies]")]
+    Stats(String),
+
+    #[command(description = "查看本群索引存储占用（仅限管理员）")]
+    Storage,
+
+    #[command(description = "查看搜索审计记录（仅限所有者）：/audit [chat] [时间段]")]
+    Audit(String),
+
+    #[command(description = "查看搜索执行细节（仅限所有者）：/explain <搜索语句>")]
+    Explain(String),
+
+    #[command(description = "查看索引队列运行状态（仅限所有者）")]
+    Queue,
+
+    #[command(description = "端到端检查写入、搜索和删除（仅限所有者）")]
+    SelfTest,
+
+    #[command(description = "查看生效的配置及其来源（仅限所有者）：/config [all]")]
+    Config(String),
+
+    #[command(description = "查看已收录的群组及其曾用名（仅限所有者）：/chats [群组 ID|here]")]
+    Chats(String),
+
+    #[command(description = "各群组对定期摘要的评价（仅限所有者）：/digestfeedback [时间段]")]
+    DigestFeedback(String),
+
+    #[command(
+        description = "查找多个群组中重复出现的消息（仅限所有者）：/crossposts [时间段] [最少群组数]"
+    )]
+    CrossPosts(String),
+
+    #[command(description = "关键词频率异常提醒（仅限管理员）：/alert add|del|list [关键词]")]
+    Alert(String),
+
+    #[command(description = "不再索引某用户的消息（仅限管理员）：/ignore @用户名")]
+    Ignore(String),
+
+    #[command(description = "恢复索引某用户的消息（仅限管理员）：/unignore @用户名")]
+    Unignore(String),
+
+    #[command(description = "查看本群不索引的用户")]
+    Ignored,
+
+    #[command(description = "桥接机器人作者识别规则（仅限管理员）：/bridge add|del|list")]
+    Bridge(String),
+
+    #[command(
+        description = "定期发送并置顶本群摘要（仅限管理员）：/digest daily|weekly|off [pin]"
+    )]
+    Digest(String),
+
+    #[command(description = "每周回顾一年前今天的热门消息（仅限管理员）：/throwback on|off|now")]
+    Throwback(String),
+
+    #[command(description = "搜索结果的链接预览（仅限管理员）：/preview auto|off|top")]
+    Preview(String),
+
+    #[command(description = "设置本群索引哪些发送者的消息（仅限管理员）：/senders")]
+    Senders(String),
+
+    #[command(description = "优先显示搜索者常互动成员的消息（仅限管理员）：/personalize on|off")]
+    Personalize(String),
+
+    #[command(description = "设置本群参考文档的语言（仅限管理员）：/language zh|en")]
+    Language(String),
+
+    #[command(description = "本群情绪和毒性趋势（仅限管理员）：/moodtrend [时间段]")]
+    MoodTrend(String),
+
+    #[command(description = "本群成员数和消息量变化（仅限管理员）：/growth [时间段]")]
+    Growth(String),
+
+    #[command(description = "列出曾经活跃但最近没有发言的成员（仅限管理员）：/quiet [时间段]")]
+    Quiet(String),
+
+    #[command(description = "列出疑似广告账号（仅限管理员）：/spamreport [时间段]")]
+    SpamReport(String),
+
+    #[command(
+        rename = "purge_before",
+        description = "删除某日期之前的索引消息（仅限管理员）：/purge_before YYYY-MM-DD"
+    )]
+    PurgeBefore(String),
+
+    #[command(description = "删除某用户在本群的所有索引消息（仅限管理员）：/forgetuser @用户名")]
+    ForgetUser(String),
+
+    #[command(description = "导出本群全部索引数据，需机器人所有者批准（仅限管理员）")]
+    ExportChat,
+
+    #[command(description = "查看本群的导出、删除等后台任务（仅限管理员）")]
+    Jobs,
+
+    #[command(description = "查看或取消后台任务（仅限管理员）：/job <任务编号> [status|cancel]")]
+    Job(String),
+}
+
+/// Who a command is meant for, deciding which command menu lists it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Audience {
+    Member,
+    Admin,
+    Owner,
+}
+
+impl Command {
+    pub fn audience(&self) -> Audience {
+        Self::audience_of(self.name())
+    }
+
+    /// Audience of a command by its canonical name (see [`Command::name`]).
+    pub fn audience_of(name: &str) -> Audience {
+        match name {
+            "storage" | "alert" | "ignore" | "unignore" | "bridge" | "digest" | "throwback"
+            | "preview" | "senders" | "personalize" | "language" | "moodtrend" | "growth"
+            | "quiet" | "spamreport" | "purge_before" | "forgetuser" | "exportchat" | "jobs"
+            | "job" => Audience::Admin,
+            "audit" | "explain" | "queue" | "selftest" | "config" | "chats" | "digestfeedback"
+            | "crossposts" => Audience::Owner,
+            _ => Audience::Member,
+        }
+    }
+
+    /// Canonical command name, used as the rate limit key.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Search(_) => "search",
+            Self::FindWizard => "findwizard",
+            Self::Help => "help",
+            Self::Syntax(_) => "syntax",
+            Self::Get(_) => "get",
+            Self::History(_) => "history",
+            Self::Bookmarks => "bookmarks",
+            Self::ExportBookmarks(_) => "exportbookmarks",
+            Self::ImportBookmarks => "importbookmarks",
+            Self::Aka(_) => "aka",
+            Self::Pins(_) => "pins",
+            Self::Links(_) => "links",
+            Self::Compare(_) => "compare",
+            Self::Entities(_) => "entities",
+            Self::Stats(_) => "stats",
+            Self::Storage => "storage",
+            Self::Audit(_) => "audit",
+            Self::Explain(_) => "explain",
+            Self::Queue => "queue",
+            Self::SelfTest => "selftest",
+            Self::Config(_) => "config",
+            Self::Chats(_) => "chats",
+            Self::DigestFeedback(_) => "digestfeedback",
+            Self::CrossPosts(_) => "crossposts",
+            Self::Alert(_) => "alert",
+            Self::Ignore(_) => "ignore",
+            Self::Unignore(_) => "unignore",
+            Self::Ignored => "ignored",
+            Self::Bridge(_) => "bridge",
+            Self::Digest(_) => "digest",
+            Self::Throwback(_) => "throwback",
+            Self::Preview(_) => "preview",
+            Self::Senders(_) => "senders",
+            Self::Personalize(_) => "personalize",
+            Self::Language(_) => "language",
+            Self::MoodTrend(_) => "moodtrend",
+            Self::Growth(_) => "growth",
+            Self::Quiet(_) => "quiet",
+            Self::SpamReport(_) => "spamreport",
+            Self::PurgeBefore(_) => "purge_before",
+            Self::ForgetUser(_) => "forgetuser",
+            Self::ExportChat => "exportchat",
+            Self::Jobs => "jobs",
+            Self::Job(_) => "job",
+        }
+    }
 }
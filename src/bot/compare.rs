@@ -0,0 +1,119 @@
+//! `/compare kw1 vs kw2 [period]`: how often each keyword came up over time.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::util::{format_timestamp, html_escape, parse_period};
+use crate::es::analytics::AnalyticsClient;
+
+const USAGE: &str = "用法: /compare 关键词1 vs 关键词2 [时间段]\n例如 /compare rust vs go 30d";
+/// Period compared when none is given.
+const DEFAULT_PERIOD_SECS: i64 = 30 * 86400;
+const MAX_KEYWORDS: usize = 4;
+/// Lines in the chart before buckets get coarser.
+const MAX_ROWS: i64 = 60;
+/// Width of the longest bar in the chart.
+const BAR_WIDTH: u64 = 10;
+const LABELS: [&str; MAX_KEYWORDS] = ["A", "B", "C", "D"];
+
+pub async fn handle_compare(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    analytics: Arc<AnalyticsClient>,
+) -> anyhow::Result<()> {
+    let Some((keywords, period)) = parse_args(&args) else {
+        bot.send_message(msg.chat.id, USAGE).await?;
+        return Ok(());
+    };
+
+    let bucket_secs = bucket_size(period);
+    let since = chrono::Utc::now().timestamp() - period;
+    let rows = analytics
+        .keyword_histogram(msg.chat.id.0, &keywords, since, bucket_secs)
+        .await?;
+
+    bot.send_message(
+        msg.chat.id,
+        format_comparison(&keywords, &rows, bucket_secs),
+    )
+    .parse_mode(ParseMode::Html)
+    .await?;
+    Ok(())
+}
+
+/// Split `a vs b [period]` into keywords and the period in seconds.
+fn parse_args(args: &str) -> Option<(Vec<String>, i64)> {
+    let mut words: Vec<&str> = args.split_whitespace().collect();
+    let period = match words.last().and_then(|w| parse_period(w)) {
+        Some(secs) => {
+            words.pop();
+            secs
+        }
+        None => DEFAULT_PERIOD_SECS,
+    };
+
+    let keywords: Vec<String> = words
+        .split(|w| w.eq_ignore_ascii_case("vs"))
+        .map(|part| part.join(" "))
+        .collect();
+    if keywords.len() < 2 || keywords.len() > MAX_KEYWORDS || keywords.iter().any(|k| k.is_empty())
+    {
+        return None;
+    }
+    Some((keywords, period))
+}
+
+/// The finest of hourly, daily, weekly or monthly buckets that keeps the
+/// chart within [`MAX_ROWS`] lines.
+fn bucket_size(period: i64) -> i64 {
+    [3600, 86400, 7 * 86400]
+        .into_iter()
+        .find(|&secs| period / secs < MAX_ROWS)
+        .unwrap_or(30 * 86400)
+}
+
+fn format_comparison(keywords: &[String], rows: &[(i64, Vec<u64>)], bucket_secs: i64) -> String {
+    let totals: Vec<u64> = (0..keywords.len())
+        .map(|i| rows.iter().map(|(_, counts)| counts[i]).sum())
+        .collect();
+
+    let mut text = "<b>关键词对比</b>\n".to_string();
+    for (i, keyword) in keywords.iter().enumerate() {
+        text.push_str(&format!(
+            "{} 「{}」 — <b>{}</b> 次\n",
+            LABELS[i],
+            html_escape(keyword),
+            totals[i]
+        ));
+    }
+    if totals.iter().all(|&t| t == 0) {
+        return text;
+    }
+
+    let max = rows
+        .iter()
+        .flat_map(|(_, counts)| counts.iter().copied())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    text.push_str("\n<pre>");
+    for (start, counts) in rows {
+        // Day-sized buckets drop the time, hourly ones keep it
+        let label = format_timestamp(*start);
+        let label = if bucket_secs < 86400 {
+            label.get(5..)
+        } else {
+            label.get(5..10)
+        };
+        text.push_str(label.unwrap_or_default());
+        for (i, &count) in counts.iter().enumerate() {
+            let bar = "█".repeat((count * BAR_WIDTH).div_ceil(max) as usize);
+            text.push_str(&format!(" {}{bar} {count}", LABELS[i]));
+        }
+        text.push('\n');
+    }
+    text.push_str("</pre>");
+    text
+}
@@ -0,0 +1,127 @@
+//! `/config`: the effective configuration, where each setting came from, and
+//! warnings about settings that probably don't do what was meant.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::util::html_escape;
+use crate::config::{AppConfig, ConfigSource, ConfigWarning};
+
+const USAGE: &str = "用法: /config 查看修改过的配置，/config all 查看全部配置";
+/// Characters per reply, under Telegram's 4096 limit with room for markup.
+const MAX_CHARS: usize = 3500;
+
+/// Handle `/config [all]` (owners, private chat only).
+pub async fn handle_config(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    config: Arc<AppConfig>,
+) -> anyhow::Result<()> {
+    if !msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "请私聊机器人使用此命令。")
+            .await?;
+        return Ok(());
+    }
+    let all = match args.trim() {
+        "" => false,
+        "all" => true,
+        _ => {
+            bot.send_message(msg.chat.id, USAGE).await?;
+            return Ok(());
+        }
+    };
+    for text in format_report(&config, all) {
+        bot.send_message(msg.chat.id, text)
+            .parse_mode(ParseMode::Html)
+            .await?;
+    }
+    Ok(())
+}
+
+/// The report split into replies; defaults are left out unless `all`.
+fn format_report(config: &AppConfig, all: bool) -> Vec<String> {
+    let mut lines = vec!["<b>当前配置</b>".to_string()];
+    let warnings = config.warnings();
+    if warnings.is_empty() {
+        lines.push("没有发现可疑的配置组合。".into());
+    } else {
+        lines.push("<b>⚠️ 可能有误的配置：</b>".into());
+        lines.extend(warnings.iter().map(|w| format!("• {}", warning_label(w))));
+    }
+    lines.push(String::new());
+
+    let entries = config.report();
+    let mut defaults = 0;
+    for entry in &entries {
+        if entry.source == ConfigSource::Default && !all {
+            defaults += 1;
+            continue;
+        }
+        lines.push(format!(
+            "<code>{}</code> = <code>{}</code> · {}",
+            html_escape(&entry.key),
+            html_escape(&entry.value),
+            source_label(&entry.source)
+        ));
+    }
+    if defaults > 0 {
+        lines.push(format!(
+            "\n另有 {defaults} 项使用默认值，发送 /config all 查看全部。"
+        ));
+    }
+
+    let mut replies = vec![String::new()];
+    for line in lines {
+        let reply = replies.last_mut().unwrap();
+        if !reply.is_empty() && reply.chars().count() + line.chars().count() >= MAX_CHARS {
+            replies.push(String::new());
+        }
+        let reply = replies.last_mut().unwrap();
+        reply.push_str(&line);
+        reply.push('\n');
+    }
+    replies
+}
+
+fn source_label(source: &ConfigSource) -> String {
+    match source {
+        ConfigSource::Default => "默认".into(),
+        ConfigSource::Toml => "config.toml".into(),
+        ConfigSource::Env(var) => format!("环境变量 {var}"),
+        ConfigSource::Cli => "命令行 --set".into(),
+    }
+}
+
+fn warning_label(warning: &ConfigWarning) -> String {
+    match warning {
+        ConfigWarning::NoOwners => "未设置 telegram.owner_ids，所有者命令无法使用".into(),
+        ConfigWarning::WebhookNotHttps => "webhook.url 不是 https，Telegram 会拒绝".into(),
+        ConfigWarning::WebhookPortUnsupported(port) => {
+            format!("webhook.url 使用端口 {port}，Telegram 只向 443、80、88、8443 推送")
+        }
+        ConfigWarning::ListenerPortClash(port) => {
+            format!("api.port 与 webhook.port 都是 {port}")
+        }
+        ConfigWarning::ShardReusesMainToken => "有分片使用了 telegram.bot_token".into(),
+        ConfigWarning::ShardChatTwice(chat) => format!("群组 {chat} 被分配给多个分片"),
+        ConfigWarning::ReadUrlIsWriteUrl => {
+            "elasticsearch.read_url 与 elasticsearch.url 相同".into()
+        }
+        ConfigWarning::DefaultPageAboveMax => {
+            "search.default_page_size 大于 search.max_page_size".into()
+        }
+        ConfigWarning::SlowQueryAboveTimeout => {
+            "search.slow_query_ms 不小于 search.timeout_ms，不会记录任何慢查询".into()
+        }
+        ConfigWarning::EventsWithoutUrl => "设置了 events.backend 但 events.url 为空".into(),
+        ConfigWarning::ProcessorWithoutEndpoint(name) => {
+            format!("已启用 {name} 但 {name}.endpoint 为空")
+        }
+        ConfigWarning::ProcessorNotListed(name) => {
+            format!("已启用 {name} 但 recorder.processors 中没有它，不会运行")
+        }
+        ConfigWarning::ZeroInterval(key) => format!("{key} 为 0"),
+    }
+}
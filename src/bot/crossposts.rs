@@ -0,0 +1,118 @@
+//! `/crossposts [period] [min chats]`: owner-only list of texts posted in
+//! several indexed chats, to spot spam campaigns across related groups.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::util::{
+    format_timestamp, html_escape, no_link_preview, parse_period, public_message_link,
+};
+use crate::es::analytics::{AnalyticsClient, CrossPost};
+use crate::es::chats::ChatStore;
+
+/// Period searched when none is given.
+const DEFAULT_PERIOD_SECS: i64 = 86400;
+/// Chats a text must appear in when no minimum is given.
+const DEFAULT_MIN_CHATS: u64 = 2;
+/// Texts listed, within Telegram's message limit.
+const MAX_LISTED: usize = 10;
+/// Chats linked per text.
+const MAX_LINKED_CHATS: usize = 5;
+/// Characters of each text shown.
+const SNIPPET_CHARS: usize = 60;
+
+const USAGE: &str = "用法: /crossposts [时间段] [最少群组数]，例如 /crossposts 7d 3";
+
+pub async fn handle_crossposts(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    analytics: Arc<AnalyticsClient>,
+    chats: Arc<ChatStore>,
+) -> anyhow::Result<()> {
+    let (mut period, mut min_chats) = (DEFAULT_PERIOD_SECS, DEFAULT_MIN_CHATS);
+    let mut period_label = "24h";
+    for token in args.split_whitespace() {
+        if let Some(secs) = parse_period(token) {
+            period = secs;
+            period_label = token;
+        } else if let Some(n) = token.parse().ok().filter(|&n| n >= 2) {
+            min_chats = n;
+        } else {
+            bot.send_message(msg.chat.id, USAGE).await?;
+            return Ok(());
+        }
+    }
+
+    let since = chrono::Utc::now().timestamp() - period;
+    let posts = analytics.cross_posts(since, min_chats, MAX_LISTED).await?;
+    let titles: HashMap<i64, (Option<String>, Option<String>)> = if posts.is_empty() {
+        HashMap::new()
+    } else {
+        chats
+            .all()
+            .await?
+            .into_iter()
+            .map(|info| (info.chat_id, (info.title, info.username)))
+            .collect()
+    };
+
+    bot.send_message(
+        msg.chat.id,
+        format_report(&posts, &titles, period_label, min_chats),
+    )
+    .parse_mode(ParseMode::Html)
+    .link_preview_options(no_link_preview())
+    .await?;
+    Ok(())
+}
+
+fn format_report(
+    posts: &[CrossPost],
+    titles: &HashMap<i64, (Option<String>, Option<String>)>,
+    period: &str,
+    min_chats: u64,
+) -> String {
+    let mut text = format!("<b>跨群重复消息（{period} 内，至少 {min_chats} 个群）</b>\n");
+    if posts.is_empty() {
+        text.push_str("\n没有发现。");
+        return text;
+    }
+    for (i, post) in posts.iter().enumerate() {
+        let snippet: String = post.text.chars().take(SNIPPET_CHARS).collect();
+        let ellipsis = if post.text.chars().count() > SNIPPET_CHARS {
+            "…"
+        } else {
+            ""
+        };
+        text.push_str(&format!(
+            "\n{}. {}{ellipsis}\n{} 个群 · {} 条 · {} 个发送者 · {} ~ {}\n",
+            i + 1,
+            html_escape(&snippet),
+            post.chat_count,
+            post.messages,
+            post.senders,
+            format_timestamp(post.first),
+            format_timestamp(post.last)
+        ));
+        let links: Vec<String> = post
+            .chats
+            .iter()
+            .take(MAX_LINKED_CHATS)
+            .map(|&(chat_id, message_id)| {
+                let (title, username) = titles.get(&chat_id).cloned().unwrap_or_default();
+                let link = public_message_link(username.as_deref(), chat_id, message_id);
+                let label = title.unwrap_or_else(|| chat_id.to_string());
+                format!("<a href=\"{link}\">{}</a>", html_escape(&label))
+            })
+            .collect();
+        text.push_str(&links.join("、"));
+        if post.chat_count > links.len() as u64 {
+            text.push_str(&format!(" 等 {} 个群", post.chat_count));
+        }
+        text.push('\n');
+    }
+    text
+}
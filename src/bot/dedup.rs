@@ -0,0 +1,52 @@
+//! Drops updates that were already handled, e.g. webhook deliveries Telegram
+//! retried after a slow response, so searches and replies don't run twice.
+
+use dashmap::DashMap;
+use std::time::{Duration, Instant};
+use teloxide::types::Update;
+
+/// Seen update ids kept before expired ones are pruned.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+pub struct UpdateDedup {
+    window: Duration,
+    seen: DashMap<u32, Instant>,
+}
+
+impl UpdateDedup {
+    /// A zero `window_secs` disables deduplication.
+    pub fn new(window_secs: u64) -> Self {
+        Self {
+            window: Duration::from_secs(window_secs),
+            seen: DashMap::new(),
+        }
+    }
+
+    /// Whether `update` hasn't been seen within the window; marks it as seen.
+    pub fn first_seen(&self, update: &Update) -> bool {
+        if self.window.is_zero() {
+            return true;
+        }
+        let now = Instant::now();
+        if self.seen.len() > PRUNE_THRESHOLD {
+            self.seen
+                .retain(|_, seen_at| now.duration_since(*seen_at) < self.window);
+        }
+
+        let mut fresh = true;
+        self.seen
+            .entry(update.id.0)
+            .and_modify(|seen_at| {
+                if now.duration_since(*seen_at) < self.window {
+                    fresh = false;
+                } else {
+                    *seen_at = now;
+                }
+            })
+            .or_insert(now);
+        if !fresh {
+            tracing::debug!("Dropping duplicate update {}", update.id.0);
+        }
+        fresh
+    }
+}
@@ -0,0 +1,235 @@
+//! Scheduled activity digests: chats opt in with `/digest`, and a background
+//! task posts a daily or weekly summary, optionally pinning it in place of the
+//! previous one. Members rate each digest with the buttons under it.
+
+use std::sync::Arc;
+use std::time::Duration;
+use teloxide::prelude::*;
+use teloxide::types::MessageId;
+
+use crate::bot::digest_feedback;
+use crate::bot::shards::Shards;
+use crate::bot::telegram::TelegramSender;
+use crate::bot::util::{format_message_link, format_timestamp, html_escape};
+use crate::config::DigestConfig;
+use crate::es::analytics::{ActivitySummary, AnalyticsClient};
+use crate::es::feedback::Tally;
+use crate::es::settings::{DigestPeriod, DigestSettings, SettingsStore};
+
+const USAGE: &str = "用法:\n\
+    /digest daily [pin] — 每天发送本群摘要，加 pin 会置顶并取消置顶上一期\n\
+    /digest weekly [pin] — 每周发送\n\
+    /digest off — 关闭摘要\n\
+    /digest — 查看当前设置";
+
+/// Handle `/digest`: configure the chat's scheduled digest (admins).
+pub async fn handle_digest(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    settings: Arc<SettingsStore>,
+) -> anyhow::Result<()> {
+    if msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "请在群组中使用此命令。")
+            .await?;
+        return Ok(());
+    }
+
+    let chat_id = msg.chat.id.0;
+    let mut words = args.split_whitespace();
+    let action = words.next().unwrap_or_default();
+    let pin = words.next() == Some("pin");
+
+    let period = match action {
+        "daily" => DigestPeriod::Daily,
+        "weekly" => DigestPeriod::Weekly,
+        "off" => {
+            settings.update(chat_id, |s| s.digest = None).await?;
+            bot.send_message(msg.chat.id, "已关闭本群摘要。").await?;
+            return Ok(());
+        }
+        "" => {
+            let text = match settings.get(chat_id).await?.digest {
+                Some(ref digest) => format!(
+                    "本群已开启{}摘要{}，上一期截至 {}。",
+                    period_label(digest.period),
+                    if digest.pin { "（置顶）" } else { "" },
+                    format_timestamp(digest.last_sent)
+                ),
+                None => format!("本群未开启摘要。\n\n{USAGE}"),
+            };
+            bot.send_message(msg.chat.id, text).await?;
+            return Ok(());
+        }
+        _ => {
+            bot.send_message(msg.chat.id, USAGE).await?;
+            return Ok(());
+        }
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    settings
+        .update(chat_id, |s| {
+            // Keep the previous digest so it still gets unpinned next time
+            let last_message_id = s.digest.as_ref().and_then(|d| d.last_message_id);
+            s.digest = Some(DigestSettings {
+                period,
+                pin,
+                last_sent: now,
+                last_message_id,
+            });
+        })
+        .await?;
+
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "已开启{}摘要{}，第一期将在一个周期后发送。",
+            period_label(period),
+            if pin { "，发送后会置顶" } else { "" }
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Spawn the background task that posts due digests.
+pub fn spawn_digest_scheduler(
+    shards: Arc<Shards>,
+    sender: Arc<TelegramSender>,
+    settings: Arc<SettingsStore>,
+    analytics: Arc<AnalyticsClient>,
+    config: DigestConfig,
+) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(config.check_interval_secs.max(60)));
+        loop {
+            interval.tick().await;
+            if let Err(e) = send_due_digests(&shards, &sender, &settings, &analytics).await {
+                tracing::warn!("Digest check failed: {e}");
+            }
+        }
+    });
+}
+
+async fn send_due_digests(
+    shards: &Shards,
+    sender: &TelegramSender,
+    settings: &SettingsStore,
+    analytics: &AnalyticsClient,
+) -> anyhow::Result<()> {
+    let now = chrono::Utc::now().timestamp();
+    for chat in settings.all().await? {
+        let Some(digest) = chat.digest else {
+            continue;
+        };
+        if now - digest.last_sent < digest.period.secs() {
+            continue;
+        }
+
+        let summary = analytics
+            .activity_summary(chat.chat_id, digest.last_sent, now)
+            .await?;
+        let chat_id = ChatId(chat.chat_id);
+        let text = format_digest(chat.chat_id, digest.period, &summary);
+        let keyboard = digest_feedback::keyboard(digest.period, Tally::default());
+        let sent = match sender
+            .send_html_with_keyboard(chat_id, text, keyboard)
+            .await
+        {
+            Ok(sent) => sent,
+            Err(e) => {
+                tracing::warn!("Failed to send digest to {}: {e}", chat.chat_id);
+                continue;
+            }
+        };
+
+        let mut last_message_id = digest.last_message_id;
+        if digest.pin {
+            let bot = shards.bot_for(chat_id);
+            repin(bot, chat_id, digest.last_message_id, sent.id).await;
+            last_message_id = Some(sent.id.0);
+        }
+        settings
+            .update(chat.chat_id, |s| {
+                if let Some(ref mut d) = s.digest {
+                    d.last_sent = now;
+                    d.last_message_id = last_message_id;
+                }
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+/// Pin the new digest and unpin the previous one. Failures (usually missing
+/// pin rights) are logged so the digest itself still counts as sent.
+async fn repin(bot: &Bot, chat_id: ChatId, previous: Option<i32>, current: MessageId) {
+    if let Some(previous) = previous
+        && let Err(e) = bot
+            .unpin_chat_message(chat_id)
+            .message_id(MessageId(previous))
+            .await
+    {
+        tracing::debug!("Failed to unpin previous digest in {chat_id}: {e}");
+    }
+    if let Err(e) = bot
+        .pin_chat_message(chat_id, current)
+        .disable_notification(true)
+        .await
+    {
+        tracing::warn!("Failed to pin digest in {chat_id}: {e}");
+    }
+}
+
+fn format_digest(chat_id: i64, period: DigestPeriod, summary: &ActivitySummary) -> String {
+    let mut text = format!(
+        "📰 <b>本群{}摘要</b>\n共 <b>{}</b> 条消息\n",
+        period_label(period),
+        summary.total
+    );
+    if summary.total == 0 {
+        return text;
+    }
+
+    if !summary.top_senders.is_empty() {
+        text.push_str("\n<b>最活跃：</b>\n");
+        for (i, (name, count)) in summary.top_senders.iter().enumerate() {
+            text.push_str(&format!("{}. {} — {count}\n", i + 1, html_escape(name)));
+        }
+    }
+    if !summary.top_domains.is_empty() {
+        text.push_str("\n<b>分享最多的网站：</b>\n");
+        for (domain, count) in &summary.top_domains {
+            text.push_str(&format!("{} — {count}\n", html_escape(domain)));
+        }
+    }
+    if let Some((hour, count)) = summary.busiest_hour {
+        text.push_str(&format!(
+            "\n<b>最热闹的时段：</b>{hour:02}:00–{:02}:00（UTC），{count} 条消息\n",
+            (hour + 1) % 24
+        ));
+    }
+    if let Some(ref replied) = summary.most_replied {
+        let snippet: String = replied.text.chars().take(30).collect();
+        let snippet = if snippet.is_empty() {
+            "（无文字）".to_string()
+        } else {
+            html_escape(&snippet)
+        };
+        text.push_str(&format!(
+            "\n<b>回复最多的消息：</b>\n<a href=\"{}\">{snippet}</a> — {} 条回复\n",
+            format_message_link(chat_id, replied.message_id),
+            replied.replies
+        ));
+    }
+    text
+}
+
+fn period_label(period: DigestPeriod) -> &'static str {
+    match period {
+        DigestPeriod::Daily => "每日",
+        DigestPeriod::Weekly => "每周",
+    }
+}
@@ -0,0 +1,190 @@
+//! 👍/👎 buttons under posted digests, and `/digestfeedback`, the owner's
+//! report of how members rated them.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::{
+    InlineKeyboardButton, InlineKeyboardMarkup, MaybeInaccessibleMessage, ParseMode,
+};
+
+use crate::bot::util::{format_timestamp, html_escape, parse_period};
+use crate::es::chats::ChatStore;
+use crate::es::feedback::{DigestFeedbackStore, DigestVote, Tally};
+use crate::es::settings::DigestPeriod;
+
+/// Callback data prefix of vote buttons: `dg:<up|down>:<daily|weekly>`.
+pub const CALLBACK_PREFIX: &str = "dg:";
+/// Window of `/digestfeedback` without an argument.
+const DEFAULT_REPORT_SECS: i64 = 30 * 86400;
+
+pub fn is_digest_feedback_callback(q: &CallbackQuery) -> bool {
+    q.data
+        .as_deref()
+        .is_some_and(|d| d.starts_with(CALLBACK_PREFIX))
+}
+
+/// Vote buttons of a digest, labelled with the votes so far.
+pub fn keyboard(period: DigestPeriod, tally: Tally) -> InlineKeyboardMarkup {
+    let period = period_key(period);
+    let label = |emoji: &str, count: u64| {
+        if count == 0 {
+            emoji.to_string()
+        } else {
+            format!("{emoji} {count}")
+        }
+    };
+    InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback(
+            label("👍", tally.up),
+            format!("{CALLBACK_PREFIX}up:{period}"),
+        ),
+        InlineKeyboardButton::callback(
+            label("👎", tally.down),
+            format!("{CALLBACK_PREFIX}down:{period}"),
+        ),
+    ]])
+}
+
+/// Record a member's vote on a digest and refresh the counts on its buttons.
+pub async fn handle_digest_feedback_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    feedback: Arc<DigestFeedbackStore>,
+) -> anyhow::Result<()> {
+    let Some(MaybeInaccessibleMessage::Regular(msg)) = q.message.as_ref() else {
+        bot.answer_callback_query(q.id).await?;
+        return Ok(());
+    };
+    let Some((helpful, period)) = q
+        .data
+        .as_deref()
+        .and_then(|d| d.strip_prefix(CALLBACK_PREFIX))
+        .and_then(|d| d.split_once(':'))
+        .and_then(|(vote, period)| {
+            let helpful = match vote {
+                "up" => true,
+                "down" => false,
+                _ => return None,
+            };
+            Some((helpful, parse_period_key(period)?))
+        })
+    else {
+        bot.answer_callback_query(q.id).await?;
+        return Ok(());
+    };
+
+    let chat_id = msg.chat.id.0;
+    let message_id = i64::from(msg.id.0);
+    feedback
+        .vote(&DigestVote {
+            chat_id,
+            message_id,
+            user_id: q.from.id.0 as i64,
+            helpful,
+            period: period_key(period).to_string(),
+            date: chrono::Utc::now().timestamp(),
+        })
+        .await?;
+    bot.answer_callback_query(q.id)
+        .text("已记录，谢谢反馈")
+        .await?;
+
+    let tally = feedback.tally(chat_id, message_id).await?;
+    match bot
+        .edit_message_reply_markup(msg.chat.id, msg.id)
+        .reply_markup(keyboard(period, tally))
+        .await
+    {
+        Ok(_) => {}
+        // The member changed nothing, e.g. pressed the same button twice
+        Err(e) if e.to_string().contains("message is not modified") => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+/// Handle `/digestfeedback [period]` (owner): digest votes across all chats,
+/// by default over the past 30 days.
+pub async fn handle_digest_feedback(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    feedback: Arc<DigestFeedbackStore>,
+    chats: Arc<ChatStore>,
+) -> anyhow::Result<()> {
+    let window = match args.trim() {
+        "" => DEFAULT_REPORT_SECS,
+        arg => match parse_period(arg) {
+            Some(secs) => secs,
+            None => {
+                bot.send_message(msg.chat.id, "用法: /digestfeedback [时间段，如 7d、30d]")
+                    .await?;
+                return Ok(());
+            }
+        },
+    };
+    let since = chrono::Utc::now().timestamp() - window;
+    let report = feedback.report(since).await?;
+    if report.total == Tally::default() {
+        bot.send_message(msg.chat.id, "这段时间内没有收到摘要反馈。")
+            .await?;
+        return Ok(());
+    }
+
+    let mut text = format!(
+        "<b>摘要反馈（{} 起）</b>\n共 {}\n",
+        format_timestamp(since),
+        format_tally(report.total)
+    );
+    if !report.by_period.is_empty() {
+        text.push('\n');
+        for (period, tally) in &report.by_period {
+            let label = match parse_period_key(period) {
+                Some(DigestPeriod::Daily) => "每日摘要",
+                Some(DigestPeriod::Weekly) => "每周摘要",
+                None => period.as_str(),
+            };
+            text.push_str(&format!("{label}：{}\n", format_tally(*tally)));
+        }
+    }
+    text.push_str("\n<b>按群组：</b>\n");
+    for (chat_id, tally) in &report.by_chat {
+        let title = chats
+            .get(*chat_id)
+            .await?
+            .and_then(|info| info.title)
+            .unwrap_or_else(|| chat_id.to_string());
+        text.push_str(&format!(
+            "{} — {}\n",
+            html_escape(&title),
+            format_tally(*tally)
+        ));
+    }
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+/// `👍 3 · 👎 1（75% 好评）`
+fn format_tally(tally: Tally) -> String {
+    let share = (tally.up * 100)
+        .checked_div(tally.up + tally.down)
+        .unwrap_or(0);
+    format!("👍 {} · 👎 {}（{share}% 好评）", tally.up, tally.down)
+}
+
+fn period_key(period: DigestPeriod) -> &'static str {
+    match period {
+        DigestPeriod::Daily => "daily",
+        DigestPeriod::Weekly => "weekly",
+    }
+}
+
+fn parse_period_key(key: &str) -> Option<DigestPeriod> {
+    match key {
+        "daily" => Some(DigestPeriod::Daily),
+        "weekly" => Some(DigestPeriod::Weekly),
+        _ => None,
+    }
+}
@@ -0,0 +1,64 @@
+//! `/entities [period]`: the people, organizations and places mentioned most,
+//! from the fields filled by entity extraction.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::util::{html_escape, parse_period};
+use crate::es::analytics::{AnalyticsClient, EntityCounts};
+
+/// Entities listed per kind.
+const TOP_ENTITIES: usize = 10;
+
+pub async fn handle_entities(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    analytics: Arc<AnalyticsClient>,
+) -> anyhow::Result<()> {
+    let args = args.trim();
+    let since = if args.is_empty() {
+        None
+    } else {
+        match parse_period(args) {
+            Some(secs) => Some(chrono::Utc::now().timestamp() - secs),
+            None => {
+                bot.send_message(msg.chat.id, "用法: /entities [时间段]，例如 /entities 30d")
+                    .await?;
+                return Ok(());
+            }
+        }
+    };
+
+    let counts = analytics
+        .top_entities(msg.chat.id.0, since, TOP_ENTITIES)
+        .await?;
+    bot.send_message(msg.chat.id, format_entities(&counts))
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+fn format_entities(counts: &EntityCounts) -> String {
+    let sections = [
+        ("人物", &counts.people),
+        ("组织", &counts.orgs),
+        ("地点", &counts.places),
+    ];
+    if sections.iter().all(|(_, entries)| entries.is_empty()) {
+        return "没有识别到任何实体，可能未启用实体识别。".to_string();
+    }
+
+    let mut text = "<b>提及最多的实体</b>\n".to_string();
+    for (title, entries) in sections {
+        if entries.is_empty() {
+            continue;
+        }
+        text.push_str(&format!("\n<b>{title}</b>\n"));
+        for (name, count) in entries {
+            text.push_str(&format!("• {} — {count} 条\n", html_escape(name)));
+        }
+    }
+    text
+}
@@ -0,0 +1,116 @@
+//! `/explain <query>`: owner-only view of how a search is executed, to debug
+//! why expected messages don't match.
+
+use serde_json::Value;
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::callback::{reply_error, search_params, SearchState};
+use crate::bot::query::{parse_query, validate_query};
+use crate::bot::util::html_escape;
+use crate::error::AppError;
+use crate::es::search::{SearchClient, SearchExplanation};
+
+/// Characters of the query JSON shown before it is cut off.
+const MAX_QUERY_CHARS: usize = 1800;
+/// Nesting depth and size of the score explanation tree shown, keeping the
+/// reply under Telegram's 4096 character limit.
+const MAX_EXPLAIN_DEPTH: usize = 4;
+const MAX_EXPLAIN_CHARS: usize = 1500;
+
+/// Handle `/explain <query>` in the chat whose index should be searched.
+pub async fn handle_explain(
+    bot: Bot,
+    msg: Message,
+    query: String,
+    search_client: Arc<SearchClient>,
+    default_page_size: usize,
+) -> Result<(), AppError> {
+    if query.trim().is_empty() {
+        bot.send_message(msg.chat.id, "用法: /explain <搜索语句>，语法与 /s 相同")
+            .await?;
+        return Ok(());
+    }
+
+    let reply_user_id = msg
+        .reply_to_message()
+        .and_then(|r| r.from.as_ref())
+        .map(|u| u.id.0 as i64);
+    let query = match validate_query(&query) {
+        Ok(query) => query,
+        Err(e) => return reply_error(&bot, &msg, e.into()).await,
+    };
+    let parsed = parse_query(&query, reply_user_id);
+    let state = SearchState {
+        page: 0,
+        message_type: parsed.message_type.clone(),
+        date_range: None,
+        user_id: parsed.user_id,
+    };
+    let params = search_params(msg.chat.id.0, parsed, &state, default_page_size);
+    let explanation = match search_client.explain(&params).await {
+        Ok(explanation) => explanation,
+        Err(e) => return reply_error(&bot, &msg, e).await,
+    };
+
+    bot.send_message(msg.chat.id, format_explanation(&explanation))
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+fn format_explanation(explanation: &SearchExplanation) -> String {
+    let mut query = serde_json::to_string_pretty(&explanation.query).unwrap_or_default();
+    if query.chars().count() > MAX_QUERY_CHARS {
+        query = query.chars().take(MAX_QUERY_CHARS).collect::<String>() + "\n…";
+    }
+
+    let tokens = if explanation.tokens.is_empty() {
+        "（无）".to_string()
+    } else {
+        explanation
+            .tokens
+            .iter()
+            .map(|t| format!("<code>{}</code>", html_escape(t)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    let mut text = format!(
+        "<b>ES 查询：</b>\n<pre>{}</pre>\n\n<b>分词结果（ik_smart）：</b>\n{tokens}\n\n",
+        html_escape(&query)
+    );
+
+    match &explanation.top_hit {
+        Some((message_id, score, detail)) => {
+            text.push_str(&format!(
+                "<b>首条结果：</b>消息 {message_id}，得分 {score:.3}\n<pre>"
+            ));
+            let mut lines = String::new();
+            explain_tree(detail, 0, &mut lines);
+            text.push_str(&html_escape(&lines));
+            text.push_str("</pre>");
+        }
+        None => text.push_str("<b>没有匹配的消息。</b>"),
+    }
+    text
+}
+
+/// Render an `_explanation` node as indented `value description` lines.
+fn explain_tree(node: &Value, depth: usize, out: &mut String) {
+    if depth >= MAX_EXPLAIN_DEPTH || out.chars().count() >= MAX_EXPLAIN_CHARS {
+        return;
+    }
+    out.push_str(&format!(
+        "{}{:.3} {}\n",
+        "  ".repeat(depth),
+        node["value"].as_f64().unwrap_or(0.0),
+        node["description"].as_str().unwrap_or_default()
+    ));
+    if let Some(details) = node["details"].as_array() {
+        for child in details {
+            explain_tree(child, depth + 1, out);
+        }
+    }
+}
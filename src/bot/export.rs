@@ -0,0 +1,553 @@
+//! `/exportchat`: a complete export of the chat's indexed messages for groups
+//! leaving the service or keeping records. An admin asks, a bot owner
+//! approves in a private chat, and the archive is built in the background
+//! and sent privately to the admin who asked.
+//!
+//! The zip holds `messages.jsonl` (every indexed document as stored),
+//! `messages.csv` (the common fields) and `summary.html`.
+
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Cursor, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use teloxide::prelude::*;
+use teloxide::types::{
+    InlineKeyboardButton, InlineKeyboardMarkup, InputFile, MaybeInaccessibleMessage, MessageId,
+    ParseMode,
+};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::bot::bookmark_transfer::csv_field;
+use crate::bot::util::{format_bytes, format_timestamp, html_escape};
+use crate::config::AppConfig;
+use crate::es::admin::AdminClient;
+use crate::es::chats::ChatStore;
+use crate::jobs::{JobHandle, JobKind, JobQueue};
+
+/// Callback data prefix of the approval buttons:
+/// `export:<ok|no>:<chat_id>:<requester>:<status message id>`.
+pub const CALLBACK_PREFIX: &str = "export:";
+const APPROVE: &str = "ok";
+const DENY: &str = "no";
+/// Largest document a bot may upload.
+const MAX_UPLOAD_BYTES: usize = 50 * 1024 * 1024;
+/// Least time between two edits of the progress message.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(3);
+/// Senders listed in the summary.
+const SUMMARY_SENDERS: usize = 20;
+/// Columns of `messages.csv`, in order; array fields are joined by spaces.
+const CSV_COLUMNS: [&str; 12] = [
+    "message_id",
+    "date",
+    "edited_at",
+    "user_id",
+    "username",
+    "display_name",
+    "message_type",
+    "reply_to_message_id",
+    "text",
+    "caption",
+    "file_name",
+    "urls",
+];
+
+/// An export waiting for, or running after, an owner's approval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ExportRequest {
+    chat_id: i64,
+    /// Admin who asked, and who receives the archive
+    requester: i64,
+    /// Message in the chat that shows the export's state
+    status: i32,
+}
+
+impl ExportRequest {
+    fn callback_data(self, action: &str) -> String {
+        format!(
+            "{CALLBACK_PREFIX}{action}:{}:{}:{}",
+            self.chat_id, self.requester, self.status
+        )
+    }
+
+    /// The action and request of a button's callback data.
+    fn parse(data: &str) -> Option<(&str, Self)> {
+        let mut parts = data.strip_prefix(CALLBACK_PREFIX)?.split(':');
+        let action = parts.next()?;
+        let request = Self {
+            chat_id: parts.next()?.parse().ok()?,
+            requester: parts.next()?.parse().ok()?,
+            status: parts.next()?.parse().ok()?,
+        };
+        parts.next().is_none().then_some((action, request))
+    }
+}
+
+pub fn is_export_callback(q: &CallbackQuery) -> bool {
+    q.data
+        .as_deref()
+        .is_some_and(|d| d.starts_with(CALLBACK_PREFIX))
+}
+
+/// Handle `/exportchat` (admins): ask every bot owner to approve the export.
+pub async fn handle_export_chat(
+    bot: Bot,
+    msg: Message,
+    config: Arc<AppConfig>,
+) -> anyhow::Result<()> {
+    if msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "请在群组中使用此命令。")
+            .await?;
+        return Ok(());
+    }
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    if config.telegram.owner_ids.is_empty() {
+        bot.send_message(msg.chat.id, "未配置机器人所有者，无法审批导出。")
+            .await?;
+        return Ok(());
+    }
+
+    let status = bot
+        .send_message(msg.chat.id, "已提交导出申请，等待机器人所有者批准。")
+        .await?;
+    let request = ExportRequest {
+        chat_id: msg.chat.id.0,
+        requester: user.id.0 as i64,
+        status: status.id.0,
+    };
+    let title = msg.chat.title().unwrap_or("（无标题）");
+    let text = format!(
+        "群组 {}（{}）的管理员 <a href=\"tg://user?id={}\">{}</a> 申请导出本群的全部索引数据。",
+        html_escape(title),
+        msg.chat.id,
+        user.id,
+        html_escape(&user.full_name())
+    );
+    let markup = InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback("批准", request.callback_data(APPROVE)),
+        InlineKeyboardButton::callback("拒绝", request.callback_data(DENY)),
+    ]]);
+
+    let mut asked = 0;
+    for &owner in &config.telegram.owner_ids {
+        let sent = bot
+            .send_message(ChatId(owner), &text)
+            .parse_mode(ParseMode::Html)
+            .reply_markup(markup.clone())
+            .await;
+        match sent {
+            Ok(_) => asked += 1,
+            Err(e) => tracing::warn!("Failed to ask owner {owner} to approve an export: {e}"),
+        }
+    }
+    if asked == 0 {
+        bot.edit_message_text(
+            msg.chat.id,
+            status.id,
+            "无法联系机器人所有者，请所有者先私聊机器人并发送 /start。",
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Approve or deny an export. Only owners may answer; an approved export
+/// runs as a background job while the chat's status message shows progress.
+pub async fn handle_export_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    config: Arc<AppConfig>,
+    admin: Arc<AdminClient>,
+    chats: Arc<ChatStore>,
+    jobs: Arc<JobQueue>,
+) -> anyhow::Result<()> {
+    let Some(MaybeInaccessibleMessage::Regular(msg)) = q.message.as_ref() else {
+        bot.answer_callback_query(q.id.clone()).await?;
+        return Ok(());
+    };
+    let Some((action, request)) = q.data.as_deref().and_then(ExportRequest::parse) else {
+        bot.answer_callback_query(q.id.clone()).await?;
+        return Ok(());
+    };
+    if !config.telegram.owner_ids.contains(&(q.from.id.0 as i64)) {
+        bot.answer_callback_query(q.id.clone())
+            .text("只有机器人所有者可以审批导出")
+            .await?;
+        return Ok(());
+    }
+    bot.answer_callback_query(q.id.clone()).await?;
+
+    let chat = ChatId(request.chat_id);
+    let status = MessageId(request.status);
+    if action == DENY {
+        bot.edit_message_text(msg.chat.id, msg.id, "已拒绝导出申请。")
+            .await?;
+        bot.edit_message_text(chat, status, "机器人所有者拒绝了导出申请。")
+            .await?;
+        return Ok(());
+    }
+    if action != APPROVE {
+        return Ok(());
+    }
+
+    tracing::info!(
+        "User {} approved an export of chat {} for {}",
+        q.from.id,
+        request.chat_id,
+        request.requester
+    );
+    // Before the job starts, so its progress is never overwritten
+    bot.edit_message_text(chat, status, "已批准，等待导出…")
+        .await?;
+    let approver = q.from.full_name();
+    let id = jobs.spawn(
+        JobKind::Export,
+        request.chat_id,
+        q.from.id.0 as i64,
+        format!("为用户 {} 导出全部索引数据", request.requester),
+        {
+            let bot = bot.clone();
+            move |job| async move {
+                let result = run_export(&bot, &admin, &chats, request, &approver, &job).await;
+                if result.is_err()
+                    && let Err(e) = bot
+                        .edit_message_text(chat, status, "导出失败，请稍后再试。")
+                        .await
+                {
+                    tracing::warn!("Failed to report export failure: {e}");
+                }
+                result
+            }
+        },
+    );
+    bot.edit_message_text(
+        msg.chat.id,
+        msg.id,
+        format!("已批准（任务 #{id}），导出完成后会私聊发送给申请人。"),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Build the archive page by page, then send it to the requester.
+async fn run_export(
+    bot: &Bot,
+    admin: &AdminClient,
+    chats: &ChatStore,
+    request: ExportRequest,
+    approver: &str,
+    job: &JobHandle,
+) -> anyhow::Result<()> {
+    let chat = ChatId(request.chat_id);
+    let status = MessageId(request.status);
+    bot.edit_message_text(chat, status, format!("正在导出…（任务 #{}）", job.id()))
+        .await?;
+
+    let title = match chats.get(request.chat_id).await {
+        Ok(info) => info.and_then(|info| info.title),
+        Err(e) => {
+            tracing::warn!("Failed to load metadata of {}: {e}", request.chat_id);
+            None
+        }
+    };
+
+    let mut archive = Archive::new()?;
+    let mut after = None;
+    let mut last_update = Instant::now();
+    loop {
+        if job.is_cancelled() {
+            bot.edit_message_text(chat, status, "导出已取消。").await?;
+            return Ok(());
+        }
+        let page = admin.export_page(request.chat_id, after).await?;
+        for doc in &page.docs {
+            archive.add(doc)?;
+        }
+        job.set_progress(archive.summary.documents, Some(page.total));
+        if last_update.elapsed() >= PROGRESS_INTERVAL {
+            last_update = Instant::now();
+            let text = format!(
+                "正在导出…（任务 #{}）{}/{}",
+                job.id(),
+                archive.summary.documents,
+                page.total
+            );
+            if let Err(e) = bot.edit_message_text(chat, status, text).await
+                && !e.to_string().contains("message is not modified")
+            {
+                tracing::warn!("Failed to update export progress: {e}");
+            }
+        }
+        after = page.next;
+        if after.is_none() {
+            break;
+        }
+    }
+
+    let documents = archive.summary.documents;
+    let data = archive.finish(&ExportInfo {
+        chat_id: request.chat_id,
+        title: title.as_deref(),
+        requester: request.requester,
+        approver,
+        exported_at: chrono::Utc::now().timestamp(),
+    })?;
+    if data.len() > MAX_UPLOAD_BYTES {
+        bot.edit_message_text(
+            chat,
+            status,
+            format!(
+                "导出文件有 {}，超过 Telegram 50 MB 的上限，无法发送。",
+                format_bytes(data.len() as u64)
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let file_name = format!("chat_{}_export.zip", request.chat_id);
+    let sent = bot
+        .send_document(
+            UserId(request.requester as u64),
+            InputFile::memory(data).file_name(file_name),
+        )
+        .caption(format!("本群索引数据导出，共 {documents} 条记录。"))
+        .await;
+    let text = match sent {
+        Ok(_) => format!("导出完成：共 {documents} 条记录，已私聊发送给申请人。"),
+        Err(e) => {
+            tracing::warn!("Failed to send export to {}: {e}", request.requester);
+            "导出完成，但无法私聊发送文件。请申请人先私聊机器人并发送 /start，再重新申请。"
+                .to_string()
+        }
+    };
+    bot.edit_message_text(chat, status, text).await?;
+    Ok(())
+}
+
+/// Who and what an export is for, shown in its summary.
+struct ExportInfo<'a> {
+    chat_id: i64,
+    title: Option<&'a str>,
+    requester: i64,
+    approver: &'a str,
+    exported_at: i64,
+}
+
+/// Counts shown in `summary.html`.
+#[derive(Default)]
+struct Summary {
+    documents: u64,
+    first: Option<i64>,
+    last: Option<i64>,
+    types: BTreeMap<String, u64>,
+    /// Keyed by user id, or by name for senders without one
+    senders: HashMap<String, (String, u64)>,
+}
+
+/// A zip being written: documents stream into `messages.jsonl` while the
+/// CSV and the summary are collected for the end.
+struct Archive {
+    zip: ZipWriter<Cursor<Vec<u8>>>,
+    csv: String,
+    summary: Summary,
+}
+
+impl Archive {
+    fn new() -> anyhow::Result<Self> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("messages.jsonl", SimpleFileOptions::default())?;
+        let mut csv = CSV_COLUMNS.join(",");
+        csv.push_str("\r\n");
+        Ok(Self {
+            zip,
+            csv,
+            summary: Summary::default(),
+        })
+    }
+
+    fn add(&mut self, doc: &Value) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut self.zip, doc)?;
+        self.zip.write_all(b"\n")?;
+
+        let fields: Vec<String> = CSV_COLUMNS
+            .iter()
+            .map(|column| csv_field(&field(doc, column)))
+            .collect();
+        self.csv.push_str(&fields.join(","));
+        self.csv.push_str("\r\n");
+
+        let summary = &mut self.summary;
+        summary.documents += 1;
+        if let Some(date) = doc["date"].as_i64() {
+            summary.first = Some(summary.first.map_or(date, |d| d.min(date)));
+            summary.last = Some(summary.last.map_or(date, |d| d.max(date)));
+        }
+        *summary.types.entry(field(doc, "message_type")).or_default() += 1;
+        let name = doc["display_name"]
+            .as_str()
+            .or(doc["username"].as_str())
+            .unwrap_or("（无名称）");
+        let key = doc["user_id"]
+            .as_i64()
+            .map_or_else(|| name.to_string(), |id| id.to_string());
+        let sender = summary
+            .senders
+            .entry(key)
+            .or_insert_with(|| (name.to_string(), 0));
+        sender.1 += 1;
+        Ok(())
+    }
+
+    fn finish(mut self, info: &ExportInfo) -> anyhow::Result<Vec<u8>> {
+        let options = SimpleFileOptions::default();
+        self.zip.start_file("messages.csv", options)?;
+        self.zip.write_all(self.csv.as_bytes())?;
+        self.zip.start_file("summary.html", options)?;
+        self.zip
+            .write_all(summary_html(&self.summary, info).as_bytes())?;
+        Ok(self.zip.finish()?.into_inner())
+    }
+}
+
+/// A document field as CSV text: numbers and strings as they are, arrays
+/// joined by spaces, missing fields empty.
+fn field(doc: &Value, name: &str) -> String {
+    match &doc[name] {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().map_or_else(|| item.to_string(), String::from))
+            .collect::<Vec<_>>()
+            .join(" "),
+        other => other.to_string(),
+    }
+}
+
+fn summary_html(summary: &Summary, info: &ExportInfo) -> String {
+    let title = html_escape(info.title.unwrap_or("（无标题）"));
+    let range = match (summary.first, summary.last) {
+        (Some(first), Some(last)) => {
+            format!(
+                "{} ~ {} UTC",
+                format_timestamp(first),
+                format_timestamp(last)
+            )
+        }
+        _ => "无".to_string(),
+    };
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"zh\">\n<head><meta charset=\"utf-8\"><title>{title} 导出</title></head>\n<body>\n\
+         <h1>{title}</h1>\n<table>\n\
+         <tr><th>群组 ID</th><td>{}</td></tr>\n\
+         <tr><th>导出时间</th><td>{} UTC</td></tr>\n\
+         <tr><th>申请人</th><td>用户 {}</td></tr>\n\
+         <tr><th>批准人</th><td>{}</td></tr>\n\
+         <tr><th>记录数（含编辑版本）</th><td>{}</td></tr>\n\
+         <tr><th>时间范围</th><td>{range}</td></tr>\n\
+         </table>\n",
+        info.chat_id,
+        format_timestamp(info.exported_at),
+        info.requester,
+        html_escape(info.approver),
+        summary.documents,
+    );
+
+    html.push_str("<h2>消息类型</h2>\n<table>\n");
+    for (kind, count) in &summary.types {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{count}</td></tr>\n",
+            html_escape(kind)
+        ));
+    }
+    html.push_str("</table>\n");
+
+    let mut senders: Vec<(&String, &(String, u64))> = summary.senders.iter().collect();
+    senders.sort_by(|a, b| b.1 .1.cmp(&a.1 .1).then_with(|| a.0.cmp(b.0)));
+    html.push_str(&format!(
+        "<h2>发送者（共 {}，列出前 {SUMMARY_SENDERS}）</h2>\n<table>\n",
+        senders.len()
+    ));
+    for (key, (name, count)) in senders.into_iter().take(SUMMARY_SENDERS) {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{count}</td></tr>\n",
+            html_escape(name),
+            html_escape(key)
+        ));
+    }
+    html.push_str("</table>\n");
+
+    html.push_str(
+        "<h2>文件</h2>\n<ul>\n\
+         <li>messages.jsonl — 每行一条索引记录，字段与索引中保存的一致</li>\n\
+         <li>messages.csv — 常用字段，UTF-8 编码</li>\n\
+         </ul>\n</body>\n</html>\n",
+    );
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::Read;
+
+    #[test]
+    fn callback_data_round_trips() {
+        let request = ExportRequest {
+            chat_id: -1001234567890,
+            requester: 1234567890,
+            status: 42,
+        };
+        let data = request.callback_data(APPROVE);
+        assert!(data.len() <= 64);
+        assert_eq!(ExportRequest::parse(&data), Some((APPROVE, request)));
+        assert_eq!(ExportRequest::parse("export:ok:1:2"), None);
+    }
+
+    #[test]
+    fn archive_holds_every_format() {
+        let mut archive = Archive::new().unwrap();
+        archive
+            .add(&json!({
+                "message_id": 7,
+                "date": 1_700_000_000,
+                "user_id": 1,
+                "display_name": "Alice",
+                "message_type": "text",
+                "text": "hello, \"world\"",
+                "urls": ["https://a.example", "https://b.example"]
+            }))
+            .unwrap();
+        let info = ExportInfo {
+            chat_id: -100,
+            title: Some("<Group>"),
+            requester: 1,
+            approver: "Owner",
+            exported_at: 1_700_000_100,
+        };
+        let data = archive.finish(&info).unwrap();
+
+        let mut zip = zip::ZipArchive::new(Cursor::new(data)).unwrap();
+        let mut read = |name: &str| {
+            let mut text = String::new();
+            zip.by_name(name)
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            text
+        };
+        let jsonl = read("messages.jsonl");
+        let doc: Value = serde_json::from_str(jsonl.trim_end()).unwrap();
+        assert_eq!(doc["message_id"], 7);
+        let csv = read("messages.csv");
+        assert!(csv.contains(",\"hello, \"\"world\"\"\",,,https://a.example https://b.example\r\n"));
+        let html = read("summary.html");
+        assert!(html.contains("&lt;Group&gt;"));
+        assert!(html.contains("<td>Alice</td><td>1</td><td>1</td>"));
+    }
+}
@@ -0,0 +1,84 @@
+//! `/get <link>`: show the indexed copy of a message in this chat, which
+//! outlives the original if it was deleted.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::util::{format_timestamp, html_escape, parse_message_link, public_message_link};
+use crate::es::chats::ChatStore;
+use crate::es::search::SearchClient;
+
+/// Stored text shown before it is cut off, leaving room for the header
+/// within Telegram's 4096 character limit.
+const MAX_TEXT_CHARS: usize = 3500;
+
+pub async fn handle_get(
+    bot: Bot,
+    msg: Message,
+    link: String,
+    search_client: Arc<SearchClient>,
+    chats: Arc<ChatStore>,
+) -> anyhow::Result<()> {
+    let Some(parsed) = parse_message_link(&link) else {
+        bot.send_message(
+            msg.chat.id,
+            "用法: /get <消息链接>\n\n示例: /get https://t.me/c/1234567890/42",
+        )
+        .await?;
+        return Ok(());
+    };
+    if !parsed.is_in(&msg.chat) {
+        bot.send_message(msg.chat.id, "只能查看本群的消息。")
+            .await?;
+        return Ok(());
+    }
+
+    let Some(stored) = search_client
+        .get_message(msg.chat.id.0, parsed.message_id)
+        .await?
+    else {
+        bot.send_message(msg.chat.id, "索引中没有这条消息。")
+            .await?;
+        return Ok(());
+    };
+
+    let name = stored.display_name.as_deref().map(html_escape);
+    let author = match (stored.user_id, name) {
+        (Some(id), name) => {
+            let name = name.unwrap_or_else(|| format!("User {id}"));
+            format!("<a href=\"tg://user?id={id}\">{name}</a>")
+        }
+        (None, Some(name)) => name,
+        (None, None) => "未知".to_string(),
+    };
+    let mut text = format!(
+        "<b>消息 {}</b>（{}）\n时间：{}\n发送者：{author}\n",
+        stored.message_id,
+        stored.message_type,
+        format_timestamp(stored.date)
+    );
+    if let Some(ref name) = stored.file_name {
+        text.push_str(&format!("文件：{}\n", html_escape(name)));
+    }
+    if !stored.text.is_empty() {
+        let mut body: String = stored.text.chars().take(MAX_TEXT_CHARS).collect();
+        if body.len() < stored.text.len() {
+            body.push('…');
+        }
+        text.push_str(&format!("\n{}\n", html_escape(&body)));
+    }
+    text.push_str(&format!(
+        "\n<a href=\"{}\">原消息链接</a>",
+        public_message_link(
+            chats.username(stored.chat_id).as_deref(),
+            stored.chat_id,
+            stored.message_id
+        )
+    ));
+
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,146 @@
+//! `/growth [period]`: member count of the chat over time next to its message
+//! volume, from the snapshots a background task takes of active chats.
+
+use std::sync::Arc;
+use std::time::Duration;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::shards::Shards;
+use crate::bot::util::{format_timestamp, parse_period};
+use crate::config::ChatsConfig;
+use crate::es::analytics::AnalyticsClient;
+use crate::es::chats::{ChatStore, MemberCount};
+
+/// Period shown when none is given.
+const DEFAULT_PERIOD_SECS: i64 = 30 * 86400;
+/// Periods longer than this are shown by week instead of by day.
+const MAX_DAILY_PERIOD_SECS: i64 = 60 * 86400;
+/// Chats without messages for this long are no longer snapshotted.
+const ACTIVE_WITHIN_SECS: i64 = 30 * 86400;
+
+/// Handle `/growth [period]` (admins).
+pub async fn handle_growth(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    analytics: Arc<AnalyticsClient>,
+    chats: Arc<ChatStore>,
+) -> anyhow::Result<()> {
+    let args = args.trim();
+    let period = if args.is_empty() {
+        DEFAULT_PERIOD_SECS
+    } else {
+        match parse_period(args) {
+            Some(secs) => secs,
+            None => {
+                bot.send_message(msg.chat.id, "用法: /growth [时间段]，例如 /growth 90d")
+                    .await?;
+                return Ok(());
+            }
+        }
+    };
+
+    let bucket_secs = if period > MAX_DAILY_PERIOD_SECS {
+        7 * 86400
+    } else {
+        86400
+    };
+    let since = chrono::Utc::now().timestamp() - period;
+    let volume = analytics
+        .message_volume(msg.chat.id.0, since, bucket_secs)
+        .await?;
+    let member_counts = chats
+        .get(msg.chat.id.0)
+        .await?
+        .map(|info| info.member_counts)
+        .unwrap_or_default();
+
+    bot.send_message(
+        msg.chat.id,
+        format_growth(&volume, &member_counts, bucket_secs),
+    )
+    .parse_mode(ParseMode::Html)
+    .await?;
+    Ok(())
+}
+
+fn format_growth(volume: &[(i64, u64)], member_counts: &[MemberCount], bucket_secs: i64) -> String {
+    // The latest snapshot taken before each bucket ends
+    let members: Vec<Option<u64>> = volume
+        .iter()
+        .map(|&(start, _)| {
+            member_counts
+                .iter()
+                .rev()
+                .find(|c| c.date < start + bucket_secs)
+                .map(|c| c.count)
+        })
+        .collect();
+
+    let mut text = "<b>群组增长</b>\n".to_string();
+    let known: Vec<u64> = members.iter().flatten().copied().collect();
+    match (known.first(), known.last()) {
+        (Some(&first), Some(&last)) => {
+            text.push_str(&format!(
+                "成员 {first} → {last}（{:+}）\n",
+                last as i64 - first as i64
+            ));
+        }
+        _ => text.push_str("还没有成员数记录，会定期自动记录。\n"),
+    }
+    let total: u64 = volume.iter().map(|&(_, count)| count).sum();
+    text.push_str(&format!("消息共 {total} 条\n<pre>"));
+    for (&(start, count), members) in volume.iter().zip(&members) {
+        let date = format_timestamp(start);
+        text.push_str(date.get(5..10).unwrap_or_default());
+        match members {
+            Some(members) => text.push_str(&format!(" 成员 {members:>6} 消息 {count}\n")),
+            None => text.push_str(&format!(" 成员      — 消息 {count}\n")),
+        }
+    }
+    text.push_str("</pre>");
+    text
+}
+
+/// Spawn the background task that snapshots the member count of every group
+/// and channel with recent messages.
+pub fn spawn_member_count_recorder(
+    shards: Arc<Shards>,
+    chats: Arc<ChatStore>,
+    config: ChatsConfig,
+) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(config.member_count_interval_secs));
+        loop {
+            interval.tick().await;
+            if let Err(e) = record_member_counts(&shards, &chats).await {
+                tracing::warn!("Member count snapshot failed: {e}");
+            }
+        }
+    });
+}
+
+async fn record_member_counts(shards: &Shards, chats: &ChatStore) -> anyhow::Result<()> {
+    let now = chrono::Utc::now().timestamp();
+    for chat in chats.all().await? {
+        let active = chat
+            .last_message_date
+            .is_some_and(|last| now - last < ACTIVE_WITHIN_SECS);
+        if !active || chat.chat_type == "private" {
+            continue;
+        }
+        // Fails when the bot was removed from the chat, which shouldn't stop the others
+        let chat_id = ChatId(chat.chat_id);
+        match shards.bot_for(chat_id).get_chat_member_count(chat_id).await {
+            Ok(count) => {
+                chats
+                    .record_member_count(chat.chat_id, u64::from(count), now)
+                    .await?
+            }
+            Err(e) => tracing::warn!("Failed to get member count of {}: {e}", chat.chat_id),
+        }
+    }
+    Ok(())
+}
@@ -1,87 +1,585 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
-use teloxide::dispatching::UpdateFilterExt;
+use teloxide::dispatching::{dialogue, UpdateFilterExt};
 use teloxide::prelude::*;
-use teloxide::update_listeners::webhooks;
-use teloxide::utils::command::BotCommands;
+use teloxide::types::ReplyParameters;
+use teloxide::update_listeners::{webhooks, Polling};
 
+use crate::bot::aka::handle_aka;
+use crate::bot::alerts::{handle_alert, spawn_alert_monitor};
+use crate::bot::audit::handle_audit;
+use crate::bot::bookmark_transfer::{handle_export_bookmarks, handle_import_bookmarks};
+use crate::bot::bookmarks::{handle_bookmarks, handle_bookmarks_callback, is_bookmarks_callback};
+use crate::bot::bridge::handle_bridge;
 use crate::bot::callback::{handle_callback, handle_search};
-use crate::bot::commands::Command;
+use crate::bot::chats::handle_chats;
+use crate::bot::commands::{Audience, Command};
+use crate::bot::compare::handle_compare;
+use crate::bot::config_report::handle_config;
+use crate::bot::crossposts::handle_crossposts;
+use crate::bot::dedup::UpdateDedup;
+use crate::bot::digest::{handle_digest, spawn_digest_scheduler};
+use crate::bot::digest_feedback::{
+    handle_digest_feedback, handle_digest_feedback_callback, is_digest_feedback_callback,
+};
+use crate::bot::entities::handle_entities;
+use crate::bot::explain::handle_explain;
+use crate::bot::export::{handle_export_callback, handle_export_chat, is_export_callback};
+use crate::bot::get::handle_get;
+use crate::bot::growth::{handle_growth, spawn_member_count_recorder};
+use crate::bot::help::{handle_help, handle_help_callback, is_help_callback};
+use crate::bot::history::handle_history;
+use crate::bot::ignore::{handle_ignore, handle_ignored};
+use crate::bot::inline::handle_inline_query;
+use crate::bot::jobs::{handle_job, handle_jobs};
+use crate::bot::links::{handle_links, handle_links_callback, is_links_callback};
+use crate::bot::menu::register_commands;
 use crate::bot::message_recorder::record_message;
-use crate::config::WebhookConfig;
+use crate::bot::mood::handle_moodtrend;
+use crate::bot::permissions::{denial_text, has_access};
+use crate::bot::personalize::handle_personalize;
+use crate::bot::pins::handle_pins;
+use crate::bot::pipeline::Pipeline;
+use crate::bot::preview::handle_preview;
+use crate::bot::purge::{
+    handle_forget_user, handle_purge_before, handle_purge_callback, is_purge_callback,
+};
+use crate::bot::quiet::handle_quiet;
+use crate::bot::ratelimit::{CallbackThrottle, RateLimiter};
+use crate::bot::selftest::handle_selftest;
+use crate::bot::senders::handle_senders;
+use crate::bot::session::SessionStore;
+use crate::bot::shards::{ShardId, Shards};
+use crate::bot::spam::handle_spam_report;
+use crate::bot::stats::{handle_queue, handle_stats, handle_storage};
+use crate::bot::syntax::{handle_language, handle_syntax};
+use crate::bot::telegram::TelegramSender;
+use crate::bot::throwback::{handle_throwback, spawn_throwback_scheduler};
+use crate::bot::wizard::{
+    handle_wizard_callback, handle_wizard_message, is_wizard_callback, start_wizard, WizardState,
+    WizardStorage,
+};
+use crate::config::{AppConfig, OutputFormat, PendingUpdates};
+use crate::error::AppError;
+use crate::es::admin::AdminClient;
+use crate::es::alerts::AlertStore;
+use crate::es::analytics::AnalyticsClient;
+use crate::es::audit::AuditLog;
+use crate::es::bookmarks::BookmarkStore;
+use crate::es::chats::ChatStore;
+use crate::es::feedback::DigestFeedbackStore;
 use crate::es::indexer::BatchIndexer;
 use crate::es::search::SearchClient;
+use crate::es::settings::SettingsStore;
+use crate::es::users::UserStore;
+use crate::jobs::JobQueue;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run_bot(
-    bot: Bot,
+    shards: Arc<Shards>,
+    config: Arc<AppConfig>,
     indexer: Arc<BatchIndexer>,
     search_client: Arc<SearchClient>,
-    default_page_size: usize,
-    webhook_config: WebhookConfig,
+    analytics: Arc<AnalyticsClient>,
+    audit: Arc<AuditLog>,
+    alerts: Arc<AlertStore>,
+    settings: Arc<SettingsStore>,
+    chats: Arc<ChatStore>,
+    users: Arc<UserStore>,
+    bookmarks: Arc<BookmarkStore>,
+    feedback: Arc<DigestFeedbackStore>,
+    admin: Arc<AdminClient>,
 ) -> anyhow::Result<()> {
+    let default_page_size = config.search.default_page_size;
+    let output_format = config.search.parse_mode;
+    let limiter = Arc::new(RateLimiter::new(&config.ratelimit));
+    let throttle = Arc::new(CallbackThrottle::new(config.ratelimit.callback_interval_ms));
+    let wizard_storage = WizardStorage::new();
+    let sessions = Arc::new(SessionStore::new(config.search.history_size));
+    let jobs = Arc::new(JobQueue::new());
+    let pending_updates = config.telegram.pending_updates;
+    let started_at = chrono::Utc::now();
+    let pipeline = Arc::new(Pipeline::from_config(&config)?);
+
+    for bot in shards.bots() {
+        register_commands(bot, &config).await;
+    }
+
+    let sender = Arc::new(TelegramSender::new(shards.clone()));
+    if config.alerts.enabled {
+        spawn_alert_monitor(
+            shards.clone(),
+            sender.clone(),
+            alerts.clone(),
+            analytics.clone(),
+            config.alerts.clone(),
+        );
+    }
+    if config.digest.enabled {
+        spawn_digest_scheduler(
+            shards.clone(),
+            sender.clone(),
+            settings.clone(),
+            analytics.clone(),
+            config.digest.clone(),
+        );
+        spawn_throwback_scheduler(
+            sender.clone(),
+            settings.clone(),
+            analytics.clone(),
+            config.digest.clone(),
+        );
+    }
+
+    if config.chats.member_count_interval_secs > 0 {
+        spawn_member_count_recorder(shards.clone(), chats.clone(), config.chats.clone());
+    }
+
     let handler = dptree::entry()
-        .branch(Update::filter_callback_query().endpoint(
-            |bot: Bot,
-             q: CallbackQuery,
-             search_client: Arc<SearchClient>,
-             default_page_size: usize| async move {
-                handle_callback(bot, q, search_client, default_page_size).await
-            },
-        ))
+        .filter(|update: Update, dedup: Arc<UpdateDedup>| dedup.first_seen(&update))
+        // A group is handled only by the bot it is assigned to
+        .filter(|update: Update, shards: Arc<Shards>, shard: ShardId| {
+            update.chat().is_none_or(|chat| shards.serves(shard, chat))
+        })
+        .branch(
+            Update::filter_callback_query()
+                .filter_async(check_callback_flood)
+                .branch(
+                    dptree::filter(|q: CallbackQuery| is_wizard_callback(&q))
+                        .chain(dialogue::enter::<
+                            CallbackQuery,
+                            WizardStorage,
+                            WizardState,
+                            _,
+                        >())
+                        .endpoint(handle_wizard_callback),
+                )
+                .branch(
+                    dptree::filter(|q: CallbackQuery| is_help_callback(&q))
+                        .endpoint(handle_help_callback),
+                )
+                .branch(
+                    dptree::filter(|q: CallbackQuery| is_links_callback(&q))
+                        .endpoint(handle_links_callback),
+                )
+                .branch(
+                    dptree::filter(|q: CallbackQuery| is_bookmarks_callback(&q))
+                        .endpoint(handle_bookmarks_callback),
+                )
+                .branch(
+                    dptree::filter(|q: CallbackQuery| is_purge_callback(&q))
+                        .endpoint(handle_purge_callback),
+                )
+                .branch(
+                    dptree::filter(|q: CallbackQuery| is_export_callback(&q))
+                        .endpoint(handle_export_callback),
+                )
+                .branch(
+                    dptree::filter(|q: CallbackQuery| is_digest_feedback_callback(&q))
+                        .endpoint(handle_digest_feedback_callback),
+                )
+                .endpoint(
+                    |bot: Bot,
+                     q: CallbackQuery,
+                     search_client: Arc<SearchClient>,
+                     audit: Arc<AuditLog>,
+                     sessions: Arc<SessionStore>,
+                     settings: Arc<SettingsStore>,
+                     bookmarks: Arc<BookmarkStore>,
+                     default_page_size: usize,
+                     output_format: OutputFormat| async move {
+                        handle_callback(
+                            bot,
+                            q,
+                            search_client,
+                            audit,
+                            sessions,
+                            settings,
+                            bookmarks,
+                            default_page_size,
+                            output_format,
+                        )
+                        .await
+                        .map_err(anyhow::Error::from)
+                    },
+                ),
+        )
+        .branch(Update::filter_inline_query().endpoint(handle_inline_query))
         .branch(
             Update::filter_message()
+                // In index_only mode, commands queued while offline fall through
+                // to the recorder, which skips them like any other command.
+                .filter(move |msg: Message| {
+                    pending_updates != PendingUpdates::IndexOnly || msg.date >= started_at
+                })
                 .filter_command::<Command>()
+                .filter_async(check_permission)
+                .filter_async(check_rate_limit)
+                // The main endpoint below is at dptree's injection limit
+                .branch(dptree::case![Command::Queue].endpoint(handle_queue))
+                .branch(dptree::case![Command::Get(link)].endpoint(handle_get))
+                .branch(dptree::case![Command::History(link)].endpoint(handle_history))
+                .branch(dptree::case![Command::Chats(args)].endpoint(handle_chats))
+                .branch(dptree::case![Command::Aka(args)].endpoint(handle_aka))
+                .branch(dptree::case![Command::Jobs].endpoint(handle_jobs))
+                .branch(dptree::case![Command::Job(args)].endpoint(handle_job))
+                .branch(dptree::case![Command::Bookmarks].endpoint(handle_bookmarks))
+                .branch(
+                    dptree::case![Command::ExportBookmarks(args)].endpoint(handle_export_bookmarks),
+                )
+                .branch(dptree::case![Command::ImportBookmarks].endpoint(handle_import_bookmarks))
+                .branch(dptree::case![Command::Growth(args)].endpoint(handle_growth))
+                .branch(
+                    dptree::case![Command::DigestFeedback(args)].endpoint(handle_digest_feedback),
+                )
+                .branch(dptree::case![Command::CrossPosts(args)].endpoint(handle_crossposts))
                 .endpoint(
                     |bot: Bot,
                      msg: Message,
                      cmd: Command,
                      search_client: Arc<SearchClient>,
-                     _indexer: Arc<BatchIndexer>,
-                     default_page_size: usize| async move {
+                     analytics: Arc<AnalyticsClient>,
+                     audit: Arc<AuditLog>,
+                     alerts: Arc<AlertStore>,
+                     settings: Arc<SettingsStore>,
+                     config: Arc<AppConfig>,
+                     wizard_storage: Arc<WizardStorage>,
+                     sessions: Arc<SessionStore>,
+                     admin: Arc<AdminClient>| async move {
+                        let default_page_size = config.search.default_page_size;
                         match cmd {
                             Command::Search(query) => {
-                                handle_search(bot, msg, query, search_client, default_page_size)
-                                    .await?;
+                                handle_search(
+                                    bot,
+                                    msg,
+                                    query,
+                                    search_client,
+                                    audit,
+                                    sessions,
+                                    settings,
+                                    default_page_size,
+                                    config.search.parse_mode,
+                                )
+                                .await?;
+                            }
+                            Command::FindWizard => {
+                                start_wizard(bot, msg, wizard_storage).await?;
                             }
                             Command::Help => {
-                                bot.send_message(msg.chat.id, Command::descriptions().to_string())
+                                handle_help(bot, msg).await?;
+                            }
+                            Command::Syntax(args) => {
+                                handle_syntax(bot, msg, args, settings).await?;
+                            }
+                            Command::Pins(keyword) => {
+                                handle_pins(bot, msg, keyword, search_client).await?;
+                            }
+                            Command::Links(args) => {
+                                handle_links(bot, msg, args, analytics).await?;
+                            }
+                            Command::Compare(args) => {
+                                handle_compare(bot, msg, args, analytics).await?;
+                            }
+                            Command::Entities(args) => {
+                                handle_entities(bot, msg, args, analytics).await?;
+                            }
+                            Command::Stats(args) => {
+                                handle_stats(bot, msg, args, analytics).await?;
+                            }
+                            Command::Storage => {
+                                handle_storage(bot, msg, analytics).await?;
+                            }
+                            Command::Audit(args) => {
+                                handle_audit(bot, msg, args, audit).await?;
+                            }
+                            Command::Explain(query) => {
+                                handle_explain(bot, msg, query, search_client, default_page_size)
                                     .await?;
                             }
+                            // Handled by their own branches
+                            Command::Queue
+                            | Command::Get(_)
+                            | Command::History(_)
+                            | Command::Chats(_)
+                            | Command::Aka(_)
+                            | Command::Jobs
+                            | Command::Job(_)
+                            | Command::Bookmarks
+                            | Command::ExportBookmarks(_)
+                            | Command::ImportBookmarks
+                            | Command::Growth(_)
+                            | Command::DigestFeedback(_)
+                            | Command::CrossPosts(_) => {}
+                            Command::SelfTest => {
+                                handle_selftest(bot, msg, admin).await?;
+                            }
+                            Command::Config(args) => {
+                                handle_config(bot, msg, args, config).await?;
+                            }
+                            Command::Alert(args) => {
+                                handle_alert(bot, msg, args, alerts, config).await?;
+                            }
+                            Command::Ignore(args) => {
+                                handle_ignore(bot, msg, args, true, settings).await?;
+                            }
+                            Command::Unignore(args) => {
+                                handle_ignore(bot, msg, args, false, settings).await?;
+                            }
+                            Command::Ignored => {
+                                handle_ignored(bot, msg, settings).await?;
+                            }
+                            Command::Bridge(args) => {
+                                handle_bridge(bot, msg, args, settings).await?;
+                            }
+                            Command::Digest(args) => {
+                                handle_digest(bot, msg, args, settings).await?;
+                            }
+                            Command::Throwback(args) => {
+                                handle_throwback(bot, msg, args, settings, analytics).await?;
+                            }
+                            Command::Preview(args) => {
+                                handle_preview(bot, msg, args, settings).await?;
+                            }
+                            Command::Senders(args) => {
+                                let ignore_bots = config.recorder.ignore_bots;
+                                handle_senders(bot, msg, args, settings, ignore_bots).await?;
+                            }
+                            Command::Personalize(args) => {
+                                handle_personalize(bot, msg, args, settings).await?;
+                            }
+                            Command::Language(args) => {
+                                handle_language(bot, msg, args, settings).await?;
+                            }
+                            Command::MoodTrend(args) => {
+                                handle_moodtrend(bot, msg, args, analytics).await?;
+                            }
+                            Command::Quiet(args) => {
+                                handle_quiet(bot, msg, args, analytics).await?;
+                            }
+                            Command::SpamReport(args) => {
+                                handle_spam_report(bot, msg, args, analytics).await?;
+                            }
+                            Command::PurgeBefore(args) => {
+                                handle_purge_before(bot, msg, args).await?;
+                            }
+                            Command::ForgetUser(args) => {
+                                handle_forget_user(bot, msg, args, admin).await?;
+                            }
+                            Command::ExportChat => {
+                                handle_export_chat(bot, msg, config).await?;
+                            }
                         }
                         Ok::<(), anyhow::Error>(())
                     },
                 ),
         )
+        .branch(
+            Update::filter_message()
+                .chain(dialogue::enter::<Message, WizardStorage, WizardState, _>())
+                .filter(|msg: Message, state: WizardState| state.awaits_message_from(&msg))
+                .endpoint(handle_wizard_message),
+        )
         .branch(Update::filter_message().endpoint(
-            |msg: Message, indexer: Arc<BatchIndexer>| async move {
-                record_message(msg, indexer).await
+            |msg: Message,
+             indexer: Arc<BatchIndexer>,
+             settings: Arc<SettingsStore>,
+             chats: Arc<ChatStore>,
+             users: Arc<UserStore>,
+             config: Arc<AppConfig>,
+             pipeline: Arc<Pipeline>| async move {
+                let recorder = &config.recorder;
+                record_message(msg, indexer, settings, chats, users, recorder, &pipeline).await
             },
-        ));
+        ))
+        // Edits become revisions next to the original; otherwise they're ignored
+        .branch(
+            Update::filter_edited_message()
+                .filter(|config: Arc<AppConfig>| config.indexer.edit_versions)
+                .endpoint(
+                    |msg: Message,
+                     indexer: Arc<BatchIndexer>,
+                     settings: Arc<SettingsStore>,
+                     chats: Arc<ChatStore>,
+                     users: Arc<UserStore>,
+                     config: Arc<AppConfig>,
+                     pipeline: Arc<Pipeline>| async move {
+                        let recorder = &config.recorder;
+                        record_message(msg, indexer, settings, chats, users, recorder, &pipeline)
+                            .await
+                    },
+                ),
+        );
 
-    let mut dispatcher = Dispatcher::builder(bot.clone(), handler)
-        .dependencies(dptree::deps![indexer, search_client, default_page_size])
-        .default_handler(|_| async {})
-        .error_handler(LoggingErrorHandler::new())
-        .enable_ctrlc_handler()
-        .build();
+    // One dispatcher per bot, sharing everything but the update ids they've seen
+    let mut dispatchers = Vec::new();
+    for (i, bot) in shards.bots().iter().enumerate() {
+        let dedup = Arc::new(UpdateDedup::new(config.telegram.dedup_window_secs));
+        let dispatcher = Dispatcher::builder(bot.clone(), handler.clone())
+            .dependencies(dptree::deps![
+                indexer.clone(),
+                search_client.clone(),
+                analytics.clone(),
+                audit.clone(),
+                alerts.clone(),
+                settings.clone(),
+                chats.clone(),
+                users.clone(),
+                bookmarks.clone(),
+                feedback.clone(),
+                admin.clone(),
+                limiter.clone(),
+                throttle.clone(),
+                wizard_storage.clone(),
+                sessions.clone(),
+                jobs.clone(),
+                dedup,
+                pipeline.clone(),
+                shards.clone(),
+                ShardId(i),
+                config.clone(),
+                default_page_size,
+                output_format
+            ])
+            .default_handler(|_| async {})
+            .error_handler(Arc::new(report_error))
+            .enable_ctrlc_handler()
+            .build();
+        dispatchers.push(dispatcher);
+    }
 
+    let webhook_config = &config.webhook;
     if webhook_config.is_enabled() {
         let addr: SocketAddr =
             format!("{}:{}", webhook_config.listen_addr, webhook_config.port).parse()?;
         let webhook_url: url::Url = webhook_config.url.parse()?;
-        let listener = webhooks::axum(bot, webhooks::Options::new(addr, webhook_url))
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to create webhook listener: {e}"))?;
+        // All bots share the listener, each on its own path
+        let mut app = axum::Router::new();
+        let mut stop_flags = Vec::new();
+        let mut runs = Vec::new();
+        for (i, (bot, mut dispatcher)) in shards.bots().iter().zip(dispatchers).enumerate() {
+            let mut options = webhooks::Options::new(addr, shard_webhook_url(&webhook_url, i));
+            if pending_updates == PendingUpdates::Drop {
+                options = options.drop_pending_updates();
+            }
+            let (listener, stop_flag, router) = webhooks::axum_to_router(bot.clone(), options)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to create webhook listener: {e}"))?;
+            app = app.merge(router);
+            stop_flags.push(stop_flag);
+            runs.push(async move {
+                dispatcher
+                    .dispatch_with_listener(
+                        listener,
+                        LoggingErrorHandler::with_custom_text("Webhook listener error"),
+                    )
+                    .await
+            });
+        }
+        let tcp_listener = tokio::net::TcpListener::bind(addr).await?;
         tracing::info!("Webhook listener bound to {addr}");
-        dispatcher
-            .dispatch_with_listener(
-                listener,
-                LoggingErrorHandler::with_custom_text("Webhook listener error"),
-            )
-            .await;
+        tokio::spawn(async move {
+            let stopped = futures::future::join_all(stop_flags);
+            if let Err(e) = axum::serve(tcp_listener, app)
+                .with_graceful_shutdown(async move {
+                    stopped.await;
+                })
+                .await
+            {
+                tracing::error!("Webhook server failed: {e}");
+            }
+        });
+        futures::future::join_all(runs).await;
     } else {
-        dispatcher.dispatch().await;
+        let mut runs = Vec::new();
+        for (bot, mut dispatcher) in shards.bots().iter().zip(dispatchers) {
+            let mut polling = Polling::builder(bot.clone()).delete_webhook().await;
+            if pending_updates == PendingUpdates::Drop {
+                polling = polling.drop_pending_updates();
+            }
+            let listener = polling.build();
+            runs.push(async move {
+                dispatcher
+                    .dispatch_with_listener(
+                        listener,
+                        LoggingErrorHandler::with_custom_text("An error from the update listener"),
+                    )
+                    .await
+            });
+        }
+        futures::future::join_all(runs).await;
     }
 
     Ok(())
 }
+
+/// Webhook URL of a shard's bot: the configured URL for the primary bot, and
+/// `/shard<N>` below it for the others.
+fn shard_webhook_url(base: &url::Url, shard: usize) -> url::Url {
+    let mut url = base.clone();
+    if shard > 0 {
+        let path = format!("{}/shard{shard}", base.path().trim_end_matches('/'));
+        url.set_path(&path);
+    }
+    url
+}
+
+/// Log errors escaping the handlers. Request errors (bad queries, timeouts)
+/// were already answered, so only infrastructure failures are logged as errors.
+async fn report_error(e: anyhow::Error) {
+    match e.downcast_ref::<AppError>() {
+        Some(app) if !app.is_infrastructure() => tracing::debug!("Request failed: {app}"),
+        _ => tracing::error!("Handler failed: {e:?}"),
+    }
+}
+
+/// Reject commands the sender isn't allowed to run before they reach a handler.
+async fn check_permission(bot: Bot, msg: Message, cmd: Command, config: Arc<AppConfig>) -> bool {
+    let audience = cmd.audience();
+    if audience == Audience::Member || has_access(&bot, &config, &msg, audience).await {
+        return true;
+    }
+    if let Err(e) = bot
+        .send_message(msg.chat.id, denial_text(audience))
+        .reply_parameters(ReplyParameters::new(msg.id))
+        .await
+    {
+        tracing::warn!("Failed to send permission denial: {e}");
+    }
+    false
+}
+
+/// Enforce per-command budgets, replying with the remaining cooldown when exceeded.
+async fn check_rate_limit(bot: Bot, msg: Message, cmd: Command, limiter: Arc<RateLimiter>) -> bool {
+    let key = msg.from.as_ref().map_or(msg.chat.id.0, |u| u.id.0 as i64);
+    match limiter.check(cmd.name(), key) {
+        Ok(()) => true,
+        Err(cooldown) => {
+            if let Err(e) = bot
+                .send_message(msg.chat.id, cooldown.message(cmd.name()))
+                .reply_parameters(ReplyParameters::new(msg.id))
+                .await
+            {
+                tracing::warn!("Failed to send cooldown reply: {e}");
+            }
+            false
+        }
+    }
+}
+
+/// Drop rapid repeated keyboard presses, answering them with a toast instead
+/// of running another search.
+async fn check_callback_flood(bot: Bot, q: CallbackQuery, throttle: Arc<CallbackThrottle>) -> bool {
+    if q.data.as_deref() == Some("noop") {
+        return true;
+    }
+    let Some(message) = q.message.as_ref() else {
+        return true;
+    };
+    if throttle.allow(q.from.id.0 as i64, message.chat().id.0, message.id().0) {
+        return true;
+    }
+    if let Err(e) = bot.answer_callback_query(q.id).text("操作太快啦").await {
+        tracing::warn!("Failed to answer throttled callback: {e}");
+    }
+    false
+}
@@ -0,0 +1,418 @@
+//! Interactive `/help`: a category keyboard whose pages are rendered from the
+//! help registry below. New commands add an entry to [`TOPICS`] to show up in
+//! the help; the filter page lists the parser's [`OPERATORS`].
+
+use teloxide::prelude::*;
+use teloxide::types::{
+    InlineKeyboardButton, InlineKeyboardMarkup, MaybeInaccessibleMessage, ParseMode,
+};
+
+use crate::bot::query::OPERATORS;
+use crate::bot::util::html_escape;
+use crate::es::settings::Language;
+
+/// Callback data prefix of help navigation buttons.
+pub const CALLBACK_PREFIX: &str = "help:";
+/// Callback key of the compact syntax reference.
+const CHEAT_SHEET_KEY: &str = "cheat";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HelpCategory {
+    Syntax,
+    Filters,
+    Admin,
+}
+
+impl HelpCategory {
+    pub const ALL: [Self; 3] = [Self::Syntax, Self::Filters, Self::Admin];
+
+    fn key(self) -> &'static str {
+        match self {
+            Self::Syntax => "syntax",
+            Self::Filters => "filters",
+            Self::Admin => "admin",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Syntax => "搜索语法",
+            Self::Filters => "过滤器",
+            Self::Admin => "管理命令",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.key() == key)
+    }
+}
+
+/// One entry of the help registry.
+pub struct HelpTopic {
+    pub category: HelpCategory,
+    /// Example invocation, shown as code
+    pub usage: &'static str,
+    pub summary: &'static str,
+}
+
+pub static TOPICS: &[HelpTopic] = &[
+    HelpTopic {
+        category: HelpCategory::Syntax,
+        usage: "/s 关键词",
+        summary: "全文搜索本群消息（也可用 /search）",
+    },
+    HelpTopic {
+        category: HelpCategory::Syntax,
+        usage: "回复某人 + /s 关键词",
+        summary: "只搜索被回复者的消息",
+    },
+    HelpTopic {
+        category: HelpCategory::Syntax,
+        usage: "/s",
+        summary: "不带参数时显示用法和最近的搜索",
+    },
+    HelpTopic {
+        category: HelpCategory::Syntax,
+        usage: "/syntax [zh|en]",
+        summary: "完整的搜索语法和所有过滤器，默认使用本群设置的语言",
+    },
+    HelpTopic {
+        category: HelpCategory::Syntax,
+        usage: "/findwizard",
+        summary: "分步选择关键词、发送者、时间和类型",
+    },
+    HelpTopic {
+        category: HelpCategory::Syntax,
+        usage: "/aka @用户名",
+        summary: "查看成员在本群用过的用户名和名字，按曾用名也能找到（也可回复其消息）",
+    },
+    HelpTopic {
+        category: HelpCategory::Syntax,
+        usage: "/pins [关键词]",
+        summary: "查看或搜索历史置顶消息",
+    },
+    HelpTopic {
+        category: HelpCategory::Syntax,
+        usage: "/get 消息链接",
+        summary: "查看索引中保存的消息内容，原消息被删除也能看到",
+    },
+    HelpTopic {
+        category: HelpCategory::Syntax,
+        usage: "/history 消息链接",
+        summary: "查看消息被编辑前的各个版本（需开启编辑历史）",
+    },
+    HelpTopic {
+        category: HelpCategory::Syntax,
+        usage: "/bookmarks",
+        summary: "查看用搜索结果的「收藏」按钮保存的消息，可逐条取消",
+    },
+    HelpTopic {
+        category: HelpCategory::Syntax,
+        usage: "/exportbookmarks [json|csv]",
+        summary: "把所有收藏导出为文件私聊发送给你",
+    },
+    HelpTopic {
+        category: HelpCategory::Syntax,
+        usage: "/importbookmarks",
+        summary: "回复导出的文件发送，把其中的收藏导入",
+    },
+    HelpTopic {
+        category: HelpCategory::Syntax,
+        usage: "/links [30d]",
+        summary: "分享最多的网站和各自最近的链接",
+    },
+    HelpTopic {
+        category: HelpCategory::Syntax,
+        usage: "/compare rust vs go [30d]",
+        summary: "对比几个关键词在一段时间内的出现次数和趋势",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/stats",
+        summary: "本群索引统计",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/entities 30d",
+        summary: "提及最多的人物、组织和地点",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/stats replies",
+        summary: "谁最常回复谁、回复最多的消息和平均回复间隔",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/storage",
+        summary: "本群索引存储占用（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/alert add|del|list 关键词",
+        summary: "关键词频率异常提醒（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/ignore @用户名",
+        summary: "不再索引该用户的消息，也可回复其消息使用；/unignore 恢复（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/ignored",
+        summary: "查看本群不索引的用户",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/bridge add @桥接机器人 正则",
+        summary: "从 IRC/Matrix 桥接消息中识别原作者（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/digest daily|weekly|off [pin]",
+        summary: "定期发送本群摘要，加 pin 自动置顶最新一期（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/throwback on|off|now",
+        summary: "每周发送一年前今天的热门消息（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/preview auto|off|top",
+        summary: "搜索结果的链接预览：自动、关闭或预览第一条结果（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/senders [humans|bots on|off] [allow ID]",
+        summary: "本群索引成员、机器人或指定机器人的消息（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/personalize on|off",
+        summary: "搜索结果优先显示与搜索者互相回复最多的成员的消息（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/language zh|en",
+        summary: "设置 /syntax 等参考文档默认使用的语言（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/moodtrend 30d",
+        summary: "每天的平均情绪和毒性，以及高毒性消息数（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/growth 90d",
+        summary: "成员数随时间的变化和同期消息量（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/quiet 60d",
+        summary: "曾经活跃、但这段时间没有发言的成员（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/spamreport [7d]",
+        summary: "第一条消息带链接、只发转发或短时间内重复发言的账号，附资料页和消息链接（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/purge_before 2023-01-01",
+        summary: "删除本群该日期之前的所有索引消息，需确认（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/forgetuser @用户名",
+        summary: "删除该用户在本群的所有索引消息，也可回复其消息使用，需确认（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/exportchat",
+        summary: "所有者批准后，私聊发送本群全部索引数据的压缩包（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/jobs",
+        summary: "本群正在进行和最近完成的导出、删除任务（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/job 3 status|cancel",
+        summary: "查看任务的进度，或取消排队中和运行中的任务（管理员）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/audit [chat] [7d]",
+        summary: "搜索审计记录（所有者）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/explain 搜索语句",
+        summary: "查看查询语句、分词和评分细节（所有者）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/queue",
+        summary: "索引队列深度、最近写入耗时、5 分钟错误率和积压的待重放消息（所有者）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/selftest",
+        summary: "写入、搜索并删除一条测试消息，报告每步耗时（所有者）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/config [all]",
+        summary: "生效的配置、每项的来源和可疑组合警告（所有者）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/chats [群组 ID|here]",
+        summary: "已收录的群组、消息时间范围和群组曾用名（所有者）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/digestfeedback [30d]",
+        summary: "成员在摘要下的 👍/👎 评价，按周期和群组汇总（所有者）",
+    },
+    HelpTopic {
+        category: HelpCategory::Admin,
+        usage: "/crossposts [7d] [3]",
+        summary: "在多个群组中发布过的相同或几乎相同的消息，用于发现群发广告（所有者）",
+    },
+];
+
+pub fn is_help_callback(q: &CallbackQuery) -> bool {
+    q.data
+        .as_deref()
+        .is_some_and(|d| d.starts_with(CALLBACK_PREFIX))
+}
+
+/// Handle `/help`: show the overview with the category keyboard.
+pub async fn handle_help(bot: Bot, msg: Message) -> anyhow::Result<()> {
+    bot.send_message(msg.chat.id, overview())
+        .parse_mode(ParseMode::Html)
+        .reply_markup(keyboard(None))
+        .await?;
+    Ok(())
+}
+
+/// Switch the help message to the chosen category page.
+pub async fn handle_help_callback(bot: Bot, q: CallbackQuery) -> anyhow::Result<()> {
+    bot.answer_callback_query(q.id.clone()).await?;
+    let Some(MaybeInaccessibleMessage::Regular(msg)) = q.message.as_ref() else {
+        return Ok(());
+    };
+
+    let key = q
+        .data
+        .as_deref()
+        .and_then(|d| d.strip_prefix(CALLBACK_PREFIX))
+        .unwrap_or_default();
+    let (text, markup) = if key == CHEAT_SHEET_KEY {
+        let more = InlineKeyboardButton::callback("更多帮助", format!("{CALLBACK_PREFIX}home"));
+        (cheat_sheet(), InlineKeyboardMarkup::new(vec![vec![more]]))
+    } else {
+        let category = HelpCategory::from_key(key);
+        (
+            category.map_or_else(overview, render_page),
+            keyboard(category),
+        )
+    };
+
+    match bot
+        .edit_message_text(msg.chat.id, msg.id, text)
+        .parse_mode(ParseMode::Html)
+        .reply_markup(markup)
+        .await
+    {
+        Ok(_) => {}
+        Err(e) if e.to_string().contains("message is not modified") => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+/// Usage and summary of every entry of `category`.
+fn entries(category: HelpCategory) -> Vec<(String, String)> {
+    if category == HelpCategory::Filters {
+        return OPERATORS
+            .iter()
+            .map(|op| {
+                let mut summary = op.summary(Language::Zh).to_string();
+                if !op.values.is_empty() {
+                    summary = format!("{summary}：{}", op.values.join(" "));
+                }
+                (format!("{}{}", op.prefix, op.example), summary)
+            })
+            .collect();
+    }
+    TOPICS
+        .iter()
+        .filter(|t| t.category == category)
+        .map(|t| (t.usage.to_string(), t.summary.to_string()))
+        .collect()
+}
+
+/// Render every registered topic of `category`.
+pub fn render_page(category: HelpCategory) -> String {
+    let mut text = format!("<b>{}</b>\n\n", category.label());
+    for (usage, summary) in entries(category) {
+        text.push_str(&format!(
+            "<code>{}</code>\n{}\n\n",
+            html_escape(&usage),
+            html_escape(&summary)
+        ));
+    }
+    text
+}
+
+/// Button that turns its message into the compact syntax reference.
+pub fn cheat_sheet_button() -> InlineKeyboardButton {
+    InlineKeyboardButton::callback("语法帮助", format!("{CALLBACK_PREFIX}{CHEAT_SHEET_KEY}"))
+}
+
+/// One line per search syntax and filter topic.
+fn cheat_sheet() -> String {
+    let mut text = "<b>搜索语法速查</b>\n".to_string();
+    for category in [HelpCategory::Syntax, HelpCategory::Filters] {
+        text.push_str(&format!("\n<b>{}</b>\n", category.label()));
+        for (usage, summary) in entries(category) {
+            text.push_str(&format!(
+                "<code>{}</code> — {}\n",
+                html_escape(&usage),
+                html_escape(&summary)
+            ));
+        }
+    }
+    text
+}
+
+fn overview() -> String {
+    "<b>群消息搜索机器人</b>\n\n\
+     把我加入群组后，我会索引群里的消息，之后用 <code>/s 关键词</code> 搜索。\n\n\
+     选择下面的分类查看详细说明："
+        .to_string()
+}
+
+fn keyboard(current: Option<HelpCategory>) -> InlineKeyboardMarkup {
+    let mut row: Vec<InlineKeyboardButton> = HelpCategory::ALL
+        .into_iter()
+        .map(|category| {
+            let label = if current == Some(category) {
+                format!("✓ {}", category.label())
+            } else {
+                category.label().to_string()
+            };
+            InlineKeyboardButton::callback(label, format!("{CALLBACK_PREFIX}{}", category.key()))
+        })
+        .collect();
+    if current.is_some() {
+        row.push(InlineKeyboardButton::callback(
+            "返回",
+            format!("{CALLBACK_PREFIX}home"),
+        ));
+    }
+    InlineKeyboardMarkup::new(vec![row])
+}
@@ -0,0 +1,92 @@
+//! `/history <link>`: every indexed revision of an edited message, when edits
+//! are indexed as revisions (`indexer.edit_versions`).
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::util::{format_timestamp, html_escape, parse_message_link, public_message_link};
+use crate::config::AppConfig;
+use crate::es::chats::ChatStore;
+use crate::es::search::{SearchClient, MAX_REVISIONS};
+
+/// Text shown per revision before it is cut off, so a message with many
+/// revisions still fits Telegram's 4096 character limit.
+const MAX_REVISION_CHARS: usize = 300;
+/// Revisions shown; the newest are kept when there are more.
+const MAX_SHOWN: usize = 10;
+
+pub async fn handle_history(
+    bot: Bot,
+    msg: Message,
+    link: String,
+    search_client: Arc<SearchClient>,
+    config: Arc<AppConfig>,
+    chats: Arc<ChatStore>,
+) -> anyhow::Result<()> {
+    if !config.indexer.edit_versions {
+        bot.send_message(msg.chat.id, "本机器人未开启编辑历史记录。")
+            .await?;
+        return Ok(());
+    }
+    // A reply to the edited message works as well as its link
+    let message_id = match parse_message_link(&link) {
+        Some(parsed) if !parsed.is_in(&msg.chat) => {
+            bot.send_message(msg.chat.id, "只能查看本群的消息。")
+                .await?;
+            return Ok(());
+        }
+        Some(parsed) => Some(parsed.message_id),
+        None if link.trim().is_empty() => msg.reply_to_message().map(|r| r.id.0 as i64),
+        None => None,
+    };
+    let Some(message_id) = message_id else {
+        bot.send_message(
+            msg.chat.id,
+            "用法: /history <消息链接>，或回复一条消息发送 /history",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    let revisions = search_client.history(msg.chat.id.0, message_id).await?;
+    if revisions.is_empty() {
+        bot.send_message(msg.chat.id, "索引中没有这条消息。")
+            .await?;
+        return Ok(());
+    }
+
+    let mut text = format!("<b>消息 {message_id} 的编辑历史</b>");
+    if revisions.len() >= MAX_REVISIONS {
+        text.push_str(&format!("（仅保留最近 {MAX_REVISIONS} 个版本）"));
+    }
+    text.push('\n');
+    let skipped = revisions.len().saturating_sub(MAX_SHOWN);
+    if skipped > 0 {
+        text.push_str(&format!("\n…省略较早的 {skipped} 个版本\n"));
+    }
+    for revision in &revisions[skipped..] {
+        let label = match revision.edited_at {
+            Some(edited_at) => format!("编辑于 {}", format_timestamp(edited_at)),
+            None => format!("原始消息 · {}", format_timestamp(revision.date)),
+        };
+        let mut body: String = revision.text.chars().take(MAX_REVISION_CHARS).collect();
+        if body.len() < revision.text.len() {
+            body.push('…');
+        }
+        text.push_str(&format!("\n<b>{label}</b>\n{}\n", html_escape(&body)));
+    }
+    text.push_str(&format!(
+        "\n<a href=\"{}\">原消息链接</a>",
+        public_message_link(
+            chats.username(msg.chat.id.0).as_deref(),
+            msg.chat.id.0,
+            message_id
+        )
+    ));
+
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
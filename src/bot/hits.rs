@@ -0,0 +1,103 @@
+//! Views opened by the per-hit buttons of a results message: the messages
+//! around a hit, or messages similar to it. They replace the results until
+//! the user goes back to the page they came from.
+
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::bot::callback::SearchState;
+use crate::bot::payload::{self, SearchAction};
+use crate::bot::session::SessionStore;
+use crate::bot::util::{format_message_link, format_timestamp, no_link_preview};
+use crate::config::OutputFormat;
+use crate::error::AppError;
+use crate::es::search::SearchClient;
+use crate::models::message::ChatMessage;
+
+/// Text shown per message before it is cut off.
+const MAX_MESSAGE_CHARS: usize = 200;
+
+/// Replace the results message `msg` with the view of a per-hit `action`.
+pub(crate) async fn show_hit_view(
+    bot: &Bot,
+    msg: &Message,
+    action: SearchAction,
+    state: &SearchState,
+    search_client: &SearchClient,
+    sessions: &SessionStore,
+    format: OutputFormat,
+) -> Result<(), AppError> {
+    let chat_id = msg.chat.id.0;
+    let (SearchAction::Context(index) | SearchAction::Similar(index)) = action else {
+        return Ok(());
+    };
+    let hit_id = sessions
+        .hit(chat_id, msg.id.0, usize::from(index))
+        .ok_or(AppError::SessionExpired)?;
+
+    let text = if let SearchAction::Context(_) = action {
+        let messages = search_client.context(chat_id, hit_id).await?;
+        format_view("前后的消息", &messages, Some(hit_id), chat_id, format)
+    } else {
+        let messages = search_client.similar(chat_id, hit_id).await?;
+        format_view("相似的消息", &messages, None, chat_id, format)
+    };
+
+    let keyboard = InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback(
+        "⬅ 返回结果",
+        payload::encode(SearchAction::Show, state),
+    )]]);
+    bot.edit_message_text(msg.chat.id, msg.id, text)
+        .parse_mode(format.parse_mode())
+        .link_preview_options(no_link_preview())
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+/// List `messages` under `title`, each dated with a link to it; the hit the
+/// view was opened from, when listed, is marked.
+fn format_view(
+    title: &str,
+    messages: &[ChatMessage],
+    marked: Option<i64>,
+    chat_id: i64,
+    format: OutputFormat,
+) -> String {
+    if messages.is_empty() {
+        return format.escape("没有找到相关消息。");
+    }
+
+    let mut text = format!("{}\n\n", format.bold(&format.escape(title)));
+    for message in messages {
+        let marker = if marked == Some(message.message_id) {
+            "▶ "
+        } else {
+            ""
+        };
+        let date = format.link(
+            &format.escape(&format_timestamp(message.date)),
+            &format_message_link(chat_id, message.message_id),
+        );
+        let name = message
+            .display_name
+            .as_deref()
+            .map(|name| format!(" {}", format.escape(name)))
+            .unwrap_or_default();
+        let body = if message.text.is_empty() {
+            format!("[{}]", message.message_type)
+        } else {
+            let mut body: String = message.text.chars().take(MAX_MESSAGE_CHARS).collect();
+            if body.len() < message.text.len() {
+                body.push('…');
+            }
+            body
+        };
+        text.push_str(&format!(
+            "{}{date}{name}\n{}\n\n",
+            format.escape(marker),
+            format.escape(&body)
+        ));
+    }
+    text
+}
@@ -0,0 +1,105 @@
+//! Per-chat ignore list: messages from listed senders (bridge bots, RSS
+//! bots, ...) are never indexed.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::util::html_escape;
+use crate::es::settings::{IgnoredSender, SettingsStore};
+
+const USAGE: &str = "用法: 回复某人的消息发送命令，或指定 @用户名 / 用户 ID\n\
+    /ignore @rss_bot — 不再索引该用户的消息\n\
+    /unignore @rss_bot — 恢复索引\n\
+    /ignored — 查看本群忽略列表";
+
+/// Handle `/ignore` and `/unignore` (admins).
+pub async fn handle_ignore(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    ignore: bool,
+    settings: Arc<SettingsStore>,
+) -> anyhow::Result<()> {
+    if msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "请在群组中使用此命令。")
+            .await?;
+        return Ok(());
+    }
+    let Some(sender) = target(&msg, &args) else {
+        bot.send_message(msg.chat.id, USAGE).await?;
+        return Ok(());
+    };
+
+    let mut changed = false;
+    settings
+        .update(msg.chat.id.0, |s| {
+            let listed = s.ignored.contains(&sender);
+            if ignore && !listed {
+                s.ignored.push(sender.clone());
+                changed = true;
+            } else if !ignore && listed {
+                s.ignored.retain(|entry| entry != &sender);
+                changed = true;
+            }
+        })
+        .await?;
+
+    let label = describe(&sender);
+    let reply = match (ignore, changed) {
+        (true, true) => format!("之后不会再索引 {label} 的消息。"),
+        (true, false) => format!("{label} 已在忽略列表中。"),
+        (false, true) => format!("已恢复索引 {label} 的消息。"),
+        (false, false) => format!("{label} 不在忽略列表中。"),
+    };
+    bot.send_message(msg.chat.id, reply)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+/// Handle `/ignored`: list the chat's ignored senders.
+pub async fn handle_ignored(
+    bot: Bot,
+    msg: Message,
+    settings: Arc<SettingsStore>,
+) -> anyhow::Result<()> {
+    let chat_settings = settings.get(msg.chat.id.0).await?;
+    let text = if chat_settings.ignored.is_empty() {
+        "本群没有忽略任何用户。".to_string()
+    } else {
+        let mut text = "<b>本群不索引以下用户的消息：</b>\n".to_string();
+        for sender in &chat_settings.ignored {
+            text.push_str(&format!("• {}\n", describe(sender)));
+        }
+        text
+    };
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+/// The sender named by the arguments, or the author of the replied-to message.
+fn target(msg: &Message, args: &str) -> Option<IgnoredSender> {
+    let args = args.trim();
+    if let Some(name) = args.strip_prefix('@') {
+        return (!name.is_empty()).then(|| IgnoredSender::Username(name.to_lowercase()));
+    }
+    if let Ok(id) = args.parse() {
+        return Some(IgnoredSender::Id(id));
+    }
+    if !args.is_empty() {
+        return None;
+    }
+    msg.reply_to_message()
+        .and_then(|reply| reply.from.as_ref())
+        .map(|user| IgnoredSender::Id(user.id.0 as i64))
+}
+
+fn describe(sender: &IgnoredSender) -> String {
+    match sender {
+        IgnoredSender::Id(id) => format!("<code>{id}</code>"),
+        IgnoredSender::Username(name) => format!("@{}", html_escape(name)),
+    }
+}
@@ -0,0 +1,47 @@
+//! Inline mode: lets a search be shared into any chat via `@bot <query>`.
+//!
+//! Inline queries carry no group context, so results are never searched here;
+//! the shared message only names the query so recipients can run it with
+//! `/s` in a group the bot indexes.
+
+use teloxide::prelude::*;
+use teloxide::types::{
+    InlineQueryResult, InlineQueryResultArticle, InputMessageContent, InputMessageContentText,
+    ParseMode,
+};
+
+use crate::bot::util::html_escape;
+
+/// Telegram caps inline query text at 256 characters.
+const MAX_INLINE_QUERY_CHARS: usize = 256;
+
+/// Query text prefilled by the "分享" button of a results message.
+pub fn share_query(query: &str) -> String {
+    query.chars().take(MAX_INLINE_QUERY_CHARS).collect()
+}
+
+/// Handle an inline query by offering the query as a shareable search card.
+pub async fn handle_inline_query(bot: Bot, q: InlineQuery) -> anyhow::Result<()> {
+    let query = q.query.trim();
+    if query.is_empty() {
+        bot.answer_inline_query(q.id, Vec::<InlineQueryResult>::new())
+            .await?;
+        return Ok(());
+    }
+
+    let text = format!(
+        "🔍 分享了一条搜索\n\n<code>/s {}</code>\n\n在已收录的群里发送上面的命令即可查看结果",
+        html_escape(query)
+    );
+    let article = InlineQueryResultArticle::new(
+        "share",
+        format!("分享搜索「{query}」"),
+        InputMessageContent::Text(InputMessageContentText::new(text).parse_mode(ParseMode::Html)),
+    )
+    .description("发送为 /s 搜索命令");
+
+    bot.answer_inline_query(q.id, vec![InlineQueryResult::Article(article)])
+        .is_personal(true)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,130 @@
+//! `/jobs`: the background jobs of this chat, or of every chat when an owner
+//! asks in a private chat, and `/job <id> status|cancel` for one of them.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::permissions::is_owner;
+use crate::bot::util::{format_timestamp, html_escape};
+use crate::config::AppConfig;
+use crate::jobs::{JobInfo, JobKind, JobQueue, JobState};
+
+/// Jobs listed, newest first, within Telegram's message limit.
+const MAX_LISTED: usize = 20;
+const JOB_USAGE: &str = "用法: /job 任务编号 [status|cancel]，任务编号见 /jobs";
+
+pub async fn handle_jobs(bot: Bot, msg: Message, jobs: Arc<JobQueue>) -> anyhow::Result<()> {
+    // Only owners reach admin commands in private chats
+    let chat = (!msg.chat.is_private()).then_some(msg.chat.id.0);
+    bot.send_message(msg.chat.id, format_jobs(&jobs.list(chat), chat.is_none()))
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+/// Handle `/job <id> [status|cancel]` (admins). Admins reach the jobs of
+/// their chat, owners every job.
+pub async fn handle_job(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    config: Arc<AppConfig>,
+    jobs: Arc<JobQueue>,
+) -> anyhow::Result<()> {
+    let mut words = args.split_whitespace();
+    let id = words
+        .next()
+        .and_then(|w| w.trim_start_matches('#').parse().ok());
+    let (Some(id), action, None) = (id, words.next(), words.next()) else {
+        bot.send_message(msg.chat.id, JOB_USAGE).await?;
+        return Ok(());
+    };
+    let job = jobs
+        .get(id)
+        .filter(|job| is_owner(&config, &msg) || job.chat_id == msg.chat.id.0);
+    let Some(job) = job else {
+        bot.send_message(msg.chat.id, format!("没有编号为 #{id} 的任务。"))
+            .await?;
+        return Ok(());
+    };
+
+    let text = match action {
+        None | Some("status") => format_job(&job),
+        Some("cancel") if jobs.cancel(id) => {
+            tracing::info!(
+                "User {:?} cancelled job {id}",
+                msg.from.as_ref().map(|u| u.id)
+            );
+            format!("已请求取消任务 #{id}，当前批次完成后停止。")
+        }
+        Some("cancel") => format!("任务 #{id} 已结束，无法取消。"),
+        Some(_) => JOB_USAGE.to_string(),
+    };
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+fn format_job(job: &JobInfo) -> String {
+    let mut text = format!(
+        "<b>任务 #{}</b> {}\n{}\n状态: {}\n群组: <code>{}</code>\n发起人: <code>{}</code>\n开始: {}\n",
+        job.id,
+        kind_label(job.kind),
+        html_escape(&job.description),
+        state_label(job),
+        job.chat_id,
+        job.requested_by,
+        format_timestamp(job.created_at)
+    );
+    if let Some(finished) = job.finished_at {
+        text.push_str(&format!("结束: {}\n", format_timestamp(finished)));
+    }
+    text
+}
+
+fn format_jobs(jobs: &[JobInfo], all_chats: bool) -> String {
+    if jobs.is_empty() {
+        return "没有后台任务。".to_string();
+    }
+    let mut text = format!("<b>后台任务（{}）</b>\n", jobs.len());
+    for job in jobs.iter().take(MAX_LISTED) {
+        text.push_str(&format!(
+            "\n#{} {} · {}\n  {}\n  {} 开始",
+            job.id,
+            kind_label(job.kind),
+            state_label(job),
+            html_escape(&job.description),
+            format_timestamp(job.created_at)
+        ));
+        if all_chats {
+            text.push_str(&format!(" · 群组 <code>{}</code>", job.chat_id));
+        }
+        text.push('\n');
+    }
+    if jobs.len() > MAX_LISTED {
+        text.push_str(&format!("\n…另有 {} 个", jobs.len() - MAX_LISTED));
+    }
+    text
+}
+
+fn kind_label(kind: JobKind) -> &'static str {
+    match kind {
+        JobKind::Export => "导出",
+        JobKind::Purge => "删除",
+    }
+}
+
+fn state_label(job: &JobInfo) -> String {
+    match &job.state {
+        JobState::Queued => "排队中".to_string(),
+        JobState::Running => match job.total {
+            Some(total) => format!("运行中 {}/{total}", job.done),
+            None => format!("运行中 {}", job.done),
+        },
+        JobState::Succeeded => "已完成".to_string(),
+        JobState::Failed(reason) => format!("失败：{}", html_escape(reason)),
+        JobState::Cancelled => "已取消".to_string(),
+    }
+}
@@ -0,0 +1,148 @@
+//! `/links [period]`: the most shared domains with their latest links, paged
+//! with an inline keyboard.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::{
+    InlineKeyboardButton, InlineKeyboardMarkup, MaybeInaccessibleMessage, ParseMode,
+};
+
+use crate::bot::util::{
+    format_message_link, format_timestamp, html_escape, no_link_preview, parse_period,
+};
+use crate::es::analytics::{AnalyticsClient, DomainLinks};
+
+/// Callback data prefix of `/links` pagination buttons: `links:<secs>:<page>`.
+pub const CALLBACK_PREFIX: &str = "links:";
+/// Domains shown per page.
+const DOMAINS_PER_PAGE: usize = 5;
+
+pub fn is_links_callback(q: &CallbackQuery) -> bool {
+    q.data
+        .as_deref()
+        .is_some_and(|d| d.starts_with(CALLBACK_PREFIX))
+}
+
+/// Handle `/links [period]`, e.g. `/links 30d`.
+pub async fn handle_links(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    analytics: Arc<AnalyticsClient>,
+) -> anyhow::Result<()> {
+    let args = args.trim();
+    let period = if args.is_empty() {
+        None
+    } else {
+        match parse_period(args) {
+            Some(secs) => Some(secs),
+            None => {
+                bot.send_message(msg.chat.id, "用法: /links [时间段]，例如 /links 30d")
+                    .await?;
+                return Ok(());
+            }
+        }
+    };
+
+    let (text, markup) = render_page(&analytics, msg.chat.id.0, period, 0).await?;
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .link_preview_options(no_link_preview())
+        .reply_markup(markup)
+        .await?;
+    Ok(())
+}
+
+/// Switch a `/links` reply to another page.
+pub async fn handle_links_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    analytics: Arc<AnalyticsClient>,
+) -> anyhow::Result<()> {
+    bot.answer_callback_query(q.id.clone()).await?;
+    let Some(MaybeInaccessibleMessage::Regular(msg)) = q.message.as_ref() else {
+        return Ok(());
+    };
+    let Some((secs, page)) = q
+        .data
+        .as_deref()
+        .and_then(|d| d.strip_prefix(CALLBACK_PREFIX))
+        .and_then(|d| d.split_once(':'))
+        .and_then(|(secs, page)| Some((secs.parse::<i64>().ok()?, page.parse().ok()?)))
+    else {
+        return Ok(());
+    };
+
+    let period = (secs > 0).then_some(secs);
+    let (text, markup) = render_page(&analytics, msg.chat.id.0, period, page).await?;
+    match bot
+        .edit_message_text(msg.chat.id, msg.id, text)
+        .parse_mode(ParseMode::Html)
+        .link_preview_options(no_link_preview())
+        .reply_markup(markup)
+        .await
+    {
+        Ok(_) => {}
+        Err(e) if e.to_string().contains("message is not modified") => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+async fn render_page(
+    analytics: &AnalyticsClient,
+    chat_id: i64,
+    period: Option<i64>,
+    page: usize,
+) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let since = period.map(|secs| chrono::Utc::now().timestamp() - secs);
+    let (domains, has_more) = analytics
+        .top_domains(chat_id, since, page * DOMAINS_PER_PAGE, DOMAINS_PER_PAGE)
+        .await?;
+
+    let text = if domains.is_empty() && page == 0 {
+        "这段时间没有人分享链接。".to_string()
+    } else {
+        format_domains(&domains, chat_id, page)
+    };
+
+    let secs = period.unwrap_or(0);
+    let mut row = vec![];
+    if page > 0 {
+        row.push(InlineKeyboardButton::callback(
+            "◀ 上一页",
+            format!("{CALLBACK_PREFIX}{secs}:{}", page - 1),
+        ));
+    }
+    if has_more {
+        row.push(InlineKeyboardButton::callback(
+            "下一页 ▶",
+            format!("{CALLBACK_PREFIX}{secs}:{}", page + 1),
+        ));
+    }
+    Ok((text, InlineKeyboardMarkup::new(vec![row])))
+}
+
+fn format_domains(domains: &[DomainLinks], chat_id: i64, page: usize) -> String {
+    let mut text = "<b>分享最多的网站</b>\n\n".to_string();
+    for (i, domain) in domains.iter().enumerate() {
+        text.push_str(&format!(
+            "{}. <b>{}</b> — {} 次\n",
+            page * DOMAINS_PER_PAGE + i + 1,
+            html_escape(&domain.domain),
+            domain.count
+        ));
+        for (url, message_id, date) in &domain.recent {
+            let label: String = url.chars().take(60).collect();
+            text.push_str(&format!(
+                "  • <a href=\"{}\">{}</a> <a href=\"{}\">{}</a>\n",
+                html_escape(url),
+                html_escape(&label),
+                format_message_link(chat_id, *message_id),
+                format_timestamp(*date)
+            ));
+        }
+        text.push('\n');
+    }
+    text
+}
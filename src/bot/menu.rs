@@ -0,0 +1,54 @@
+//! Registers the command menus and bot description with Telegram at startup.
+
+use teloxide::prelude::*;
+use teloxide::types::{BotCommand, BotCommandScope, Recipient};
+use teloxide::utils::command::BotCommands;
+
+use crate::bot::commands::{Audience, Command};
+use crate::config::AppConfig;
+
+const SHORT_DESCRIPTION: &str = "群聊消息全文搜索：把我加入群组，用 /s 关键词 搜索历史消息";
+
+/// Publish member commands everywhere, admin commands to chat admins and all
+/// commands to the owners' private chats. Failures are logged, not fatal.
+pub async fn register_commands(bot: &Bot, config: &AppConfig) {
+    if let Err(e) = try_register(bot, config).await {
+        tracing::warn!("Failed to register bot commands: {e}");
+    }
+}
+
+async fn try_register(bot: &Bot, config: &AppConfig) -> anyhow::Result<()> {
+    let member = commands_for(&[Audience::Member]);
+    let admin = commands_for(&[Audience::Member, Audience::Admin]);
+    let owner = commands_for(&[Audience::Member, Audience::Admin, Audience::Owner]);
+
+    bot.set_my_commands(member.clone())
+        .scope(BotCommandScope::Default)
+        .await?;
+    bot.set_my_commands(member)
+        .scope(BotCommandScope::AllGroupChats)
+        .await?;
+    bot.set_my_commands(admin)
+        .scope(BotCommandScope::AllChatAdministrators)
+        .await?;
+    for &owner_id in &config.telegram.owner_ids {
+        bot.set_my_commands(owner.clone())
+            .scope(BotCommandScope::Chat {
+                chat_id: Recipient::Id(ChatId(owner_id)),
+            })
+            .await?;
+    }
+
+    bot.set_my_short_description()
+        .short_description(SHORT_DESCRIPTION)
+        .await?;
+    tracing::info!("Registered bot commands");
+    Ok(())
+}
+
+fn commands_for(audiences: &[Audience]) -> Vec<BotCommand> {
+    Command::bot_commands()
+        .into_iter()
+        .filter(|c| audiences.contains(&Command::audience_of(&c.command)))
+        .collect()
+}
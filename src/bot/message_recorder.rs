@@ -1,37 +1,255 @@
 use std::sync::Arc;
 use teloxide::prelude::*;
+use teloxide::types::{
+    DiceEmoji, MaybeInaccessibleMessage, MediaKind, MessageEntityKind, MessageKind, MessageOrigin,
+};
 
+use crate::bot::pipeline::Pipeline;
+use crate::config::RecorderConfig;
+use crate::es::chats::ChatStore;
 use crate::es::indexer::BatchIndexer;
-use crate::models::message::{ChatMessage, MessageType};
+use crate::es::settings::{IndexedSenders, SettingsStore};
+use crate::es::users::UserStore;
+use crate::models::message::{ChatMessage, GeoPoint, MessageType};
 
-pub async fn record_message(msg: Message, indexer: Arc<BatchIndexer>) -> anyhow::Result<()> {
+pub async fn record_message(
+    msg: Message,
+    indexer: Arc<BatchIndexer>,
+    settings: Arc<SettingsStore>,
+    chats: Arc<ChatStore>,
+    users: Arc<UserStore>,
+    config: &RecorderConfig,
+    pipeline: &Pipeline,
+) -> anyhow::Result<()> {
     if !msg.chat.is_group() && !msg.chat.is_supergroup() {
         return Ok(());
     }
 
-    let text = msg
+    let sender = msg.from.as_ref();
+    let chat_settings = match settings.get(msg.chat.id.0).await {
+        Ok(s) => Some(s),
+        // Better to index an ignored sender than to lose messages
+        Err(e) => {
+            tracing::warn!("Failed to load settings of {}: {e}", msg.chat.id);
+            None
+        }
+    };
+    if let (Some(s), Some(user)) = (&chat_settings, sender)
+        && s.is_ignored(user.id.0 as i64, user.username.as_deref())
+    {
+        return Ok(());
+    }
+    let bridge = chat_settings
+        .as_ref()
+        .and_then(|s| s.bridge_rule(sender.and_then(|u| u.username.as_deref())));
+
+    // Bridge bots relay people, so they're indexed even when bots are ignored
+    if let Some(user) = sender
+        && bridge.is_none()
+    {
+        let default = IndexedSenders::default();
+        let senders = chat_settings.as_ref().map_or(&default, |s| &s.senders);
+        if !senders.indexes(user.id.0 as i64, user.is_bot, config.ignore_bots) {
+            return Ok(());
+        }
+    }
+
+    if let Some(pinned) = msg.pinned_message() {
+        indexer.index(pin_record(&msg, pinned)).await;
+        observe_chat(&chats, &msg, msg.date.timestamp()).await;
+        return Ok(());
+    }
+
+    // Stickers carry no text, but their emoji makes them findable by emoji search
+    let mut text = msg
         .text()
         .or_else(|| msg.caption())
-        .unwrap_or_default()
-        .to_string();
+        .or_else(|| msg.sticker().and_then(|s| s.emoji.as_deref()))
+        .map(String::from)
+        .or_else(|| msg.venue().map(|v| format!("{} {}", v.title, v.address)))
+        .or_else(|| describe_special(&msg))
+        .unwrap_or_default();
+
+    let (mut username, mut display_name) = sender_names(&msg);
+    if let Some((author, rest)) = bridge.and_then(|rule| rule.split(&text)) {
+        username = None;
+        display_name = Some(author.to_string());
+        text = rest.to_string();
+    }
 
-    if text.is_empty() || text.starts_with('/') {
+    let file = extract_file(&msg);
+    let location = msg
+        .venue()
+        .map(|v| &v.location)
+        .or_else(|| msg.location())
+        .map(|l| GeoPoint {
+            lat: l.latitude,
+            lon: l.longitude,
+        });
+
+    // Files are worth indexing by name and locations by position, even without text
+    if text.is_empty() && file.as_ref().is_none_or(|f| f.name.is_none()) && location.is_none() {
+        return Ok(());
+    }
+
+    if config.skip_commands && text.starts_with('/') {
         return Ok(());
     }
 
-    let chat_message = ChatMessage {
+    if file.is_none() && location.is_none() && text.trim().chars().count() < config.min_length {
+        return Ok(());
+    }
+
+    let urls = extract_urls(&msg);
+    let domains = extract_domains(&urls);
+    let (external_reply_chat_id, external_reply_message_id) = external_reply_origin(&msg);
+    let scheduled = matches!(&msg.kind, MessageKind::Common(common) if common.is_from_offline);
+
+    let mut chat_message = ChatMessage {
         message_id: msg.id.0 as i64,
         chat_id: msg.chat.id.0,
         user_id: msg.from.as_ref().map(|u| u.id.0 as i64),
+        username,
+        display_name,
         text,
-        date: msg.date.timestamp(),
+        caption: msg.caption().map(String::from),
+        date: delivery_date(
+            msg.date.timestamp(),
+            scheduled,
+            chrono::Utc::now().timestamp(),
+        ),
         message_type: classify_message(&msg),
+        reply_to_message_id: msg.reply_to_message().map(|r| r.id.0 as i64),
+        urls,
+        domains,
+        mentions: extract_mentions(&msg),
+        file_ext: file.as_ref().and_then(|f| f.extension()),
+        file_name: file.as_ref().and_then(|f| f.name.clone()),
+        mime_type: file.as_ref().and_then(|f| f.mime_type.clone()),
+        file_size: file.as_ref().map(|f| f.size),
+        location,
+        quote_text: msg.quote().map(|q| q.text.clone()),
+        external_reply_chat_id,
+        external_reply_message_id,
+        pinned_message_id: None,
+        views: None,
+        forwards: None,
+        scheduled,
+        forwarded: msg.forward_origin().is_some(),
+        people: Vec::new(),
+        orgs: Vec::new(),
+        places: Vec::new(),
+        sentiment: None,
+        toxicity: None,
+        edited_at: msg.edit_date().map(|d| d.timestamp()),
+        content_hash: None,
     };
 
+    pipeline.run(&mut chat_message).await;
+    let date = chat_message.date;
     indexer.index(chat_message).await;
+    observe_chat(&chats, &msg, date).await;
+    observe_sender(&users, &msg, date).await;
     Ok(())
 }
 
+/// Keep the chat's metadata current; a failed write never blocks indexing.
+async fn observe_chat(chats: &ChatStore, msg: &Message, date: i64) {
+    let chat = &msg.chat;
+    let chat_type = if chat.is_supergroup() {
+        "supergroup"
+    } else if chat.is_group() {
+        "group"
+    } else if chat.is_channel() {
+        "channel"
+    } else {
+        "private"
+    };
+    if let Err(e) = chats
+        .observe(chat.id.0, chat.title(), chat.username(), chat_type, date)
+        .await
+    {
+        tracing::warn!("Failed to update metadata of {}: {e}", chat.id);
+    }
+}
+
+/// Keep the sender's name history current; like the chat metadata, a failed
+/// write never blocks indexing.
+async fn observe_sender(users: &UserStore, msg: &Message, date: i64) {
+    let Some(user) = msg.from.as_ref() else {
+        return;
+    };
+    let full_name = user.full_name();
+    if let Err(e) = users
+        .observe(
+            msg.chat.id.0,
+            user.id.0 as i64,
+            user.username.as_deref(),
+            Some(&full_name),
+            date,
+        )
+        .await
+    {
+        tracing::warn!(
+            "Failed to update names of {} in {}: {e}",
+            user.id,
+            msg.chat.id
+        );
+    }
+}
+
+/// Index a pin service message under its own id, carrying the pinned text, so
+/// the pin history survives later pins and unpins.
+fn pin_record(msg: &Message, pinned: &MaybeInaccessibleMessage) -> ChatMessage {
+    let (pinned_id, text) = match pinned {
+        MaybeInaccessibleMessage::Regular(m) => (
+            m.id,
+            m.text()
+                .or_else(|| m.caption())
+                .map(String::from)
+                .or_else(|| describe_special(m))
+                .unwrap_or_default(),
+        ),
+        MaybeInaccessibleMessage::Inaccessible(m) => (m.message_id, String::new()),
+    };
+    let (username, display_name) = sender_names(msg);
+    ChatMessage {
+        message_id: msg.id.0 as i64,
+        chat_id: msg.chat.id.0,
+        user_id: msg.from.as_ref().map(|u| u.id.0 as i64),
+        username,
+        display_name,
+        text,
+        date: msg.date.timestamp(),
+        message_type: MessageType::Pinned,
+        pinned_message_id: Some(pinned_id.0 as i64),
+        ..Default::default()
+    }
+}
+
+/// When a message was posted in the chat. Scheduled messages carry the time
+/// they were scheduled at rather than posted, so the time of receipt is used
+/// instead; other dates are kept, since updates queued while the bot was down
+/// arrive late, but never lie in the future.
+fn delivery_date(sent: i64, scheduled: bool, received: i64) -> i64 {
+    if scheduled {
+        received
+    } else {
+        sent.min(received)
+    }
+}
+
+/// Lowercased username and full name of the sender.
+fn sender_names(msg: &Message) -> (Option<String>, Option<String>) {
+    match msg.from.as_ref() {
+        Some(user) => (
+            user.username.as_ref().map(|u| u.to_lowercase()),
+            Some(user.full_name()),
+        ),
+        None => (None, None),
+    }
+}
+
 fn classify_message(msg: &Message) -> MessageType {
     if msg.text().is_some() {
         MessageType::Text
@@ -39,15 +257,245 @@ fn classify_message(msg: &Message) -> MessageType {
         MessageType::Photo
     } else if msg.video().is_some() {
         MessageType::Video
+    } else if msg.video_note().is_some() {
+        MessageType::VideoNote
     } else if msg.document().is_some() {
         MessageType::Document
     } else if msg.sticker().is_some() {
         MessageType::Sticker
     } else if msg.voice().is_some() {
         MessageType::Voice
+    } else if msg.audio().is_some() {
+        MessageType::Audio
     } else if msg.animation().is_some() {
         MessageType::Animation
+    } else if msg.contact().is_some() {
+        MessageType::Contact
+    } else if msg.game().is_some() {
+        MessageType::Game
+    } else if msg.dice().is_some() {
+        MessageType::Dice
+    } else if msg.venue().is_some() {
+        MessageType::Venue
+    } else if msg.location().is_some() {
+        MessageType::Location
+    } else if msg.story().is_some() {
+        MessageType::Story
     } else {
-        MessageType::Other
+        unmodeled_kind(msg).map_or(MessageType::Other, |kind| {
+            MessageType::Unknown(kind.to_string())
+        })
+    }
+}
+
+/// Name of a Telegram message kind without its own [`MessageType`], so it
+/// stays filterable instead of being lumped into `other`.
+fn unmodeled_kind(msg: &Message) -> Option<&'static str> {
+    match &msg.kind {
+        MessageKind::Common(common) => match common.media_kind {
+            MediaKind::Poll(_) => Some("poll"),
+            MediaKind::PaidMedia(_) => Some("paid_media"),
+            MediaKind::Checklist(_) => Some("checklist"),
+            _ => None,
+        },
+        MessageKind::Giveaway(_) => Some("giveaway"),
+        MessageKind::GiveawayWinners(_) => Some("giveaway_winners"),
+        MessageKind::Invoice(_) => Some("invoice"),
+        _ => None,
+    }
+}
+
+/// Searchable label for audio, contacts, games and dice, which carry no text
+/// of their own. Contacts are indexed by name only; phone numbers are never stored.
+fn describe_special(msg: &Message) -> Option<String> {
+    if let Some(tags) = audio_tags(msg) {
+        return Some(tags);
+    }
+    if let Some(c) = msg.contact() {
+        let name = match c.last_name.as_deref() {
+            Some(last) => format!("{} {last}", c.first_name),
+            None => c.first_name.clone(),
+        };
+        return Some(name);
+    }
+    if let Some(g) = msg.game() {
+        return Some(format!("{} {}", g.title, g.description));
+    }
+    msg.dice().map(|d| {
+        let emoji = match d.emoji {
+            DiceEmoji::Dice => "🎲",
+            DiceEmoji::Darts => "🎯",
+            DiceEmoji::Bowling => "🎳",
+            DiceEmoji::Basketball => "🏀",
+            DiceEmoji::Football => "⚽",
+            DiceEmoji::SlotMachine => "🎰",
+        };
+        format!("{emoji} {}", d.value)
+    })
+}
+
+/// `performer - title` of a music file, from whichever tags it has.
+fn audio_tags(msg: &Message) -> Option<String> {
+    let audio = msg.audio()?;
+    let tags: Vec<&str> = [audio.performer.as_deref(), audio.title.as_deref()]
+        .into_iter()
+        .flatten()
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    (!tags.is_empty()).then(|| tags.join(" - "))
+}
+
+/// Chat and message id of a replied-to message from another chat.
+fn external_reply_origin(msg: &Message) -> (Option<i64>, Option<i64>) {
+    let MessageKind::Common(common) = &msg.kind else {
+        return (None, None);
+    };
+    let Some(ext) = common.external_reply.as_ref() else {
+        return (None, None);
+    };
+
+    let chat_id = ext.chat.as_ref().map(|c| c.id.0).or(match &ext.origin {
+        MessageOrigin::Chat { sender_chat, .. } => Some(sender_chat.id.0),
+        MessageOrigin::Channel { chat, .. } => Some(chat.id.0),
+        _ => None,
+    });
+    let message_id = ext.message_id.map(|id| id.0 as i64).or(match &ext.origin {
+        MessageOrigin::Channel { message_id, .. } => Some(message_id.0 as i64),
+        _ => None,
+    });
+    (chat_id, message_id)
+}
+
+struct FileInfo {
+    name: Option<String>,
+    mime_type: Option<String>,
+    size: u64,
+}
+
+impl FileInfo {
+    fn extension(&self) -> Option<String> {
+        let (_, ext) = self.name.as_deref()?.rsplit_once('.')?;
+        (!ext.is_empty() && ext.len() <= 10).then(|| ext.to_lowercase())
+    }
+}
+
+fn extract_file(msg: &Message) -> Option<FileInfo> {
+    let (name, mime_type, size) = if let Some(d) = msg.document() {
+        (
+            d.file_name.clone(),
+            d.mime_type.as_ref().map(ToString::to_string),
+            d.file.size,
+        )
+    } else if let Some(v) = msg.video() {
+        (
+            v.file_name.clone(),
+            v.mime_type.as_ref().map(ToString::to_string),
+            v.file.size,
+        )
+    } else if let Some(a) = msg.audio() {
+        // Tags stay searchable by file name when a caption takes the text
+        (
+            a.file_name.clone().or_else(|| audio_tags(msg)),
+            a.mime_type.as_ref().map(ToString::to_string),
+            a.file.size,
+        )
+    } else if let Some(a) = msg.animation() {
+        (
+            a.file_name.clone(),
+            a.mime_type.as_ref().map(ToString::to_string),
+            a.file.size,
+        )
+    } else if let Some(v) = msg.voice() {
+        (
+            None,
+            v.mime_type.as_ref().map(ToString::to_string),
+            v.file.size,
+        )
+    } else {
+        return None;
+    };
+
+    Some(FileInfo {
+        name,
+        // Drop parameters such as "; charset=utf-8"
+        mime_type: mime_type.map(|m| m.split(';').next().unwrap_or_default().trim().to_string()),
+        size: size as u64,
+    })
+}
+
+fn extract_urls(msg: &Message) -> Vec<String> {
+    let entities = msg
+        .parse_entities()
+        .or_else(|| msg.parse_caption_entities())
+        .unwrap_or_default();
+
+    let mut urls: Vec<String> = entities
+        .iter()
+        .filter_map(|e| match e.kind() {
+            MessageEntityKind::Url => Some(e.text().to_string()),
+            MessageEntityKind::TextLink { url } => Some(url.to_string()),
+            _ => None,
+        })
+        .collect();
+    urls.dedup();
+    urls
+}
+
+fn extract_mentions(msg: &Message) -> Vec<String> {
+    let entities = msg
+        .parse_entities()
+        .or_else(|| msg.parse_caption_entities())
+        .unwrap_or_default();
+
+    let mut mentions: Vec<String> = entities
+        .iter()
+        .filter(|e| matches!(e.kind(), MessageEntityKind::Mention))
+        .map(|e| e.text().trim_start_matches('@').to_lowercase())
+        .collect();
+    mentions.sort();
+    mentions.dedup();
+    mentions
+}
+
+fn extract_domains(urls: &[String]) -> Vec<String> {
+    let mut domains: Vec<String> = urls
+        .iter()
+        .filter_map(|raw| {
+            // Url entities may omit the scheme, e.g. "example.com/page"
+            let parsed = url::Url::parse(raw)
+                .or_else(|_| url::Url::parse(&format!("http://{raw}")))
+                .ok()?;
+            let host = parsed.host_str()?.to_lowercase();
+            Some(host.strip_prefix("www.").map(String::from).unwrap_or(host))
+        })
+        .collect();
+    domains.sort();
+    domains.dedup();
+    domains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    #[test]
+    fn keeps_date_of_regular_messages() {
+        assert_eq!(delivery_date(NOW - 5, false, NOW), NOW - 5);
+        // Queued while the bot was offline
+        assert_eq!(delivery_date(NOW - 86400, false, NOW), NOW - 86400);
+    }
+
+    #[test]
+    fn clamps_future_dates() {
+        assert_eq!(delivery_date(NOW + 3600, false, NOW), NOW);
+    }
+
+    #[test]
+    fn scheduled_messages_use_receipt_time() {
+        assert_eq!(delivery_date(NOW - 7 * 86400, true, NOW), NOW);
+        assert_eq!(delivery_date(NOW + 60, true, NOW), NOW);
     }
 }
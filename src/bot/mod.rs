@@ -1,4 +1,52 @@
+pub mod aka;
+pub mod alerts;
+pub mod audit;
+pub mod bookmark_transfer;
+pub mod bookmarks;
+pub mod bridge;
 pub mod callback;
+pub mod chats;
 pub mod commands;
+pub mod compare;
+pub mod config_report;
+pub mod crossposts;
+pub mod dedup;
+pub mod digest;
+pub mod digest_feedback;
+pub mod entities;
+pub mod explain;
+pub mod export;
+pub mod get;
+pub mod growth;
 pub mod handler;
+pub mod help;
+pub mod history;
+pub mod hits;
+pub mod ignore;
+pub mod inline;
+pub mod jobs;
+pub mod links;
+pub mod menu;
 pub mod message_recorder;
+pub mod mood;
+pub mod payload;
+pub mod permissions;
+pub mod personalize;
+pub mod pins;
+pub mod pipeline;
+pub mod preview;
+pub mod purge;
+pub mod query;
+pub mod quiet;
+pub mod ratelimit;
+pub mod selftest;
+pub mod senders;
+pub mod session;
+pub mod shards;
+pub mod spam;
+pub mod stats;
+pub mod syntax;
+pub mod telegram;
+pub mod throwback;
+pub mod util;
+pub mod wizard;
@@ -0,0 +1,78 @@
+//! `/moodtrend [period]`: average sentiment and toxicity of the chat over
+//! time, from the scores stored by message classification.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::util::{format_timestamp, parse_period};
+use crate::es::analytics::{AnalyticsClient, MoodBucket};
+use crate::es::search::TOXIC_THRESHOLD;
+
+/// Period shown when none is given.
+const DEFAULT_PERIOD_SECS: i64 = 30 * 86400;
+/// Periods longer than this are shown by week instead of by day.
+const MAX_DAILY_PERIOD_SECS: i64 = 60 * 86400;
+
+/// Handle `/moodtrend [period]` (admins).
+pub async fn handle_moodtrend(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    analytics: Arc<AnalyticsClient>,
+) -> anyhow::Result<()> {
+    let args = args.trim();
+    let period = if args.is_empty() {
+        DEFAULT_PERIOD_SECS
+    } else {
+        match parse_period(args) {
+            Some(secs) => secs,
+            None => {
+                bot.send_message(
+                    msg.chat.id,
+                    "用法: /moodtrend [时间段]，例如 /moodtrend 90d",
+                )
+                .await?;
+                return Ok(());
+            }
+        }
+    };
+
+    let bucket_secs = if period > MAX_DAILY_PERIOD_SECS {
+        7 * 86400
+    } else {
+        86400
+    };
+    let since = chrono::Utc::now().timestamp() - period;
+    let buckets = analytics
+        .mood_trend(msg.chat.id.0, since, bucket_secs, TOXIC_THRESHOLD)
+        .await?;
+
+    bot.send_message(msg.chat.id, format_trend(&buckets))
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+fn format_trend(buckets: &[MoodBucket]) -> String {
+    if buckets.iter().all(|b| b.count == 0) {
+        return "这段时间没有已评分的消息，可能未启用消息分类。".to_string();
+    }
+
+    let mut text = "<b>情绪趋势</b>\n\
+        情绪 -1（负面）~ 1（正面），毒性 0 ~ 1，高毒性为 is:toxic 可搜到的消息\n<pre>"
+        .to_string();
+    for bucket in buckets {
+        let date = format_timestamp(bucket.start);
+        text.push_str(date.get(5..10).unwrap_or_default());
+        match (bucket.sentiment, bucket.toxicity) {
+            (Some(sentiment), Some(toxicity)) => text.push_str(&format!(
+                " 情绪 {sentiment:+.2} 毒性 {toxicity:.2} 高毒性 {} / {}\n",
+                bucket.toxic, bucket.count
+            )),
+            _ => text.push_str(" —\n"),
+        }
+    }
+    text.push_str("</pre>");
+    text
+}
@@ -0,0 +1,320 @@
+//! Callback data of the search results keyboard: URL-safe base64 of
+//! `[version][action][fields…]`. The version byte lets later builds change
+//! the fields while still reading keyboards left in chat history, and the
+//! action byte lets buttons do more than show a page.
+//!
+//! Version 1 fields: a flags byte (bit 0: user filter present), the page as
+//! a LEB128 varint, the type and date filter codes, then the zigzag-encoded
+//! user id when flagged. Actions on one hit put the hit's position on the
+//! page right after the action byte; the fields then describe the page to
+//! return to.
+//!
+//! Keyboards sent before payloads were versioned carry the plain-text
+//! `{page}|{type}|{date}|{user_id}` format, which is still read and migrated.
+
+use anyhow::{bail, Context};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+use crate::bot::callback::SearchState;
+
+/// Payload version written by this build.
+pub(crate) const VERSION: u8 = 1;
+
+const FLAG_USER: u8 = 1;
+
+/// What pressing a results button does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SearchAction {
+    /// Show the page and filters of the payload's state
+    Show,
+    /// Show the messages around the hit at this position on the page
+    Context(u8),
+    /// Show messages similar to the hit at this position on the page
+    Similar(u8),
+    /// Save the hit at this position on the page to the presser's bookmarks
+    Bookmark(u8),
+}
+
+impl SearchAction {
+    fn code(self) -> u8 {
+        match self {
+            Self::Show => 1,
+            Self::Context(_) => 2,
+            Self::Similar(_) => 3,
+            Self::Bookmark(_) => 4,
+        }
+    }
+
+    fn read(reader: &mut Reader) -> anyhow::Result<Self> {
+        match reader.byte()? {
+            1 => Ok(Self::Show),
+            2 => Ok(Self::Context(reader.byte()?)),
+            3 => Ok(Self::Similar(reader.byte()?)),
+            4 => Ok(Self::Bookmark(reader.byte()?)),
+            code => bail!("Unknown search action {code}"),
+        }
+    }
+}
+
+pub(crate) fn encode(action: SearchAction, state: &SearchState) -> String {
+    let flags = state.user_id.map_or(0, |_| FLAG_USER);
+    let mut bytes = vec![VERSION, action.code()];
+    if let SearchAction::Context(hit) | SearchAction::Similar(hit) | SearchAction::Bookmark(hit) =
+        action
+    {
+        bytes.push(hit);
+    }
+    bytes.push(flags);
+    write_varint(&mut bytes, state.page as u64);
+    bytes.push(type_code(state.message_type.as_deref()));
+    bytes.push(date_code(state.date_range));
+    if let Some(user_id) = state.user_id {
+        write_varint(&mut bytes, zigzag(user_id));
+    }
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Decode callback data of any payload version, including the legacy text
+/// format. Errors mean the button comes from a keyboard this build can't
+/// read, and the user has to search again.
+pub(crate) fn decode(data: &str) -> anyhow::Result<(SearchAction, SearchState)> {
+    // `|` is outside the base64 alphabet, so it only appears in legacy data
+    if data.contains('|') {
+        return decode_legacy(data).map(|state| (SearchAction::Show, state));
+    }
+    let bytes = URL_SAFE_NO_PAD
+        .decode(data)
+        .context("Callback data is not a search payload")?;
+    let mut reader = Reader(&bytes);
+    let version = reader.byte()?;
+    if version != VERSION {
+        bail!("Unsupported search payload version {version}");
+    }
+    let action = SearchAction::read(&mut reader)?;
+    let flags = reader.byte()?;
+    let page = usize::try_from(reader.varint()?)?;
+    let message_type = type_from_code(reader.byte()?)?.map(String::from);
+    let date_range = date_from_code(reader.byte()?)?;
+    let user_id = if flags & FLAG_USER != 0 {
+        Some(unzigzag(reader.varint()?))
+    } else {
+        None
+    };
+
+    Ok((
+        action,
+        SearchState {
+            page,
+            message_type,
+            date_range,
+            user_id,
+        },
+    ))
+}
+
+/// Read the pre-versioning `{page}|{t}|{d}|{uid}` format, e.g. `2|p|7|-`.
+fn decode_legacy(data: &str) -> anyhow::Result<SearchState> {
+    let parts: Vec<&str> = data.split('|').collect();
+    let [page, message_type, date_range, user_id] = parts[..] else {
+        bail!("Invalid legacy search state: {data}");
+    };
+
+    let message_type = match message_type {
+        "t" => Some("text"),
+        "p" => Some("photo"),
+        "v" => Some("video"),
+        "d" => Some("document"),
+        "-" => None,
+        _ => bail!("Invalid legacy message type: {message_type}"),
+    };
+    let date_range = match date_range {
+        "7" => Some("7d"),
+        "3" => Some("30d"),
+        "9" => Some("90d"),
+        "-" => None,
+        _ => bail!("Invalid legacy date range: {date_range}"),
+    };
+    let user_id = match user_id {
+        "-" => None,
+        id => Some(id.parse()?),
+    };
+
+    Ok(SearchState {
+        page: page.parse()?,
+        message_type: message_type.map(String::from),
+        date_range,
+        user_id,
+    })
+}
+
+fn type_code(message_type: Option<&str>) -> u8 {
+    match message_type {
+        Some("text") => 1,
+        Some("photo") => 2,
+        Some("video") => 3,
+        Some("document") => 4,
+        Some("voice") => 5,
+        Some("audio") => 6,
+        Some("video_note") => 7,
+        _ => 0,
+    }
+}
+
+fn type_from_code(code: u8) -> anyhow::Result<Option<&'static str>> {
+    Ok(match code {
+        0 => None,
+        1 => Some("text"),
+        2 => Some("photo"),
+        3 => Some("video"),
+        4 => Some("document"),
+        5 => Some("voice"),
+        6 => Some("audio"),
+        7 => Some("video_note"),
+        _ => bail!("Invalid message type code {code}"),
+    })
+}
+
+fn date_code(date_range: Option<&str>) -> u8 {
+    match date_range {
+        Some("7d") => 1,
+        Some("30d") => 2,
+        Some("90d") => 3,
+        _ => 0,
+    }
+}
+
+fn date_from_code(code: u8) -> anyhow::Result<Option<&'static str>> {
+    Ok(match code {
+        0 => None,
+        1 => Some("7d"),
+        2 => Some("30d"),
+        3 => Some("90d"),
+        _ => bail!("Invalid date range code {code}"),
+    })
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Map signed ids to unsigned so negative ids stay short as varints.
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn byte(&mut self) -> anyhow::Result<u8> {
+        let (&first, rest) = self.0.split_first().context("Truncated search payload")?;
+        self.0 = rest;
+        Ok(first)
+    }
+
+    fn varint(&mut self) -> anyhow::Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("Varint too long in search payload")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(page: usize, user_id: Option<i64>) -> SearchState {
+        SearchState {
+            page,
+            message_type: Some("photo".into()),
+            date_range: Some("30d"),
+            user_id,
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        for user_id in [
+            None,
+            Some(7),
+            Some(-1001234567890),
+            Some(i64::MAX),
+            Some(i64::MIN),
+        ] {
+            let data = encode(SearchAction::Show, &state(300, user_id));
+            let (action, decoded) = decode(&data).unwrap();
+            assert_eq!(action, SearchAction::Show);
+            assert_eq!(decoded.page, 300);
+            assert_eq!(decoded.message_type.as_deref(), Some("photo"));
+            assert_eq!(decoded.date_range, Some("30d"));
+            assert_eq!(decoded.user_id, user_id);
+        }
+    }
+
+    #[test]
+    fn round_trips_hit_actions() {
+        for action in [
+            SearchAction::Context(0),
+            SearchAction::Similar(255),
+            SearchAction::Bookmark(9),
+        ] {
+            let (decoded, state) = decode(&encode(action, &state(3, Some(-42)))).unwrap();
+            assert_eq!(decoded, action);
+            assert_eq!(state.page, 3);
+            assert_eq!(state.user_id, Some(-42));
+        }
+    }
+
+    #[test]
+    fn fits_telegram_limit() {
+        // Callback data is capped at 64 bytes
+        let data = encode(
+            SearchAction::Similar(255),
+            &state(usize::MAX, Some(i64::MIN)),
+        );
+        assert!(data.len() <= 64, "{} bytes", data.len());
+    }
+
+    #[test]
+    fn rejects_other_versions_and_garbage() {
+        let mut bytes = URL_SAFE_NO_PAD
+            .decode(encode(SearchAction::Show, &state(0, None)))
+            .unwrap();
+        bytes[0] = VERSION + 1;
+        assert!(decode(&URL_SAFE_NO_PAD.encode(&bytes)).is_err());
+        assert!(decode("noop").is_err());
+        assert!(decode("").is_err());
+    }
+
+    #[test]
+    fn migrates_legacy_format() {
+        let (action, state) = decode("2|p|3|-1001234567890").unwrap();
+        assert_eq!(action, SearchAction::Show);
+        assert_eq!(state.page, 2);
+        assert_eq!(state.message_type.as_deref(), Some("photo"));
+        assert_eq!(state.date_range, Some("30d"));
+        assert_eq!(state.user_id, Some(-1001234567890));
+
+        let (_, state) = decode("0|-|-|-").unwrap();
+        assert_eq!(state.page, 0);
+        assert!(state.message_type.is_none() && state.date_range.is_none());
+        assert!(state.user_id.is_none());
+
+        assert!(decode("0|x|-|-").is_err());
+        assert!(decode("0|-|-").is_err());
+    }
+}
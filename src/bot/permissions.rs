@@ -0,0 +1,49 @@
+use teloxide::prelude::*;
+
+use crate::bot::commands::Audience;
+use crate::config::AppConfig;
+
+/// Whether the sender of `msg` is one of the configured bot owners.
+pub fn is_owner(config: &AppConfig, msg: &Message) -> bool {
+    msg.from
+        .as_ref()
+        .is_some_and(|u| config.telegram.owner_ids.contains(&(u.id.0 as i64)))
+}
+
+/// Whether the sender of `msg` is an owner or an administrator of the chat.
+pub async fn is_admin(bot: &Bot, config: &AppConfig, msg: &Message) -> bool {
+    if is_owner(config, msg) {
+        return true;
+    }
+    let Some(user) = msg.from.as_ref() else {
+        return false;
+    };
+    if msg.chat.is_private() {
+        return false;
+    }
+    match bot.get_chat_member(msg.chat.id, user.id).await {
+        Ok(member) => member.is_privileged(),
+        Err(e) => {
+            tracing::warn!("Failed to fetch chat member {}: {e}", user.id);
+            false
+        }
+    }
+}
+
+/// Whether the sender of `msg` may run commands meant for `audience`.
+pub async fn has_access(bot: &Bot, config: &AppConfig, msg: &Message, audience: Audience) -> bool {
+    match audience {
+        Audience::Member => true,
+        Audience::Admin => is_admin(bot, config, msg).await,
+        Audience::Owner => is_owner(config, msg),
+    }
+}
+
+/// Reply for a sender lacking access to `audience` commands.
+pub fn denial_text(audience: Audience) -> &'static str {
+    match audience {
+        Audience::Member => "",
+        Audience::Admin => "此命令仅限群管理员使用。",
+        Audience::Owner => "此命令仅限机器人所有者使用。",
+    }
+}
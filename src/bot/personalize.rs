@@ -0,0 +1,53 @@
+//! `/personalize`: rank search hits from the searcher's frequent reply
+//! partners higher in this chat.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+
+use crate::es::settings::SettingsStore;
+
+const USAGE: &str = "用法:\n\
+    /personalize on — 优先显示与搜索者互相回复最多的成员的消息\n\
+    /personalize off — 所有人看到相同的排序";
+
+/// Handle `/personalize on|off` (admins).
+pub async fn handle_personalize(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    settings: Arc<SettingsStore>,
+) -> anyhow::Result<()> {
+    if msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "请在群组中使用此命令。")
+            .await?;
+        return Ok(());
+    }
+    let on = match args.trim() {
+        "on" => true,
+        "off" => false,
+        _ => {
+            let current = settings.get(msg.chat.id.0).await?.personalize;
+            bot.send_message(
+                msg.chat.id,
+                format!("当前设置：{}\n\n{USAGE}", label(current)),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    settings
+        .update(msg.chat.id.0, |s| s.personalize = on)
+        .await?;
+    bot.send_message(msg.chat.id, format!("个性化排序已{}。", label(on)))
+        .await?;
+    Ok(())
+}
+
+fn label(on: bool) -> &'static str {
+    if on {
+        "开启"
+    } else {
+        "关闭"
+    }
+}
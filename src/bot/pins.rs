@@ -0,0 +1,66 @@
+//! `/pins [keyword]`: history of pinned messages, of which Telegram itself
+//! only shows the latest.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::callback::reply_error;
+use crate::bot::util::{format_message_link, format_timestamp};
+use crate::config::OutputFormat;
+use crate::error::AppError;
+use crate::es::search::{SearchClient, SearchParams, SearchResult};
+use crate::models::message::MessageType;
+
+/// Pins listed per reply.
+const PINS_PAGE_SIZE: usize = 10;
+
+pub async fn handle_pins(
+    bot: Bot,
+    msg: Message,
+    keyword: String,
+    search_client: Arc<SearchClient>,
+) -> Result<(), AppError> {
+    let keyword = keyword.trim();
+    let params = SearchParams {
+        chat_id: msg.chat.id.0,
+        keyword: (!keyword.is_empty()).then(|| keyword.to_string()),
+        message_type: Some(MessageType::Pinned.to_string()),
+        page_size: PINS_PAGE_SIZE,
+        ..Default::default()
+    };
+    let result = match search_client.search(&params).await {
+        Ok(result) => result,
+        Err(e) => return reply_error(&bot, &msg, e).await,
+    };
+
+    bot.send_message(msg.chat.id, format_pins(&result, msg.chat.id.0))
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+fn format_pins(result: &SearchResult, chat_id: i64) -> String {
+    if result.total == 0 {
+        return "没有找到置顶记录。".to_string();
+    }
+
+    let mut text = format!("<b>置顶历史</b>（共 {} 条）\n\n", result.total);
+    for hit in &result.messages {
+        let pinned = &hit.message;
+        let snippet = if hit.snippet.is_empty() {
+            "（无文字内容）".to_string()
+        } else {
+            OutputFormat::Html.highlight(&hit.snippet)
+        };
+        let link = format_message_link(
+            chat_id,
+            pinned.pinned_message_id.unwrap_or(pinned.message_id),
+        );
+        text.push_str(&format!(
+            "📌 <i>{}</i>\n{snippet}\n<a href=\"{link}\">跳转到消息</a>\n\n",
+            format_timestamp(pinned.date)
+        ));
+    }
+    text
+}
@@ -0,0 +1,94 @@
+//! Enrichment stages run on every recorded message before it is indexed, in
+//! the order listed in `recorder.processors`.
+
+use futures::future::BoxFuture;
+
+use crate::config::AppConfig;
+use crate::models::message::ChatMessage;
+use crate::nlp::classifier::MessageClassifier;
+use crate::nlp::entities::EntityExtractor;
+
+/// One stage of the pipeline, free to fill or rewrite fields of the message.
+pub trait MessageProcessor: Send + Sync {
+    /// Name of the stage in `recorder.processors`
+    fn name(&self) -> &'static str;
+
+    fn process<'a>(&'a self, message: &'a mut ChatMessage) -> BoxFuture<'a, anyhow::Result<()>>;
+}
+
+pub struct Pipeline {
+    stages: Vec<Box<dyn MessageProcessor>>,
+}
+
+impl Pipeline {
+    /// Assemble the stages named in `recorder.processors`, skipping those
+    /// whose own section is disabled.
+    pub fn from_config(config: &AppConfig) -> anyhow::Result<Self> {
+        let mut stages: Vec<Box<dyn MessageProcessor>> = Vec::new();
+        for name in &config.recorder.processors {
+            match name.as_str() {
+                "entities" if config.entities.enabled => {
+                    stages.push(Box::new(EntityExtractor::new(&config.entities)?))
+                }
+                "classifier" if config.classifier.enabled => {
+                    stages.push(Box::new(MessageClassifier::new(&config.classifier)?))
+                }
+                "entities" | "classifier" => {}
+                other => anyhow::bail!("Unknown message processor '{other}'"),
+            }
+        }
+        Ok(Self { stages })
+    }
+
+    /// Run every stage in order. Enrichment is an extra, so a failing stage
+    /// is logged and skipped rather than losing the message.
+    pub async fn run(&self, message: &mut ChatMessage) {
+        for stage in &self.stages {
+            if let Err(e) = stage.process(message).await {
+                tracing::warn!(
+                    "Processor {} failed on {}_{}: {e}",
+                    stage.name(),
+                    message.chat_id,
+                    message.message_id
+                );
+            }
+        }
+    }
+}
+
+impl MessageProcessor for EntityExtractor {
+    fn name(&self) -> &'static str {
+        "entities"
+    }
+
+    fn process<'a>(&'a self, message: &'a mut ChatMessage) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            if message.text.is_empty() {
+                return Ok(());
+            }
+            let found = self.extract(&message.text).await?;
+            message.people = found.people;
+            message.orgs = found.orgs;
+            message.places = found.places;
+            Ok(())
+        })
+    }
+}
+
+impl MessageProcessor for MessageClassifier {
+    fn name(&self) -> &'static str {
+        "classifier"
+    }
+
+    fn process<'a>(&'a self, message: &'a mut ChatMessage) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            if message.text.is_empty() {
+                return Ok(());
+            }
+            let scores = self.classify(&message.text).await?;
+            message.sentiment = Some(scores.sentiment);
+            message.toxicity = Some(scores.toxicity);
+            Ok(())
+        })
+    }
+}
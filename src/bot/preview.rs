@@ -0,0 +1,52 @@
+//! `/preview`: how search results in this chat show link previews.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+
+use crate::es::settings::{LinkPreviewMode, SettingsStore};
+
+const USAGE: &str = "用法:\n\
+    /preview auto — 由 Telegram 决定预览哪个链接\n\
+    /preview off — 搜索结果不显示链接预览\n\
+    /preview top — 预览第一条结果";
+
+/// Handle `/preview auto|off|top` (admins).
+pub async fn handle_preview(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    settings: Arc<SettingsStore>,
+) -> anyhow::Result<()> {
+    let mode = match args.trim() {
+        "auto" => LinkPreviewMode::Auto,
+        "off" => LinkPreviewMode::Off,
+        "top" => LinkPreviewMode::TopHit,
+        _ => {
+            let current = settings.get(msg.chat.id.0).await?.link_preview;
+            bot.send_message(
+                msg.chat.id,
+                format!("当前设置：{}\n\n{USAGE}", mode_label(current)),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    settings
+        .update(msg.chat.id.0, |s| s.link_preview = mode)
+        .await?;
+    bot.send_message(
+        msg.chat.id,
+        format!("搜索结果的链接预览已设为：{}", mode_label(mode)),
+    )
+    .await?;
+    Ok(())
+}
+
+fn mode_label(mode: LinkPreviewMode) -> &'static str {
+    match mode {
+        LinkPreviewMode::Auto => "自动",
+        LinkPreviewMode::Off => "关闭",
+        LinkPreviewMode::TopHit => "预览第一条结果",
+    }
+}
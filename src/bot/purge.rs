@@ -0,0 +1,285 @@
+//! Bulk deletes from the index after the requesting admin confirms:
+//! `/purge_before <date>` removes the chat's messages older than a date,
+//! `/forgetuser` removes everything a user sent in the chat.
+
+use chrono::NaiveDate;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::{
+    InlineKeyboardButton, InlineKeyboardMarkup, MaybeInaccessibleMessage, MessageId,
+};
+
+use crate::bot::util::format_timestamp;
+use crate::es::admin::{AdminClient, DeleteProgress};
+use crate::jobs::{JobHandle, JobKind, JobQueue};
+
+/// Callback data prefix of the confirmation buttons: `purge:<scope>:<user_id>`
+/// to delete, `purge:cancel:<user_id>` to abort.
+pub const CALLBACK_PREFIX: &str = "purge:";
+const CANCEL: &str = "cancel";
+const USAGE: &str = "用法: /purge_before YYYY-MM-DD，例如 /purge_before 2023-01-01";
+const FORGET_USAGE: &str = "用法: 回复某人的消息发送 /forgetuser，或指定 @用户名 / 用户 ID";
+
+/// Which of the chat's messages a purge removes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PurgeScope {
+    /// Messages sent before the unix timestamp
+    Before(i64),
+    /// Messages sent by the user id
+    User(i64),
+}
+
+impl PurgeScope {
+    /// Encoding in callback data: the timestamp, or `u<user_id>`.
+    fn key(self) -> String {
+        match self {
+            Self::Before(ts) => ts.to_string(),
+            Self::User(id) => format!("u{id}"),
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        match key.strip_prefix('u') {
+            Some(id) => id.parse().ok().map(Self::User),
+            None => key.parse().ok().map(Self::Before),
+        }
+    }
+
+    fn filter(self) -> Value {
+        match self {
+            Self::Before(ts) => json!({ "range": { "date": { "lt": ts } } }),
+            Self::User(id) => json!({ "term": { "user_id": id } }),
+        }
+    }
+
+    /// The purged messages, e.g. `2023-01-01 之前的消息`.
+    fn describe(self) -> String {
+        match self {
+            Self::Before(ts) => {
+                let day: String = format_timestamp(ts).chars().take(10).collect();
+                format!("{day} 之前的消息")
+            }
+            Self::User(id) => format!("用户 {id} 的消息"),
+        }
+    }
+}
+
+pub fn is_purge_callback(q: &CallbackQuery) -> bool {
+    q.data
+        .as_deref()
+        .is_some_and(|d| d.starts_with(CALLBACK_PREFIX))
+}
+
+/// Handle `/purge_before <date>` by asking for confirmation; nothing is
+/// deleted until the requester presses the button.
+pub async fn handle_purge_before(bot: Bot, msg: Message, args: String) -> anyhow::Result<()> {
+    let Some(before) = NaiveDate::parse_from_str(args.trim(), "%Y-%m-%d")
+        .ok()
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .map(|midnight| midnight.and_utc().timestamp())
+    else {
+        bot.send_message(msg.chat.id, USAGE).await?;
+        return Ok(());
+    };
+    confirm(&bot, &msg, PurgeScope::Before(before), None).await
+}
+
+/// Handle `/forgetuser [@username|id]`, or as a reply to one of the user's
+/// messages. Usernames are resolved from the user's indexed messages.
+pub async fn handle_forget_user(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    admin: Arc<AdminClient>,
+) -> anyhow::Result<()> {
+    if msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "请在群组中使用此命令。")
+            .await?;
+        return Ok(());
+    }
+    let args = args.trim();
+    let (user_id, username) = if let Some(name) = args.strip_prefix('@') {
+        let name = name.to_lowercase();
+        if name.is_empty() {
+            bot.send_message(msg.chat.id, FORGET_USAGE).await?;
+            return Ok(());
+        }
+        match admin.resolve_username(msg.chat.id.0, &name).await? {
+            Some(id) => (id, Some(name)),
+            None => {
+                bot.send_message(msg.chat.id, format!("索引中没有 @{name} 的消息。"))
+                    .await?;
+                return Ok(());
+            }
+        }
+    } else if let Ok(id) = args.parse() {
+        (id, None)
+    } else if let Some(user) = msg
+        .reply_to_message()
+        .and_then(|reply| reply.from.as_ref())
+        .filter(|_| args.is_empty())
+    {
+        (user.id.0 as i64, user.username.clone())
+    } else {
+        bot.send_message(msg.chat.id, FORGET_USAGE).await?;
+        return Ok(());
+    };
+    confirm(&bot, &msg, PurgeScope::User(user_id), username.as_deref()).await
+}
+
+/// Ask the sender of `msg` to confirm a purge of `scope`.
+async fn confirm(
+    bot: &Bot,
+    msg: &Message,
+    scope: PurgeScope,
+    username: Option<&str>,
+) -> anyhow::Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    let mut target = scope.describe();
+    if let Some(name) = username {
+        target = format!("@{name} ({target})");
+    }
+
+    let markup = InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback(
+            "确认删除",
+            format!("{CALLBACK_PREFIX}{}:{}", scope.key(), user.id),
+        ),
+        InlineKeyboardButton::callback("取消", format!("{CALLBACK_PREFIX}{CANCEL}:{}", user.id)),
+    ]]);
+    bot.send_message(
+        msg.chat.id,
+        format!("确定删除本群索引中{target}吗？此操作无法撤销。"),
+    )
+    .reply_markup(markup)
+    .await?;
+    Ok(())
+}
+
+/// Start or cancel a confirmed purge. Only the admin who asked may answer;
+/// the purge runs as a background job.
+pub async fn handle_purge_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    admin: Arc<AdminClient>,
+    jobs: Arc<JobQueue>,
+) -> anyhow::Result<()> {
+    let Some(MaybeInaccessibleMessage::Regular(msg)) = q.message.as_ref() else {
+        bot.answer_callback_query(q.id.clone()).await?;
+        return Ok(());
+    };
+    let Some((action, requester)) = q
+        .data
+        .as_deref()
+        .and_then(|d| d.strip_prefix(CALLBACK_PREFIX))
+        .and_then(|d| d.rsplit_once(':'))
+    else {
+        bot.answer_callback_query(q.id.clone()).await?;
+        return Ok(());
+    };
+    if requester != q.from.id.to_string() {
+        bot.answer_callback_query(q.id.clone())
+            .text("只有发起删除的管理员可以确认")
+            .await?;
+        return Ok(());
+    }
+    bot.answer_callback_query(q.id.clone()).await?;
+
+    if action == CANCEL {
+        bot.edit_message_text(msg.chat.id, msg.id, "已取消删除。")
+            .await?;
+        return Ok(());
+    }
+    let Some(scope) = PurgeScope::from_key(action) else {
+        return Ok(());
+    };
+
+    tracing::info!("User {} purging chat {}: {scope:?}", q.from.id, msg.chat.id);
+    let (chat_id, message_id) = (msg.chat.id, msg.id);
+    let id = jobs.spawn(
+        JobKind::Purge,
+        chat_id.0,
+        q.from.id.0 as i64,
+        scope.describe(),
+        {
+            let bot = bot.clone();
+            move |job| run_purge(bot, admin, job, chat_id, message_id, scope)
+        },
+    );
+    bot.edit_message_text(
+        msg.chat.id,
+        msg.id,
+        format!("正在删除{}…（任务 #{id}）", scope.describe()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Delete the messages of `scope`, keeping the confirmation message
+/// `message_id` current with the progress and, at the end, the outcome.
+async fn run_purge(
+    bot: Bot,
+    admin: Arc<AdminClient>,
+    job: JobHandle,
+    chat_id: ChatId,
+    message_id: MessageId,
+    scope: PurgeScope,
+) -> anyhow::Result<()> {
+    let target = scope.describe();
+    let result = admin
+        .delete_by_query(
+            chat_id.0,
+            vec![scope.filter()],
+            job.token(),
+            |progress: DeleteProgress| {
+                job.set_progress(progress.deleted, Some(progress.total));
+                let bot = bot.clone();
+                let text = format!(
+                    "正在删除{target}…（任务 #{}）{}/{}",
+                    job.id(),
+                    progress.deleted,
+                    progress.total
+                );
+                async move {
+                    if progress.completed {
+                        return;
+                    }
+                    if let Err(e) = bot.edit_message_text(chat_id, message_id, text).await
+                        && !e.to_string().contains("message is not modified")
+                    {
+                        tracing::warn!("Failed to update purge progress: {e}");
+                    }
+                }
+            },
+        )
+        .await;
+
+    let text = match &result {
+        Ok(progress) => summary(progress, &target),
+        Err(_) => "删除失败，请稍后再试。".to_string(),
+    };
+    bot.edit_message_text(chat_id, message_id, text).await?;
+    result.map(|_| ())
+}
+
+fn summary(progress: &DeleteProgress, target: &str) -> String {
+    let mut text = if progress.cancelled {
+        format!("删除已取消，此前已删除 {} 条{target}。", progress.deleted)
+    } else {
+        format!("已删除 {} 条{target}。", progress.deleted)
+    };
+    if progress.version_conflicts > 0 {
+        text.push_str(&format!(
+            "\n{} 条消息在删除时被修改，已跳过。",
+            progress.version_conflicts
+        ));
+    }
+    if !progress.failures.is_empty() {
+        text.push_str(&format!("\n{} 条消息删除失败。", progress.failures.len()));
+        tracing::warn!("Purge failures: {:?}", progress.failures);
+    }
+    text
+}
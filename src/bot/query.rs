@@ -0,0 +1,431 @@
+//! Parser for the `/s` query syntax.
+//!
+//! Operator tokens (`id:123`, `from:alice`, `type:photo`, ...) may appear
+//! anywhere in the query; everything else is joined back into the full-text
+//! keyword. The operators are registered in [`OPERATORS`], which `/syntax`
+//! and the help's filter page are rendered from.
+//!
+//! Queries are normalized first: full-width letters, digits and punctuation
+//! become their ASCII forms, zero-width characters are dropped and keywords
+//! are split where CJK text meets Latin letters or digits, the way the IK
+//! analyzer splits indexed text, so `docker部署` searches like `docker 部署`.
+
+use crate::es::search::{
+    is_emoji, Attachment, GeoFilter, SearchParams, SearchSort, MAX_RESULT_WINDOW,
+};
+use crate::es::settings::Language;
+use crate::models::message::MessageType;
+
+/// Longest accepted query, in characters.
+pub const MAX_QUERY_CHARS: usize = 256;
+/// Most keywords and operators one query may combine.
+pub const MAX_QUERY_TERMS: usize = 16;
+
+/// Why a query was refused; the message tells the user how to fix it.
+#[derive(Debug, thiserror::Error)]
+pub enum QueryError {
+    #[error("搜索内容太长（最多 {MAX_QUERY_CHARS} 个字符），请精简关键词。")]
+    TooLong,
+    #[error("搜索条件太多（最多 {MAX_QUERY_TERMS} 个关键词和过滤器），请拆分成几次搜索。")]
+    TooManyTerms,
+    #[error("翻页太深（最多查看前 {MAX_RESULT_WINDOW} 条结果），请缩小搜索范围。")]
+    PageTooDeep,
+}
+
+/// Check a raw query against the limits above, returning it normalized with
+/// control characters replaced by spaces.
+pub fn validate_query(raw: &str) -> Result<String, QueryError> {
+    let query: String = normalize_query(raw)
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    let query = query.trim();
+
+    if query.chars().count() > MAX_QUERY_CHARS {
+        return Err(QueryError::TooLong);
+    }
+    let terms: Vec<&str> = query.split_whitespace().collect();
+    if terms.len() > MAX_QUERY_TERMS {
+        return Err(QueryError::TooManyTerms);
+    }
+    Ok(terms.join(" "))
+}
+
+/// Validate a bare keyword, as the API sends it, and split it where scripts
+/// meet like [`parse_query`] splits the words of a query.
+pub fn validate_keyword(raw: &str) -> Result<String, QueryError> {
+    let keyword = validate_query(raw)?;
+    Ok(keyword
+        .split(' ')
+        .map(split_scripts)
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// Map full-width ASCII variants and the ideographic space to ASCII, and drop
+/// zero-width characters. Joiners inside emoji sequences are kept.
+pub fn normalize_query(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut prev = None;
+    for c in raw.chars() {
+        let mapped = match c {
+            '\u{3000}' => Some(' '),
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0),
+            '\u{200D}' if prev.is_some_and(|p| is_emoji(p) || p == '\u{FE0F}') => Some(c),
+            '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}' => None,
+            _ => Some(c),
+        };
+        if let Some(mapped) = mapped {
+            out.push(mapped);
+        }
+        prev = Some(c);
+    }
+    out
+}
+
+/// Put a space wherever CJK text meets an ASCII letter or digit.
+fn split_scripts(word: &str) -> String {
+    let mut out = String::with_capacity(word.len() + 4);
+    let mut prev: Option<char> = None;
+    for c in word.chars() {
+        if let Some(p) = prev
+            && ((is_cjk(p) && c.is_ascii_alphanumeric())
+                || (p.is_ascii_alphanumeric() && is_cjk(c)))
+        {
+            out.push(' ');
+        }
+        out.push(c);
+        prev = Some(c);
+    }
+    out
+}
+
+/// Han ideographs, kana and hangul.
+fn is_cjk(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x3040..=0x30FF
+            | 0x3400..=0x4DBF
+            | 0x4E00..=0x9FFF
+            | 0xAC00..=0xD7AF
+            | 0xF900..=0xFAFF
+            | 0x20000..=0x2FA1F
+    )
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParsedQuery {
+    pub keyword: String,
+    pub user_id: Option<i64>,
+    /// `@name`, lowercased
+    pub username: Option<String>,
+    pub from: Option<String>,
+    pub message_type: Option<String>,
+    pub has: Vec<Attachment>,
+    pub file_ext: Option<String>,
+    pub mime_type: Option<String>,
+    pub near: Option<GeoFilter>,
+    pub entity: Option<String>,
+    pub caption: Option<String>,
+    pub toxic: bool,
+    pub sort: SearchSort,
+    /// Senders to rank higher; set by the caller, not part of the syntax
+    pub boost_users: Vec<i64>,
+}
+
+impl ParsedQuery {
+    /// The search of `chat_id` this query describes, on its first page; pass
+    /// it to [`crate::es::search::search_body`] for the Elasticsearch query.
+    pub fn into_params(self, chat_id: i64) -> SearchParams {
+        SearchParams {
+            chat_id,
+            keyword: Some(self.keyword),
+            user_id: self.user_id,
+            username: self.username,
+            from: self.from,
+            message_type: self.message_type,
+            has: self.has,
+            file_ext: self.file_ext,
+            mime_type: self.mime_type,
+            near: self.near,
+            entity: self.entity,
+            caption: self.caption,
+            toxic: self.toxic,
+            sort: self.sort,
+            boost_users: self.boost_users,
+            ..Default::default()
+        }
+    }
+}
+
+/// A filter token of the query syntax: a prefix and its value, e.g.
+/// `from:alice`.
+pub struct Operator {
+    pub prefix: &'static str,
+    /// Value shown in the syntax reference
+    pub example: &'static str,
+    /// Accepted values when they are a fixed set
+    pub values: &'static [&'static str],
+    summary_zh: &'static str,
+    summary_en: &'static str,
+    /// Store the value in the query; `false` keeps the token as a keyword
+    apply: fn(&str, &mut ParsedQuery) -> bool,
+}
+
+impl Operator {
+    pub fn summary(&self, language: Language) -> &'static str {
+        match language {
+            Language::Zh => self.summary_zh,
+            Language::En => self.summary_en,
+        }
+    }
+}
+
+/// Every operator the parser understands, in the order they're documented.
+pub static OPERATORS: &[Operator] = &[
+    Operator {
+        prefix: "id:",
+        example: "123456",
+        values: &[],
+        summary_zh: "只看指定用户 ID 的消息",
+        summary_en: "Only messages of this user id",
+        apply: |v, q| v.parse().map(|id| q.user_id = Some(id)).is_ok(),
+    },
+    Operator {
+        prefix: "from:",
+        example: "alice",
+        values: &[],
+        summary_zh: "按发送者用户名或昵称过滤，曾用名也可以，也适用于桥接消息的原作者",
+        summary_en: "Sender username or display name, former ones included, or the author relayed by a bridge",
+        apply: |v, q| {
+            q.from = Some(v.to_string());
+            true
+        },
+    },
+    Operator {
+        prefix: "@",
+        example: "alice",
+        values: &[],
+        summary_zh: "只看该用户名的用户的消息，改过用户名也能找到",
+        summary_en: "Only messages of the user with this username, even if they changed it since",
+        apply: |v, q| {
+            let valid = v.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if valid {
+                q.username = Some(v.to_lowercase());
+            }
+            valid
+        },
+    },
+    Operator {
+        prefix: "type:",
+        example: "photo",
+        values: MessageType::NAMES,
+        summary_zh: "按消息类型过滤",
+        summary_en: "Message type",
+        apply: |v, q| {
+            let known = v.parse::<MessageType>().is_ok();
+            if known {
+                q.message_type = Some(v.to_string());
+            }
+            known
+        },
+    },
+    Operator {
+        prefix: "has:",
+        example: "link",
+        values: Attachment::NAMES,
+        summary_zh: "包含指定内容，可以写多个",
+        summary_en: "Messages carrying this; may be repeated",
+        apply: |v, q| {
+            let Ok(attachment) = v.parse() else {
+                return false;
+            };
+            if !q.has.contains(&attachment) {
+                q.has.push(attachment);
+            }
+            true
+        },
+    },
+    Operator {
+        prefix: "ext:",
+        example: "pdf",
+        values: &[],
+        summary_zh: "按文件扩展名过滤",
+        summary_en: "File extension",
+        apply: |v, q| {
+            q.file_ext = Some(v.trim_start_matches('.').to_lowercase());
+            true
+        },
+    },
+    Operator {
+        prefix: "mime:",
+        example: "image/*",
+        values: &[],
+        summary_zh: "按文件 MIME 类型过滤，支持 类型/* 前缀",
+        summary_en: "File MIME type, or a type/* prefix",
+        apply: |v, q| {
+            let valid = v.contains('/');
+            if valid {
+                q.mime_type = Some(v.to_lowercase());
+            }
+            valid
+        },
+    },
+    Operator {
+        prefix: "near:",
+        example: "31.23,121.47,2km",
+        values: &[],
+        summary_zh: "搜索坐标附近的位置和地点，半径默认 1km",
+        summary_en: "Locations and venues near a point, within 1km unless a radius is given",
+        apply: |v, q| parse_near(v).map(|near| q.near = Some(near)).is_some(),
+    },
+    Operator {
+        prefix: "entity:",
+        example: "名称",
+        values: &[],
+        summary_zh: "提到该人物、组织或地点的消息（需启用实体识别）",
+        summary_en: "Messages naming this person, organization or place (needs entity extraction)",
+        apply: |v, q| {
+            q.entity = Some(v.to_string());
+            true
+        },
+    },
+    Operator {
+        prefix: "caption:",
+        example: "关键词",
+        values: &[],
+        summary_zh: "只搜索图片、视频等媒体的说明文字",
+        summary_en: "Words in the caption of a photo, video or other media",
+        apply: |v, q| {
+            q.caption = Some(split_scripts(v));
+            true
+        },
+    },
+    Operator {
+        prefix: "is:",
+        example: "toxic",
+        values: &[],
+        summary_zh: "只看高毒性消息（需启用消息分类）",
+        summary_en: "Only highly toxic messages (needs the classifier)",
+        apply: |v, q| {
+            q.toxic |= v == "toxic";
+            v == "toxic"
+        },
+    },
+    Operator {
+        prefix: "sort:",
+        example: "views",
+        values: SearchSort::NAMES,
+        summary_zh: "排序方式，views 和 forwards 按频道消息的浏览量和转发数",
+        summary_en: "Result order; views and forwards rank channel posts",
+        apply: |v, q| v.parse().map(|sort| q.sort = sort).is_ok(),
+    },
+];
+
+pub fn parse_query(query: &str, reply_user_id: Option<i64>) -> ParsedQuery {
+    let mut parsed = ParsedQuery {
+        user_id: reply_user_id,
+        ..Default::default()
+    };
+    let mut words = vec![];
+
+    for token in query.split_whitespace() {
+        let applied = OPERATORS.iter().any(|op| {
+            token
+                .strip_prefix(op.prefix)
+                .filter(|value| !value.is_empty())
+                .is_some_and(|value| (op.apply)(value, &mut parsed))
+        });
+        if !applied {
+            words.push(split_scripts(token));
+        }
+    }
+
+    parsed.keyword = words.join(" ");
+    parsed
+}
+
+/// Parse `lat,lon[,radius]` where radius is `500m`, `5km` or a bare number of
+/// kilometers (default 1km).
+fn parse_near(s: &str) -> Option<GeoFilter> {
+    let mut parts = s.split(',');
+    let lat: f64 = parts.next()?.parse().ok()?;
+    let lon: f64 = parts.next()?.parse().ok()?;
+    let radius_m = match parts.next() {
+        None => 1000.0,
+        Some(r) => {
+            if let Some(km) = r.strip_suffix("km") {
+                km.parse::<f64>().ok()? * 1000.0
+            } else if let Some(m) = r.strip_suffix('m') {
+                m.parse().ok()?
+            } else {
+                r.parse::<f64>().ok()? * 1000.0
+            }
+        }
+    };
+    if parts.next().is_some()
+        || !(-90.0..=90.0).contains(&lat)
+        || !(-180.0..=180.0).contains(&lon)
+        || radius_m <= 0.0
+    {
+        return None;
+    }
+    Some(GeoFilter { lat, lon, radius_m })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_width_and_invisible_characters() {
+        assert_eq!(normalize_query("ｄｏｃｋｅｒ　１２３"), "docker 123");
+        assert_eq!(normalize_query("from：alice"), "from:alice");
+        assert_eq!(normalize_query("dock\u{200B}er\u{FEFF}"), "docker");
+        // The joiner of an emoji sequence is part of the emoji
+        assert_eq!(normalize_query("👨\u{200D}💻"), "👨\u{200D}💻");
+        assert_eq!(normalize_query("a\u{200D}b"), "ab");
+    }
+
+    #[test]
+    fn splits_cjk_latin_boundaries_of_keywords() {
+        let mixed = parse_query(&validate_query("docker部署").unwrap(), None);
+        let spaced = parse_query(&validate_query("docker 部署").unwrap(), None);
+        assert_eq!(mixed.keyword, "docker 部署");
+        assert_eq!(mixed.keyword, spaced.keyword);
+        assert_eq!(parse_query("用win10装k8s", None).keyword, "用 win10 装 k8s");
+
+        // Operator values are left alone
+        let parsed = parse_query("from:张三abc 部署", None);
+        assert_eq!(parsed.from.as_deref(), Some("张三abc"));
+    }
+
+    #[test]
+    fn mentions_filter_by_username() {
+        let parsed = parse_query("@Alice_01 部署", None);
+        assert_eq!(parsed.username.as_deref(), Some("alice_01"));
+        assert_eq!(parsed.keyword, "部署");
+
+        // Not a username: stays a keyword
+        let parsed = parse_query("@所有人 ops@example.com", None);
+        assert_eq!(parsed.username, None);
+        assert_eq!(parsed.keyword, "@所有人 ops@example.com");
+    }
+
+    #[test]
+    fn documented_operators_parse() {
+        for op in OPERATORS {
+            let example = format!("{}{}", op.prefix, op.example);
+            assert_eq!(parse_query(&example, None).keyword, "", "{example}");
+            for value in op.values {
+                let token = format!("{}{value}", op.prefix);
+                assert_eq!(parse_query(&token, None).keyword, "", "{token}");
+            }
+        }
+        // Invalid values stay keywords
+        assert_eq!(
+            parse_query("type:nope has:", None).keyword,
+            "type:nope has:"
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! `/quiet [period]`: members who used to post but have no indexed messages
+//! within the period, for re-engaging them.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::util::{format_timestamp, html_escape, parse_period};
+use crate::es::analytics::{AnalyticsClient, QuietMember};
+
+/// Period used when none is given.
+const DEFAULT_PERIOD_SECS: i64 = 30 * 86400;
+/// Messages a member needs in total to count as previously active.
+const MIN_MESSAGES: u64 = 5;
+/// Members listed before the list is cut off.
+const MAX_LISTED: usize = 50;
+
+/// Handle `/quiet [period]` (admins).
+pub async fn handle_quiet(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    analytics: Arc<AnalyticsClient>,
+) -> anyhow::Result<()> {
+    let args = args.trim();
+    let period = if args.is_empty() {
+        DEFAULT_PERIOD_SECS
+    } else {
+        match parse_period(args) {
+            Some(secs) => secs,
+            None => {
+                bot.send_message(msg.chat.id, "用法: /quiet [时间段]，例如 /quiet 60d")
+                    .await?;
+                return Ok(());
+            }
+        }
+    };
+
+    let since = chrono::Utc::now().timestamp() - period;
+    let members = analytics
+        .quiet_members(msg.chat.id.0, since, MIN_MESSAGES)
+        .await?;
+
+    bot.send_message(msg.chat.id, format_quiet(&members, args))
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+fn format_quiet(members: &[QuietMember], period: &str) -> String {
+    let period = if period.is_empty() { "30d" } else { period };
+    if members.is_empty() {
+        return format!("最近 {} 内没有沉寂的活跃成员。", html_escape(period));
+    }
+
+    let mut text = format!(
+        "<b>沉寂成员</b>（曾发言至少 {MIN_MESSAGES} 条，最近 {} 内没有发言，共 {} 人）\n",
+        html_escape(period),
+        members.len()
+    );
+    for member in members.iter().take(MAX_LISTED) {
+        let name = member.display_name.as_deref().unwrap_or("（未知）");
+        text.push_str(&format!("\n• {}", html_escape(name)));
+        if let Some(ref username) = member.username {
+            text.push_str(&format!(" @{}", html_escape(username)));
+        }
+        text.push_str(&format!(
+            " — {} 条，最后发言 {}",
+            member.messages,
+            format_timestamp(member.last_date)
+        ));
+    }
+    if members.len() > MAX_LISTED {
+        text.push_str(&format!("\n\n…另有 {} 人", members.len() - MAX_LISTED));
+    }
+    text
+}
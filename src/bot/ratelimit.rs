@@ -0,0 +1,155 @@
+//! Sliding-window rate limiter shared by command handlers.
+
+use dashmap::DashMap;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::config::{RateLimitConfig, RateLimitRule};
+
+/// Entries kept by [`RateLimiter`] and [`CallbackThrottle`] before stale ones
+/// are pruned.
+const THROTTLE_PRUNE_THRESHOLD: usize = 10_000;
+
+pub struct RateLimiter {
+    default_rule: Option<RateLimitRule>,
+    rules: HashMap<String, RateLimitRule>,
+    hits: DashMap<(String, i64), VecDeque<Instant>>,
+}
+
+/// Returned when a key has exhausted its budget.
+#[derive(Debug)]
+pub struct Cooldown {
+    pub rule: RateLimitRule,
+    pub retry_after: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            default_rule: config.default,
+            rules: config.commands.clone(),
+            hits: DashMap::new(),
+        }
+    }
+
+    /// Record an attempt of `command` by `key` (usually the user id), or
+    /// report how long the caller has to wait if the budget is used up.
+    pub fn check(&self, command: &str, key: i64) -> Result<(), Cooldown> {
+        let Some(rule) = self.rule(command) else {
+            return Ok(());
+        };
+        if rule.limit == 0 {
+            return Ok(());
+        }
+
+        let window = Duration::from_secs(rule.window_secs);
+        let now = Instant::now();
+        if self.hits.len() > THROTTLE_PRUNE_THRESHOLD {
+            // Drop keys whose last attempt has left the window of their command
+            self.hits.retain(|(other, _), attempts| {
+                attempts.back().is_some_and(|t| {
+                    self.rule(other).is_some_and(|r| {
+                        now.duration_since(*t) < Duration::from_secs(r.window_secs)
+                    })
+                })
+            });
+        }
+        let mut entry = self.hits.entry((command.to_string(), key)).or_default();
+        while entry
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= window)
+        {
+            entry.pop_front();
+        }
+
+        if entry.len() >= rule.limit as usize {
+            let oldest = *entry.front().expect("non-empty when over limit");
+            return Err(Cooldown {
+                rule,
+                retry_after: window.saturating_sub(now.duration_since(oldest)),
+            });
+        }
+
+        entry.push_back(now);
+        Ok(())
+    }
+
+    fn rule(&self, command: &str) -> Option<RateLimitRule> {
+        self.rules.get(command).copied().or(self.default_rule)
+    }
+}
+
+impl Cooldown {
+    /// Human readable cooldown reply for `/command`.
+    pub fn message(&self, command: &str) -> String {
+        format!(
+            "⏳ /{command} 每 {} 最多使用 {} 次，请在 {} 后再试。",
+            format_duration(self.rule.window_secs),
+            self.rule.limit,
+            format_duration(self.retry_after.as_secs().max(1)),
+        )
+    }
+}
+
+fn format_duration(secs: u64) -> String {
+    let (days, rem) = (secs / 86400, secs % 86400);
+    let (hours, rem) = (rem / 3600, rem % 3600);
+    let (mins, secs) = (rem / 60, rem % 60);
+    let mut out = String::new();
+    if days > 0 {
+        out.push_str(&format!("{days} 天"));
+    }
+    if hours > 0 {
+        out.push_str(&format!("{hours} 小时"));
+    }
+    if mins > 0 {
+        out.push_str(&format!("{mins} 分钟"));
+    }
+    if secs > 0 || out.is_empty() {
+        out.push_str(&format!("{secs} 秒"));
+    }
+    out
+}
+
+/// Debounces repeated inline keyboard presses per (user, chat, message),
+/// independently of the per-command budgets.
+pub struct CallbackThrottle {
+    min_interval: Duration,
+    last_press: DashMap<(i64, i64, i32), Instant>,
+}
+
+impl CallbackThrottle {
+    pub fn new(min_interval_ms: u64) -> Self {
+        Self {
+            min_interval: Duration::from_millis(min_interval_ms),
+            last_press: DashMap::new(),
+        }
+    }
+
+    /// Returns `false` when the same user pressed a button on the same message
+    /// less than `min_interval` ago.
+    pub fn allow(&self, user_id: i64, chat_id: i64, message_id: i32) -> bool {
+        if self.min_interval.is_zero() {
+            return true;
+        }
+
+        let now = Instant::now();
+        if self.last_press.len() > THROTTLE_PRUNE_THRESHOLD {
+            self.last_press
+                .retain(|_, t| now.duration_since(*t) < self.min_interval);
+        }
+
+        match self.last_press.entry((user_id, chat_id, message_id)) {
+            dashmap::Entry::Occupied(mut e) => {
+                if now.duration_since(*e.get()) < self.min_interval {
+                    return false;
+                }
+                e.insert(now);
+            }
+            dashmap::Entry::Vacant(e) => {
+                e.insert(now);
+            }
+        }
+        true
+    }
+}
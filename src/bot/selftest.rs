@@ -0,0 +1,58 @@
+//! `/selftest`: owner-only end-to-end check of the index, without waiting
+//! for real messages to arrive.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::util::html_escape;
+use crate::es::admin::{AdminClient, SelfTestStep};
+
+/// Handle `/selftest`: index, refresh, search and delete a synthetic message,
+/// replying with the latency of each step.
+pub async fn handle_selftest(
+    bot: Bot,
+    msg: Message,
+    admin: Arc<AdminClient>,
+) -> anyhow::Result<()> {
+    let status = bot.send_message(msg.chat.id, "正在自检…").await?;
+    let steps = admin.self_test().await;
+    bot.edit_message_text(msg.chat.id, status.id, format_steps(&steps))
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+fn format_steps(steps: &[SelfTestStep]) -> String {
+    let passed = steps.iter().all(|s| s.error.is_none());
+    let mut text = if passed {
+        "<b>自检通过</b>\n".to_string()
+    } else {
+        "<b>自检失败</b>\n".to_string()
+    };
+    for step in steps {
+        let mark = if step.error.is_none() { "✅" } else { "❌" };
+        text.push_str(&format!(
+            "{mark} {} — {} ms\n",
+            step_label(step.name),
+            step.elapsed.as_millis()
+        ));
+        if let Some(error) = &step.error {
+            let error: String = error.chars().take(300).collect();
+            text.push_str(&format!("<code>{}</code>\n", html_escape(&error)));
+        }
+    }
+    let total: u128 = steps.iter().map(|s| s.elapsed.as_millis()).sum();
+    text.push_str(&format!("合计 {total} ms"));
+    text
+}
+
+fn step_label(name: &str) -> &str {
+    match name {
+        "index" => "写入",
+        "refresh" => "刷新",
+        "search" => "搜索",
+        "delete" => "删除",
+        other => other,
+    }
+}
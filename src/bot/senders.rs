@@ -0,0 +1,125 @@
+//! `/senders`: which kinds of senders are indexed in this chat, e.g. to index
+//! an announcement bot while other bots stay out of search results.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::es::settings::{IndexedSenders, SettingsStore};
+
+const USAGE: &str = "用法:\n\
+    /senders — 查看本群设置\n\
+    /senders humans on|off — 是否索引成员的消息\n\
+    /senders bots on|off|default — 是否索引机器人的消息，default 跟随全局设置\n\
+    /senders allow 机器人ID — 始终索引该机器人的消息，也可回复它的消息发送\n\
+    /senders disallow 机器人ID — 取消上一条";
+
+/// Handle `/senders [humans|bots|allow|disallow ...]` (admins). `ignore_bots`
+/// is the global default for bots.
+pub async fn handle_senders(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    settings: Arc<SettingsStore>,
+    ignore_bots: bool,
+) -> anyhow::Result<()> {
+    if msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "请在群组中使用此命令。")
+            .await?;
+        return Ok(());
+    }
+    let mut words = args.split_whitespace();
+    let change = match (words.next(), words.next()) {
+        (None, _) => {
+            let current = settings.get(msg.chat.id.0).await?;
+            bot.send_message(
+                msg.chat.id,
+                format!("{}\n\n{USAGE}", describe(&current.senders, ignore_bots)),
+            )
+            .parse_mode(ParseMode::Html)
+            .await?;
+            return Ok(());
+        }
+        (Some("humans"), Some("on")) => Change::Humans(true),
+        (Some("humans"), Some("off")) => Change::Humans(false),
+        (Some("bots"), Some("on")) => Change::Bots(Some(true)),
+        (Some("bots"), Some("off")) => Change::Bots(Some(false)),
+        (Some("bots"), Some("default")) => Change::Bots(None),
+        (Some(action @ ("allow" | "disallow")), value) => {
+            let bot_id = match value {
+                Some(value) => value.parse().ok(),
+                None => msg
+                    .reply_to_message()
+                    .and_then(|reply| reply.from.as_ref())
+                    .filter(|user| user.is_bot)
+                    .map(|user| user.id.0 as i64),
+            };
+            match bot_id {
+                Some(id) if action == "allow" => Change::Allow(id),
+                Some(id) => Change::Disallow(id),
+                None => {
+                    bot.send_message(msg.chat.id, USAGE).await?;
+                    return Ok(());
+                }
+            }
+        }
+        _ => {
+            bot.send_message(msg.chat.id, USAGE).await?;
+            return Ok(());
+        }
+    };
+
+    let updated = settings
+        .update(msg.chat.id.0, |s| change.apply(&mut s.senders))
+        .await?;
+    bot.send_message(
+        msg.chat.id,
+        format!("已更新。\n\n{}", describe(&updated.senders, ignore_bots)),
+    )
+    .parse_mode(ParseMode::Html)
+    .await?;
+    Ok(())
+}
+
+enum Change {
+    Humans(bool),
+    Bots(Option<bool>),
+    Allow(i64),
+    Disallow(i64),
+}
+
+impl Change {
+    fn apply(self, senders: &mut IndexedSenders) {
+        match self {
+            Self::Humans(on) => senders.humans = on,
+            Self::Bots(on) => senders.bots = on,
+            Self::Allow(id) => {
+                if !senders.bot_ids.contains(&id) {
+                    senders.bot_ids.push(id);
+                }
+            }
+            Self::Disallow(id) => senders.bot_ids.retain(|&listed| listed != id),
+        }
+    }
+}
+
+fn describe(senders: &IndexedSenders, ignore_bots: bool) -> String {
+    let on_off = |on: bool| if on { "索引" } else { "不索引" };
+    let bots = match senders.bots {
+        Some(on) => on_off(on).to_string(),
+        None => format!("{}（跟随全局设置）", on_off(!ignore_bots)),
+    };
+    let mut text = format!(
+        "<b>本群索引的发送者</b>\n成员：{}\n机器人：{bots}",
+        on_off(senders.humans)
+    );
+    if !senders.bot_ids.is_empty() {
+        let ids: Vec<String> = senders
+            .bot_ids
+            .iter()
+            .map(|id| format!("<code>{id}</code>"))
+            .collect();
+        text.push_str(&format!("\n始终索引的机器人：{}", ids.join("、")));
+    }
+    text
+}
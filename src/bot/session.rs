@@ -0,0 +1,86 @@
+//! In-memory search sessions: recent queries per user, queries of result
+//! messages that can't be recovered from the message they reply to, and the
+//! hits each results message shows, which its per-hit buttons refer to by
+//! position.
+
+use dashmap::DashMap;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Result-message queries and hits kept before stale ones are pruned.
+const QUERY_PRUNE_THRESHOLD: usize = 10_000;
+/// Result-message queries and hits older than this are dropped when pruning.
+const QUERY_TTL: Duration = Duration::from_secs(7 * 86400);
+
+pub struct SessionStore {
+    history_size: usize,
+    /// (chat_id, user_id) → recent queries, newest first
+    history: DashMap<(i64, i64), VecDeque<String>>,
+    /// (chat_id, message_id) of a results message → the query it shows
+    queries: DashMap<(i64, i32), (String, Instant)>,
+    /// (chat_id, message_id) of a results message → message ids of its hits
+    hits: DashMap<(i64, i32), (Vec<i64>, Instant)>,
+}
+
+impl SessionStore {
+    pub fn new(history_size: usize) -> Self {
+        Self {
+            history_size,
+            history: DashMap::new(),
+            queries: DashMap::new(),
+            hits: DashMap::new(),
+        }
+    }
+
+    /// Remember `query` as the user's most recent search in `chat_id`.
+    pub fn push_history(&self, chat_id: i64, user_id: i64, query: &str) {
+        if self.history_size == 0 {
+            return;
+        }
+        let mut entry = self.history.entry((chat_id, user_id)).or_default();
+        entry.retain(|q| q != query);
+        entry.push_front(query.to_string());
+        entry.truncate(self.history_size);
+    }
+
+    pub fn history(&self, chat_id: i64, user_id: i64) -> Vec<String> {
+        self.history
+            .get(&(chat_id, user_id))
+            .map(|h| h.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Associate a results message with the query it displays.
+    pub fn set_query(&self, chat_id: i64, message_id: i32, query: &str) {
+        let now = Instant::now();
+        if self.queries.len() > QUERY_PRUNE_THRESHOLD {
+            self.queries
+                .retain(|_, (_, created)| now.duration_since(*created) < QUERY_TTL);
+        }
+        self.queries
+            .insert((chat_id, message_id), (query.to_string(), now));
+    }
+
+    pub fn query(&self, chat_id: i64, message_id: i32) -> Option<String> {
+        self.queries
+            .get(&(chat_id, message_id))
+            .map(|e| e.0.clone())
+    }
+
+    /// Remember the message ids of the hits a results message shows, in order.
+    pub fn set_hits(&self, chat_id: i64, message_id: i32, hits: Vec<i64>) {
+        let now = Instant::now();
+        if self.hits.len() > QUERY_PRUNE_THRESHOLD {
+            self.hits
+                .retain(|_, (_, created)| now.duration_since(*created) < QUERY_TTL);
+        }
+        self.hits.insert((chat_id, message_id), (hits, now));
+    }
+
+    /// Message id of the hit at `index` on a results message.
+    pub fn hit(&self, chat_id: i64, message_id: i32, index: usize) -> Option<i64> {
+        self.hits
+            .get(&(chat_id, message_id))
+            .and_then(|e| e.0.get(index).copied())
+    }
+}
@@ -0,0 +1,57 @@
+//! The bots of one deployment. Each group is served by exactly one of them:
+//! the shard it is assigned to in `telegram.shards`, or the primary bot.
+//! Private chats and inline queries are answered by whichever bot receives
+//! them, since users talk to a specific bot.
+
+use std::collections::HashMap;
+use teloxide::prelude::*;
+use teloxide::types::Chat;
+
+use crate::config::TelegramConfig;
+
+pub struct Shards {
+    /// The primary bot first, then one bot per configured shard
+    bots: Vec<Bot>,
+    /// Index into `bots` of the bot serving each assigned chat
+    owners: HashMap<i64, usize>,
+}
+
+/// Position of a dispatcher's bot in [`Shards`], injected into its handlers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShardId(pub usize);
+
+impl Shards {
+    pub fn new(config: &TelegramConfig) -> anyhow::Result<Self> {
+        let mut bots = vec![Bot::new(&config.bot_token)];
+        let mut owners = HashMap::new();
+        for (i, shard) in config.shards.iter().enumerate() {
+            bots.push(Bot::new(&shard.bot_token));
+            for &chat_id in &shard.chats {
+                if owners.insert(chat_id, i + 1).is_some() {
+                    anyhow::bail!("Chat {chat_id} is assigned to more than one shard");
+                }
+            }
+        }
+        Ok(Self { bots, owners })
+    }
+
+    pub fn primary(&self) -> &Bot {
+        &self.bots[0]
+    }
+
+    pub fn bots(&self) -> &[Bot] {
+        &self.bots
+    }
+
+    /// The bot that talks to `chat_id`; users' private chats go through the
+    /// primary bot.
+    pub fn bot_for(&self, chat_id: ChatId) -> &Bot {
+        let shard = self.owners.get(&chat_id.0).copied().unwrap_or(0);
+        &self.bots[shard]
+    }
+
+    /// Whether the bot of `shard` handles updates from `chat`.
+    pub fn serves(&self, shard: ShardId, chat: &Chat) -> bool {
+        chat.is_private() || self.owners.get(&chat.id.0).copied().unwrap_or(0) == shard.0
+    }
+}
@@ -0,0 +1,87 @@
+//! `/spamreport [period]`: senders of this chat that look like spam accounts,
+//! for admins to review. Nothing is acted on automatically.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::util::{format_message_link, html_escape, no_link_preview, parse_period};
+use crate::es::analytics::{AnalyticsClient, SpamSignal, SpamSuspect};
+
+/// Period checked when none is given.
+const DEFAULT_PERIOD_SECS: i64 = 7 * 86400;
+/// Suspects listed, within Telegram's message limit.
+const MAX_LISTED: usize = 20;
+
+/// Handle `/spamreport [period]` (admins).
+pub async fn handle_spam_report(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    analytics: Arc<AnalyticsClient>,
+) -> anyhow::Result<()> {
+    if msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "请在群组中使用此命令。")
+            .await?;
+        return Ok(());
+    }
+    let args = args.trim();
+    let period = if args.is_empty() {
+        DEFAULT_PERIOD_SECS
+    } else {
+        match parse_period(args) {
+            Some(secs) => secs,
+            None => {
+                bot.send_message(
+                    msg.chat.id,
+                    "用法: /spamreport [时间段]，例如 /spamreport 30d",
+                )
+                .await?;
+                return Ok(());
+            }
+        }
+    };
+
+    let since = chrono::Utc::now().timestamp() - period;
+    let suspects = analytics.spam_suspects(msg.chat.id.0, since).await?;
+    bot.send_message(msg.chat.id, format_report(msg.chat.id.0, &suspects))
+        .parse_mode(ParseMode::Html)
+        .link_preview_options(no_link_preview())
+        .await?;
+    Ok(())
+}
+
+fn format_report(chat_id: i64, suspects: &[SpamSuspect]) -> String {
+    if suspects.is_empty() {
+        return "没有发现可疑账号。".to_string();
+    }
+    let mut text = format!("<b>可疑账号（{}）</b>\n", suspects.len());
+    for suspect in suspects.iter().take(MAX_LISTED) {
+        let name = suspect
+            .display_name
+            .as_deref()
+            .or(suspect.username.as_deref())
+            .unwrap_or("（无名称）");
+        let signals: Vec<String> = suspect.signals.iter().map(|s| describe(*s)).collect();
+        text.push_str(&format!(
+            "\n• <a href=\"tg://user?id={id}\">{}</a> <code>{id}</code>\n  {} · <a href=\"{}\">消息</a>\n",
+            html_escape(name),
+            signals.join("，"),
+            format_message_link(chat_id, suspect.message_id),
+            id = suspect.user_id,
+        ));
+    }
+    if suspects.len() > MAX_LISTED {
+        text.push_str(&format!("\n…另有 {} 个", suspects.len() - MAX_LISTED));
+    }
+    text.push_str("\n点击名称打开资料页，或打开消息后长按以封禁。");
+    text
+}
+
+fn describe(signal: SpamSignal) -> String {
+    match signal {
+        SpamSignal::LinkFirst => "第一条消息带链接或提及".to_string(),
+        SpamSignal::ForwardsOnly { messages } => format!("只发转发（{messages} 条）"),
+        SpamSignal::Burst { copies } => format!("一小时内重复发送 {copies} 次"),
+    }
+}
@@ -0,0 +1,213 @@
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::util::{format_bytes, format_message_link, html_escape};
+use crate::es::analytics::{AnalyticsClient, ChatStats, ReplyStats, StorageReport};
+use crate::es::indexer::{BatchIndexer, QueueSnapshot};
+
+/// Handle `/stats`: indexed message counts for the current chat, or the reply
+/// graph with `/stats replies`.
+pub async fn handle_stats(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    analytics: Arc<AnalyticsClient>,
+) -> anyhow::Result<()> {
+    let chat_id = msg.chat.id.0;
+    let text = match args.trim() {
+        "replies" => format_replies(&analytics.reply_stats(chat_id).await?, chat_id),
+        _ => format_stats(&analytics.chat_stats(chat_id).await?),
+    };
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+/// Handle `/storage` (admins): estimated index footprint of the current chat.
+pub async fn handle_storage(
+    bot: Bot,
+    msg: Message,
+    analytics: Arc<AnalyticsClient>,
+) -> anyhow::Result<()> {
+    let report = analytics.storage_report(msg.chat.id.0).await?;
+    bot.send_message(msg.chat.id, format_storage(&report))
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+/// Handle `/queue` (owners): internals of the indexer's flush loop.
+pub async fn handle_queue(
+    bot: Bot,
+    msg: Message,
+    indexer: Arc<BatchIndexer>,
+) -> anyhow::Result<()> {
+    bot.send_message(msg.chat.id, format_queue(&indexer.snapshot()))
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+fn format_queue(queue: &QueueSnapshot) -> String {
+    let last_flush = queue
+        .last_flush
+        .map_or_else(|| "尚无".to_string(), |d| format!("{} ms", d.as_millis()));
+    let error_rate = if queue.recent_bulks == 0 {
+        "无请求".to_string()
+    } else {
+        format!(
+            "{:.1}%（{}/{}）",
+            queue.recent_errors as f64 * 100.0 / queue.recent_bulks as f64,
+            queue.recent_errors,
+            queue.recent_bulks
+        )
+    };
+    format!(
+        "<b>索引队列</b>\n\
+         通道: {}/{}\n\
+         缓冲区: {} 条\n\
+         上次写入耗时: {last_flush}\n\
+         5 分钟批量写入错误率: {error_rate}\n\
+         待重放（死信）: {} 条",
+        queue.channel_depth, queue.channel_capacity, queue.buffered, queue.spooled,
+    )
+}
+
+fn format_storage(report: &StorageReport) -> String {
+    let share = if report.index_docs == 0 {
+        0.0
+    } else {
+        report.chat_docs as f64 * 100.0 / report.index_docs as f64
+    };
+    let mut text = format!(
+        "<b>本群存储占用（估算）</b>\n\
+         {} 条消息，约 <b>{}</b>（占索引 {share:.1}%）\n\
+         索引总计 {} 条 / {}，平均每条 {}\n",
+        report.chat_docs,
+        format_bytes(report.chat_bytes()),
+        report.index_docs,
+        format_bytes(report.index_bytes),
+        format_bytes(report.avg_doc_bytes()),
+    );
+
+    if !report.by_type.is_empty() {
+        text.push_str("\n<b>按类型：</b>\n");
+        for (kind, count, bytes) in &report.by_type {
+            text.push_str(&format!(
+                "{} — {count} 条，约 {}\n",
+                type_label(kind),
+                format_bytes(*bytes)
+            ));
+        }
+    }
+    text
+}
+
+fn format_stats(stats: &ChatStats) -> String {
+    let mut text = format!("<b>本群索引统计</b>\n共 <b>{}</b> 条消息\n", stats.total);
+    if stats.total == 0 {
+        return text;
+    }
+
+    text.push_str("\n<b>按类型：</b>\n");
+    for (kind, count) in &stats.by_type {
+        text.push_str(&format!("{} — {count}\n", type_label(kind)));
+    }
+    if stats.captioned > 0 {
+        text.push_str(&format!(
+            "（其中带说明文字的媒体 {} 条）\n",
+            stats.captioned
+        ));
+    }
+
+    if !stats.by_ext.is_empty() {
+        text.push_str("\n<b>文件扩展名：</b>\n");
+        for (ext, count) in &stats.by_ext {
+            text.push_str(&format!(".{} — {count}\n", html_escape(ext)));
+        }
+    }
+
+    if !stats.by_mime.is_empty() {
+        text.push_str("\n<b>文件类型：</b>\n");
+        for (mime, count) in &stats.by_mime {
+            text.push_str(&format!("{} — {count}\n", html_escape(mime)));
+        }
+    }
+    text
+}
+
+fn format_replies(stats: &ReplyStats, chat_id: i64) -> String {
+    if stats.top_messages.is_empty() {
+        return "本群还没有索引到回复消息。".to_string();
+    }
+    let name = |id: &i64| {
+        let label = stats
+            .names
+            .get(id)
+            .map_or_else(|| format!("User {id}"), |n| html_escape(n));
+        format!("<a href=\"tg://user?id={id}\">{label}</a>")
+    };
+
+    let mut text = "<b>本群回复统计</b>\n".to_string();
+    if let Some(secs) = stats.avg_latency_secs {
+        text.push_str(&format!("平均回复间隔：{}\n", format_duration(secs)));
+    }
+
+    if !stats.pairs.is_empty() {
+        text.push_str("\n<b>谁最常回复谁：</b>\n");
+        for (replier, author, count) in &stats.pairs {
+            text.push_str(&format!("{} → {} — {count}\n", name(replier), name(author)));
+        }
+    }
+
+    text.push_str("\n<b>回复最多的消息：</b>\n");
+    for (i, (message_id, count, snippet)) in stats.top_messages.iter().enumerate() {
+        let snippet: String = snippet.chars().take(30).collect();
+        let snippet = if snippet.is_empty() {
+            "（无文字）".to_string()
+        } else {
+            html_escape(&snippet)
+        };
+        text.push_str(&format!(
+            "{}. <a href=\"{}\">{snippet}</a> — {count} 条回复\n",
+            i + 1,
+            format_message_link(chat_id, *message_id)
+        ));
+    }
+    text
+}
+
+fn format_duration(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    match secs {
+        0..60 => format!("{secs} 秒"),
+        60..3600 => format!("{} 分钟", secs / 60),
+        3600..86400 => format!("{:.1} 小时", secs as f64 / 3600.0),
+        _ => format!("{:.1} 天", secs as f64 / 86400.0),
+    }
+}
+
+fn type_label(kind: &str) -> &str {
+    match kind {
+        "text" => "文字",
+        "photo" => "图片",
+        "video" => "视频",
+        "video_note" => "视频消息",
+        "document" => "文件",
+        "sticker" => "贴纸",
+        "voice" => "语音",
+        "audio" => "音频",
+        "animation" => "动图",
+        "location" => "位置",
+        "venue" => "地点",
+        "contact" => "联系人",
+        "game" => "游戏",
+        "dice" => "骰子",
+        "story" => "动态",
+        "pinned" => "置顶",
+        "other" => "其他",
+        other => other,
+    }
+}
@@ -0,0 +1,167 @@
+//! `/syntax`: the full search syntax reference, rendered from the parser's
+//! operator registry in the chat's language, and `/language` to choose it.
+
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::query::{MAX_QUERY_CHARS, MAX_QUERY_TERMS, OPERATORS};
+use crate::bot::util::html_escape;
+use crate::es::settings::{Language, SettingsStore};
+
+const LANGUAGE_USAGE: &str = "用法:\n\
+    /language zh — 参考文档使用中文\n\
+    /language en — 参考文档使用英文";
+
+/// Handle `/syntax [zh|en]`. Without an argument groups get their configured
+/// language and private chats the user's Telegram language.
+pub async fn handle_syntax(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    settings: Arc<SettingsStore>,
+) -> anyhow::Result<()> {
+    let language = match parse_language(&args) {
+        Some(language) => language,
+        None if msg.chat.is_private() => msg
+            .from
+            .as_ref()
+            .and_then(|user| user.language_code.as_deref())
+            .and_then(parse_language)
+            .unwrap_or_default(),
+        None => settings.get(msg.chat.id.0).await?.language,
+    };
+    bot.send_message(msg.chat.id, render(language))
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+/// Handle `/language zh|en` (admins).
+pub async fn handle_language(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    settings: Arc<SettingsStore>,
+) -> anyhow::Result<()> {
+    if msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "请在群组中使用此命令。")
+            .await?;
+        return Ok(());
+    }
+    let Some(language) = parse_language(&args) else {
+        let current = settings.get(msg.chat.id.0).await?.language;
+        bot.send_message(
+            msg.chat.id,
+            format!("当前语言：{}\n\n{LANGUAGE_USAGE}", label(current)),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    settings
+        .update(msg.chat.id.0, |s| s.language = language)
+        .await?;
+    bot.send_message(
+        msg.chat.id,
+        format!("本群参考文档的语言已设为：{}", label(language)),
+    )
+    .await?;
+    Ok(())
+}
+
+/// `zh`/`en`, also accepting IETF tags such as `zh-hans` or `en-US`.
+fn parse_language(code: &str) -> Option<Language> {
+    let code = code.trim().to_lowercase();
+    match code.split(['-', '_']).next()? {
+        "zh" => Some(Language::Zh),
+        "en" => Some(Language::En),
+        _ => None,
+    }
+}
+
+fn label(language: Language) -> &'static str {
+    match language {
+        Language::Zh => "中文",
+        Language::En => "English",
+    }
+}
+
+/// The whole reference: general rules, then one entry per operator.
+fn render(language: Language) -> String {
+    let (title, general, operators, values) = match language {
+        Language::Zh => (
+            "搜索语法",
+            vec![
+                ("/s 关键词".to_string(), "全文搜索本群消息，多个关键词之间用空格分隔".to_string()),
+                ("回复某人 + /s 关键词".to_string(), "只搜索被回复者的消息".to_string()),
+                (
+                    "限制".to_string(),
+                    format!("最多 {MAX_QUERY_CHARS} 个字符、{MAX_QUERY_TERMS} 个关键词和过滤器"),
+                ),
+            ],
+            "过滤器（可以写在任意位置，与关键词组合）",
+            "可选值",
+        ),
+        Language::En => (
+            "Search syntax",
+            vec![
+                ("/s words".to_string(), "Full-text search of this chat; separate words with spaces".to_string()),
+                ("reply + /s words".to_string(), "Only messages of the person replied to".to_string()),
+                (
+                    "Limits".to_string(),
+                    format!("At most {MAX_QUERY_CHARS} characters and {MAX_QUERY_TERMS} words and filters"),
+                ),
+            ],
+            "Filters (anywhere in the search, combined with words)",
+            "Values",
+        ),
+    };
+
+    let mut text = format!("<b>{title}</b>\n\n");
+    for (usage, summary) in general {
+        text.push_str(&format!(
+            "<code>{}</code>\n{}\n\n",
+            html_escape(&usage),
+            html_escape(&summary)
+        ));
+    }
+    text.push_str(&format!("<b>{operators}</b>\n\n"));
+    for op in OPERATORS {
+        text.push_str(&format!(
+            "<code>{}{}</code>\n{}\n",
+            op.prefix,
+            html_escape(op.example),
+            html_escape(op.summary(language))
+        ));
+        if !op.values.is_empty() {
+            text.push_str(&format!("{values}: {}\n", op.values.join(" ")));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn languages_parse_from_telegram_codes() {
+        assert_eq!(parse_language(" en "), Some(Language::En));
+        assert_eq!(parse_language("zh-hans"), Some(Language::Zh));
+        assert_eq!(parse_language("en-US"), Some(Language::En));
+        assert_eq!(parse_language("de"), None);
+        assert_eq!(parse_language(""), None);
+    }
+
+    #[test]
+    fn reference_lists_every_operator() {
+        for language in [Language::Zh, Language::En] {
+            let text = render(language);
+            for op in OPERATORS {
+                assert!(text.contains(op.summary(language)), "{}", op.prefix);
+            }
+        }
+    }
+}
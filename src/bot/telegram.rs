@@ -0,0 +1,143 @@
+//! Outgoing message helper for bulk sends (digests, throwbacks, alerts):
+//! paces messages per chat, waits out flood control and retries transient
+//! network failures.
+
+use dashmap::DashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardMarkup, ParseMode};
+use teloxide::RequestError;
+
+use crate::bot::shards::Shards;
+
+/// Attempts per message before giving up.
+const MAX_ATTEMPTS: u32 = 4;
+/// First backoff after a network error, doubled on every retry.
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+/// Upper bound of the random delay added to each backoff.
+const MAX_JITTER_MS: u64 = 250;
+/// Telegram allows about one message per second in a private chat and
+/// twenty per minute in a group.
+const PRIVATE_INTERVAL: Duration = Duration::from_secs(1);
+const GROUP_INTERVAL: Duration = Duration::from_secs(3);
+
+pub struct TelegramSender {
+    shards: Arc<Shards>,
+    /// Earliest time the next message may go to each chat
+    next_slot: DashMap<ChatId, Instant>,
+}
+
+impl TelegramSender {
+    pub fn new(shards: Arc<Shards>) -> Self {
+        Self {
+            shards,
+            next_slot: DashMap::new(),
+        }
+    }
+
+    /// Send an HTML message through the bot serving the chat, honoring
+    /// `retry_after` and retrying network errors with jittered exponential
+    /// backoff. API errors fail immediately.
+    pub async fn send_html(
+        &self,
+        chat_id: impl Into<ChatId>,
+        text: impl Into<String>,
+    ) -> Result<Message, RequestError> {
+        let chat_id = chat_id.into();
+        self.send(chat_id, chat_id, text.into(), None).await
+    }
+
+    /// Like [`send_html`](Self::send_html), with buttons under the message.
+    pub async fn send_html_with_keyboard(
+        &self,
+        chat_id: impl Into<ChatId>,
+        text: impl Into<String>,
+        keyboard: InlineKeyboardMarkup,
+    ) -> Result<Message, RequestError> {
+        let chat_id = chat_id.into();
+        self.send(chat_id, chat_id, text.into(), Some(keyboard))
+            .await
+    }
+
+    /// Like [`send_html`](Self::send_html), but through the bot serving the
+    /// group `via`, e.g. to message that group's admins privately.
+    pub async fn send_html_via(
+        &self,
+        via: ChatId,
+        chat_id: impl Into<ChatId>,
+        text: impl Into<String>,
+    ) -> Result<Message, RequestError> {
+        self.send(via, chat_id.into(), text.into(), None).await
+    }
+
+    async fn send(
+        &self,
+        via: ChatId,
+        chat_id: ChatId,
+        text: String,
+        keyboard: Option<InlineKeyboardMarkup>,
+    ) -> Result<Message, RequestError> {
+        let bot = self.shards.bot_for(via);
+        let mut attempt = 0;
+        loop {
+            self.wait_for_slot(chat_id).await;
+            attempt += 1;
+            let mut request = bot
+                .send_message(chat_id, text.clone())
+                .parse_mode(ParseMode::Html);
+            if let Some(ref keyboard) = keyboard {
+                request = request.reply_markup(keyboard.clone());
+            }
+            let error = match request.await {
+                Ok(sent) => return Ok(sent),
+                Err(e) => e,
+            };
+            let Some(delay) = retry_delay(&error, attempt) else {
+                return Err(error);
+            };
+            tracing::debug!("Send to {chat_id} failed ({error}), retrying in {delay:?}");
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Reserve the chat's next send slot and sleep until it comes up.
+    async fn wait_for_slot(&self, chat_id: ChatId) {
+        let interval = if chat_id.is_user() {
+            PRIVATE_INTERVAL
+        } else {
+            GROUP_INTERVAL
+        };
+        let now = Instant::now();
+        let slot = {
+            let mut next = self.next_slot.entry(chat_id).or_insert(now);
+            let slot = (*next).max(now);
+            *next = slot + interval;
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+    }
+}
+
+/// How long to wait before retrying after `error`, or `None` when the error
+/// is final or the attempts are used up.
+fn retry_delay(error: &RequestError, attempt: u32) -> Option<Duration> {
+    if attempt >= MAX_ATTEMPTS {
+        return None;
+    }
+    match error {
+        RequestError::RetryAfter(secs) => Some(secs.duration()),
+        RequestError::Network(_) | RequestError::Io(_) => {
+            Some(BASE_BACKOFF * 2u32.pow(attempt - 1) + jitter())
+        }
+        _ => None,
+    }
+}
+
+/// Random-enough delay to spread retries of concurrent senders apart.
+fn jitter() -> Duration {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos() as u64);
+    Duration::from_millis(nanos % MAX_JITTER_MS)
+}
@@ -0,0 +1,161 @@
+//! Weekly "on this day" throwback: opted-in chats get the most replied-to (or
+//! a few random) messages from the same day one year earlier.
+
+use chrono::{Months, NaiveTime, Utc};
+use std::sync::Arc;
+use std::time::Duration;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use crate::bot::telegram::TelegramSender;
+use crate::bot::util::{format_message_link, html_escape};
+use crate::config::DigestConfig;
+use crate::es::analytics::AnalyticsClient;
+use crate::es::settings::SettingsStore;
+use crate::models::message::ChatMessage;
+
+const USAGE: &str = "用法:\n\
+    /throwback on — 每周发送一年前今天的热门消息\n\
+    /throwback off — 关闭\n\
+    /throwback now — 立即查看";
+/// Messages shown per throwback.
+const THROWBACK_SIZE: usize = 3;
+const WEEK_SECS: i64 = 7 * 86400;
+
+/// Handle `/throwback on|off|now` (admins).
+pub async fn handle_throwback(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    settings: Arc<SettingsStore>,
+    analytics: Arc<AnalyticsClient>,
+) -> anyhow::Result<()> {
+    if msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "请在群组中使用此命令。")
+            .await?;
+        return Ok(());
+    }
+
+    let chat_id = msg.chat.id.0;
+    let now = Utc::now().timestamp();
+    let reply = match args.trim() {
+        "on" => {
+            settings
+                .update(chat_id, |s| s.throwback_last_sent = Some(now))
+                .await?;
+            "已开启每周回顾，下周起会发送一年前今天的热门消息。".to_string()
+        }
+        "off" => {
+            settings
+                .update(chat_id, |s| s.throwback_last_sent = None)
+                .await?;
+            "已关闭每周回顾。".to_string()
+        }
+        "now" => match render_throwback(&analytics, chat_id).await? {
+            Some(text) => text,
+            None => "一年前的今天本群没有索引到消息。".to_string(),
+        },
+        _ => USAGE.to_string(),
+    };
+
+    bot.send_message(msg.chat.id, reply)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+/// Spawn the background task that posts due throwbacks.
+pub fn spawn_throwback_scheduler(
+    sender: Arc<TelegramSender>,
+    settings: Arc<SettingsStore>,
+    analytics: Arc<AnalyticsClient>,
+    config: DigestConfig,
+) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(config.check_interval_secs.max(60)));
+        loop {
+            interval.tick().await;
+            if let Err(e) = send_due_throwbacks(&sender, &settings, &analytics).await {
+                tracing::warn!("Throwback check failed: {e}");
+            }
+        }
+    });
+}
+
+async fn send_due_throwbacks(
+    sender: &TelegramSender,
+    settings: &SettingsStore,
+    analytics: &AnalyticsClient,
+) -> anyhow::Result<()> {
+    let now = Utc::now().timestamp();
+    for chat in settings.all().await? {
+        let Some(last_sent) = chat.throwback_last_sent else {
+            continue;
+        };
+        if now - last_sent < WEEK_SECS {
+            continue;
+        }
+
+        // A quiet day a year ago skips this week rather than posting nothing
+        if let Some(text) = render_throwback(analytics, chat.chat_id).await?
+            && let Err(e) = sender.send_html(ChatId(chat.chat_id), text).await
+        {
+            tracing::warn!("Failed to send throwback to {}: {e}", chat.chat_id);
+            continue;
+        }
+        settings
+            .update(chat.chat_id, |s| {
+                if s.throwback_last_sent.is_some() {
+                    s.throwback_last_sent = Some(now);
+                }
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+/// The throwback post for today, or `None` when nothing was indexed that day.
+async fn render_throwback(
+    analytics: &AnalyticsClient,
+    chat_id: i64,
+) -> anyhow::Result<Option<String>> {
+    let Some(day) = Utc::now().date_naive().checked_sub_months(Months::new(12)) else {
+        return Ok(None);
+    };
+    let from = day.and_time(NaiveTime::MIN).and_utc().timestamp();
+    let picks = analytics
+        .throwback(chat_id, from, from + 86400, THROWBACK_SIZE)
+        .await?;
+    if picks.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(format_throwback(
+        &day.format("%Y-%m-%d").to_string(),
+        &picks,
+        chat_id,
+    )))
+}
+
+fn format_throwback(day: &str, picks: &[(ChatMessage, u64)], chat_id: i64) -> String {
+    let mut text = format!("📅 <b>一年前的今天（{day}）</b>\n\n");
+    for (message, replies) in picks {
+        let snippet: String = message.text.chars().take(100).collect();
+        let author = message
+            .display_name
+            .as_deref()
+            .map(|name| format!("<i>{}</i>：", html_escape(name)))
+            .unwrap_or_default();
+        let replies = if *replies > 0 {
+            format!("（{replies} 条回复）")
+        } else {
+            String::new()
+        };
+        text.push_str(&format!(
+            "{author}{}{replies}\n<a href=\"{}\">跳转到消息</a>\n\n",
+            html_escape(&snippet),
+            format_message_link(chat_id, message.message_id)
+        ));
+    }
+    text
+}
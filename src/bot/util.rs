@@ -0,0 +1,231 @@
+//! Small formatting and argument-parsing helpers shared by command handlers.
+
+use teloxide::types::{LinkPreviewOptions, ParseMode};
+
+use crate::config::OutputFormat;
+use crate::es::search::{HIGHLIGHT_END, HIGHLIGHT_START};
+
+pub fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Escape every character MarkdownV2 reserves outside of code entities.
+pub fn markdown_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(
+            c,
+            '_' | '*'
+                | '['
+                | ']'
+                | '('
+                | ')'
+                | '~'
+                | '`'
+                | '>'
+                | '#'
+                | '+'
+                | '-'
+                | '='
+                | '|'
+                | '{'
+                | '}'
+                | '.'
+                | '!'
+                | '\\'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Builders for the markup of result messages. Arguments named `text` or
+/// `label` must already be escaped for the format.
+impl OutputFormat {
+    pub fn parse_mode(self) -> ParseMode {
+        match self {
+            Self::Html => ParseMode::Html,
+            Self::MarkdownV2 => ParseMode::MarkdownV2,
+        }
+    }
+
+    pub fn escape(self, s: &str) -> String {
+        match self {
+            Self::Html => html_escape(s),
+            Self::MarkdownV2 => markdown_escape(s),
+        }
+    }
+
+    pub fn bold(self, text: &str) -> String {
+        match self {
+            Self::Html => format!("<b>{text}</b>"),
+            Self::MarkdownV2 => format!("*{text}*"),
+        }
+    }
+
+    pub fn italic(self, text: &str) -> String {
+        match self {
+            Self::Html => format!("<i>{text}</i>"),
+            Self::MarkdownV2 => format!("_{text}_"),
+        }
+    }
+
+    pub fn link(self, label: &str, url: &str) -> String {
+        match self {
+            Self::Html => format!("<a href=\"{}\">{label}</a>", html_escape(url)),
+            Self::MarkdownV2 => format!(
+                "[{label}]({})",
+                url.replace('\\', "\\\\").replace(')', "\\)")
+            ),
+        }
+    }
+
+    /// Escape a highlighted fragment, turning its match markers into bold.
+    pub fn highlight(self, fragment: &str) -> String {
+        let mut out = String::new();
+        for (i, part) in fragment.split(HIGHLIGHT_START).enumerate() {
+            match part.split_once(HIGHLIGHT_END) {
+                Some((matched, rest)) if i > 0 => {
+                    out.push_str(&self.bold(&self.escape(matched)));
+                    out.push_str(&self.escape(rest));
+                }
+                _ => out.push_str(&self.escape(part)),
+            }
+        }
+        out
+    }
+}
+
+/// Link preview options that show no preview at all.
+pub fn no_link_preview() -> LinkPreviewOptions {
+    LinkPreviewOptions {
+        is_disabled: true,
+        ..link_preview(None)
+    }
+}
+
+/// Link preview options previewing `url`, or whichever link Telegram picks.
+pub fn link_preview(url: Option<String>) -> LinkPreviewOptions {
+    LinkPreviewOptions {
+        is_disabled: false,
+        url,
+        prefer_small_media: false,
+        prefer_large_media: false,
+        show_above_text: false,
+    }
+}
+
+/// Parse a period token such as `24h`, `7d` or `4w` into seconds.
+pub fn parse_period(token: &str) -> Option<i64> {
+    let token = token.trim();
+    let unit = token.chars().last()?;
+    let amount: i64 = token[..token.len() - unit.len_utf8()].parse().ok()?;
+    if amount <= 0 {
+        return None;
+    }
+    let secs = match unit {
+        'h' => 3600,
+        'd' => 86400,
+        'w' => 7 * 86400,
+        _ => return None,
+    };
+    Some(amount * secs)
+}
+
+/// Format a unix timestamp as `YYYY-MM-DD HH:MM` (UTC).
+pub fn format_timestamp(ts: i64) -> String {
+    chrono::DateTime::from_timestamp(ts, 0)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+/// Format a byte count with a binary unit, e.g. `12.3 MiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Link to a message in a supergroup, e.g. `https://t.me/c/123456/789`.
+pub fn format_message_link(chat_id: i64, message_id: i64) -> String {
+    let abs_id = chat_id.unsigned_abs();
+    let channel_id = if abs_id > 1_000_000_000_000 {
+        abs_id - 1_000_000_000_000
+    } else {
+        abs_id
+    };
+    format!("https://t.me/c/{channel_id}/{message_id}")
+}
+
+/// Link to a message: the public `t.me/<username>` address when the chat has
+/// one, which works without joining, otherwise the members-only one.
+pub fn public_message_link(username: Option<&str>, chat_id: i64, message_id: i64) -> String {
+    match username {
+        Some(username) => format!("https://t.me/{username}/{message_id}"),
+        None => format_message_link(chat_id, message_id),
+    }
+}
+
+/// A parsed `t.me` message link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageLink {
+    pub chat: LinkChat,
+    pub message_id: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkChat {
+    /// Bot API chat id of a `t.me/c/<id>/...` link
+    Id(i64),
+    /// Public username of a `t.me/<username>/...` link
+    Username(String),
+}
+
+impl MessageLink {
+    /// Whether the link points into the given chat.
+    pub fn is_in(&self, chat: &teloxide::types::Chat) -> bool {
+        match &self.chat {
+            LinkChat::Id(id) => *id == chat.id.0,
+            LinkChat::Username(name) => chat
+                .username()
+                .is_some_and(|u| u.eq_ignore_ascii_case(name)),
+        }
+    }
+}
+
+/// Parse message links such as `https://t.me/c/123456/789`,
+/// `t.me/c/123456/12/789` (forum topic) or `https://t.me/somegroup/789?single`.
+pub fn parse_message_link(link: &str) -> Option<MessageLink> {
+    let link = link.trim();
+    let with_scheme = if link.contains("://") {
+        link.to_string()
+    } else {
+        format!("https://{link}")
+    };
+    let url = url::Url::parse(&with_scheme).ok()?;
+    if !matches!(url.host_str()?, "t.me" | "telegram.me" | "www.t.me") {
+        return None;
+    }
+
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    let message_id = segments.last()?.parse().ok()?;
+    let chat = match segments.as_slice() {
+        ["c", id, .., _] if segments.len() <= 4 => LinkChat::Id(format!("-100{id}").parse().ok()?),
+        [name, _] | [name, _, _] if *name != "c" => LinkChat::Username(name.to_string()),
+        _ => return None,
+    };
+    Some(MessageLink { chat, message_id })
+}
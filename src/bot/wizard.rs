@@ -0,0 +1,354 @@
+//! `/findwizard`: a dialogue that walks the user through keyword → sender →
+//! date range → type and then runs the regular search pipeline.
+
+use std::sync::Arc;
+use teloxide::dispatching::dialogue::{Dialogue, InMemStorage};
+use teloxide::prelude::*;
+use teloxide::types::{
+    InlineKeyboardButton, InlineKeyboardMarkup, MaybeInaccessibleMessage, MessageId,
+    ReplyParameters,
+};
+
+use crate::bot::callback::{
+    hit_ids, render_page, search_params, SearchState, TYPE_FILTERS, TYPE_FILTERS_PER_ROW,
+};
+use crate::bot::query::{parse_query, validate_query};
+use crate::bot::session::SessionStore;
+use crate::config::OutputFormat;
+use crate::es::audit::{AuditEntry, AuditLog};
+use crate::es::search::SearchClient;
+
+pub type WizardStorage = InMemStorage<WizardState>;
+pub type WizardDialogue = Dialogue<WizardState, WizardStorage>;
+
+/// Callback data prefix of wizard buttons, distinguishing them from result keyboards.
+pub const CALLBACK_PREFIX: &str = "wz:";
+
+/// Dialogues are keyed by chat, so each state remembers which user started it
+/// and input from anyone else is ignored.
+#[derive(Debug, Clone, Default)]
+pub enum WizardState {
+    #[default]
+    Idle,
+    Keyword {
+        owner: i64,
+    },
+    User {
+        owner: i64,
+        keyword_msg: MessageId,
+        keyword: String,
+    },
+    Date {
+        owner: i64,
+        keyword: String,
+        user_id: Option<i64>,
+    },
+    Type {
+        owner: i64,
+        keyword: String,
+        user_id: Option<i64>,
+        date_range: Option<&'static str>,
+    },
+}
+
+impl WizardState {
+    fn owner(&self) -> Option<i64> {
+        match self {
+            Self::Idle => None,
+            Self::Keyword { owner }
+            | Self::User { owner, .. }
+            | Self::Date { owner, .. }
+            | Self::Type { owner, .. } => Some(*owner),
+        }
+    }
+
+    /// Whether this state is waiting for a text message from `msg`'s sender.
+    pub fn awaits_message_from(&self, msg: &Message) -> bool {
+        matches!(self, Self::Keyword { .. } | Self::User { .. })
+            && msg.from.as_ref().map(|u| u.id.0 as i64) == self.owner()
+    }
+}
+
+pub fn is_wizard_callback(q: &CallbackQuery) -> bool {
+    q.data
+        .as_deref()
+        .is_some_and(|d| d.starts_with(CALLBACK_PREFIX))
+}
+
+/// Handle `/findwizard`: start (or restart) the wizard for the sender.
+pub async fn start_wizard(
+    bot: Bot,
+    msg: Message,
+    storage: Arc<WizardStorage>,
+) -> anyhow::Result<()> {
+    let Some(owner) = msg.from.as_ref().map(|u| u.id.0 as i64) else {
+        return Ok(());
+    };
+    let dialogue = WizardDialogue::new(storage, msg.chat.id);
+    dialogue.update(WizardState::Keyword { owner }).await?;
+
+    bot.send_message(msg.chat.id, "🔎 第 1/4 步：请发送要搜索的关键词")
+        .reply_markup(InlineKeyboardMarkup::new(vec![vec![cancel_button()]]))
+        .reply_parameters(ReplyParameters::new(msg.id))
+        .await?;
+    Ok(())
+}
+
+/// Handle text input for the keyword and sender steps.
+pub async fn handle_wizard_message(
+    bot: Bot,
+    msg: Message,
+    dialogue: WizardDialogue,
+    state: WizardState,
+) -> anyhow::Result<()> {
+    match state {
+        WizardState::Keyword { owner } => {
+            let Some(keyword) = msg.text().map(str::trim).filter(|t| !t.is_empty()) else {
+                bot.send_message(msg.chat.id, "请发送文字关键词。").await?;
+                return Ok(());
+            };
+            let keyword = match validate_query(keyword) {
+                Ok(keyword) => keyword,
+                Err(e) => {
+                    bot.send_message(msg.chat.id, e.to_string()).await?;
+                    return Ok(());
+                }
+            };
+            dialogue
+                .update(WizardState::User {
+                    owner,
+                    keyword_msg: msg.id,
+                    keyword,
+                })
+                .await?;
+            bot.send_message(msg.chat.id, user_prompt())
+                .reply_markup(user_keyboard())
+                .reply_parameters(ReplyParameters::new(msg.id))
+                .await?;
+        }
+        WizardState::User {
+            owner,
+            keyword_msg,
+            keyword,
+        } => {
+            // Either reply to the wanted user's message, or send `id:123456`
+            let user_id = msg
+                .reply_to_message()
+                .and_then(|r| r.from.as_ref())
+                .map(|u| u.id.0 as i64)
+                .or_else(|| {
+                    let text = msg.text()?.trim();
+                    text.strip_prefix("id:").unwrap_or(text).parse().ok()
+                });
+            let Some(user_id) = user_id else {
+                bot.send_message(
+                    msg.chat.id,
+                    "无法识别用户，请回复对方的消息或发送 id:123456",
+                )
+                .await?;
+                return Ok(());
+            };
+            dialogue
+                .update(WizardState::Date {
+                    owner,
+                    keyword,
+                    user_id: Some(user_id),
+                })
+                .await?;
+            bot.send_message(msg.chat.id, date_prompt())
+                .reply_markup(date_keyboard())
+                .reply_parameters(ReplyParameters::new(keyword_msg))
+                .await?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Handle wizard button presses.
+#[allow(clippy::too_many_arguments)]
+pub async fn handle_wizard_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    dialogue: WizardDialogue,
+    state: WizardState,
+    search_client: Arc<SearchClient>,
+    audit: Arc<AuditLog>,
+    sessions: Arc<SessionStore>,
+    default_page_size: usize,
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    let data = q.data.as_deref().unwrap_or_default();
+    let action = data.strip_prefix(CALLBACK_PREFIX).unwrap_or_default();
+    let presser = q.from.id.0 as i64;
+
+    let Some(MaybeInaccessibleMessage::Regular(msg)) = q.message.as_ref() else {
+        bot.answer_callback_query(q.id.clone()).await?;
+        return Ok(());
+    };
+
+    if state.owner() != Some(presser) {
+        let text = if state.owner().is_none() {
+            "向导已结束，请重新发送 /findwizard"
+        } else {
+            "这不是你的搜索向导"
+        };
+        bot.answer_callback_query(q.id.clone()).text(text).await?;
+        return Ok(());
+    }
+    bot.answer_callback_query(q.id.clone()).await?;
+
+    if action == "cancel" {
+        dialogue.exit().await?;
+        bot.edit_message_text(msg.chat.id, msg.id, "已取消搜索向导。")
+            .await?;
+        return Ok(());
+    }
+
+    match (state, action.split_once(':')) {
+        (WizardState::User { owner, keyword, .. }, Some(("u", choice))) => {
+            let user_id = (choice == "me").then_some(presser);
+            dialogue
+                .update(WizardState::Date {
+                    owner,
+                    keyword,
+                    user_id,
+                })
+                .await?;
+            bot.edit_message_text(msg.chat.id, msg.id, date_prompt())
+                .reply_markup(date_keyboard())
+                .await?;
+        }
+        (
+            WizardState::Date {
+                owner,
+                keyword,
+                user_id,
+            },
+            Some(("d", range)),
+        ) => {
+            dialogue
+                .update(WizardState::Type {
+                    owner,
+                    keyword,
+                    user_id,
+                    date_range: static_date_range(range),
+                })
+                .await?;
+            bot.edit_message_text(msg.chat.id, msg.id, "🔎 第 4/4 步：选择消息类型")
+                .reply_markup(type_keyboard())
+                .await?;
+        }
+        (
+            WizardState::Type {
+                keyword,
+                user_id,
+                date_range,
+                ..
+            },
+            Some(("t", kind)),
+        ) => {
+            dialogue.exit().await?;
+
+            let parsed = parse_query(&keyword, None);
+            let state = SearchState {
+                page: 0,
+                message_type: (kind != "any").then(|| kind.to_string()),
+                date_range,
+                user_id,
+            };
+            let params = search_params(msg.chat.id.0, parsed, &state, default_page_size);
+            let result = match search_client.search(&params).await {
+                Ok(result) => result,
+                Err(e) => match e.user_message() {
+                    Some(text) => {
+                        bot.edit_message_text(msg.chat.id, msg.id, text).await?;
+                        return Ok(());
+                    }
+                    None => return Err(e.into()),
+                },
+            };
+
+            audit.record(AuditEntry {
+                chat_id: msg.chat.id.0,
+                user_id: Some(presser),
+                query: keyword.clone(),
+                result_count: result.total,
+                date: chrono::Utc::now().timestamp(),
+            });
+
+            // The prompt replies to the keyword message, so the result keyboard
+            // can recover the query exactly like a regular /s result.
+            let (text, keyboard) = render_page(&result, &state, msg.chat.id.0, &keyword, format);
+            bot.edit_message_text(msg.chat.id, msg.id, text)
+                .parse_mode(format.parse_mode())
+                .reply_markup(keyboard)
+                .await?;
+            sessions.set_hits(msg.chat.id.0, msg.id.0, hit_ids(&result));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// ── Prompts ────────────────────────────────────────────────────
+
+fn cancel_button() -> InlineKeyboardButton {
+    InlineKeyboardButton::callback("取消", format!("{CALLBACK_PREFIX}cancel"))
+}
+
+fn user_prompt() -> &'static str {
+    "🔎 第 2/4 步：只看某个人的消息吗？\n\n\
+     可以回复对方的任意消息，或发送 id:123456"
+}
+
+fn user_keyboard() -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![
+        vec![
+            InlineKeyboardButton::callback("不限", format!("{CALLBACK_PREFIX}u:any")),
+            InlineKeyboardButton::callback("只看我的", format!("{CALLBACK_PREFIX}u:me")),
+        ],
+        vec![cancel_button()],
+    ])
+}
+
+fn date_prompt() -> &'static str {
+    "🔎 第 3/4 步：选择时间范围"
+}
+
+fn date_keyboard() -> InlineKeyboardMarkup {
+    let row = [
+        ("7d", "7天内"),
+        ("30d", "30天内"),
+        ("90d", "90天内"),
+        ("all", "全部"),
+    ]
+    .map(|(key, label)| InlineKeyboardButton::callback(label, format!("{CALLBACK_PREFIX}d:{key}")))
+    .to_vec();
+    InlineKeyboardMarkup::new(vec![row, vec![cancel_button()]])
+}
+
+/// The result keyboard's type filters, after a button for no filter.
+fn type_keyboard() -> InlineKeyboardMarkup {
+    let buttons: Vec<_> = std::iter::once(("any", "不限"))
+        .chain(TYPE_FILTERS)
+        .map(|(key, label)| {
+            InlineKeyboardButton::callback(label, format!("{CALLBACK_PREFIX}t:{key}"))
+        })
+        .collect();
+    let mut rows: Vec<_> = buttons
+        .chunks(TYPE_FILTERS_PER_ROW)
+        .map(<[_]>::to_vec)
+        .collect();
+    rows.push(vec![cancel_button()]);
+    InlineKeyboardMarkup::new(rows)
+}
+
+fn static_date_range(range: &str) -> Option<&'static str> {
+    match range {
+        "7d" => Some("7d"),
+        "30d" => Some("30d"),
+        "90d" => Some("90d"),
+        _ => None,
+    }
+}
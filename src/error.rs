@@ -1,5 +1,20 @@
 use thiserror::Error;
 
+use crate::bot::query::QueryError;
+
+/// Reply shown when a search runs into the timeout.
+const TIMEOUT_TEXT: &str = "搜索超时，请缩小范围";
+/// Reply shown while the circuit breaker keeps searches off Elasticsearch.
+const MAINTENANCE_TEXT: &str = "搜索服务维护中，请稍后再试";
+/// Toast for buttons of keyboards this build can no longer read.
+const STALE_KEYBOARD_TEXT: &str = "按钮已失效，请重新搜索";
+/// Toast when the query behind a results keyboard can't be found any more.
+const SESSION_EXPIRED_TEXT: &str = "搜索已过期，请重新搜索";
+/// Alert for Elasticsearch failures other than timeouts and outages.
+const SEARCH_ERROR_TEXT: &str = "搜索服务出错，请稍后重试";
+/// Alert for everything else going wrong while handling a button.
+const INTERNAL_ERROR_TEXT: &str = "处理失败，请稍后重试";
+
 #[derive(Debug, Error)]
 #[allow(dead_code)]
 pub enum AppError {
@@ -15,6 +30,76 @@ pub enum AppError {
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
 
+    #[error("{0}")]
+    InvalidQuery(#[from] QueryError),
+
+    #[error("Search timed out")]
+    SearchTimeout,
+
+    #[error("Elasticsearch is unavailable")]
+    Unavailable,
+
+    #[error("Unreadable keyboard callback: {0}")]
+    StaleKeyboard(String),
+
+    #[error("Search session expired")]
+    SessionExpired,
+
+    #[error("Search failed (status {status_code}): {details}")]
+    SearchFailure { status_code: u16, details: String },
+
     #[error("Bulk index failed (status {status_code}): {details}")]
     BulkIndexFailure { status_code: u16, details: String },
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl AppError {
+    /// Reply for errors caused by the request rather than a bug or outage.
+    pub fn user_message(&self) -> Option<String> {
+        match self {
+            Self::InvalidQuery(e) => Some(e.to_string()),
+            Self::SearchTimeout => Some(TIMEOUT_TEXT.to_string()),
+            Self::Unavailable => Some(MAINTENANCE_TEXT.to_string()),
+            Self::StaleKeyboard(_) => Some(STALE_KEYBOARD_TEXT.to_string()),
+            Self::SessionExpired => Some(SESSION_EXPIRED_TEXT.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Alert shown when a keyboard press fails: the user-facing text, or the
+    /// class of the failure for errors that have none.
+    pub fn alert_message(&self) -> String {
+        self.user_message().unwrap_or_else(|| {
+            match self {
+                Self::Elasticsearch(_) | Self::SearchFailure { .. } => SEARCH_ERROR_TEXT,
+                _ => INTERNAL_ERROR_TEXT,
+            }
+            .to_string()
+        })
+    }
+
+    /// Whether repeating the same request later may succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::Elasticsearch(_)
+                | Self::SearchTimeout
+                | Self::Unavailable
+                | Self::SearchFailure { .. }
+        )
+    }
+
+    /// Whether the error points at broken infrastructure or code, and should
+    /// be logged as such instead of being just a reply to the user.
+    pub fn is_infrastructure(&self) -> bool {
+        !matches!(
+            self,
+            Self::InvalidQuery(_)
+                | Self::SearchTimeout
+                | Self::StaleKeyboard(_)
+                | Self::SessionExpired
+        )
+    }
 }
@@ -0,0 +1,397 @@
+use elasticsearch::indices::IndicesRefreshParts;
+use elasticsearch::params::Conflicts;
+use elasticsearch::tasks::{TasksCancelParts, TasksGetParts};
+use elasticsearch::{DeleteByQueryParts, DeleteParts, Elasticsearch, IndexParts, SearchParts};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+use crate::events::{DocumentEvent, EventPublisher};
+
+/// How often a running delete task is polled for progress.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Documents fetched per page of a chat export.
+const EXPORT_PAGE_SIZE: usize = 1000;
+
+/// Chat id of the self-test document. Telegram never assigns 0, so the
+/// document can't show up in a real chat's searches.
+const SELFTEST_CHAT_ID: i64 = 0;
+
+/// Destructive maintenance on the message index: scoped deletes for
+/// retention and purge commands, and the end-to-end self-test.
+pub struct AdminClient {
+    es: Arc<Elasticsearch>,
+    index_name: String,
+    events: Option<Arc<EventPublisher>>,
+}
+
+/// State of a delete-by-query task.
+#[derive(Debug, Clone, Default)]
+pub struct DeleteProgress {
+    /// Documents matched by the query
+    pub total: u64,
+    pub deleted: u64,
+    /// Documents changed while the task ran, skipped instead of aborting
+    pub version_conflicts: u64,
+    pub completed: bool,
+    /// Stopped by `cancel` before every matched document was deleted
+    pub cancelled: bool,
+    /// Reasons of documents that couldn't be deleted
+    pub failures: Vec<String>,
+}
+
+/// A page of a chat export.
+#[derive(Debug, Default)]
+pub struct ExportPage {
+    /// `_source` of each document, oldest first
+    pub docs: Vec<Value>,
+    /// Documents of the chat in the whole export
+    pub total: u64,
+    /// Where the next page starts; `None` after the last page
+    pub next: Option<Value>,
+}
+
+/// One step of the self-test.
+#[derive(Debug)]
+pub struct SelfTestStep {
+    pub name: &'static str,
+    pub elapsed: Duration,
+    /// Why the step failed, if it did
+    pub error: Option<String>,
+}
+
+impl AdminClient {
+    pub fn new(
+        es: Arc<Elasticsearch>,
+        index_name: String,
+        events: Option<Arc<EventPublisher>>,
+    ) -> Self {
+        Self {
+            es,
+            index_name,
+            events,
+        }
+    }
+
+    /// Delete the chat's messages matching every clause of `filters`, running
+    /// as a background ES task that is polled until it finishes.
+    /// `on_progress` sees each poll, e.g. to keep a status message current.
+    /// Cancelling `cancel` cancels the ES task; what it deleted so far stays
+    /// deleted.
+    ///
+    /// The chat filter is always added, so a delete can never leave the chat;
+    /// a chat id of 0 is rejected as a likely bug.
+    pub async fn delete_by_query<Fut>(
+        &self,
+        chat_id: i64,
+        filters: Vec<Value>,
+        cancel: &CancellationToken,
+        mut on_progress: impl FnMut(DeleteProgress) -> Fut,
+    ) -> anyhow::Result<DeleteProgress>
+    where
+        Fut: Future<Output = ()>,
+    {
+        if chat_id == 0 {
+            anyhow::bail!("Refusing to delete without a chat filter");
+        }
+        let mut filter = vec![json!({ "term": { "chat_id": chat_id } })];
+        filter.extend(filters);
+        let query = json!({ "bool": { "filter": filter } });
+
+        let response = self
+            .es
+            .delete_by_query(DeleteByQueryParts::Index(&[&self.index_name]))
+            .conflicts(Conflicts::Proceed)
+            .refresh(true)
+            .wait_for_completion(false)
+            .body(json!({ "query": query }))
+            .send()
+            .await?;
+        let status = response.status_code();
+        let body: Value = response.json().await?;
+        if !status.is_success() {
+            anyhow::bail!("Delete by query failed (status {status}): {body}");
+        }
+        let Some(task_id) = body["task"].as_str() else {
+            anyhow::bail!("Delete by query returned no task: {body}");
+        };
+        tracing::info!("Started delete task {task_id} in chat {chat_id}");
+
+        let mut cancelled = false;
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                _ = cancel.cancelled(), if !cancelled => {
+                    self.cancel_task(task_id).await?;
+                    cancelled = true;
+                }
+            }
+            let mut progress = self.task_progress(task_id).await?;
+            progress.cancelled = cancelled;
+            on_progress(progress.clone()).await;
+            if progress.completed {
+                if let Some(events) = &self.events
+                    && progress.deleted > 0
+                {
+                    let event = DocumentEvent::Deleted {
+                        chat_id,
+                        query,
+                        deleted: progress.deleted,
+                    };
+                    events.publish(&self.index_name, event);
+                }
+                return Ok(progress);
+            }
+        }
+    }
+
+    /// The page of every document of the chat, revisions included, that
+    /// starts after `after` (`None` for the first page).
+    pub async fn export_page(
+        &self,
+        chat_id: i64,
+        after: Option<Value>,
+    ) -> anyhow::Result<ExportPage> {
+        let mut body = json!({
+            "size": EXPORT_PAGE_SIZE,
+            "track_total_hits": true,
+            "query": { "bool": { "filter": [{ "term": { "chat_id": chat_id } }] } },
+            "sort": [
+                { "date": "asc" },
+                { "message_id": "asc" },
+                { "edited_at": { "order": "asc", "missing": "_first" } }
+            ]
+        });
+        if let Some(after) = after {
+            body["search_after"] = after;
+        }
+        let response = self
+            .es
+            .search(SearchParts::Index(&[&self.index_name]))
+            .body(body)
+            .send()
+            .await?;
+        let status = response.status_code();
+        let body: Value = response.json().await?;
+        if !status.is_success() {
+            anyhow::bail!("Export scan failed (status {status}): {body}");
+        }
+
+        let hits = body["hits"]["hits"].as_array().cloned().unwrap_or_default();
+        let next = (hits.len() == EXPORT_PAGE_SIZE)
+            .then(|| hits.last().map(|hit| hit["sort"].clone()))
+            .flatten();
+        Ok(ExportPage {
+            docs: hits.into_iter().map(|hit| hit["_source"].clone()).collect(),
+            total: body["hits"]["total"]["value"].as_u64().unwrap_or(0),
+            next,
+        })
+    }
+
+    /// Index a synthetic message, refresh, search for it and delete it again,
+    /// timing each step. Stops at the first failing step, except that a
+    /// written document is always deleted again.
+    pub async fn self_test(&self) -> Vec<SelfTestStep> {
+        let nonce = chrono::Utc::now().timestamp_micros();
+        let doc_id = format!("selftest_{nonce}");
+        let token = format!("selftest{nonce}");
+        let mut steps = Vec::new();
+
+        let written = timed(&mut steps, "index", async {
+            let response = self
+                .es
+                .index(IndexParts::IndexId(&self.index_name, &doc_id))
+                .body(json!({
+                    "message_id": nonce,
+                    "chat_id": SELFTEST_CHAT_ID,
+                    "text": token,
+                    "date": nonce / 1_000_000,
+                    "message_type": "text",
+                }))
+                .send()
+                .await?;
+            check(response, "Index").await.map(|_| ())
+        })
+        .await;
+
+        if written {
+            let refreshed = timed(&mut steps, "refresh", async {
+                let response = self
+                    .es
+                    .indices()
+                    .refresh(IndicesRefreshParts::Index(&[&self.index_name]))
+                    .send()
+                    .await?;
+                check(response, "Refresh").await.map(|_| ())
+            })
+            .await;
+            if refreshed {
+                timed(&mut steps, "search", async {
+                    let response = self
+                        .es
+                        .search(SearchParts::Index(&[&self.index_name]))
+                        .body(json!({
+                            "size": 1,
+                            "_source": false,
+                            "query": { "bool": {
+                                "must": [{ "match": { "text": token } }],
+                                "filter": [{ "term": { "chat_id": SELFTEST_CHAT_ID } }],
+                            } },
+                        }))
+                        .send()
+                        .await?;
+                    let body = check(response, "Search").await?;
+                    if body["hits"]["hits"][0]["_id"].as_str() != Some(doc_id.as_str()) {
+                        anyhow::bail!("Indexed document not found");
+                    }
+                    Ok(())
+                })
+                .await;
+            }
+
+            // Clean up even after a failed refresh or search
+            timed(&mut steps, "delete", async {
+                let response = self
+                    .es
+                    .delete(DeleteParts::IndexId(&self.index_name, &doc_id))
+                    .send()
+                    .await?;
+                check(response, "Delete").await.map(|_| ())
+            })
+            .await;
+        }
+        steps
+    }
+
+    /// Id of the sender whose latest message in the chat carries `username`
+    /// (lowercased, without `@`), or `None` if no such message is indexed.
+    pub async fn resolve_username(
+        &self,
+        chat_id: i64,
+        username: &str,
+    ) -> anyhow::Result<Option<i64>> {
+        latest_sender(&self.es, &self.index_name, chat_id, username).await
+    }
+
+    async fn cancel_task(&self, task_id: &str) -> anyhow::Result<()> {
+        let response = self
+            .es
+            .tasks()
+            .cancel(TasksCancelParts::TaskId(task_id))
+            .send()
+            .await?;
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Task cancel failed (status {status}): {body}");
+        }
+        tracing::info!("Cancelled delete task {task_id}");
+        Ok(())
+    }
+
+    async fn task_progress(&self, task_id: &str) -> anyhow::Result<DeleteProgress> {
+        let response = self
+            .es
+            .tasks()
+            .get(TasksGetParts::TaskId(task_id))
+            .send()
+            .await?;
+        let status = response.status_code();
+        let body: Value = response.json().await?;
+        if !status.is_success() {
+            anyhow::bail!("Task lookup failed (status {status}): {body}");
+        }
+
+        let completed = body["completed"].as_bool().unwrap_or(false);
+        // Finished tasks report their final counts in `response`
+        let counts = if completed {
+            &body["response"]
+        } else {
+            &body["task"]["status"]
+        };
+        let failures = counts["failures"]
+            .as_array()
+            .map(|failures| {
+                failures
+                    .iter()
+                    .map(|f| {
+                        f["cause"]["reason"]
+                            .as_str()
+                            .map_or_else(|| f.to_string(), String::from)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(DeleteProgress {
+            total: counts["total"].as_u64().unwrap_or(0),
+            deleted: counts["deleted"].as_u64().unwrap_or(0),
+            version_conflicts: counts["version_conflicts"].as_u64().unwrap_or(0),
+            completed,
+            cancelled: false,
+            failures,
+        })
+    }
+}
+
+/// Run one self-test step, recording its duration and outcome. Returns
+/// whether it succeeded.
+async fn timed(
+    steps: &mut Vec<SelfTestStep>,
+    name: &'static str,
+    step: impl Future<Output = anyhow::Result<()>>,
+) -> bool {
+    let started = Instant::now();
+    let result = step.await;
+    let ok = result.is_ok();
+    steps.push(SelfTestStep {
+        name,
+        elapsed: started.elapsed(),
+        error: result.err().map(|e| e.to_string()),
+    });
+    ok
+}
+
+/// Body of a successful response, or an error naming the failed operation.
+async fn check(
+    response: elasticsearch::http::response::Response,
+    what: &str,
+) -> anyhow::Result<Value> {
+    let status = response.status_code();
+    let body: Value = response.json().await?;
+    if !status.is_success() {
+        anyhow::bail!("{what} failed (status {status}): {body}");
+    }
+    Ok(body)
+}
+
+/// Id of the sender whose latest message in the chat carries `username`
+/// (lowercased, without `@`), or `None` if no such message is indexed.
+pub(crate) async fn latest_sender(
+    es: &Elasticsearch,
+    index_name: &str,
+    chat_id: i64,
+    username: &str,
+) -> anyhow::Result<Option<i64>> {
+    let response = es
+        .search(SearchParts::Index(&[index_name]))
+        .body(json!({
+            "size": 1,
+            "_source": ["user_id"],
+            "query": { "bool": { "filter": [
+                { "term": { "chat_id": chat_id } },
+                { "term": { "username": username } },
+                { "exists": { "field": "user_id" } },
+            ] } },
+            "sort": [{ "date": "desc" }],
+        }))
+        .send()
+        .await?;
+    let status = response.status_code();
+    let body: Value = response.json().await?;
+    if !status.is_success() {
+        anyhow::bail!("Username lookup failed (status {status}): {body}");
+    }
+    Ok(body["hits"]["hits"][0]["_source"]["user_id"].as_i64())
+}
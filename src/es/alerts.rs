@@ -0,0 +1,173 @@
+use elasticsearch::params::Refresh;
+use elasticsearch::{DeleteParts, Elasticsearch, IndexParts, SearchParts, UpdateParts};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Most watches returned by one query.
+const MAX_RESULTS: i64 = 10_000;
+
+/// A keyword whose daily frequency is monitored in one chat.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeywordWatch {
+    pub chat_id: i64,
+    pub keyword: String,
+    pub created_by: i64,
+    /// Unix epoch seconds
+    pub created_at: i64,
+    /// Unix epoch seconds of the last spike notification
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_alerted: Option<i64>,
+}
+
+/// Stores watched keywords in a dedicated index.
+pub struct AlertStore {
+    es: Arc<Elasticsearch>,
+    index_name: String,
+}
+
+impl AlertStore {
+    pub fn new(es: Arc<Elasticsearch>, index_name: String) -> Self {
+        Self { es, index_name }
+    }
+
+    /// Add or replace a watch, stored with its percolator query.
+    pub async fn add(&self, watch: &KeywordWatch) -> anyhow::Result<()> {
+        let id = doc_id(watch.chat_id, &watch.keyword);
+        let mut body = serde_json::to_value(watch)?;
+        body["query"] = keyword_query(watch.chat_id, &watch.keyword);
+        let response = self
+            .es
+            .index(IndexParts::IndexId(&self.index_name, &id))
+            .refresh(Refresh::WaitFor)
+            .body(body)
+            .send()
+            .await?;
+        check_status(response, "Alert write").await
+    }
+
+    /// Remove a watch, returning whether it existed.
+    pub async fn remove(&self, chat_id: i64, keyword: &str) -> anyhow::Result<bool> {
+        let id = doc_id(chat_id, keyword);
+        let response = self
+            .es
+            .delete(DeleteParts::IndexId(&self.index_name, &id))
+            .refresh(Refresh::WaitFor)
+            .send()
+            .await?;
+        if response.status_code().as_u16() == 404 {
+            return Ok(false);
+        }
+        check_status(response, "Alert delete").await?;
+        Ok(true)
+    }
+
+    pub async fn mark_alerted(&self, watch: &KeywordWatch, at: i64) -> anyhow::Result<()> {
+        let id = doc_id(watch.chat_id, &watch.keyword);
+        let response = self
+            .es
+            .update(UpdateParts::IndexId(&self.index_name, &id))
+            .body(json!({ "doc": { "last_alerted": at } }))
+            .send()
+            .await?;
+        check_status(response, "Alert update").await
+    }
+
+    pub async fn list(&self, chat_id: i64) -> anyhow::Result<Vec<KeywordWatch>> {
+        self.query(json!({ "term": { "chat_id": chat_id } })).await
+    }
+
+    /// Watches matched by any of `messages` (`_source` documents of the
+    /// message index), found with a single percolate query.
+    pub async fn matching(&self, messages: &[Value]) -> anyhow::Result<Vec<KeywordWatch>> {
+        if messages.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.query(json!({
+            "percolate": { "field": "query", "documents": messages }
+        }))
+        .await
+    }
+
+    /// Store the percolator query of watches saved before watches had one,
+    /// returning how many were updated.
+    pub async fn backfill_queries(&self) -> anyhow::Result<usize> {
+        let legacy = self
+            .query(json!({ "bool": { "must_not": { "exists": { "field": "query" } } } }))
+            .await?;
+        for watch in &legacy {
+            self.add(watch).await?;
+        }
+        Ok(legacy.len())
+    }
+
+    async fn query(&self, query: Value) -> anyhow::Result<Vec<KeywordWatch>> {
+        let response = self
+            .es
+            .search(SearchParts::Index(&[&self.index_name]))
+            .size(MAX_RESULTS)
+            .body(json!({
+                "query": query,
+                "_source": { "excludes": ["query"] },
+                "sort": [{ "created_at": { "order": "asc" } }]
+            }))
+            .send()
+            .await?;
+
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Alert query failed (status {status}): {body}");
+        }
+
+        let body: Value = response.json().await?;
+        Ok(body["hits"]["hits"]
+            .as_array()
+            .map(|hits| {
+                hits.iter()
+                    .filter_map(|h| serde_json::from_value(h["_source"].clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+/// Normalize a keyword the way watches are stored and compared.
+pub fn normalize_keyword(keyword: &str) -> String {
+    keyword.trim().to_lowercase()
+}
+
+/// Messages of `chat_id` mentioning `keyword`, used both to count matches
+/// and as the watch's percolator query.
+pub fn keyword_query(chat_id: i64, keyword: &str) -> Value {
+    json!({
+        "bool": {
+            "filter": [{ "term": { "chat_id": chat_id } }],
+            "must": [{
+                "match": {
+                    "text": {
+                        "query": keyword,
+                        "analyzer": "ik_smart",
+                        "operator": "and"
+                    }
+                }
+            }]
+        }
+    })
+}
+
+fn doc_id(chat_id: i64, keyword: &str) -> String {
+    format!("{chat_id}:{}", normalize_keyword(keyword))
+}
+
+async fn check_status(
+    response: elasticsearch::http::response::Response,
+    what: &str,
+) -> anyhow::Result<()> {
+    let status = response.status_code();
+    if !status.is_success() {
+        let body: Value = response.json().await.unwrap_or_default();
+        anyhow::bail!("{what} failed (status {status}): {body}");
+    }
+    Ok(())
+}
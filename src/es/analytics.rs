@@ -0,0 +1,1344 @@
+use elasticsearch::indices::IndicesStatsParts;
+use elasticsearch::{Elasticsearch, MgetParts, SearchParts};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::es::alerts::keyword_query;
+use crate::models::message::ChatMessage;
+
+/// Most replied-to messages sampled for reply statistics.
+const REPLY_TARGETS: usize = 500;
+/// Documents fetched per page when scanning messages.
+const SCAN_PAGE_SIZE: usize = 1000;
+/// Senders considered when looking for members who went quiet.
+const QUIET_CANDIDATES: usize = 10_000;
+/// Distinct texts considered when looking for cross-chat copies.
+const CROSS_POST_CANDIDATES: usize = 1000;
+/// Chats listed per cross-posted text.
+const CROSS_POST_CHATS: usize = 20;
+/// Senders checked by the spam report, most active first.
+const SPAM_CANDIDATES: usize = 1000;
+/// Messages a sender needs before "only forwards" counts against them.
+const MIN_FORWARDS: u64 = 3;
+/// Copies of one text that make a burst when sent within [`BURST_WINDOW_SECS`].
+const BURST_COPIES: u64 = 3;
+const BURST_WINDOW_SECS: i64 = 3600;
+
+/// Aggregation queries over the message index used by reporting commands.
+pub struct AnalyticsClient {
+    es: Arc<Elasticsearch>,
+    index_name: String,
+}
+
+#[derive(Debug)]
+pub struct ChatStats {
+    pub total: u64,
+    /// Media messages with a caption, which count under their media type
+    pub captioned: u64,
+    pub by_type: Vec<(String, u64)>,
+    pub by_ext: Vec<(String, u64)>,
+    pub by_mime: Vec<(String, u64)>,
+}
+
+/// Activity of one chat over a period, for the scheduled digest.
+#[derive(Debug)]
+pub struct ActivitySummary {
+    pub total: u64,
+    /// Most active senders by display name
+    pub top_senders: Vec<(String, u64)>,
+    pub top_domains: Vec<(String, u64)>,
+    /// Hour of day (UTC) with the most messages, and its message count
+    pub busiest_hour: Option<(u32, u64)>,
+    /// Message that got the most replies in the period
+    pub most_replied: Option<RepliedMessage>,
+}
+
+#[derive(Debug)]
+pub struct RepliedMessage {
+    pub message_id: i64,
+    pub replies: u64,
+    /// Text of the message, empty when it isn't indexed
+    pub text: String,
+}
+
+/// Most mentioned named entities of one chat, by kind.
+#[derive(Debug)]
+pub struct EntityCounts {
+    pub people: Vec<(String, u64)>,
+    pub orgs: Vec<(String, u64)>,
+    pub places: Vec<(String, u64)>,
+}
+
+/// Average scores of the classified messages in one histogram bucket.
+#[derive(Debug)]
+pub struct MoodBucket {
+    /// Unix epoch seconds
+    pub start: i64,
+    /// Classified messages in the bucket
+    pub count: u64,
+    pub sentiment: Option<f64>,
+    pub toxicity: Option<f64>,
+    /// Messages at or above the toxicity threshold
+    pub toxic: u64,
+}
+
+/// One text posted in several chats.
+#[derive(Debug)]
+pub struct CrossPost {
+    /// Text of the earliest copy
+    pub text: String,
+    /// Chats with a copy, each with its earliest copy's message id
+    pub chats: Vec<(i64, i64)>,
+    /// Total number of chats with a copy, which `chats` may cut short
+    pub chat_count: u64,
+    pub messages: u64,
+    pub senders: u64,
+    /// Unix epoch seconds of the first and last copy
+    pub first: i64,
+    pub last: i64,
+}
+
+/// A sender the spam report flags, with an example message.
+#[derive(Debug)]
+pub struct SpamSuspect {
+    pub user_id: i64,
+    pub display_name: Option<String>,
+    pub username: Option<String>,
+    pub signals: Vec<SpamSignal>,
+    /// Message showing the first signal
+    pub message_id: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpamSignal {
+    /// Their first indexed message carried links or @-mentions
+    LinkFirst,
+    /// All of their messages in the period were forwards
+    ForwardsOnly { messages: u64 },
+    /// They sent the same text this often within an hour
+    Burst { copies: u64 },
+}
+
+/// Who replies to whom, computed over the most replied-to messages.
+#[derive(Debug, Default)]
+pub struct ReplyStats {
+    /// `(replier, author, replies)`, most frequent first
+    pub pairs: Vec<(i64, i64, u64)>,
+    /// Most replied-to messages with their reply count and text
+    pub top_messages: Vec<(i64, u64, String)>,
+    /// Mean seconds between a message and its replies
+    pub avg_latency_secs: Option<f64>,
+    /// Display names of the users appearing in `pairs`
+    pub names: HashMap<i64, String>,
+}
+
+/// A member with no messages since some date.
+#[derive(Debug)]
+pub struct QuietMember {
+    pub user_id: i64,
+    pub display_name: Option<String>,
+    pub username: Option<String>,
+    /// Messages the member sent in total
+    pub messages: u64,
+    /// Unix epoch seconds of the member's newest message
+    pub last_date: i64,
+}
+
+/// A shared domain with its most recent links.
+#[derive(Debug)]
+pub struct DomainLinks {
+    pub domain: String,
+    /// Messages that linked to the domain
+    pub count: u64,
+    /// `(url, message_id, date)`, newest first
+    pub recent: Vec<(String, i64, i64)>,
+}
+
+#[derive(Debug)]
+pub struct StorageReport {
+    /// Primary store size of the whole index
+    pub index_bytes: u64,
+    pub index_docs: u64,
+    pub chat_docs: u64,
+    /// Document count and estimated bytes per message type
+    pub by_type: Vec<(String, u64, u64)>,
+}
+
+impl StorageReport {
+    pub fn avg_doc_bytes(&self) -> u64 {
+        self.index_bytes.checked_div(self.index_docs).unwrap_or(0)
+    }
+
+    pub fn chat_bytes(&self) -> u64 {
+        self.chat_docs * self.avg_doc_bytes()
+    }
+}
+
+impl AnalyticsClient {
+    pub fn new(es: Arc<Elasticsearch>, index_name: String) -> Self {
+        Self { es, index_name }
+    }
+
+    pub async fn chat_stats(&self, chat_id: i64) -> anyhow::Result<ChatStats> {
+        let body = self
+            .aggregate(json!({
+                "query": { "term": { "chat_id": chat_id } },
+                "track_total_hits": true,
+                "aggs": {
+                    "types": { "terms": { "field": "message_type", "size": 20 } },
+                    "exts": { "terms": { "field": "file_ext", "size": 10 } },
+                    "mimes": { "terms": { "field": "mime_type", "size": 10 } },
+                    "captioned": { "filter": { "exists": { "field": "caption" } } }
+                }
+            }))
+            .await?;
+
+        Ok(ChatStats {
+            total: body["hits"]["total"]["value"].as_u64().unwrap_or(0),
+            captioned: body["aggregations"]["captioned"]["doc_count"]
+                .as_u64()
+                .unwrap_or(0),
+            by_type: string_buckets(&body["aggregations"]["types"]),
+            by_ext: string_buckets(&body["aggregations"]["exts"]),
+            by_mime: string_buckets(&body["aggregations"]["mimes"]),
+        })
+    }
+
+    /// Activity between `from` and `to` (Unix epoch seconds), excluding pin
+    /// records. Hourly counts are folded into hours of the day, so a weekly
+    /// summary reports the hour that is busiest across the week.
+    pub async fn activity_summary(
+        &self,
+        chat_id: i64,
+        from: i64,
+        to: i64,
+    ) -> anyhow::Result<ActivitySummary> {
+        let body = self
+            .aggregate(json!({
+                "query": {
+                    "bool": {
+                        "filter": [
+                            { "term": { "chat_id": chat_id } },
+                            { "range": { "date": { "gte": from, "lt": to } } }
+                        ],
+                        "must_not": [{ "term": { "message_type": "pinned" } }]
+                    }
+                },
+                "track_total_hits": true,
+                "aggs": {
+                    "senders": { "terms": { "field": "display_name.keyword", "size": 5 } },
+                    "domains": { "terms": { "field": "domains", "size": 5 } },
+                    "hours": { "histogram": { "field": "date", "interval": 3600 } },
+                    "replies": {
+                        "filter": { "exists": { "field": "reply_to_message_id" } },
+                        "aggs": {
+                            "targets": { "terms": { "field": "reply_to_message_id", "size": 1 } }
+                        }
+                    }
+                }
+            }))
+            .await?;
+
+        let mut by_hour = [0u64; 24];
+        for bucket in body["aggregations"]["hours"]["buckets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            if let (Some(start), Some(count)) =
+                (bucket["key"].as_f64(), bucket["doc_count"].as_u64())
+            {
+                by_hour[(start as i64 / 3600).rem_euclid(24) as usize] += count;
+            }
+        }
+        let busiest_hour = (0..24u32)
+            .map(|hour| (hour, by_hour[hour as usize]))
+            .filter(|&(_, count)| count > 0)
+            .max_by_key(|&(hour, count)| (count, std::cmp::Reverse(hour)));
+
+        let target = &body["aggregations"]["replies"]["targets"]["buckets"][0];
+        let most_replied = match (target["key"].as_i64(), target["doc_count"].as_u64()) {
+            (Some(message_id), Some(replies)) => Some(RepliedMessage {
+                message_id,
+                replies,
+                text: self
+                    .message_text(chat_id, message_id)
+                    .await?
+                    .unwrap_or_default(),
+            }),
+            _ => None,
+        };
+
+        Ok(ActivitySummary {
+            total: body["hits"]["total"]["value"].as_u64().unwrap_or(0),
+            top_senders: string_buckets(&body["aggregations"]["senders"]),
+            top_domains: string_buckets(&body["aggregations"]["domains"]),
+            busiest_hour,
+            most_replied,
+        })
+    }
+
+    /// Text of a message as first sent, looked up by field rather than
+    /// document id so it doesn't depend on the id strategy.
+    async fn message_text(&self, chat_id: i64, message_id: i64) -> anyhow::Result<Option<String>> {
+        let response = self
+            .es
+            .search(SearchParts::Index(&[&self.index_name]))
+            .size(1)
+            .body(json!({
+                "query": {
+                    "bool": {
+                        "filter": [
+                            { "term": { "chat_id": chat_id } },
+                            { "term": { "message_id": message_id } }
+                        ],
+                        "must_not": [{ "exists": { "field": "edited_at" } }]
+                    }
+                },
+                "_source": ["text"]
+            }))
+            .send()
+            .await?;
+
+        let status = response.status_code();
+        let body: Value = response.json().await?;
+        if !status.is_success() {
+            anyhow::bail!("Message lookup failed (status {status}): {body}");
+        }
+        Ok(body["hits"]["hits"][0]["_source"]["text"]
+            .as_str()
+            .map(str::to_string))
+    }
+
+    /// Reply graph of a chat. Replies are grouped by the message they answer;
+    /// the answered messages are then fetched to learn their authors and dates.
+    pub async fn reply_stats(&self, chat_id: i64) -> anyhow::Result<ReplyStats> {
+        let body = self
+            .aggregate(json!({
+                "query": {
+                    "bool": {
+                        "filter": [
+                            { "term": { "chat_id": chat_id } },
+                            { "exists": { "field": "reply_to_message_id" } }
+                        ]
+                    }
+                },
+                "aggs": {
+                    "targets": {
+                        "terms": { "field": "reply_to_message_id", "size": REPLY_TARGETS },
+                        "aggs": {
+                            "repliers": { "terms": { "field": "user_id", "size": 20 } },
+                            "avg_date": { "avg": { "field": "date" } }
+                        }
+                    },
+                    "users": {
+                        "terms": { "field": "user_id", "size": 100 },
+                        "aggs": {
+                            "name": { "terms": { "field": "display_name.keyword", "size": 1 } }
+                        }
+                    }
+                }
+            }))
+            .await?;
+
+        let targets = body["aggregations"]["targets"]["buckets"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        if targets.is_empty() {
+            return Ok(ReplyStats::default());
+        }
+
+        let ids: Vec<String> = targets
+            .iter()
+            .filter_map(|b| b["key"].as_i64())
+            .map(|id| format!("{chat_id}_{id}"))
+            .collect();
+        let parents = self.fetch_sources(&ids).await?;
+
+        let mut stats = ReplyStats::default();
+        let mut pairs: HashMap<(i64, i64), u64> = HashMap::new();
+        let (mut latency_sum, mut latency_count) = (0.0, 0u64);
+        for bucket in &targets {
+            let (Some(message_id), Some(count)) =
+                (bucket["key"].as_i64(), bucket["doc_count"].as_u64())
+            else {
+                continue;
+            };
+            let parent = parents.get(&message_id);
+            if stats.top_messages.len() < 5 {
+                let text = parent.and_then(|p| p["text"].as_str()).unwrap_or_default();
+                stats
+                    .top_messages
+                    .push((message_id, count, text.to_string()));
+            }
+            let Some(parent) = parent else {
+                continue;
+            };
+
+            if let (Some(avg_date), Some(date)) = (
+                bucket["avg_date"]["value"].as_f64(),
+                parent["date"].as_i64(),
+            ) && avg_date >= date as f64
+            {
+                latency_sum += (avg_date - date as f64) * count as f64;
+                latency_count += count;
+            }
+
+            let Some(author) = parent["user_id"].as_i64() else {
+                continue;
+            };
+            for replier in bucket["repliers"]["buckets"]
+                .as_array()
+                .into_iter()
+                .flatten()
+            {
+                if let (Some(replier), Some(n)) =
+                    (replier["key"].as_i64(), replier["doc_count"].as_u64())
+                    && replier != author
+                {
+                    *pairs.entry((replier, author)).or_default() += n;
+                }
+            }
+        }
+
+        let mut pairs: Vec<(i64, i64, u64)> = pairs
+            .into_iter()
+            .map(|((from, to), n)| (from, to, n))
+            .collect();
+        pairs.sort_by_key(|&(_, _, n)| std::cmp::Reverse(n));
+        pairs.truncate(5);
+        stats.pairs = pairs;
+        stats.avg_latency_secs = (latency_count > 0).then(|| latency_sum / latency_count as f64);
+
+        for user in body["aggregations"]["users"]["buckets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            if let (Some(id), Some(name)) = (
+                user["key"].as_i64(),
+                user["name"]["buckets"][0]["key"].as_str(),
+            ) {
+                stats.names.insert(id, name.to_string());
+            }
+        }
+        for parent in parents.values() {
+            if let (Some(id), Some(name)) =
+                (parent["user_id"].as_i64(), parent["display_name"].as_str())
+            {
+                stats.names.entry(id).or_insert_with(|| name.to_string());
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Members with at least `min_messages` messages but none since `since`,
+    /// the most recently active first. Pins and edit revisions don't count.
+    pub async fn quiet_members(
+        &self,
+        chat_id: i64,
+        since: i64,
+        min_messages: u64,
+    ) -> anyhow::Result<Vec<QuietMember>> {
+        let body = self
+            .aggregate(json!({
+                "query": {
+                    "bool": {
+                        "filter": [{ "term": { "chat_id": chat_id } }],
+                        "must_not": [
+                            { "term": { "message_type": "pinned" } },
+                            { "exists": { "field": "edited_at" } }
+                        ]
+                    }
+                },
+                "aggs": {
+                    "users": {
+                        "terms": {
+                            "field": "user_id",
+                            "size": QUIET_CANDIDATES,
+                            "min_doc_count": min_messages
+                        },
+                        "aggs": {
+                            "last": { "max": { "field": "date" } },
+                            "name": { "terms": { "field": "display_name.keyword", "size": 1 } },
+                            "username": { "terms": { "field": "username", "size": 1 } }
+                        }
+                    }
+                }
+            }))
+            .await?;
+
+        let mut members: Vec<QuietMember> = body["aggregations"]["users"]["buckets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|user| {
+                Some(QuietMember {
+                    user_id: user["key"].as_i64()?,
+                    display_name: user["name"]["buckets"][0]["key"].as_str().map(String::from),
+                    username: user["username"]["buckets"][0]["key"]
+                        .as_str()
+                        .map(String::from),
+                    messages: user["doc_count"].as_u64().unwrap_or(0),
+                    last_date: user["last"]["value"].as_f64()? as i64,
+                })
+            })
+            .filter(|member| member.last_date < since)
+            .collect();
+        members.sort_by_key(|member| std::cmp::Reverse(member.last_date));
+        Ok(members)
+    }
+
+    /// Most mentioned people, organizations and locations since `since` (all
+    /// time when `None`).
+    pub async fn top_entities(
+        &self,
+        chat_id: i64,
+        since: Option<i64>,
+        size: usize,
+    ) -> anyhow::Result<EntityCounts> {
+        let mut filter = vec![json!({ "term": { "chat_id": chat_id } })];
+        if let Some(since) = since {
+            filter.push(json!({ "range": { "date": { "gte": since } } }));
+        }
+        let body = self
+            .aggregate(json!({
+                "query": { "bool": { "filter": filter } },
+                "aggs": {
+                    "people": { "terms": { "field": "people", "size": size } },
+                    "orgs": { "terms": { "field": "orgs", "size": size } },
+                    "places": { "terms": { "field": "places", "size": size } }
+                }
+            }))
+            .await?;
+
+        let aggs = &body["aggregations"];
+        Ok(EntityCounts {
+            people: string_buckets(&aggs["people"]),
+            orgs: string_buckets(&aggs["orgs"]),
+            places: string_buckets(&aggs["places"]),
+        })
+    }
+
+    /// Most linked domains since `since` (all time when `None`), skipping the
+    /// first `offset`. Also returns whether more domains follow.
+    pub async fn top_domains(
+        &self,
+        chat_id: i64,
+        since: Option<i64>,
+        offset: usize,
+        limit: usize,
+    ) -> anyhow::Result<(Vec<DomainLinks>, bool)> {
+        let mut filter = vec![
+            json!({ "term": { "chat_id": chat_id } }),
+            json!({ "exists": { "field": "domains" } }),
+        ];
+        if let Some(since) = since {
+            filter.push(json!({ "range": { "date": { "gte": since } } }));
+        }
+
+        let body = self
+            .aggregate(json!({
+                "query": {
+                    "bool": {
+                        "filter": filter,
+                        "must_not": [{ "term": { "message_type": "pinned" } }]
+                    }
+                },
+                "aggs": {
+                    "domains": {
+                        "terms": { "field": "domains", "size": offset + limit + 1 },
+                        "aggs": {
+                            "recent": {
+                                "top_hits": {
+                                    "size": 3,
+                                    "sort": [{ "date": { "order": "desc" } }],
+                                    "_source": ["urls", "message_id", "date"]
+                                }
+                            }
+                        }
+                    }
+                }
+            }))
+            .await?;
+
+        let buckets = body["aggregations"]["domains"]["buckets"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let has_more = buckets.len() > offset + limit;
+        let domains = buckets
+            .iter()
+            .skip(offset)
+            .take(limit)
+            .filter_map(|bucket| {
+                let domain = bucket["key"].as_str()?.to_string();
+                let recent = bucket["recent"]["hits"]["hits"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|hit| {
+                        let source = &hit["_source"];
+                        let urls = source["urls"].as_array()?;
+                        // A message may link several sites; show the one for this domain
+                        let url = urls
+                            .iter()
+                            .filter_map(Value::as_str)
+                            .find(|u| u.contains(domain.as_str()))
+                            .or_else(|| urls.first()?.as_str())?;
+                        Some((
+                            url.to_string(),
+                            source["message_id"].as_i64()?,
+                            source["date"].as_i64()?,
+                        ))
+                    })
+                    .collect();
+                Some(DomainLinks {
+                    count: bucket["doc_count"].as_u64().unwrap_or(0),
+                    domain,
+                    recent,
+                })
+            })
+            .collect();
+        Ok((domains, has_more))
+    }
+
+    /// Up to `limit` messages sent between `from` and `to`, most replied-to
+    /// first, topped up with random picks when too few got replies.
+    pub async fn throwback(
+        &self,
+        chat_id: i64,
+        from: i64,
+        to: i64,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(ChatMessage, u64)>> {
+        // Replies to the day's messages mostly arrive within the following week
+        let body = self
+            .aggregate(json!({
+                "query": {
+                    "bool": {
+                        "filter": [
+                            { "term": { "chat_id": chat_id } },
+                            { "exists": { "field": "reply_to_message_id" } },
+                            { "range": { "date": { "gte": from, "lt": to + 7 * 86400 } } }
+                        ]
+                    }
+                },
+                "aggs": {
+                    "targets": { "terms": { "field": "reply_to_message_id", "size": 100 } }
+                }
+            }))
+            .await?;
+
+        let counts: HashMap<i64, u64> = body["aggregations"]["targets"]["buckets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|b| Some((b["key"].as_i64()?, b["doc_count"].as_u64()?)))
+            .collect();
+        let ids: Vec<String> = counts.keys().map(|id| format!("{chat_id}_{id}")).collect();
+
+        let mut picks: Vec<(ChatMessage, u64)> = if ids.is_empty() {
+            Vec::new()
+        } else {
+            self.fetch_sources(&ids)
+                .await?
+                .into_values()
+                .filter_map(|source| serde_json::from_value::<ChatMessage>(source).ok())
+                .filter(|m| (from..to).contains(&m.date) && !m.text.trim().is_empty())
+                .map(|m| {
+                    let replies = counts.get(&m.message_id).copied().unwrap_or(0);
+                    (m, replies)
+                })
+                .collect()
+        };
+        picks.sort_by_key(|(m, replies)| (std::cmp::Reverse(*replies), m.date));
+        picks.truncate(limit);
+        if picks.len() >= limit {
+            return Ok(picks);
+        }
+
+        let response = self
+            .es
+            .search(SearchParts::Index(&[&self.index_name]))
+            .size((limit - picks.len()) as i64)
+            .body(json!({
+                "query": {
+                    "function_score": {
+                        "query": {
+                            "bool": {
+                                "filter": [
+                                    { "term": { "chat_id": chat_id } },
+                                    { "term": { "message_type": "text" } },
+                                    { "range": { "date": { "gte": from, "lt": to } } }
+                                ],
+                                "must_not": [{ "ids": { "values": ids } }]
+                            }
+                        },
+                        "random_score": { "seed": from, "field": "_seq_no" }
+                    }
+                }
+            }))
+            .send()
+            .await?;
+
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Throwback search failed (status {status}): {body}");
+        }
+        let body: Value = response.json().await?;
+        picks.extend(
+            body["hits"]["hits"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|hit| serde_json::from_value(hit["_source"].clone()).ok())
+                .map(|m| (m, 0)),
+        );
+        Ok(picks)
+    }
+
+    /// Matches of each keyword per `bucket_secs` window since `since`, oldest
+    /// bucket first. Each row is `(bucket_start, counts in keyword order)`.
+    pub async fn keyword_histogram(
+        &self,
+        chat_id: i64,
+        keywords: &[String],
+        since: i64,
+        bucket_secs: i64,
+    ) -> anyhow::Result<Vec<(i64, Vec<u64>)>> {
+        let filters: serde_json::Map<String, Value> = keywords
+            .iter()
+            .enumerate()
+            .map(|(i, keyword)| {
+                let filter = json!({
+                    "match": {
+                        "text": { "query": keyword, "analyzer": "ik_smart", "operator": "and" }
+                    }
+                });
+                (i.to_string(), filter)
+            })
+            .collect();
+        let now = chrono::Utc::now().timestamp();
+
+        // `date` holds epoch seconds, so a numeric histogram stands in for date_histogram
+        let body = self
+            .aggregate(json!({
+                "query": {
+                    "bool": {
+                        "filter": [
+                            { "term": { "chat_id": chat_id } },
+                            { "range": { "date": { "gte": since } } }
+                        ],
+                        "must_not": [{ "term": { "message_type": "pinned" } }]
+                    }
+                },
+                "aggs": {
+                    "windows": {
+                        "histogram": {
+                            "field": "date",
+                            "interval": bucket_secs,
+                            "offset": since.rem_euclid(bucket_secs),
+                            "min_doc_count": 0,
+                            "extended_bounds": { "min": since, "max": now }
+                        },
+                        "aggs": {
+                            "keywords": { "filters": { "filters": filters } }
+                        }
+                    }
+                }
+            }))
+            .await?;
+
+        Ok(body["aggregations"]["windows"]["buckets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|bucket| {
+                let start = bucket["key"].as_f64()? as i64;
+                let counts = (0..keywords.len())
+                    .map(|i| {
+                        bucket["keywords"]["buckets"][i.to_string()]["doc_count"]
+                            .as_u64()
+                            .unwrap_or(0)
+                    })
+                    .collect();
+                Some((start, counts))
+            })
+            .collect())
+    }
+
+    /// Up to `limit` chats with the most messages since `since`, busiest first.
+    pub async fn most_active_chats(&self, since: i64, limit: usize) -> anyhow::Result<Vec<i64>> {
+        let body = self
+            .aggregate(json!({
+                "query": { "range": { "date": { "gte": since } } },
+                "aggs": {
+                    "chats": { "terms": { "field": "chat_id", "size": limit } }
+                }
+            }))
+            .await?;
+
+        Ok(body["aggregations"]["chats"]["buckets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|bucket| bucket["key"].as_i64())
+            .collect())
+    }
+
+    /// Texts posted in at least `min_chats` chats since `since`, grouped by
+    /// content hash, most widespread first. Pins and edit revisions don't
+    /// count as copies.
+    pub async fn cross_posts(
+        &self,
+        since: i64,
+        min_chats: u64,
+        limit: usize,
+    ) -> anyhow::Result<Vec<CrossPost>> {
+        let body = self
+            .aggregate(json!({
+                "query": {
+                    "bool": {
+                        "filter": [
+                            { "range": { "date": { "gte": since } } },
+                            { "exists": { "field": "content_hash" } }
+                        ],
+                        "must_not": [
+                            { "term": { "message_type": "pinned" } },
+                            { "exists": { "field": "edited_at" } }
+                        ]
+                    }
+                },
+                "aggs": {
+                    "hashes": {
+                        "terms": {
+                            "field": "content_hash",
+                            "size": CROSS_POST_CANDIDATES,
+                            "min_doc_count": min_chats,
+                            "order": { "chats": "desc" }
+                        },
+                        "aggs": {
+                            "chats": { "cardinality": { "field": "chat_id" } },
+                            "spread": {
+                                "bucket_selector": {
+                                    "buckets_path": { "chats": "chats" },
+                                    "script": {
+                                        "source": "params.chats >= params.min",
+                                        "params": { "min": min_chats }
+                                    }
+                                }
+                            },
+                            "senders": { "cardinality": { "field": "user_id" } },
+                            "first": { "min": { "field": "date" } },
+                            "last": { "max": { "field": "date" } },
+                            "per_chat": {
+                                "terms": { "field": "chat_id", "size": CROSS_POST_CHATS },
+                                "aggs": {
+                                    "earliest": {
+                                        "top_hits": {
+                                            "size": 1,
+                                            "sort": [{ "date": { "order": "asc" } }],
+                                            "_source": ["message_id", "text"]
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }))
+            .await?;
+
+        let mut posts: Vec<CrossPost> = body["aggregations"]["hashes"]["buckets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|bucket| {
+                let mut earliest: Option<(i64, &Value)> = None;
+                let mut chats = Vec::new();
+                for chat in bucket["per_chat"]["buckets"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                {
+                    let hit = &chat["earliest"]["hits"]["hits"][0];
+                    let (Some(chat_id), Some(message_id)) =
+                        (chat["key"].as_i64(), hit["_source"]["message_id"].as_i64())
+                    else {
+                        continue;
+                    };
+                    chats.push((chat_id, message_id));
+                    let date = hit["sort"][0].as_i64().unwrap_or(i64::MAX);
+                    if earliest.is_none_or(|(first, _)| date < first) {
+                        earliest = Some((date, hit));
+                    }
+                }
+                CrossPost {
+                    text: earliest
+                        .and_then(|(_, hit)| hit["_source"]["text"].as_str())
+                        .unwrap_or_default()
+                        .to_string(),
+                    chats,
+                    chat_count: bucket["chats"]["value"].as_u64().unwrap_or(0),
+                    messages: bucket["doc_count"].as_u64().unwrap_or(0),
+                    senders: bucket["senders"]["value"].as_u64().unwrap_or(0),
+                    first: bucket["first"]["value"].as_f64().unwrap_or(0.0) as i64,
+                    last: bucket["last"]["value"].as_f64().unwrap_or(0.0) as i64,
+                }
+            })
+            .collect();
+        // Cardinalities are approximate, so the terms order is only roughly right
+        posts.sort_by_key(|p| {
+            (
+                std::cmp::Reverse(p.chat_count),
+                std::cmp::Reverse(p.messages),
+            )
+        });
+        posts.truncate(limit);
+        Ok(posts)
+    }
+
+    /// Senders of the chat active since `since` who look like spam accounts:
+    /// a first message with links or mentions, nothing but forwards, or the
+    /// same text repeated in a burst.
+    pub async fn spam_suspects(
+        &self,
+        chat_id: i64,
+        since: i64,
+    ) -> anyhow::Result<Vec<SpamSuspect>> {
+        let newest = json!({
+            "top_hits": {
+                "size": 1,
+                "sort": [{ "date": { "order": "desc" } }],
+                "_source": ["message_id", "display_name", "username"]
+            }
+        });
+        let body = self
+            .aggregate(json!({
+                "query": {
+                    "bool": {
+                        "filter": [
+                            { "term": { "chat_id": chat_id } },
+                            { "range": { "date": { "gte": since } } }
+                        ],
+                        "must_not": [
+                            { "term": { "message_type": "pinned" } },
+                            { "exists": { "field": "edited_at" } }
+                        ]
+                    }
+                },
+                "aggs": {
+                    "senders": {
+                        "terms": { "field": "user_id", "size": SPAM_CANDIDATES },
+                        "aggs": {
+                            "newest": newest,
+                            "forwards": { "filter": { "term": { "forwarded": true } } },
+                            "repeats": {
+                                "terms": {
+                                    "field": "content_hash",
+                                    "size": 3,
+                                    "min_doc_count": BURST_COPIES
+                                },
+                                "aggs": {
+                                    "first": { "min": { "field": "date" } },
+                                    "last": { "max": { "field": "date" } },
+                                    "newest": newest
+                                }
+                            }
+                        }
+                    }
+                }
+            }))
+            .await?;
+
+        let mut suspects: Vec<SpamSuspect> = Vec::new();
+        for sender in body["aggregations"]["senders"]["buckets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            let Some(user_id) = sender["key"].as_i64() else {
+                continue;
+            };
+            let newest = &sender["newest"]["hits"]["hits"][0]["_source"];
+            let mut suspect = SpamSuspect {
+                user_id,
+                display_name: newest["display_name"].as_str().map(String::from),
+                username: newest["username"].as_str().map(String::from),
+                signals: Vec::new(),
+                message_id: newest["message_id"].as_i64().unwrap_or(0),
+            };
+            let messages = sender["doc_count"].as_u64().unwrap_or(0);
+            if messages >= MIN_FORWARDS
+                && sender["forwards"]["doc_count"].as_u64() == Some(messages)
+            {
+                suspect.signals.push(SpamSignal::ForwardsOnly { messages });
+            }
+            let burst = sender["repeats"]["buckets"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find(|text| {
+                    let span = text["last"]["value"].as_f64().unwrap_or(0.0)
+                        - text["first"]["value"].as_f64().unwrap_or(0.0);
+                    span <= BURST_WINDOW_SECS as f64
+                });
+            if let Some(text) = burst {
+                let copies = text["doc_count"].as_u64().unwrap_or(0);
+                suspect.signals.push(SpamSignal::Burst { copies });
+                if suspect.signals.len() == 1
+                    && let Some(id) =
+                        text["newest"]["hits"]["hits"][0]["_source"]["message_id"].as_i64()
+                {
+                    suspect.message_id = id;
+                }
+            }
+            suspects.push(suspect);
+        }
+
+        // First messages ever, for the senders active in the period
+        let user_ids: Vec<i64> = suspects.iter().map(|s| s.user_id).collect();
+        if !user_ids.is_empty() {
+            let body = self
+                .aggregate(json!({
+                    "query": {
+                        "bool": {
+                            "filter": [
+                                { "term": { "chat_id": chat_id } },
+                                { "terms": { "user_id": user_ids } }
+                            ],
+                            "must_not": [{ "term": { "message_type": "pinned" } }]
+                        }
+                    },
+                    "aggs": {
+                        "senders": {
+                            "terms": { "field": "user_id", "size": user_ids.len() },
+                            "aggs": {
+                                "first": {
+                                    "top_hits": {
+                                        "size": 1,
+                                        "sort": [{ "date": { "order": "asc" } }],
+                                        "_source": ["message_id", "urls", "mentions"]
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }))
+                .await?;
+            let firsts: HashMap<i64, &Value> = body["aggregations"]["senders"]["buckets"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|b| {
+                    Some((
+                        b["key"].as_i64()?,
+                        &b["first"]["hits"]["hits"][0]["_source"],
+                    ))
+                })
+                .collect();
+            for suspect in &mut suspects {
+                let Some(first) = firsts.get(&suspect.user_id) else {
+                    continue;
+                };
+                let has = |field: &str| first[field].as_array().is_some_and(|v| !v.is_empty());
+                if has("urls") || has("mentions") {
+                    suspect.signals.insert(0, SpamSignal::LinkFirst);
+                    suspect.message_id = first["message_id"].as_i64().unwrap_or(suspect.message_id);
+                }
+            }
+        }
+
+        suspects.retain(|s| !s.signals.is_empty());
+        suspects.sort_by_key(|s| std::cmp::Reverse(s.signals.len()));
+        Ok(suspects)
+    }
+
+    /// Messages sent per `bucket_secs` window since `since`, oldest bucket
+    /// first, as `(bucket_start, count)`. Pins and edit revisions don't count.
+    pub async fn message_volume(
+        &self,
+        chat_id: i64,
+        since: i64,
+        bucket_secs: i64,
+    ) -> anyhow::Result<Vec<(i64, u64)>> {
+        let now = chrono::Utc::now().timestamp();
+        let body = self
+            .aggregate(json!({
+                "query": {
+                    "bool": {
+                        "filter": [
+                            { "term": { "chat_id": chat_id } },
+                            { "range": { "date": { "gte": since } } }
+                        ],
+                        "must_not": [
+                            { "term": { "message_type": "pinned" } },
+                            { "exists": { "field": "edited_at" } }
+                        ]
+                    }
+                },
+                "aggs": {
+                    "windows": {
+                        "histogram": {
+                            "field": "date",
+                            "interval": bucket_secs,
+                            "offset": since.rem_euclid(bucket_secs),
+                            "min_doc_count": 0,
+                            "extended_bounds": { "min": since, "max": now }
+                        }
+                    }
+                }
+            }))
+            .await?;
+
+        Ok(body["aggregations"]["windows"]["buckets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|bucket| {
+                let start = bucket["key"].as_f64()? as i64;
+                Some((start, bucket["doc_count"].as_u64().unwrap_or(0)))
+            })
+            .collect())
+    }
+
+    /// Average sentiment and toxicity of classified messages since `since`,
+    /// in `bucket_secs` wide buckets.
+    pub async fn mood_trend(
+        &self,
+        chat_id: i64,
+        since: i64,
+        bucket_secs: i64,
+        toxic_threshold: f32,
+    ) -> anyhow::Result<Vec<MoodBucket>> {
+        let now = chrono::Utc::now().timestamp();
+        let body = self
+            .aggregate(json!({
+                "query": {
+                    "bool": {
+                        "filter": [
+                            { "term": { "chat_id": chat_id } },
+                            { "range": { "date": { "gte": since } } },
+                            { "exists": { "field": "toxicity" } }
+                        ]
+                    }
+                },
+                "aggs": {
+                    "windows": {
+                        "histogram": {
+                            "field": "date",
+                            "interval": bucket_secs,
+                            "offset": since.rem_euclid(bucket_secs),
+                            "min_doc_count": 0,
+                            "extended_bounds": { "min": since, "max": now }
+                        },
+                        "aggs": {
+                            "sentiment": { "avg": { "field": "sentiment" } },
+                            "toxicity": { "avg": { "field": "toxicity" } },
+                            "toxic": {
+                                "filter": { "range": { "toxicity": { "gte": toxic_threshold } } }
+                            }
+                        }
+                    }
+                }
+            }))
+            .await?;
+
+        Ok(body["aggregations"]["windows"]["buckets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|bucket| {
+                Some(MoodBucket {
+                    start: bucket["key"].as_f64()? as i64,
+                    count: bucket["doc_count"].as_u64().unwrap_or(0),
+                    sentiment: bucket["sentiment"]["value"].as_f64(),
+                    toxicity: bucket["toxicity"]["value"].as_f64(),
+                    toxic: bucket["toxic"]["doc_count"].as_u64().unwrap_or(0),
+                })
+            })
+            .collect())
+    }
+
+    /// Estimate the chat's share of the index as doc count × average doc size.
+    pub async fn storage_report(&self, chat_id: i64) -> anyhow::Result<StorageReport> {
+        let response = self
+            .es
+            .indices()
+            .stats(IndicesStatsParts::Index(&[&self.index_name]))
+            .send()
+            .await?;
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Index stats failed (status {status}): {body}");
+        }
+        let index_stats: Value = response.json().await?;
+        let primaries = &index_stats["_all"]["primaries"];
+        let index_bytes = primaries["store"]["size_in_bytes"].as_u64().unwrap_or(0);
+        let index_docs = primaries["docs"]["count"].as_u64().unwrap_or(0);
+
+        let stats = self.chat_stats(chat_id).await?;
+        let avg = index_bytes.checked_div(index_docs).unwrap_or(0);
+        Ok(StorageReport {
+            index_bytes,
+            index_docs,
+            chat_docs: stats.total,
+            by_type: stats
+                .by_type
+                .into_iter()
+                .map(|(kind, count)| (kind, count, count * avg))
+                .collect(),
+        })
+    }
+
+    /// Matches of `keyword` in each of the last `days` 24-hour windows, most
+    /// recent first.
+    pub async fn keyword_daily_counts(
+        &self,
+        chat_id: i64,
+        keyword: &str,
+        days: u32,
+    ) -> anyhow::Result<Vec<u64>> {
+        let now = chrono::Utc::now().timestamp();
+        let ranges: Vec<Value> = (0..days as i64)
+            .map(|day| {
+                json!({
+                    "key": day.to_string(),
+                    "from": now - (day + 1) * 86400,
+                    "to": now - day * 86400
+                })
+            })
+            .collect();
+
+        let body = self
+            .aggregate(json!({
+                "query": keyword_query(chat_id, keyword),
+                "aggs": {
+                    "days": { "range": { "field": "date", "ranges": ranges } }
+                }
+            }))
+            .await?;
+
+        let buckets = body["aggregations"]["days"]["buckets"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        Ok((0..days)
+            .map(|day| {
+                buckets
+                    .iter()
+                    .find(|b| b["key"].as_str() == Some(day.to_string().as_str()))
+                    .and_then(|b| b["doc_count"].as_u64())
+                    .unwrap_or(0)
+            })
+            .collect())
+    }
+
+    /// Chat and text of every message dated in `[from, to)`, for matching
+    /// against keyword watches.
+    pub async fn messages_between(&self, from: i64, to: i64) -> anyhow::Result<Vec<Value>> {
+        let mut messages = Vec::new();
+        let mut search_after: Option<Value> = None;
+        loop {
+            let mut body = json!({
+                "query": { "range": { "date": { "gte": from, "lt": to } } },
+                "_source": ["chat_id", "text"],
+                "sort": [
+                    { "date": "asc" },
+                    { "chat_id": "asc" },
+                    { "message_id": "asc" }
+                ]
+            });
+            if let Some(after) = search_after.take() {
+                body["search_after"] = after;
+            }
+            let response = self
+                .es
+                .search(SearchParts::Index(&[&self.index_name]))
+                .size(SCAN_PAGE_SIZE as i64)
+                .body(body)
+                .send()
+                .await?;
+
+            let status = response.status_code();
+            let body: Value = response.json().await?;
+            if !status.is_success() {
+                anyhow::bail!("Message scan failed (status {status}): {body}");
+            }
+            let hits = body["hits"]["hits"].as_array().cloned().unwrap_or_default();
+            search_after = hits.last().map(|hit| hit["sort"].clone());
+            let done = hits.len() < SCAN_PAGE_SIZE;
+            messages.extend(hits.into_iter().map(|hit| hit["_source"].clone()));
+            if done {
+                return Ok(messages);
+            }
+        }
+    }
+
+    /// `_source` of each existing document, keyed by message id.
+    async fn fetch_sources(&self, ids: &[String]) -> anyhow::Result<HashMap<i64, Value>> {
+        let response = self
+            .es
+            .mget(MgetParts::Index(&self.index_name))
+            .body(json!({ "ids": ids }))
+            .send()
+            .await?;
+
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Multi-get failed (status {status}): {body}");
+        }
+
+        let body: Value = response.json().await?;
+        Ok(body["docs"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|doc| doc["found"].as_bool() == Some(true))
+            .filter_map(|doc| {
+                Some((
+                    doc["_source"]["message_id"].as_i64()?,
+                    doc["_source"].clone(),
+                ))
+            })
+            .collect())
+    }
+
+    /// Run a `size: 0` search and return the raw response body.
+    async fn aggregate(&self, query: Value) -> anyhow::Result<Value> {
+        let response = self
+            .es
+            .search(SearchParts::Index(&[&self.index_name]))
+            .size(0)
+            .body(query)
+            .send()
+            .await?;
+
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Aggregation failed (status {status}): {body}");
+        }
+
+        Ok(response.json().await?)
+    }
+}
+
+/// Collect `(key, doc_count)` pairs from a terms aggregation over a keyword field.
+fn string_buckets(agg: &Value) -> Vec<(String, u64)> {
+    agg["buckets"]
+        .as_array()
+        .map(|buckets| {
+            buckets
+                .iter()
+                .filter_map(|b| Some((b["key"].as_str()?.to_string(), b["doc_count"].as_u64()?)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,136 @@
+use elasticsearch::{Elasticsearch, IndexParts, SearchParts};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// A single search performed through the bot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub chat_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<i64>,
+    pub query: String,
+    pub result_count: u64,
+    /// Unix epoch seconds
+    pub date: i64,
+}
+
+#[derive(Debug)]
+pub struct AuditSummary {
+    pub total: u64,
+    pub top_users: Vec<(i64, u64)>,
+    pub top_queries: Vec<(String, u64)>,
+    pub recent: Vec<AuditEntry>,
+}
+
+/// Writes search audit entries to a dedicated index and summarizes them.
+pub struct AuditLog {
+    es: Arc<Elasticsearch>,
+    index_name: String,
+    enabled: bool,
+}
+
+impl AuditLog {
+    pub fn new(es: Arc<Elasticsearch>, index_name: String, enabled: bool) -> Self {
+        Self {
+            es,
+            index_name,
+            enabled,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Record an entry in the background so searches never wait on the audit write.
+    pub fn record(self: &Arc<Self>, entry: AuditEntry) {
+        if !self.enabled {
+            return;
+        }
+        let this = Arc::clone(self);
+        tokio::spawn(async move {
+            let result = this
+                .es
+                .index(IndexParts::Index(&this.index_name))
+                .body(&entry)
+                .send()
+                .await;
+            match result {
+                Ok(response) if response.status_code().is_success() => {}
+                Ok(response) => {
+                    tracing::warn!("Audit write returned status {}", response.status_code())
+                }
+                Err(e) => tracing::warn!("Audit write failed: {e}"),
+            }
+        });
+    }
+
+    pub async fn summary(
+        &self,
+        chat_id: Option<i64>,
+        since: Option<i64>,
+    ) -> anyhow::Result<AuditSummary> {
+        let mut filter = vec![];
+        if let Some(chat_id) = chat_id {
+            filter.push(json!({ "term": { "chat_id": chat_id } }));
+        }
+        if let Some(since) = since {
+            filter.push(json!({ "range": { "date": { "gte": since } } }));
+        }
+
+        let response = self
+            .es
+            .search(SearchParts::Index(&[&self.index_name]))
+            .size(10)
+            .body(json!({
+                "query": { "bool": { "filter": filter } },
+                "sort": [{ "date": { "order": "desc" } }],
+                "track_total_hits": true,
+                "aggs": {
+                    "users": { "terms": { "field": "user_id", "size": 10 } },
+                    "queries": { "terms": { "field": "query", "size": 10 } }
+                }
+            }))
+            .send()
+            .await?;
+
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Audit query failed (status {status}): {body}");
+        }
+
+        let body: Value = response.json().await?;
+        let buckets = |name: &str| {
+            body["aggregations"][name]["buckets"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default()
+        };
+
+        let top_users = buckets("users")
+            .iter()
+            .filter_map(|b| Some((b["key"].as_i64()?, b["doc_count"].as_u64()?)))
+            .collect();
+        let top_queries = buckets("queries")
+            .iter()
+            .filter_map(|b| Some((b["key"].as_str()?.to_string(), b["doc_count"].as_u64()?)))
+            .collect();
+        let recent = body["hits"]["hits"]
+            .as_array()
+            .map(|hits| {
+                hits.iter()
+                    .filter_map(|h| serde_json::from_value(h["_source"].clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(AuditSummary {
+            total: body["hits"]["total"]["value"].as_u64().unwrap_or(0),
+            top_users,
+            top_queries,
+            recent,
+        })
+    }
+}
@@ -0,0 +1,160 @@
+use elasticsearch::http::request::JsonBody;
+use elasticsearch::params::Refresh;
+use elasticsearch::{BulkParts, DeleteParts, Elasticsearch, IndexParts, SearchParts};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Most bookmarks returned by one query.
+const MAX_RESULTS: i64 = 1000;
+
+/// A message a user saved from search results to their personal list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub user_id: i64,
+    pub chat_id: i64,
+    pub message_id: i64,
+    /// Start of the message text, shown in the list
+    #[serde(default)]
+    pub preview: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// Unix epoch seconds the message was sent
+    pub date: i64,
+    /// Unix epoch seconds the bookmark was saved
+    pub saved_at: i64,
+}
+
+/// Stores bookmarks in a dedicated index, one document per user and message.
+pub struct BookmarkStore {
+    es: Arc<Elasticsearch>,
+    index_name: String,
+}
+
+impl BookmarkStore {
+    pub fn new(es: Arc<Elasticsearch>, index_name: String) -> Self {
+        Self { es, index_name }
+    }
+
+    /// Save a bookmark, replacing an earlier one of the same message.
+    pub async fn add(&self, bookmark: &Bookmark) -> anyhow::Result<()> {
+        let id = doc_id(bookmark.user_id, bookmark.chat_id, bookmark.message_id);
+        let response = self
+            .es
+            .index(IndexParts::IndexId(&self.index_name, &id))
+            .refresh(Refresh::WaitFor)
+            .body(bookmark)
+            .send()
+            .await?;
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Bookmark write failed (status {status}): {body}");
+        }
+        Ok(())
+    }
+
+    /// Save several bookmarks in one request, replacing earlier ones of the
+    /// same messages. Returns how many were stored.
+    pub async fn add_all(&self, bookmarks: &[Bookmark]) -> anyhow::Result<usize> {
+        if bookmarks.is_empty() {
+            return Ok(0);
+        }
+        let mut body: Vec<JsonBody<Value>> = Vec::with_capacity(bookmarks.len() * 2);
+        for bookmark in bookmarks {
+            let id = doc_id(bookmark.user_id, bookmark.chat_id, bookmark.message_id);
+            body.push(json!({ "index": { "_id": id } }).into());
+            body.push(serde_json::to_value(bookmark)?.into());
+        }
+        let response = self
+            .es
+            .bulk(BulkParts::Index(&self.index_name))
+            .refresh(Refresh::WaitFor)
+            .body(body)
+            .send()
+            .await?;
+        let status = response.status_code();
+        let body: Value = response.json().await?;
+        if !status.is_success() {
+            anyhow::bail!("Bookmark bulk write failed (status {status}): {body}");
+        }
+        let failed = body["items"]
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .filter(|i| i["index"]["error"].is_object())
+                    .count()
+            })
+            .unwrap_or(0);
+        if failed > 0 {
+            tracing::warn!("{failed} of {} bookmarks were rejected", bookmarks.len());
+        }
+        Ok(bookmarks.len() - failed)
+    }
+
+    /// Remove a bookmark, returning whether it existed.
+    pub async fn remove(
+        &self,
+        user_id: i64,
+        chat_id: i64,
+        message_id: i64,
+    ) -> anyhow::Result<bool> {
+        let response = self
+            .es
+            .delete(DeleteParts::IndexId(
+                &self.index_name,
+                &doc_id(user_id, chat_id, message_id),
+            ))
+            .refresh(Refresh::WaitFor)
+            .send()
+            .await?;
+        let status = response.status_code();
+        if status.as_u16() == 404 {
+            return Ok(false);
+        }
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Bookmark delete failed (status {status}): {body}");
+        }
+        Ok(true)
+    }
+
+    /// Bookmarks of `user_id`, newest first, from one chat or all of them.
+    pub async fn list(&self, user_id: i64, chat_id: Option<i64>) -> anyhow::Result<Vec<Bookmark>> {
+        let mut filter = vec![json!({ "term": { "user_id": user_id } })];
+        if let Some(chat_id) = chat_id {
+            filter.push(json!({ "term": { "chat_id": chat_id } }));
+        }
+        let response = self
+            .es
+            .search(SearchParts::Index(&[&self.index_name]))
+            .size(MAX_RESULTS)
+            .body(json!({
+                "query": { "bool": { "filter": filter } },
+                "sort": [{ "saved_at": { "order": "desc" } }]
+            }))
+            .send()
+            .await?;
+
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Bookmark query failed (status {status}): {body}");
+        }
+
+        let body: Value = response.json().await?;
+        Ok(body["hits"]["hits"]
+            .as_array()
+            .map(|hits| {
+                hits.iter()
+                    .filter_map(|h| serde_json::from_value(h["_source"].clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+fn doc_id(user_id: i64, chat_id: i64, message_id: i64) -> String {
+    format!("{user_id}_{chat_id}_{message_id}")
+}
@@ -0,0 +1,63 @@
+use elasticsearch::Elasticsearch;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Trips after repeated Elasticsearch failures so searches fail fast and
+/// indexing spools to disk; a background probe closes it once ES answers again.
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    consecutive_failures: AtomicU32,
+    open: AtomicBool,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            consecutive_failures: AtomicU32::new(0),
+            open: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    pub fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        if self.open.swap(false, Ordering::Relaxed) {
+            tracing::info!("Elasticsearch reachable again, circuit breaker closed");
+        }
+    }
+
+    pub fn record_failure(&self) {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= self.failure_threshold && !self.open.swap(true, Ordering::Relaxed) {
+            tracing::error!(
+                "Elasticsearch failed {failures} times in a row, circuit breaker opened"
+            );
+        }
+    }
+
+    /// Ping ES every `interval` while the breaker is open and close it on success.
+    pub fn spawn_probe(self: &Arc<Self>, es: Arc<Elasticsearch>, interval: Duration) {
+        let this = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(interval);
+            loop {
+                tick.tick().await;
+                if !this.is_open() {
+                    continue;
+                }
+                match es.ping().send().await {
+                    Ok(response) if response.status_code().is_success() => this.record_success(),
+                    Ok(response) => {
+                        tracing::debug!("ES probe returned status {}", response.status_code())
+                    }
+                    Err(e) => tracing::debug!("ES probe failed: {e}"),
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,258 @@
+use dashmap::DashMap;
+use elasticsearch::{Elasticsearch, GetParts, SearchParts, UpdateParts};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Most chats listed by one query.
+const MAX_RESULTS: i64 = 1000;
+/// Title changes kept per chat, oldest dropped first.
+const MAX_TITLE_HISTORY: usize = 50;
+/// Member count snapshots kept per chat, oldest dropped first.
+const MAX_MEMBER_COUNTS: usize = 2000;
+/// Seconds the stored message date range may lag behind before the recorder
+/// writes it again, so busy chats don't cost a write per message.
+const DATE_RANGE_SLACK_SECS: i64 = 3600;
+
+/// Metadata of a chat the bot indexes messages from, kept up to date by the
+/// recorder.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatInfo {
+    pub chat_id: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Public username, without the `@`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// `group`, `supergroup`, `channel` or `private`
+    #[serde(default)]
+    pub chat_type: String,
+    /// Unix epoch seconds of the oldest and newest indexed message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_message_date: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_message_date: Option<i64>,
+    /// Titles the chat had, oldest first, the current one last
+    #[serde(default)]
+    pub title_history: Vec<TitleChange>,
+    /// Member count snapshots, oldest first
+    #[serde(default)]
+    pub member_counts: Vec<MemberCount>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TitleChange {
+    pub title: String,
+    /// Unix epoch seconds of the first message seen under this title
+    pub since: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemberCount {
+    /// Unix epoch seconds
+    pub date: i64,
+    pub count: u64,
+}
+
+/// What was last written for a chat, to skip writes that change nothing.
+struct Written {
+    title: Option<String>,
+    username: Option<String>,
+    first_message_date: i64,
+    last_message_date: i64,
+}
+
+/// Stores one metadata document per chat in a dedicated index.
+pub struct ChatStore {
+    es: Arc<Elasticsearch>,
+    index_name: String,
+    written: DashMap<i64, Written>,
+}
+
+impl ChatStore {
+    pub fn new(es: Arc<Elasticsearch>, index_name: String) -> Self {
+        Self {
+            es,
+            index_name,
+            written: DashMap::new(),
+        }
+    }
+
+    /// Record that a message sent at `date` was indexed from a chat currently
+    /// named `title`. Only writes when the title or username changed, or the
+    /// date range moved noticeably.
+    pub async fn observe(
+        &self,
+        chat_id: i64,
+        title: Option<&str>,
+        username: Option<&str>,
+        chat_type: &str,
+        date: i64,
+    ) -> anyhow::Result<()> {
+        let title = title.map(String::from);
+        let username = username.map(str::to_lowercase);
+        let unchanged = self.written.get(&chat_id).is_some_and(|w| {
+            w.title == title
+                && w.username == username
+                && date >= w.first_message_date
+                && date <= w.last_message_date + DATE_RANGE_SLACK_SECS
+        });
+        if unchanged {
+            return Ok(());
+        }
+
+        let response = self
+            .es
+            .update(UpdateParts::IndexId(&self.index_name, &chat_id.to_string()))
+            .retry_on_conflict(3)
+            .body(json!({
+                "scripted_upsert": true,
+                "upsert": {},
+                "script": {
+                    "source": OBSERVE_SCRIPT,
+                    "params": {
+                        "chat_id": chat_id,
+                        "title": title,
+                        "username": username,
+                        "chat_type": chat_type,
+                        "date": date,
+                        "max_titles": MAX_TITLE_HISTORY
+                    }
+                }
+            }))
+            .send()
+            .await?;
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Chat metadata write failed (status {status}): {body}");
+        }
+
+        let mut written = self.written.entry(chat_id).or_insert(Written {
+            title: None,
+            username: None,
+            first_message_date: date,
+            last_message_date: date,
+        });
+        written.title = title;
+        written.username = username;
+        written.first_message_date = written.first_message_date.min(date);
+        written.last_message_date = written.last_message_date.max(date);
+        Ok(())
+    }
+
+    pub async fn get(&self, chat_id: i64) -> anyhow::Result<Option<ChatInfo>> {
+        let response = self
+            .es
+            .get(GetParts::IndexId(&self.index_name, &chat_id.to_string()))
+            .send()
+            .await?;
+
+        let status = response.status_code();
+        if status.as_u16() == 404 {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Chat metadata read failed (status {status}): {body}");
+        }
+
+        let body: Value = response.json().await?;
+        Ok(Some(serde_json::from_value(body["_source"].clone())?))
+    }
+
+    /// Every known chat, most recently active first.
+    pub async fn all(&self) -> anyhow::Result<Vec<ChatInfo>> {
+        let response = self
+            .es
+            .search(SearchParts::Index(&[&self.index_name]))
+            .size(MAX_RESULTS)
+            .body(json!({
+                "query": { "match_all": {} },
+                "sort": [{ "last_message_date": { "order": "desc", "missing": "_last" } }]
+            }))
+            .send()
+            .await?;
+
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Chat metadata search failed (status {status}): {body}");
+        }
+
+        let body: Value = response.json().await?;
+        Ok(body["hits"]["hits"]
+            .as_array()
+            .map(|hits| {
+                hits.iter()
+                    .filter_map(|h| serde_json::from_value(h["_source"].clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Append a member count snapshot taken at `date` to a known chat.
+    pub async fn record_member_count(
+        &self,
+        chat_id: i64,
+        count: u64,
+        date: i64,
+    ) -> anyhow::Result<()> {
+        let response = self
+            .es
+            .update(UpdateParts::IndexId(&self.index_name, &chat_id.to_string()))
+            .retry_on_conflict(3)
+            .body(json!({
+                "script": {
+                    "source": MEMBER_COUNT_SCRIPT,
+                    "params": {
+                        "snapshot": { "date": date, "count": count },
+                        "max_counts": MAX_MEMBER_COUNTS
+                    }
+                }
+            }))
+            .send()
+            .await?;
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Member count write failed (status {status}): {body}");
+        }
+        Ok(())
+    }
+
+    /// Public username of a chat observed since startup, for links that work
+    /// without joining the chat.
+    pub fn username(&self, chat_id: i64) -> Option<String> {
+        self.written.get(&chat_id).and_then(|w| w.username.clone())
+    }
+}
+
+/// Upsert of the recorder: refresh the chat's names, append a title change
+/// and widen the message date range.
+const OBSERVE_SCRIPT: &str = "
+    def s = ctx._source;
+    s.chat_id = params.chat_id;
+    s.username = params.username;
+    s.chat_type = params.chat_type;
+    if (s.title_history == null) { s.title_history = []; }
+    if (s.member_counts == null) { s.member_counts = []; }
+    if (params.title != null && s.title != params.title) {
+        s.title_history.add(['title': params.title, 'since': params.date]);
+        if (s.title_history.size() > params.max_titles) { s.title_history.remove(0); }
+    }
+    s.title = params.title;
+    if (s.first_message_date == null || params.date < s.first_message_date) {
+        s.first_message_date = params.date;
+    }
+    if (s.last_message_date == null || params.date > s.last_message_date) {
+        s.last_message_date = params.date;
+    }
+";
+
+const MEMBER_COUNT_SCRIPT: &str = "
+    def s = ctx._source;
+    if (s.member_counts == null) { s.member_counts = []; }
+    s.member_counts.add(params.snapshot);
+    if (s.member_counts.size() > params.max_counts) { s.member_counts.remove(0); }
+";
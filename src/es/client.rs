@@ -1,24 +1,104 @@
 use elasticsearch::http::transport::{SingleNodeConnectionPool, TransportBuilder};
-use elasticsearch::indices::{IndicesCreateParts, IndicesExistsParts};
+use elasticsearch::indices::{IndicesCreateParts, IndicesExistsParts, IndicesPutMappingParts};
+use elasticsearch::ingest::{IngestGetPipelineParts, IngestPutPipelineParts};
 use elasticsearch::Elasticsearch;
 use std::sync::Arc;
 use url::Url;
 
 use crate::config::AppConfig;
-use crate::es::mapping::index_settings_and_mappings;
+use crate::es::mapping::{
+    alerts_settings_and_mappings, audit_settings_and_mappings, bookmarks_settings_and_mappings,
+    chat_settings_and_mappings, chats_settings_and_mappings, default_ingest_pipeline,
+    digest_feedback_settings_and_mappings, index_settings_and_mappings,
+    users_settings_and_mappings,
+};
 
 pub async fn create_client(config: &AppConfig) -> anyhow::Result<Arc<Elasticsearch>> {
-    let url = Url::parse(&config.elasticsearch.url)?;
-    let pool = SingleNodeConnectionPool::new(url);
-    let transport = TransportBuilder::new(pool).disable_proxy().build()?;
-    let client = Elasticsearch::new(transport);
+    let client = connect(&config.elasticsearch.url)?;
 
-    ensure_index(&client, &config.elasticsearch.index_name).await?;
+    ensure_index(
+        &client,
+        &config.elasticsearch.index_name,
+        index_settings_and_mappings(),
+    )
+    .await?;
+    if !config.indexer.pipeline.is_empty() {
+        ensure_pipeline(&client, &config.indexer.pipeline).await?;
+    }
+    if config.audit.enabled {
+        ensure_index(
+            &client,
+            &config.audit.index_name,
+            audit_settings_and_mappings(),
+        )
+        .await?;
+    }
+    ensure_index(
+        &client,
+        &config.settings.index_name,
+        chat_settings_and_mappings(),
+    )
+    .await?;
+    ensure_index(
+        &client,
+        &config.chats.index_name,
+        chats_settings_and_mappings(),
+    )
+    .await?;
+    ensure_index(
+        &client,
+        &config.users.index_name,
+        users_settings_and_mappings(),
+    )
+    .await?;
+    ensure_index(
+        &client,
+        &config.bookmarks.index_name,
+        bookmarks_settings_and_mappings(),
+    )
+    .await?;
+    if config.digest.enabled {
+        ensure_index(
+            &client,
+            &config.digest.feedback_index,
+            digest_feedback_settings_and_mappings(),
+        )
+        .await?;
+    }
+    if config.alerts.enabled {
+        ensure_index(
+            &client,
+            &config.alerts.index_name,
+            alerts_settings_and_mappings(),
+        )
+        .await?;
+    }
 
     Ok(Arc::new(client))
 }
 
-async fn ensure_index(client: &Elasticsearch, index_name: &str) -> anyhow::Result<()> {
+/// The client searches and reports read through: a separate one when
+/// `elasticsearch.read_url` is set, `None` to read through the write client.
+/// Indices are created through the write client only.
+pub fn create_read_client(config: &AppConfig) -> anyhow::Result<Option<Arc<Elasticsearch>>> {
+    let url = &config.elasticsearch.read_url;
+    if url.is_empty() || *url == config.elasticsearch.url {
+        return Ok(None);
+    }
+    Ok(Some(Arc::new(connect(url)?)))
+}
+
+fn connect(url: &str) -> anyhow::Result<Elasticsearch> {
+    let pool = SingleNodeConnectionPool::new(Url::parse(url)?);
+    let transport = TransportBuilder::new(pool).disable_proxy().build()?;
+    Ok(Elasticsearch::new(transport))
+}
+
+async fn ensure_index(
+    client: &Elasticsearch,
+    index_name: &str,
+    body: serde_json::Value,
+) -> anyhow::Result<()> {
     let exists = client
         .indices()
         .exists(IndicesExistsParts::Index(&[index_name]))
@@ -26,7 +106,6 @@ async fn ensure_index(client: &Elasticsearch, index_name: &str) -> anyhow::Resul
         .await?;
 
     if exists.status_code().as_u16() == 404 {
-        let body = index_settings_and_mappings();
         let response = client
             .indices()
             .create(IndicesCreateParts::Index(index_name))
@@ -39,8 +118,60 @@ async fn ensure_index(client: &Elasticsearch, index_name: &str) -> anyhow::Resul
             anyhow::bail!("Failed to create index: {error_body}");
         }
 
-        tracing::info!("Created index '{index_name}' with IK analyzer mapping");
+        tracing::info!("Created index '{index_name}'");
+    } else {
+        sync_mappings(client, index_name, &body["mappings"]).await;
+    }
+
+    Ok(())
+}
+
+/// Create the ingest pipeline with the default definition unless it already
+/// exists, so operator changes made in Elasticsearch survive restarts.
+async fn ensure_pipeline(client: &Elasticsearch, name: &str) -> anyhow::Result<()> {
+    let existing = client
+        .ingest()
+        .get_pipeline(IngestGetPipelineParts::Id(name))
+        .send()
+        .await?;
+    if existing.status_code().is_success() {
+        return Ok(());
+    }
+
+    let response = client
+        .ingest()
+        .put_pipeline(IngestPutPipelineParts::Id(name))
+        .body(default_ingest_pipeline())
+        .send()
+        .await?;
+    if !response.status_code().is_success() {
+        let error_body: serde_json::Value = response.json().await?;
+        anyhow::bail!("Failed to create ingest pipeline: {error_body}");
     }
 
+    tracing::info!("Created ingest pipeline '{name}'");
     Ok(())
 }
+
+/// Add fields introduced since the index was created. Changes that can't be
+/// applied in place (e.g. fields needing analyzers the index lacks) only take
+/// effect after a reindex.
+async fn sync_mappings(client: &Elasticsearch, index_name: &str, mappings: &serde_json::Value) {
+    let result = client
+        .indices()
+        .put_mapping(IndicesPutMappingParts::Index(&[index_name]))
+        .body(mappings)
+        .send()
+        .await;
+
+    match result {
+        Ok(response) if response.status_code().is_success() => {}
+        Ok(response) => {
+            let error_body: serde_json::Value = response.json().await.unwrap_or_default();
+            tracing::warn!(
+                "Could not update mapping of '{index_name}' in place, reindex to pick up new fields: {error_body}"
+            );
+        }
+        Err(e) => tracing::warn!("Mapping update request for '{index_name}' failed: {e}"),
+    }
+}
@@ -0,0 +1,155 @@
+use elasticsearch::params::Refresh;
+use elasticsearch::{Elasticsearch, IndexParts, SearchParts};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Chats broken down in the feedback report.
+const REPORT_CHATS: usize = 20;
+
+/// A member's rating of one posted digest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestVote {
+    pub chat_id: i64,
+    /// The digest message that was rated
+    pub message_id: i64,
+    pub user_id: i64,
+    pub helpful: bool,
+    /// `daily` or `weekly`
+    pub period: String,
+    /// Unix epoch seconds of the (latest) vote
+    pub date: i64,
+}
+
+/// Helpful and unhelpful votes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub up: u64,
+    pub down: u64,
+}
+
+/// Votes since some date, overall and for the chats with the most votes.
+#[derive(Debug, Default)]
+pub struct FeedbackReport {
+    pub total: Tally,
+    pub by_period: Vec<(String, Tally)>,
+    /// Most voted chats first
+    pub by_chat: Vec<(i64, Tally)>,
+}
+
+/// Stores digest ratings in a dedicated index, one document per member and
+/// digest, so voting again replaces the earlier vote.
+pub struct DigestFeedbackStore {
+    es: Arc<Elasticsearch>,
+    index_name: String,
+}
+
+impl DigestFeedbackStore {
+    pub fn new(es: Arc<Elasticsearch>, index_name: String) -> Self {
+        Self { es, index_name }
+    }
+
+    pub async fn vote(&self, vote: &DigestVote) -> anyhow::Result<()> {
+        let id = format!("{}_{}_{}", vote.chat_id, vote.message_id, vote.user_id);
+        let response = self
+            .es
+            .index(IndexParts::IndexId(&self.index_name, &id))
+            .refresh(Refresh::WaitFor)
+            .body(vote)
+            .send()
+            .await?;
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Digest vote write failed (status {status}): {body}");
+        }
+        Ok(())
+    }
+
+    /// Votes on one digest message.
+    pub async fn tally(&self, chat_id: i64, message_id: i64) -> anyhow::Result<Tally> {
+        let body = self
+            .aggregate(json!({
+                "query": {
+                    "bool": {
+                        "filter": [
+                            { "term": { "chat_id": chat_id } },
+                            { "term": { "message_id": message_id } }
+                        ]
+                    }
+                },
+                "aggs": { "votes": { "terms": { "field": "helpful" } } }
+            }))
+            .await?;
+        Ok(tally(&body["aggregations"]["votes"]))
+    }
+
+    /// Votes cast since `since` (Unix epoch seconds).
+    pub async fn report(&self, since: i64) -> anyhow::Result<FeedbackReport> {
+        let body = self
+            .aggregate(json!({
+                "query": { "range": { "date": { "gte": since } } },
+                "aggs": {
+                    "votes": { "terms": { "field": "helpful" } },
+                    "periods": {
+                        "terms": { "field": "period" },
+                        "aggs": { "votes": { "terms": { "field": "helpful" } } }
+                    },
+                    "chats": {
+                        "terms": { "field": "chat_id", "size": REPORT_CHATS },
+                        "aggs": { "votes": { "terms": { "field": "helpful" } } }
+                    }
+                }
+            }))
+            .await?;
+
+        let aggs = &body["aggregations"];
+        let buckets = |name: &str| {
+            aggs[name]["buckets"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default()
+        };
+        Ok(FeedbackReport {
+            total: tally(&aggs["votes"]),
+            by_period: buckets("periods")
+                .iter()
+                .filter_map(|b| Some((b["key"].as_str()?.to_string(), tally(&b["votes"]))))
+                .collect(),
+            by_chat: buckets("chats")
+                .iter()
+                .filter_map(|b| Some((b["key"].as_i64()?, tally(&b["votes"]))))
+                .collect(),
+        })
+    }
+
+    async fn aggregate(&self, query: Value) -> anyhow::Result<Value> {
+        let response = self
+            .es
+            .search(SearchParts::Index(&[&self.index_name]))
+            .size(0)
+            .body(query)
+            .send()
+            .await?;
+        let status = response.status_code();
+        let body: Value = response.json().await?;
+        if !status.is_success() {
+            anyhow::bail!("Digest feedback query failed (status {status}): {body}");
+        }
+        Ok(body)
+    }
+}
+
+/// Read a terms aggregation over the boolean `helpful` field.
+fn tally(agg: &Value) -> Tally {
+    let mut tally = Tally::default();
+    for bucket in agg["buckets"].as_array().into_iter().flatten() {
+        let count = bucket["doc_count"].as_u64().unwrap_or(0);
+        match bucket["key_as_string"].as_str() {
+            Some("true") => tally.up += count,
+            Some("false") => tally.down += count,
+            _ => {}
+        }
+    }
+    tally
+}
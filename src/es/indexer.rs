@@ -1,43 +1,229 @@
 use elasticsearch::http::request::JsonBody;
 use elasticsearch::{BulkParts, Elasticsearch};
-use serde_json::json;
-use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
-use tokio::time::{interval, Duration};
+use tokio::time::{interval, Duration, Instant};
 
-use crate::models::message::ChatMessage;
+use crate::config::{IdStrategy, IndexerConfig};
+use crate::es::breaker::CircuitBreaker;
+use crate::es::spool::Spool;
+use crate::events::{DocumentEvent, EventPublisher};
+use crate::models::message::{content_hash, ChatMessage};
+
+/// Spooled operations sent per bulk request when replaying.
+const REPLAY_CHUNK: usize = 500;
+/// Window of the bulk error rate reported by `/queue`.
+const ERROR_WINDOW: Duration = Duration::from_secs(5 * 60);
 
 pub struct BatchIndexer {
-    sender: mpsc::Sender<ChatMessage>,
+    sender: mpsc::Sender<IndexOp>,
+    stats: Arc<IndexerStats>,
+    id_strategy: IdStrategy,
+}
+
+/// Internals of the flush loop, updated as it runs and read by `/queue`.
+#[derive(Default)]
+pub struct IndexerStats {
+    /// Operations taken off the channel but not yet flushed
+    buffered: AtomicUsize,
+    /// Duration of the latest bulk request, in microseconds (0 = none yet)
+    last_flush_us: AtomicU64,
+    /// Operations waiting in the spool for Elasticsearch to recover
+    spooled: AtomicU64,
+    /// Finish time and outcome of the bulk requests within `ERROR_WINDOW`
+    recent_bulks: Mutex<VecDeque<(Instant, bool)>>,
+}
+
+/// Point-in-time view of the indexer for `/queue`.
+#[derive(Debug, Clone)]
+pub struct QueueSnapshot {
+    /// Operations waiting in the channel, and its capacity
+    pub channel_depth: usize,
+    pub channel_capacity: usize,
+    pub buffered: usize,
+    pub last_flush: Option<Duration>,
+    /// Bulk requests sent within `ERROR_WINDOW`, and how many of them failed
+    pub recent_bulks: usize,
+    pub recent_errors: usize,
+    /// Dead-letter size: operations spooled during an outage
+    pub spooled: u64,
+}
+
+impl IndexerStats {
+    fn record_bulk(&self, elapsed: Duration, ok: bool) {
+        self.last_flush_us
+            .store(elapsed.as_micros().max(1) as u64, Ordering::Relaxed);
+        let now = Instant::now();
+        let mut recent = self.recent_bulks.lock().unwrap();
+        recent.push_back((now, ok));
+        prune(&mut recent, now);
+    }
+
+    fn set_buffered(&self, len: usize) {
+        self.buffered.store(len, Ordering::Relaxed);
+    }
+}
+
+/// Drop bulk outcomes older than `ERROR_WINDOW`.
+fn prune(recent: &mut VecDeque<(Instant, bool)>, now: Instant) {
+    while recent
+        .front()
+        .is_some_and(|(at, _)| now.duration_since(*at) > ERROR_WINDOW)
+    {
+        recent.pop_front();
+    }
+}
+
+/// One action of a bulk request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum IndexOp {
+    /// Index (or fully replace) a message
+    Index(Box<ChatMessage>),
+    /// Merge `doc` into the existing document `doc_id`
+    Update {
+        doc_id: String,
+        doc: Value,
+        /// The message updated, for event consumers; missing in older spools
+        #[serde(default)]
+        chat_id: i64,
+        #[serde(default)]
+        message_id: i64,
+    },
+}
+
+impl IndexOp {
+    fn event(&self, id_strategy: IdStrategy) -> DocumentEvent {
+        match self {
+            Self::Index(msg) => DocumentEvent::Indexed {
+                doc_id: msg.doc_id(id_strategy),
+                doc: msg.clone(),
+            },
+            Self::Update {
+                doc_id,
+                doc,
+                chat_id,
+                message_id,
+            } => DocumentEvent::Updated {
+                doc_id: doc_id.clone(),
+                chat_id: *chat_id,
+                message_id: *message_id,
+                doc: doc.clone(),
+            },
+        }
+    }
 }
 
 impl BatchIndexer {
     pub fn new(
         es_client: Arc<Elasticsearch>,
         index_name: String,
-        batch_size: usize,
-        flush_interval_ms: u64,
+        config: &IndexerConfig,
+        breaker: Arc<CircuitBreaker>,
+        events: Option<Arc<EventPublisher>>,
     ) -> Self {
-        let (tx, rx) = mpsc::channel::<ChatMessage>(batch_size * 4);
-        tokio::spawn(flush_loop(rx, es_client, index_name, batch_size, flush_interval_ms));
-        Self { sender: tx }
+        let (tx, rx) = mpsc::channel::<IndexOp>(config.batch_size * 4);
+        let stats = Arc::new(IndexerStats::default());
+        let sink = Sink {
+            es: es_client,
+            index_name,
+            breaker,
+            spool: Spool::new(PathBuf::from(&config.spool_path)),
+            pipeline: Some(config.pipeline.clone()).filter(|p| !p.is_empty()),
+            id_strategy: config.id_strategy,
+            events,
+            stats: stats.clone(),
+        };
+        tokio::spawn(flush_loop(
+            rx,
+            sink,
+            config.batch_size,
+            config.flush_interval_ms,
+        ));
+        Self {
+            sender: tx,
+            stats,
+            id_strategy: config.id_strategy,
+        }
+    }
+
+    pub fn snapshot(&self) -> QueueSnapshot {
+        let (recent_bulks, recent_errors) = {
+            let mut recent = self.stats.recent_bulks.lock().unwrap();
+            prune(&mut recent, Instant::now());
+            (recent.len(), recent.iter().filter(|(_, ok)| !ok).count())
+        };
+        let last_flush_us = self.stats.last_flush_us.load(Ordering::Relaxed);
+        QueueSnapshot {
+            channel_depth: self.sender.max_capacity() - self.sender.capacity(),
+            channel_capacity: self.sender.max_capacity(),
+            buffered: self.stats.buffered.load(Ordering::Relaxed),
+            last_flush: (last_flush_us > 0).then(|| Duration::from_micros(last_flush_us)),
+            recent_bulks,
+            recent_errors,
+            spooled: self.stats.spooled.load(Ordering::Relaxed),
+        }
     }
 
-    pub async fn index(&self, msg: ChatMessage) {
-        if let Err(e) = self.sender.send(msg).await {
+    /// Queue a message, hashing its text for duplicate detection unless the
+    /// sender already did.
+    pub async fn index(&self, mut msg: ChatMessage) {
+        if msg.content_hash.is_none() {
+            msg.content_hash = content_hash(&msg.text);
+        }
+        if let Err(e) = self.sender.send(IndexOp::Index(Box::new(msg))).await {
             tracing::warn!("Failed to queue message for indexing: {e}");
         }
     }
+
+    /// Queue a partial update of an indexed message, e.g. its reaction count,
+    /// sent in the same bulk requests as new messages.
+    #[allow(dead_code)]
+    pub async fn update(&self, chat_id: i64, message_id: i64, doc: Value) {
+        let doc_id = self.id_strategy.doc_id(chat_id, message_id, None);
+        let op = IndexOp::Update {
+            doc_id,
+            doc,
+            chat_id,
+            message_id,
+        };
+        if let Err(e) = self.sender.send(op).await {
+            tracing::warn!("Failed to queue partial update: {e}");
+        }
+    }
 }
 
-async fn flush_loop(
-    mut rx: mpsc::Receiver<ChatMessage>,
+/// Where flushed batches go: Elasticsearch while it's healthy, the on-disk
+/// spool while the circuit breaker is open.
+struct Sink {
     es: Arc<Elasticsearch>,
     index_name: String,
+    breaker: Arc<CircuitBreaker>,
+    spool: Spool,
+    /// Ingest pipeline indexed messages run through
+    pipeline: Option<String>,
+    id_strategy: IdStrategy,
+    /// Told about every operation Elasticsearch accepted
+    events: Option<Arc<EventPublisher>>,
+    stats: Arc<IndexerStats>,
+}
+
+async fn flush_loop(
+    mut rx: mpsc::Receiver<IndexOp>,
+    sink: Sink,
     batch_size: usize,
     flush_interval_ms: u64,
 ) {
-    let mut buffer: Vec<ChatMessage> = Vec::with_capacity(batch_size);
+    let mut buffer: Vec<IndexOp> = Vec::with_capacity(batch_size);
+    match sink.spool.len().await {
+        Ok(len) => sink.stats.spooled.store(len as u64, Ordering::Relaxed),
+        Err(e) => tracing::warn!("Failed to read index spool: {e}"),
+    }
     let mut tick = interval(Duration::from_millis(flush_interval_ms));
     tick.tick().await; // consume first immediate tick
 
@@ -48,12 +234,13 @@ async fn flush_loop(
                     Some(m) => {
                         buffer.push(m);
                         if buffer.len() >= batch_size {
-                            flush_buffer(&es, &index_name, &mut buffer).await;
+                            sink.flush(&mut buffer).await;
                         }
+                        sink.stats.set_buffered(buffer.len());
                     }
                     None => {
                         if !buffer.is_empty() {
-                            flush_buffer(&es, &index_name, &mut buffer).await;
+                            sink.flush(&mut buffer).await;
                         }
                         return;
                     }
@@ -61,50 +248,195 @@ async fn flush_loop(
             }
             _ = tick.tick() => {
                 if !buffer.is_empty() {
-                    flush_buffer(&es, &index_name, &mut buffer).await;
+                    sink.flush(&mut buffer).await;
+                    sink.stats.set_buffered(0);
+                } else if !sink.breaker.is_open() {
+                    sink.replay().await;
                 }
             }
         }
     }
 }
 
-async fn flush_buffer(es: &Elasticsearch, index_name: &str, buffer: &mut Vec<ChatMessage>) {
-    let count = buffer.len();
-    let mut body: Vec<JsonBody<serde_json::Value>> = Vec::with_capacity(count * 2);
+impl Sink {
+    async fn flush(&self, buffer: &mut Vec<IndexOp>) {
+        let ops = std::mem::take(buffer);
+        if self.breaker.is_open() {
+            self.spool(&ops).await;
+            return;
+        }
 
-    for msg in buffer.drain(..) {
-        let doc_id = format!("{}_{}", msg.chat_id, msg.message_id);
-        body.push(json!({"index": {"_id": doc_id}}).into());
-        match serde_json::to_value(&msg) {
-            Ok(val) => body.push(val.into()),
+        match self.bulk(&ops).await {
+            Ok(()) => {
+                self.breaker.record_success();
+                self.replay().await;
+            }
             Err(e) => {
-                tracing::error!("Failed to serialize message: {e}");
-                continue;
+                tracing::error!("Bulk index request failed: {e}");
+                self.breaker.record_failure();
+                self.spool(&ops).await;
             }
         }
     }
 
-    if body.is_empty() {
-        return;
-    }
-
-    match es.bulk(BulkParts::Index(index_name)).body(body).send().await {
-        Ok(response) if response.status_code().is_success() => {
-            match response.json::<serde_json::Value>().await {
-                Ok(body) if body["errors"].as_bool().unwrap_or(false) => {
-                    let errs = body["items"]
-                        .as_array()
-                        .map(|items| {
-                            items.iter().filter(|i| i["index"]["error"].is_object()).count()
-                        })
-                        .unwrap_or(0);
-                    tracing::error!("Bulk index had {errs} errors out of {count}");
+    async fn bulk(&self, ops: &[IndexOp]) -> anyhow::Result<()> {
+        let started = Instant::now();
+        let result = bulk_index(
+            &self.es,
+            &self.index_name,
+            self.pipeline.as_deref(),
+            self.id_strategy,
+            ops,
+        )
+        .await;
+        self.stats.record_bulk(started.elapsed(), result.is_ok());
+        let accepted = result?;
+        if let Some(events) = &self.events {
+            for op in accepted {
+                events.publish(&self.index_name, op.event(self.id_strategy));
+            }
+        }
+        Ok(())
+    }
+
+    async fn spool(&self, ops: &[IndexOp]) {
+        let count = ops.len();
+        match self.spool.append(ops).await {
+            Ok(()) => {
+                self.stats
+                    .spooled
+                    .fetch_add(count as u64, Ordering::Relaxed);
+                tracing::warn!("Spooled {count} operations until Elasticsearch recovers");
+            }
+            Err(e) => tracing::error!("Failed to spool {count} operations, dropping them: {e}"),
+        }
+    }
+
+    /// Index everything spooled during an outage.
+    async fn replay(&self) {
+        if self.spool.is_empty().await {
+            return;
+        }
+        let ops = match self.spool.drain().await {
+            Ok(ops) => {
+                self.stats.spooled.store(0, Ordering::Relaxed);
+                ops
+            }
+            Err(e) => {
+                tracing::error!("Failed to read index spool: {e}");
+                return;
+            }
+        };
+
+        tracing::info!("Replaying {} spooled operations", ops.len());
+        for (i, chunk) in ops.chunks(REPLAY_CHUNK).enumerate() {
+            if let Err(e) = self.bulk(chunk).await {
+                tracing::error!("Replaying spooled operations failed: {e}");
+                self.breaker.record_failure();
+                self.spool(&ops[i * REPLAY_CHUNK..]).await;
+                return;
+            }
+        }
+    }
+}
+
+/// Send `ops` as one bulk request, returning the operations Elasticsearch
+/// accepted. Errors mean ES is unavailable and the batch should be retried
+/// later; rejected documents are only logged, with inserts and partial
+/// updates counted separately. `pipeline` only applies to inserts;
+/// Elasticsearch doesn't run ingest pipelines on partial updates.
+async fn bulk_index<'a>(
+    es: &Elasticsearch,
+    index_name: &str,
+    pipeline: Option<&str>,
+    id_strategy: IdStrategy,
+    ops: &'a [IndexOp],
+) -> anyhow::Result<Vec<&'a IndexOp>> {
+    let mut body: Vec<JsonBody<Value>> = Vec::with_capacity(ops.len() * 2);
+    // Operations in the order of the response's items
+    let mut sent = Vec::with_capacity(ops.len());
+    let (mut inserts, mut updates) = (0, 0);
+
+    for op in ops {
+        match op {
+            IndexOp::Index(msg) => {
+                let doc_id = msg.doc_id(id_strategy);
+                match serde_json::to_value(msg) {
+                    Ok(val) => {
+                        body.push(json!({"index": {"_id": doc_id}}).into());
+                        body.push(val.into());
+                        sent.push(op);
+                        inserts += 1;
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to serialize message: {e}");
+                        continue;
+                    }
                 }
-                Ok(_) => tracing::debug!("Indexed {count} messages"),
-                Err(e) => tracing::error!("Failed to read bulk response: {e}"),
             }
+            IndexOp::Update { doc_id, doc, .. } => {
+                body.push(json!({"update": {"_id": doc_id}}).into());
+                body.push(json!({ "doc": doc }).into());
+                sent.push(op);
+                updates += 1;
+            }
+        }
+    }
+
+    if body.is_empty() {
+        return Ok(sent);
+    }
+
+    let mut request = es.bulk(BulkParts::Index(index_name));
+    if let Some(pipeline) = pipeline {
+        request = request.pipeline(pipeline);
+    }
+    let response = request.body(body).send().await?;
+    let status = response.status_code();
+    if status.is_server_error() || status.as_u16() == 429 {
+        anyhow::bail!("Bulk index returned status {status}");
+    }
+    if !status.is_success() {
+        tracing::error!("Bulk index returned status {status}");
+        return Ok(Vec::new());
+    }
+
+    match response.json::<Value>().await {
+        Ok(body) if body["errors"].as_bool().unwrap_or(false) => {
+            let failed = |action: &str| {
+                body["items"]
+                    .as_array()
+                    .map(|items| {
+                        items
+                            .iter()
+                            .filter(|i| i[action]["error"].is_object())
+                            .count()
+                    })
+                    .unwrap_or(0)
+            };
+            let (index_errs, update_errs) = (failed("index"), failed("update"));
+            if index_errs > 0 {
+                tracing::error!("Bulk index had {index_errs} errors out of {inserts}");
+            }
+            // Usually the target message was never indexed
+            if update_errs > 0 {
+                tracing::warn!("Bulk index had {update_errs} failed updates out of {updates}");
+            }
+            let items = body["items"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+            let rejected = |item: &Value| {
+                ["index", "update"]
+                    .iter()
+                    .any(|a| item[a]["error"].is_object())
+            };
+            return Ok(sent
+                .into_iter()
+                .zip(items)
+                .filter(|(_, item)| !rejected(item))
+                .map(|(op, _)| op)
+                .collect());
         }
-        Ok(response) => tracing::error!("Bulk index returned status {}", response.status_code()),
-        Err(e) => tracing::error!("Bulk index request failed: {e}"),
+        Ok(_) => tracing::debug!("Indexed {inserts} messages and applied {updates} updates"),
+        Err(e) => tracing::error!("Failed to read bulk response: {e}"),
     }
+    Ok(sent)
 }
@@ -0,0 +1,350 @@
+//! End-to-end tests of indexing and search against a real Elasticsearch.
+//!
+//! Run with `cargo test --features es-integration`. Each test starts a
+//! throwaway node from `docker/elasticsearch`, so the IK analyzers match
+//! production, and removes it afterwards. Set `ES_TEST_URL` to run against an
+//! already running node instead; every test uses its own indexes.
+
+use elasticsearch::indices::IndicesRefreshParts;
+use elasticsearch::{CountParts, Elasticsearch};
+use serde_json::json;
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::AppConfig;
+use crate::es::breaker::CircuitBreaker;
+use crate::es::client::create_client;
+use crate::es::indexer::BatchIndexer;
+use crate::es::search::{SearchClient, SearchParams, SearchSort, HIGHLIGHT_START};
+use crate::es::users::UserStore;
+use crate::models::message::{ChatMessage, MessageType};
+
+const IMAGE: &str = "search-bot-es-test";
+/// How long a fresh node may take to report yellow health.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(180);
+/// Flush interval of the test indexer.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+/// How long indexed messages may take to become searchable.
+const INDEX_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A node for one test, removed again when dropped.
+struct EsContainer {
+    /// `None` when reusing the node from `ES_TEST_URL`
+    id: Option<String>,
+    url: String,
+}
+
+impl EsContainer {
+    async fn start() -> Self {
+        if let Ok(url) = std::env::var("ES_TEST_URL") {
+            return Self { id: None, url };
+        }
+
+        let context = concat!(env!("CARGO_MANIFEST_DIR"), "/docker/elasticsearch");
+        docker(&["build", "-q", "-t", IMAGE, context]);
+        let id = docker(&[
+            "run",
+            "-d",
+            "--rm",
+            "--user",
+            "0",
+            "-p",
+            "127.0.0.1::9200",
+            "-e",
+            "discovery.type=single-node",
+            "-e",
+            "xpack.security.enabled=false",
+            "-e",
+            "ES_JAVA_OPTS=-Xms512m -Xmx512m",
+            IMAGE,
+        ]);
+        // Constructed before waiting, so a node that never comes up is removed
+        let mut container = Self {
+            id: Some(id.clone()),
+            url: String::new(),
+        };
+        let mapped = docker(&["port", &id, "9200/tcp"]);
+        let port = mapped
+            .lines()
+            .next()
+            .and_then(|line| line.rsplit(':').next())
+            .expect("no mapped port");
+        container.url = format!("http://127.0.0.1:{port}");
+        container.wait_healthy().await;
+        container
+    }
+
+    async fn wait_healthy(&self) {
+        let health = format!(
+            "{}/_cluster/health?wait_for_status=yellow&timeout=5s",
+            self.url
+        );
+        let client = reqwest::Client::new();
+        let deadline = tokio::time::Instant::now() + STARTUP_TIMEOUT;
+        while tokio::time::Instant::now() < deadline {
+            if let Ok(response) = client.get(&health).send().await
+                && response.status().is_success()
+            {
+                return;
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        panic!("Elasticsearch at {} did not become healthy", self.url);
+    }
+}
+
+impl Drop for EsContainer {
+    fn drop(&mut self) {
+        if let Some(id) = &self.id {
+            let _ = Command::new("docker").args(["rm", "-f", id]).output();
+        }
+    }
+}
+
+/// Run a docker command, returning its trimmed stdout.
+fn docker(args: &[&str]) -> String {
+    let output = Command::new("docker")
+        .args(args)
+        .output()
+        .expect("failed to run docker");
+    assert!(
+        output.status.success(),
+        "docker {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Clients wired up like `main`, on indexes unique to one test.
+struct Harness {
+    es: Arc<Elasticsearch>,
+    index_name: String,
+    indexer: BatchIndexer,
+    search: SearchClient,
+    _node: EsContainer,
+}
+
+impl Harness {
+    async fn new(name: &str) -> Self {
+        let node = EsContainer::start().await;
+        let suffix = chrono::Utc::now().timestamp_micros();
+        let index_name = format!("test_{name}_{suffix}");
+
+        let mut config = AppConfig::defaults();
+        config.elasticsearch.url = node.url.clone();
+        config.elasticsearch.index_name = index_name.clone();
+        config.settings.index_name = format!("{index_name}_settings");
+        config.chats.index_name = format!("{index_name}_chats");
+        config.bookmarks.index_name = format!("{index_name}_bookmarks");
+        config.audit.index_name = format!("{index_name}_audit");
+        config.alerts.index_name = format!("{index_name}_alerts");
+        config.indexer.batch_size = 10;
+        config.indexer.flush_interval_ms = FLUSH_INTERVAL.as_millis() as u64;
+        config.indexer.spool_path = std::env::temp_dir()
+            .join(format!("{index_name}.jsonl"))
+            .to_string_lossy()
+            .into_owned();
+        let es = create_client(&config)
+            .await
+            .expect("failed to create index");
+
+        let breaker = Arc::new(CircuitBreaker::new(config.breaker.failure_threshold));
+        let indexer = BatchIndexer::new(
+            es.clone(),
+            index_name.clone(),
+            &config.indexer,
+            breaker.clone(),
+            None,
+        );
+        let search = SearchClient::new(
+            es.clone(),
+            index_name.clone(),
+            &config.search,
+            &config.indexer,
+            breaker,
+            Arc::new(UserStore::new(es.clone(), config.users.index_name.clone())),
+        );
+        Self {
+            es,
+            index_name,
+            indexer,
+            search,
+            _node: node,
+        }
+    }
+
+    async fn refresh(&self) {
+        self.es
+            .indices()
+            .refresh(IndicesRefreshParts::Index(&[&self.index_name]))
+            .send()
+            .await
+            .expect("refresh failed");
+    }
+
+    async fn count(&self) -> u64 {
+        let response = self
+            .es
+            .count(CountParts::Index(&[&self.index_name]))
+            .send()
+            .await
+            .expect("count failed");
+        let body: serde_json::Value = response.json().await.expect("bad count response");
+        body["count"].as_u64().unwrap_or(0)
+    }
+
+    /// Wait until the index holds `expected` searchable documents.
+    async fn wait_for_docs(&self, expected: u64) {
+        let deadline = tokio::time::Instant::now() + INDEX_TIMEOUT;
+        loop {
+            self.refresh().await;
+            let count = self.count().await;
+            if count == expected {
+                return;
+            }
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "index holds {count} documents, expected {expected}"
+            );
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+
+    /// Wait for queued partial updates, which don't change the document
+    /// count, to be flushed and become searchable.
+    async fn settle(&self) {
+        tokio::time::sleep(FLUSH_INTERVAL * 5).await;
+        self.refresh().await;
+    }
+
+    async fn search(&self, params: SearchParams) -> Vec<i64> {
+        self.search
+            .search(&SearchParams {
+                page_size: 10,
+                ..params
+            })
+            .await
+            .expect("search failed")
+            .messages
+            .into_iter()
+            .map(|hit| hit.message.message_id)
+            .collect()
+    }
+}
+
+fn message(chat_id: i64, message_id: i64, user_id: i64, text: &str, date: i64) -> ChatMessage {
+    ChatMessage {
+        chat_id,
+        message_id,
+        user_id: Some(user_id),
+        text: text.to_string(),
+        date,
+        message_type: MessageType::Text,
+        ..Default::default()
+    }
+}
+
+const DAY: i64 = 86400;
+const START: i64 = 1_700_000_000;
+
+#[tokio::test]
+async fn indexed_messages_are_found_by_keyword() {
+    let h = Harness::new("keyword").await;
+    h.indexer
+        .index(message(1, 1, 10, "今天天气很好，适合出门", START))
+        .await;
+    h.indexer
+        .index(message(1, 2, 11, "明天去爬山吧", START))
+        .await;
+    h.indexer
+        .index(message(2, 3, 10, "天气预报说会下雨", START))
+        .await;
+    h.wait_for_docs(3).await;
+
+    let result = h
+        .search
+        .search(&SearchParams {
+            chat_id: 1,
+            keyword: Some("天气".into()),
+            page_size: 10,
+            ..Default::default()
+        })
+        .await
+        .expect("search failed");
+    assert_eq!(result.total, 1, "other chats must not leak into results");
+    assert_eq!(result.messages[0].message.message_id, 1);
+    assert!(result.messages[0].snippet.contains(HIGHLIGHT_START));
+}
+
+#[tokio::test]
+async fn filters_narrow_results() {
+    let h = Harness::new("filters").await;
+    h.indexer.index(message(1, 1, 10, "周末聚餐", START)).await;
+    h.indexer
+        .index(message(1, 2, 11, "周末聚餐改期", START + DAY))
+        .await;
+    let mut photo = message(1, 3, 10, "周末聚餐照片", START + 2 * DAY);
+    photo.message_type = MessageType::Photo;
+    h.indexer.index(photo).await;
+    h.wait_for_docs(3).await;
+
+    let keyword = Some("聚餐".to_string());
+    let by_user = h
+        .search(SearchParams {
+            chat_id: 1,
+            keyword: keyword.clone(),
+            user_id: Some(11),
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(by_user, vec![2]);
+
+    let photos = h
+        .search(SearchParams {
+            chat_id: 1,
+            keyword: keyword.clone(),
+            message_type: Some("photo".into()),
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(photos, vec![3]);
+
+    let in_range = h
+        .search(SearchParams {
+            chat_id: 1,
+            keyword,
+            date_from: Some(START + DAY),
+            date_to: Some(START + DAY + 1),
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(in_range, vec![2]);
+}
+
+#[tokio::test]
+async fn partial_updates_feed_sorting() {
+    let h = Harness::new("updates").await;
+    h.indexer
+        .index(message(1, 1, 10, "频道公告一", START))
+        .await;
+    h.indexer
+        .index(message(1, 2, 10, "频道公告二", START))
+        .await;
+    h.wait_for_docs(2).await;
+
+    h.indexer.update(1, 1, json!({ "views": 5 })).await;
+    h.indexer.update(1, 2, json!({ "views": 500 })).await;
+    h.settle().await;
+
+    let by_views = h
+        .search(SearchParams {
+            chat_id: 1,
+            keyword: Some("公告".into()),
+            sort: SearchSort::Views,
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(by_views, vec![2, 1]);
+}
@@ -4,20 +4,269 @@ pub fn index_settings_and_mappings() -> Value {
     json!({
         "settings": {
             "number_of_shards": 1,
-            "number_of_replicas": 0
+            "number_of_replicas": 0,
+            "analysis": {
+                "tokenizer": {
+                    // IK drops emoji entirely; emit every emoji code point as a token instead
+                    "emoji_tokenizer": {
+                        "type": "pattern",
+                        "pattern": "\\p{So}",
+                        "group": 0
+                    }
+                },
+                "analyzer": {
+                    "emoji": {
+                        "type": "custom",
+                        "tokenizer": "emoji_tokenizer"
+                    }
+                }
+            }
         },
         "mappings": {
             "properties": {
                 "message_id":   { "type": "long" },
                 "chat_id":      { "type": "long" },
                 "user_id":      { "type": "long" },
+                "username":     { "type": "keyword" },
+                "display_name": {
+                    "type": "text",
+                    "fields": {
+                        "keyword": { "type": "keyword", "ignore_above": 256 }
+                    }
+                },
                 "text": {
+                    "type": "text",
+                    "analyzer": "ik_max_word",
+                    "search_analyzer": "ik_smart",
+                    "fields": {
+                        "emoji": { "type": "text", "analyzer": "emoji" }
+                    }
+                },
+                "caption": {
+                    "type": "text",
+                    "analyzer": "ik_max_word",
+                    "search_analyzer": "ik_smart"
+                },
+                "date":         { "type": "long" },
+                "message_type": { "type": "keyword" },
+                "reply_to_message_id": { "type": "long" },
+                "urls":         { "type": "keyword" },
+                "domains":      { "type": "keyword" },
+                "mentions":     { "type": "keyword" },
+                "file_name": {
+                    "type": "text",
+                    "analyzer": "ik_max_word",
+                    "search_analyzer": "ik_smart",
+                    "fields": {
+                        "keyword": { "type": "keyword", "ignore_above": 256 }
+                    }
+                },
+                "file_ext":     { "type": "keyword" },
+                "mime_type":    { "type": "keyword" },
+                "file_size":    { "type": "long" },
+                "location":     { "type": "geo_point" },
+                "quote_text": {
                     "type": "text",
                     "analyzer": "ik_max_word",
                     "search_analyzer": "ik_smart"
                 },
+                "external_reply_chat_id":    { "type": "long" },
+                "external_reply_message_id": { "type": "long" },
+                "pinned_message_id":         { "type": "long" },
+                "views":        { "type": "long" },
+                "forwards":     { "type": "long" },
+                "scheduled":    { "type": "boolean" },
+                "forwarded":    { "type": "boolean" },
+                "people":       { "type": "keyword" },
+                "orgs":         { "type": "keyword" },
+                "places":       { "type": "keyword" },
+                "sentiment":    { "type": "float" },
+                "toxicity":     { "type": "float" },
+                "edited_at":    { "type": "long" },
+                "content_hash": { "type": "keyword" },
+                // Set by the default ingest pipeline, absent without one
+                "indexed_at":   { "type": "date" }
+            }
+        }
+    })
+}
+
+/// Ingest pipeline created when `indexer.pipeline` names one that doesn't
+/// exist. Operators extend it (or replace it) in Elasticsearch to enrich
+/// messages server-side; the bot never overwrites an existing pipeline.
+pub fn default_ingest_pipeline() -> Value {
+    json!({
+        "description": "search-bot message enrichment",
+        "processors": [
+            { "trim": { "field": "text", "ignore_missing": true } },
+            { "set": { "field": "indexed_at", "value": "{{_ingest.timestamp}}" } }
+        ]
+    })
+}
+
+pub fn audit_settings_and_mappings() -> Value {
+    json!({
+        "settings": {
+            "number_of_shards": 1,
+            "number_of_replicas": 0
+        },
+        "mappings": {
+            "properties": {
+                "chat_id":      { "type": "long" },
+                "user_id":      { "type": "long" },
+                "query":        { "type": "keyword" },
+                "result_count": { "type": "long" },
+                "date":         { "type": "long" }
+            }
+        }
+    })
+}
+
+pub fn bookmarks_settings_and_mappings() -> Value {
+    json!({
+        "settings": {
+            "number_of_shards": 1,
+            "number_of_replicas": 0
+        },
+        "mappings": {
+            "properties": {
+                "user_id":      { "type": "long" },
+                "chat_id":      { "type": "long" },
+                "message_id":   { "type": "long" },
+                "preview":      { "type": "text", "index": false },
+                "display_name": { "type": "keyword", "index": false },
                 "date":         { "type": "long" },
-                "message_type": { "type": "keyword" }
+                "saved_at":     { "type": "long" }
+            }
+        }
+    })
+}
+
+pub fn alerts_settings_and_mappings() -> Value {
+    json!({
+        "settings": {
+            "number_of_shards": 1,
+            "number_of_replicas": 0
+        },
+        "mappings": {
+            "properties": {
+                "chat_id":       { "type": "long" },
+                "keyword":       { "type": "keyword" },
+                "created_by":    { "type": "long" },
+                "created_at":    { "type": "long" },
+                "last_alerted":  { "type": "long" },
+                // The watch as a query, matched against messages by percolation
+                "query":         { "type": "percolator" },
+                // Fields of percolated messages, analyzed like the message index
+                "text": {
+                    "type": "text",
+                    "analyzer": "ik_max_word",
+                    "search_analyzer": "ik_smart"
+                }
+            }
+        }
+    })
+}
+
+pub fn chats_settings_and_mappings() -> Value {
+    json!({
+        "settings": {
+            "number_of_shards": 1,
+            "number_of_replicas": 0
+        },
+        "mappings": {
+            "properties": {
+                "chat_id":   { "type": "long" },
+                "title": {
+                    "type": "text",
+                    "analyzer": "ik_max_word",
+                    "search_analyzer": "ik_smart",
+                    "fields": {
+                        "keyword": { "type": "keyword", "ignore_above": 256 }
+                    }
+                },
+                "username":  { "type": "keyword" },
+                "chat_type": { "type": "keyword" },
+                "first_message_date": { "type": "long" },
+                "last_message_date":  { "type": "long" },
+                "title_history": {
+                    "properties": {
+                        "title": { "type": "keyword", "ignore_above": 256 },
+                        "since": { "type": "long" }
+                    }
+                },
+                "member_counts": {
+                    "properties": {
+                        "date":  { "type": "long" },
+                        "count": { "type": "long" }
+                    }
+                }
+            }
+        }
+    })
+}
+
+pub fn users_settings_and_mappings() -> Value {
+    json!({
+        "settings": {
+            "number_of_shards": 1,
+            "number_of_replicas": 0
+        },
+        "mappings": {
+            "properties": {
+                "chat_id":  { "type": "long" },
+                "user_id":  { "type": "long" },
+                "username": { "type": "keyword" },
+                "display_name": {
+                    "type": "text",
+                    "analyzer": "ik_max_word",
+                    "search_analyzer": "ik_smart",
+                    "fields": {
+                        "keyword": { "type": "keyword", "ignore_above": 256 }
+                    }
+                },
+                "name_history": {
+                    "properties": {
+                        "username":     { "type": "keyword" },
+                        "display_name": { "type": "keyword", "ignore_above": 256 },
+                        "since":        { "type": "long" }
+                    }
+                }
+            }
+        }
+    })
+}
+
+pub fn chat_settings_and_mappings() -> Value {
+    json!({
+        "settings": {
+            "number_of_shards": 1,
+            "number_of_replicas": 0
+        },
+        "mappings": {
+            // Settings are only read back by id, never searched
+            "dynamic": false,
+            "properties": {
+                "chat_id": { "type": "long" }
+            }
+        }
+    })
+}
+
+pub fn digest_feedback_settings_and_mappings() -> Value {
+    json!({
+        "settings": {
+            "number_of_shards": 1,
+            "number_of_replicas": 0
+        },
+        "mappings": {
+            "properties": {
+                "chat_id":    { "type": "long" },
+                "message_id": { "type": "long" },
+                "user_id":    { "type": "long" },
+                "helpful":    { "type": "boolean" },
+                "period":     { "type": "keyword" },
+                "date":       { "type": "long" }
             }
         }
     })
@@ -0,0 +1,168 @@
+//! Deterministic in-memory [`SearchBackend`], so the search flow and its
+//! rendering can be tested without Elasticsearch.
+
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+
+use crate::error::AppError;
+use crate::es::search::{
+    FacetCounts, SearchBackend, SearchHit, SearchParams, SearchResult, SearchSort, HIGHLIGHT_END,
+    HIGHLIGHT_START, TOXIC_THRESHOLD,
+};
+use crate::models::message::ChatMessage;
+
+/// Matches every whitespace-separated keyword term as an ASCII
+/// case-insensitive substring instead of analyzing text, and orders
+/// relevance searches newest first. Of the filters, it models chat, sender,
+/// type, date, file extension and toxicity; the rest are ignored.
+pub struct MemoryBackend {
+    messages: Vec<ChatMessage>,
+}
+
+impl MemoryBackend {
+    pub fn new(messages: Vec<ChatMessage>) -> Self {
+        Self { messages }
+    }
+
+    fn matches(
+        &self,
+        message: &ChatMessage,
+        params: &SearchParams,
+        message_type: Option<&str>,
+        date_from: Option<i64>,
+    ) -> bool {
+        let kind = message.message_type.to_string();
+        message.chat_id == params.chat_id
+            && terms(params).all(|term| find(&message.text, term, 0).is_some())
+            && params.user_id.is_none_or(|id| message.user_id == Some(id))
+            && message_type.map_or(kind != "pinned", |t| kind == t)
+            && date_from.is_none_or(|from| message.date >= from)
+            && params.date_to.is_none_or(|to| message.date <= to)
+            && params
+                .file_ext
+                .as_ref()
+                .is_none_or(|ext| message.file_ext.as_ref() == Some(ext))
+            && (!params.toxic || message.toxicity.is_some_and(|t| t >= TOXIC_THRESHOLD))
+    }
+
+    fn count(
+        &self,
+        params: &SearchParams,
+        message_type: Option<&str>,
+        date_from: Option<i64>,
+    ) -> u64 {
+        self.messages
+            .iter()
+            .filter(|m| self.matches(m, params, message_type, date_from))
+            .count() as u64
+    }
+
+    /// Counts of each facet alternative, the other filter kept as it is.
+    fn facets(&self, params: &SearchParams) -> Option<FacetCounts> {
+        let request = params.facets.as_ref()?;
+        let types: HashMap<String, u64> = request
+            .types
+            .iter()
+            .map(|t| (t.clone(), self.count(params, Some(t), params.date_from)))
+            .collect();
+        let dates: HashMap<String, u64> = request
+            .dates
+            .iter()
+            .map(|(key, from)| {
+                let count = self.count(params, params.message_type.as_deref(), *from);
+                (key.clone(), count)
+            })
+            .collect();
+        Some(FacetCounts { types, dates })
+    }
+}
+
+impl SearchBackend for MemoryBackend {
+    fn search<'a>(
+        &'a self,
+        params: &'a SearchParams,
+    ) -> BoxFuture<'a, Result<SearchResult, AppError>> {
+        Box::pin(async move {
+            let mut hits: Vec<&ChatMessage> = self
+                .messages
+                .iter()
+                .filter(|m| {
+                    self.matches(m, params, params.message_type.as_deref(), params.date_from)
+                })
+                .collect();
+            let key = |m: &ChatMessage| match params.sort {
+                SearchSort::Relevance => (m.date, m.message_id),
+                SearchSort::Views => (m.views.unwrap_or(0) as i64, m.message_id),
+                SearchSort::Forwards => (m.forwards.unwrap_or(0) as i64, m.message_id),
+            };
+            hits.sort_by_key(|m| std::cmp::Reverse(key(m)));
+
+            let total = hits.len() as u64;
+            let page_size = params.page_size.max(1);
+            Ok(SearchResult {
+                total,
+                messages: hits
+                    .into_iter()
+                    .skip(params.page * page_size)
+                    .take(page_size)
+                    .map(|m| SearchHit {
+                        message: m.clone(),
+                        snippet: highlight(&m.text, params),
+                        quote_highlight: None,
+                    })
+                    .collect(),
+                page: params.page,
+                page_size,
+                total_pages: (total as usize).div_ceil(page_size),
+                facets: self.facets(params),
+            })
+        })
+    }
+}
+
+fn terms(params: &SearchParams) -> impl Iterator<Item = &str> {
+    params
+        .keyword
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+}
+
+/// Byte offset of the first ASCII case-insensitive occurrence of `term` in
+/// `text` at or after `start`.
+fn find(text: &str, term: &str, start: usize) -> Option<usize> {
+    (start..=text.len().checked_sub(term.len())?).find(|&i| {
+        text.is_char_boundary(i)
+            && text
+                .get(i..i + term.len())
+                .is_some_and(|s| s.eq_ignore_ascii_case(term))
+    })
+}
+
+/// The whole text with every term occurrence wrapped in highlight markers.
+fn highlight(text: &str, params: &SearchParams) -> String {
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    for term in terms(params) {
+        let mut start = 0;
+        while let Some(at) = find(text, term, start) {
+            spans.push((at, at + term.len()));
+            start = at + term.len();
+        }
+    }
+    spans.sort();
+
+    let mut out = String::new();
+    let mut pos = 0;
+    for (from, to) in spans {
+        if from < pos {
+            continue;
+        }
+        out.push_str(&text[pos..from]);
+        out.push(HIGHLIGHT_START);
+        out.push_str(&text[from..to]);
+        out.push(HIGHLIGHT_END);
+        pos = to;
+    }
+    out.push_str(&text[pos..]);
+    out
+}
@@ -1,4 +1,21 @@
+pub mod admin;
+pub mod alerts;
+pub mod analytics;
+pub mod audit;
+pub mod bookmarks;
+pub mod breaker;
+pub mod chats;
 pub mod client;
+pub mod feedback;
 pub mod indexer;
+#[cfg(all(test, feature = "es-integration"))]
+mod integration;
 pub mod mapping;
+#[cfg(test)]
+pub mod memory;
+pub mod personalize;
 pub mod search;
+pub mod settings;
+pub mod spool;
+pub mod users;
+pub mod warmup;
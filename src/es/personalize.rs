@@ -0,0 +1,198 @@
+//! The people a member talks with most in a chat, computed from the reply
+//! graph and boosted in that member's search results when the chat turns on
+//! personalized ranking.
+
+use dashmap::DashMap;
+use elasticsearch::{Elasticsearch, SearchParts};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::config::SearchConfig;
+
+/// Replies and own messages sampled per member.
+const SAMPLE_SIZE: usize = 500;
+
+/// Frequent interlocutors per chat and member, recomputed once the cached
+/// list is older than the configured TTL.
+pub struct Interlocutors {
+    es: Arc<Elasticsearch>,
+    index_name: String,
+    ttl: Duration,
+    limit: usize,
+    /// Keyed by chat and member
+    cache: DashMap<(i64, i64), Cached>,
+}
+
+struct Cached {
+    computed: Instant,
+    users: Arc<Vec<i64>>,
+}
+
+impl Interlocutors {
+    pub fn new(es: Arc<Elasticsearch>, index_name: String, config: &SearchConfig) -> Self {
+        Self {
+            es,
+            index_name,
+            ttl: Duration::from_secs(config.personal_cache_secs),
+            limit: config.personal_boost_users,
+            cache: DashMap::new(),
+        }
+    }
+
+    /// User ids `user_id` replies to or gets replies from most in the chat,
+    /// most frequent first. Failures are logged and give an empty list, so a
+    /// search is never held up by its personalization.
+    pub async fn of(&self, chat_id: i64, user_id: i64) -> Arc<Vec<i64>> {
+        if let Some(entry) = self.cache.get(&(chat_id, user_id))
+            && entry.computed.elapsed() < self.ttl
+        {
+            return Arc::clone(&entry.users);
+        }
+        let users = match self.compute(chat_id, user_id).await {
+            Ok(users) => Arc::new(users),
+            Err(e) => {
+                tracing::warn!("Failed to load interlocutors of {user_id} in {chat_id}: {e}");
+                return Arc::default();
+            }
+        };
+        let cached = Cached {
+            computed: Instant::now(),
+            users: Arc::clone(&users),
+        };
+        self.cache.insert((chat_id, user_id), cached);
+        users
+    }
+
+    async fn compute(&self, chat_id: i64, user_id: i64) -> anyhow::Result<Vec<i64>> {
+        let mut counts: HashMap<i64, u64> = HashMap::new();
+
+        // Authors of the messages the member replied to
+        let body = self
+            .search(json!({
+                "size": 0,
+                "query": { "bool": { "filter": [
+                    { "term": { "chat_id": chat_id } },
+                    { "term": { "user_id": user_id } },
+                    { "exists": { "field": "reply_to_message_id" } }
+                ] } },
+                "aggs": {
+                    "targets": { "terms": { "field": "reply_to_message_id", "size": SAMPLE_SIZE } }
+                }
+            }))
+            .await?;
+        let targets = bucket_keys(&body["aggregations"]["targets"]);
+        if !targets.is_empty() {
+            let body = self
+                .search(json!({
+                    "size": 0,
+                    "query": { "bool": { "filter": [
+                        { "term": { "chat_id": chat_id } },
+                        { "terms": { "message_id": targets } }
+                    ] } },
+                    "aggs": { "users": { "terms": { "field": "user_id", "size": self.limit * 2 } } }
+                }))
+                .await?;
+            add_counts(&mut counts, &body["aggregations"]["users"]);
+        }
+
+        // Members who replied to the member's recent messages
+        let body = self
+            .search(json!({
+                "size": SAMPLE_SIZE,
+                "_source": ["message_id"],
+                "sort": [{ "date": { "order": "desc" } }],
+                "query": { "bool": { "filter": [
+                    { "term": { "chat_id": chat_id } },
+                    { "term": { "user_id": user_id } }
+                ] } }
+            }))
+            .await?;
+        let own: Vec<i64> = body["hits"]["hits"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|hit| hit["_source"]["message_id"].as_i64())
+            .collect();
+        if !own.is_empty() {
+            let body = self
+                .search(json!({
+                    "size": 0,
+                    "query": { "bool": { "filter": [
+                        { "term": { "chat_id": chat_id } },
+                        { "terms": { "reply_to_message_id": own } }
+                    ] } },
+                    "aggs": { "users": { "terms": { "field": "user_id", "size": self.limit * 2 } } }
+                }))
+                .await?;
+            add_counts(&mut counts, &body["aggregations"]["users"]);
+        }
+
+        counts.remove(&user_id);
+        Ok(top_users(counts, self.limit))
+    }
+
+    async fn search(&self, body: Value) -> anyhow::Result<Value> {
+        let response = self
+            .es
+            .search(SearchParts::Index(&[&self.index_name]))
+            .body(body)
+            .send()
+            .await?;
+        let status = response.status_code();
+        let body: Value = response.json().await?;
+        if !status.is_success() {
+            anyhow::bail!("Interlocutor query failed (status {status}): {body}");
+        }
+        Ok(body)
+    }
+}
+
+fn bucket_keys(agg: &Value) -> Vec<i64> {
+    agg["buckets"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|b| b["key"].as_i64())
+        .collect()
+}
+
+fn add_counts(counts: &mut HashMap<i64, u64>, agg: &Value) {
+    for bucket in agg["buckets"].as_array().into_iter().flatten() {
+        if let (Some(user), Some(count)) = (bucket["key"].as_i64(), bucket["doc_count"].as_u64()) {
+            *counts.entry(user).or_default() += count;
+        }
+    }
+}
+
+/// The `limit` users with the highest counts; ties go to the lower id so the
+/// boost list, and with it the ranking, is stable across recomputations.
+fn top_users(counts: HashMap<i64, u64>, limit: usize) -> Vec<i64> {
+    let mut users: Vec<(i64, u64)> = counts.into_iter().collect();
+    users.sort_by_key(|&(user, count)| (std::cmp::Reverse(count), user));
+    users
+        .into_iter()
+        .take(limit)
+        .map(|(user, _)| user)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replies_both_ways_add_up() {
+        let mut counts = HashMap::new();
+        add_counts(
+            &mut counts,
+            &json!({ "buckets": [{ "key": 1, "doc_count": 3 }, { "key": 2, "doc_count": 2 }] }),
+        );
+        add_counts(
+            &mut counts,
+            &json!({ "buckets": [{ "key": 2, "doc_count": 2 }, { "key": 3, "doc_count": 3 }] }),
+        );
+        assert_eq!(top_users(counts, 2), [2, 1]);
+    }
+}
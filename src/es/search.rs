@@ -1,24 +1,225 @@
-use elasticsearch::{Elasticsearch, SearchParts};
+use elasticsearch::http::request::JsonBody;
+use elasticsearch::indices::IndicesAnalyzeParts;
+use elasticsearch::{Elasticsearch, MsearchParts, SearchParts};
+use futures::future::BoxFuture;
+use serde::Deserialize;
 use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use unicode_segmentation::UnicodeSegmentation;
 
+use crate::bot::query::QueryError;
+use crate::config::{IdStrategy, IndexerConfig, SearchConfig};
+use crate::error::AppError;
+use crate::es::admin::latest_sender;
+use crate::es::breaker::CircuitBreaker;
+use crate::es::personalize::Interlocutors;
+use crate::es::users::UserStore;
 use crate::models::message::ChatMessage;
 
+/// Extra time allowed for the HTTP round trip on top of the ES-side timeout.
+const CLIENT_TIMEOUT_GRACE: Duration = Duration::from_millis(500);
+/// Markers around highlighted terms; private-use characters never appear in
+/// indexed text, so fragments can be escaped for any parse mode afterwards.
+pub const HIGHLIGHT_START: char = '\u{E000}';
+pub const HIGHLIGHT_END: char = '\u{E001}';
+/// Toxicity score from which `is:toxic` counts a message as highly toxic.
+pub const TOXIC_THRESHOLD: f32 = 0.8;
+/// Score multiplier of hits sent by [`SearchParams::boost_users`].
+pub const PERSONAL_BOOST: f32 = 2.0;
+/// Highlight fragments requested per hit for the message text.
+const MAX_HIGHLIGHT_FRAGMENTS: usize = 3;
+/// Revisions `/history` lists, ES's default `index.max_inner_result_window`.
+pub const MAX_REVISIONS: usize = 100;
+/// Messages shown on each side of a hit by its context view.
+pub const CONTEXT_RADIUS: i64 = 5;
+/// Messages listed by a hit's similar messages view.
+pub const MAX_SIMILAR: usize = 5;
+/// Deepest hit a search may page to, ES's default `index.max_result_window`.
+pub const MAX_RESULT_WINDOW: usize = 10_000;
+
 pub struct SearchClient {
     es: Arc<Elasticsearch>,
     index_name: String,
+    /// Searches slower than this are logged; `None` disables the slow log
+    slow_query: Option<Duration>,
+    /// Re-run slow searches with profiling enabled
+    profile_slow: bool,
+    timeout: Duration,
+    /// Cap on the joined highlight fragments of one hit
+    snippet_max_chars: usize,
+    /// Edits are indexed as revisions, so hits are collapsed per message
+    collapse_edits: bool,
+    /// How the indexer derived document ids, for fetching messages by id
+    id_strategy: IdStrategy,
+    breaker: Arc<CircuitBreaker>,
+    interlocutors: Interlocutors,
+    /// Name history that `@username` and `from:` filters resolve through
+    users: Arc<UserStore>,
 }
 
-#[derive(Debug, Clone, Default)]
+/// A structured search: what the `/s` syntax parses into, and what the
+/// HTTP API accepts as JSON. Omitted fields don't filter.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
 pub struct SearchParams {
     pub chat_id: i64,
     pub keyword: Option<String>,
     pub user_id: Option<i64>,
+    /// Current or former username of the sender, lowercased and without the
+    /// `@`; resolved to `user_id` by [`SearchClient`] unless that is set
+    pub username: Option<String>,
+    /// Sender username or display name, including bridged authors
+    pub from: Option<String>,
+    /// Senders who used `from` as a former username or display name; filled
+    /// in by [`SearchClient`] from the name history
+    #[serde(skip)]
+    pub from_users: Vec<i64>,
     pub date_from: Option<i64>,
     pub date_to: Option<i64>,
     pub message_type: Option<String>,
+    pub has: Vec<Attachment>,
+    /// File extension without the dot, e.g. `pdf`
+    pub file_ext: Option<String>,
+    /// Exact MIME type, or a `type/*` prefix
+    pub mime_type: Option<String>,
+    pub near: Option<GeoFilter>,
+    /// Person, organization or location named in the message
+    pub entity: Option<String>,
+    /// Words the caption of a media message must contain
+    pub caption: Option<String>,
+    /// Only messages scored at least [`TOXIC_THRESHOLD`]
+    pub toxic: bool,
+    pub sort: SearchSort,
+    /// Senders whose hits rank [`PERSONAL_BOOST`] times higher
+    pub boost_users: Vec<i64>,
     pub page: usize,
     pub page_size: usize,
+    /// Filter buttons to count hits for alongside the search
+    #[serde(skip)]
+    pub facets: Option<FacetRequest>,
+}
+
+impl SearchParams {
+    /// Offset of the first hit of the page, or `None` when the page ends
+    /// beyond [`MAX_RESULT_WINDOW`].
+    pub fn offset(&self) -> Option<usize> {
+        let from = self.page.checked_mul(self.page_size)?;
+        (from.checked_add(self.page_size)? <= MAX_RESULT_WINDOW).then_some(from)
+    }
+}
+
+/// Alternatives of the type and date filters to count, each counted with
+/// the other filter kept as it is.
+#[derive(Debug, Clone, Default)]
+pub struct FacetRequest {
+    pub types: Vec<String>,
+    /// Button key and the lower date bound it applies (`None` = all time)
+    pub dates: Vec<(String, Option<i64>)>,
+}
+
+/// Hit counts per requested facet key.
+#[derive(Debug, Default)]
+pub struct FacetCounts {
+    pub types: HashMap<String, u64>,
+    pub dates: HashMap<String, u64>,
+}
+
+/// Messages whose location lies within `radius_m` meters of a point.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct GeoFilter {
+    pub lat: f64,
+    pub lon: f64,
+    pub radius_m: f64,
+}
+
+/// Things a message can carry, filtered with `has:<name>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Attachment {
+    Photo,
+    Link,
+    File,
+    Reply,
+}
+
+impl std::str::FromStr for Attachment {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "photo" => Ok(Self::Photo),
+            "link" => Ok(Self::Link),
+            "file" => Ok(Self::File),
+            "reply" => Ok(Self::Reply),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Attachment {
+    /// Names accepted by `has:`.
+    pub const NAMES: &[&str] = &["photo", "link", "file", "reply"];
+
+    fn filter(self) -> Value {
+        match self {
+            Self::Photo => json!({ "term": { "message_type": "photo" } }),
+            Self::File => json!({ "term": { "message_type": "document" } }),
+            Self::Link => json!({ "exists": { "field": "urls" } }),
+            Self::Reply => json!({ "exists": { "field": "reply_to_message_id" } }),
+        }
+    }
+}
+
+/// Result order, chosen with `sort:<name>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchSort {
+    #[default]
+    Relevance,
+    /// Most viewed channel posts first
+    Views,
+    /// Most forwarded channel posts first
+    Forwards,
+}
+
+impl SearchSort {
+    /// Names accepted by `sort:`.
+    pub const NAMES: &[&str] = &["relevance", "views", "forwards"];
+}
+
+impl std::str::FromStr for SearchSort {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "relevance" => Ok(Self::Relevance),
+            "views" => Ok(Self::Views),
+            "forwards" => Ok(Self::Forwards),
+            _ => Err(()),
+        }
+    }
+}
+
+impl SearchSort {
+    /// Sort clauses; popularity sorts fall back to relevance, and messages
+    /// without counts go last.
+    fn clauses(self) -> Value {
+        let relevance = [
+            json!({ "_score": { "order": "desc" } }),
+            json!({ "date": { "order": "desc" } }),
+        ];
+        let field = match self {
+            Self::Relevance => return json!(relevance),
+            Self::Views => "views",
+            Self::Forwards => "forwards",
+        };
+        let mut clauses = vec![json!({ field: { "order": "desc", "missing": "_last" } })];
+        clauses.extend(relevance);
+        json!(clauses)
+    }
 }
 
 #[derive(Debug)]
@@ -26,106 +227,558 @@ pub struct SearchResult {
     pub total: u64,
     pub messages: Vec<SearchHit>,
     pub page: usize,
+    /// Hits per page the search ran with, so results can be numbered globally
+    pub page_size: usize,
     pub total_pages: usize,
+    /// Counts for [`SearchParams::facets`], `None` when not requested or failed
+    pub facets: Option<FacetCounts>,
 }
 
 #[derive(Debug)]
 pub struct SearchHit {
     pub message: ChatMessage,
-    pub highlight: Option<String>,
+    /// Highlighted fragments, or the start of the text when nothing was
+    /// highlighted; matches are wrapped in [`HIGHLIGHT_START`]/[`HIGHLIGHT_END`]
+    pub snippet: String,
+    /// Highlighted quote when the match came from the quoted reply text
+    pub quote_highlight: Option<String>,
+}
+
+/// Debug view of how a search is executed, for `/explain`.
+#[derive(Debug)]
+pub struct SearchExplanation {
+    /// The request body sent to Elasticsearch
+    pub query: Value,
+    /// Tokens the keyword analyzer produces for the keyword
+    pub tokens: Vec<String>,
+    /// Message id, score and `_explanation` of the top hit
+    pub top_hit: Option<(i64, f64, Value)>,
 }
 
 impl SearchClient {
-    pub fn new(es: Arc<Elasticsearch>, index_name: String) -> Self {
-        Self { es, index_name }
+    pub fn new(
+        es: Arc<Elasticsearch>,
+        index_name: String,
+        config: &SearchConfig,
+        indexer: &IndexerConfig,
+        breaker: Arc<CircuitBreaker>,
+        users: Arc<UserStore>,
+    ) -> Self {
+        Self {
+            interlocutors: Interlocutors::new(es.clone(), index_name.clone(), config),
+            es,
+            index_name,
+            slow_query: (config.slow_query_ms > 0)
+                .then(|| Duration::from_millis(config.slow_query_ms)),
+            profile_slow: config.profile_slow_queries,
+            timeout: Duration::from_millis(config.timeout_ms),
+            snippet_max_chars: config.snippet_max_chars,
+            collapse_edits: indexer.edit_versions,
+            id_strategy: indexer.id_strategy,
+            breaker,
+            users,
+        }
     }
 
-    pub async fn search(&self, params: &SearchParams) -> anyhow::Result<SearchResult> {
-        let query = self.build_query(params);
-        let from = params.page * params.page_size;
+    /// Run a search. Fails with [`AppError::SearchTimeout`] when ES doesn't
+    /// finish within the configured timeout, and with [`AppError::Unavailable`]
+    /// while the circuit breaker is open.
+    pub async fn search(&self, params: &SearchParams) -> Result<SearchResult, AppError> {
+        if self.breaker.is_open() {
+            return Err(AppError::Unavailable);
+        }
+
+        let from = params.offset().ok_or(QueryError::PageTooDeep)?;
+        let params = &*self.resolve_senders(params).await;
+        let mut query = self.search_body(params);
+        query["timeout"] = json!(format!("{}ms", self.timeout.as_millis()));
+        query["from"] = json!(from);
+        query["size"] = json!(params.page_size);
+        let facet_query = params.facets.as_ref().map(|request| {
+            let mut facet_query = self.facet_query(params, request);
+            facet_query["timeout"] = query["timeout"].clone();
+            facet_query
+        });
+        let started = Instant::now();
 
+        let request = async {
+            // Facet counts ride along in the same _msearch as the hits
+            let sent = match facet_query {
+                Some(ref facet_query) => {
+                    let header = || JsonBody::from(json!({}));
+                    self.es
+                        .msearch(MsearchParts::Index(&[&self.index_name]))
+                        .body(vec![
+                            header(),
+                            JsonBody::from(query.clone()),
+                            header(),
+                            JsonBody::from(facet_query.clone()),
+                        ])
+                        .send()
+                        .await
+                }
+                None => {
+                    self.es
+                        .search(SearchParts::Index(&[&self.index_name]))
+                        .body(query.clone())
+                        .send()
+                        .await
+                }
+            };
+            let response = match sent {
+                Ok(response) => response,
+                Err(e) => {
+                    tracing::warn!("Search request failed: {e}");
+                    self.breaker.record_failure();
+                    return Err(AppError::Unavailable);
+                }
+            };
+
+            let status = response.status_code();
+            if status.is_server_error() {
+                self.breaker.record_failure();
+            }
+            if !status.is_success() {
+                let body: Value = response.json().await?;
+                return Err(AppError::SearchFailure {
+                    status_code: status.as_u16(),
+                    details: body.to_string(),
+                });
+            }
+            let body = response.json::<Value>().await?;
+            if facet_query.is_none() {
+                return Ok((body, None));
+            }
+
+            let [hits, facets] = body["responses"]
+                .as_array()
+                .and_then(|responses| <[Value; 2]>::try_from(responses.clone()).ok())
+                .ok_or_else(|| AppError::SearchFailure {
+                    status_code: status.as_u16(),
+                    details: format!("unexpected msearch response: {body}"),
+                })?;
+            if let Some(error) = hits.get("error") {
+                let status_code = hits["status"].as_u64().unwrap_or(0) as u16;
+                if status_code >= 500 {
+                    self.breaker.record_failure();
+                }
+                return Err(AppError::SearchFailure {
+                    status_code,
+                    details: error.to_string(),
+                });
+            }
+            let facets = match facets.get("error") {
+                Some(error) => {
+                    tracing::warn!("Facet count failed: {error}");
+                    None
+                }
+                None => Some(parse_facets(&facets)),
+            };
+            Ok((hits, facets))
+        };
+        let (body, facets) =
+            match tokio::time::timeout(self.timeout + CLIENT_TIMEOUT_GRACE, request).await {
+                Ok(body) => body?,
+                Err(_) => {
+                    self.log_if_slow(&query, started.elapsed(), None);
+                    return Err(AppError::SearchTimeout);
+                }
+            };
+
+        self.breaker.record_success();
+        self.log_if_slow(&query, started.elapsed(), body["took"].as_u64());
+        if body["timed_out"].as_bool() == Some(true) {
+            return Err(AppError::SearchTimeout);
+        }
+        let mut result = self.parse_response(&body, params.page, params.page_size);
+        result.facets = facets;
+        Ok(result)
+    }
+
+    /// Size-0 search counting hits per facet alternative: the query without
+    /// its type and date filters, aggregated per alternative.
+    fn facet_query(&self, params: &SearchParams, request: &FacetRequest) -> Value {
+        let types: serde_json::Map<String, Value> = request
+            .types
+            .iter()
+            .map(|t| (t.clone(), type_filter(Some(t))))
+            .collect();
+        let dates: serde_json::Map<String, Value> = request
+            .dates
+            .iter()
+            .map(|(key, from)| {
+                let filter =
+                    date_filter(*from, params.date_to).unwrap_or(json!({ "match_all": {} }));
+                (key.clone(), filter)
+            })
+            .collect();
+        let current_date =
+            date_filter(params.date_from, params.date_to).unwrap_or(json!({ "match_all": {} }));
+        let counts = |filters| {
+            let mut counts = json!({ "filters": { "filters": filters } });
+            if self.collapse_edits {
+                counts["aggs"] = json!({ "messages": message_count() });
+            }
+            counts
+        };
+
+        json!({
+            "size": 0,
+            "query": bool_query(params, false),
+            "aggs": {
+                "types": {
+                    "filter": current_date,
+                    "aggs": { "counts": counts(types) }
+                },
+                "dates": {
+                    "filter": type_filter(params.message_type.as_deref()),
+                    "aggs": { "counts": counts(dates) }
+                }
+            }
+        })
+    }
+
+    /// Log a search that exceeded the slow query threshold, optionally
+    /// profiling it again in the background.
+    fn log_if_slow(&self, query: &Value, elapsed: Duration, took_ms: Option<u64>) {
+        let Some(threshold) = self.slow_query else {
+            return;
+        };
+        if elapsed < threshold {
+            return;
+        }
+        tracing::warn!(
+            elapsed_ms = elapsed.as_millis() as u64,
+            es_took_ms = took_ms,
+            "Slow search: {query}"
+        );
+
+        if self.profile_slow {
+            let es = Arc::clone(&self.es);
+            let index_name = self.index_name.clone();
+            let mut query = query.clone();
+            query["profile"] = json!(true);
+            tokio::spawn(async move {
+                if let Err(e) = profile_search(&es, &index_name, query).await {
+                    tracing::warn!("Profiling slow search failed: {e}");
+                }
+            });
+        }
+    }
+
+    /// Fetch the latest indexed revision of a message; with
+    /// `indexer.edit_versions` the document of the original id holds the
+    /// text as first sent.
+    pub async fn get_message(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> Result<Option<ChatMessage>, AppError> {
+        let messages = self
+            .fetch_messages(json!({
+                "size": 1,
+                "query": {
+                    "bool": {
+                        "filter": [
+                            { "term": { "chat_id": chat_id } },
+                            { "term": { "message_id": message_id } }
+                        ]
+                    }
+                },
+                "sort": [newest_revision_first()]
+            }))
+            .await?;
+        Ok(messages.into_iter().next())
+    }
+
+    /// The latest [`MAX_REVISIONS`] indexed revisions of a message, oldest
+    /// first: the message as first sent, then its edits. Empty when the
+    /// message isn't indexed.
+    pub async fn history(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> Result<Vec<ChatMessage>, AppError> {
+        let query = json!({
+            "size": 1,
+            "query": {
+                "bool": {
+                    "filter": [
+                        { "term": { "chat_id": chat_id } },
+                        { "term": { "message_id": message_id } }
+                    ]
+                }
+            },
+            "collapse": {
+                "field": "message_id",
+                "inner_hits": {
+                    "name": "revisions",
+                    "size": MAX_REVISIONS,
+                    "sort": [newest_revision_first()]
+                }
+            }
+        });
         let response = self
             .es
             .search(SearchParts::Index(&[&self.index_name]))
-            .from(from as i64)
-            .size(params.page_size as i64)
             .body(query)
             .send()
             .await?;
 
         let status = response.status_code();
+        let body: Value = response.json().await?;
         if !status.is_success() {
-            let body: Value = response.json().await?;
-            anyhow::bail!("Search failed (status {status}): {body}");
+            return Err(AppError::SearchFailure {
+                status_code: status.as_u16(),
+                details: body.to_string(),
+            });
         }
-
-        let body: Value = response.json().await?;
-        self.parse_response(&body, params.page, params.page_size)
+        let hits = body["hits"]["hits"][0]["inner_hits"]["revisions"]["hits"]["hits"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        Ok(hits
+            .into_iter()
+            .rev()
+            .filter_map(|hit| serde_json::from_value(hit["_source"].clone()).ok())
+            .collect())
     }
 
-    fn build_query(&self, params: &SearchParams) -> Value {
-        let mut must = vec![];
-        let mut filter = vec![json!({ "term": { "chat_id": params.chat_id } })];
+    /// The indexed messages of a chat whose ids are within [`CONTEXT_RADIUS`]
+    /// of `message_id`, in order and each at its latest revision. Pin records
+    /// are left out.
+    pub async fn context(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> Result<Vec<ChatMessage>, AppError> {
+        self.fetch_messages(json!({
+            "size": 2 * CONTEXT_RADIUS + 1,
+            "query": {
+                "bool": {
+                    "filter": [
+                        { "term": { "chat_id": chat_id } },
+                        {
+                            "range": {
+                                "message_id": {
+                                    "gte": message_id - CONTEXT_RADIUS,
+                                    "lte": message_id + CONTEXT_RADIUS
+                                }
+                            }
+                        }
+                    ],
+                    "must_not": [{ "term": { "message_type": "pinned" } }]
+                }
+            },
+            "sort": [{ "message_id": "asc" }, newest_revision_first()],
+            "collapse": { "field": "message_id" }
+        }))
+        .await
+    }
 
-        if let Some(ref kw) = params.keyword
-            && !kw.is_empty()
-        {
-            must.push(json!({
-                "match": { "text": { "query": kw, "analyzer": "ik_smart" } }
-            }));
+    /// Up to [`MAX_SIMILAR`] messages of a chat sharing the most significant
+    /// terms with the latest revision of `message_id`, best match first.
+    pub async fn similar(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+    ) -> Result<Vec<ChatMessage>, AppError> {
+        let Some(message) = self.get_message(chat_id, message_id).await? else {
+            return Ok(Vec::new());
+        };
+        let mut query = json!({
+            "size": MAX_SIMILAR,
+            "query": {
+                "bool": {
+                    "must": [{
+                        "more_like_this": {
+                            "fields": ["text"],
+                            "like": [{
+                                "_index": self.index_name,
+                                "_id": message.doc_id(self.id_strategy)
+                            }],
+                            // Chat messages are short, so single occurrences count
+                            "min_term_freq": 1,
+                            "min_doc_freq": 2
+                        }
+                    }],
+                    "filter": [{ "term": { "chat_id": chat_id } }],
+                    "must_not": [
+                        { "term": { "message_id": message_id } },
+                        { "term": { "message_type": "pinned" } }
+                    ]
+                }
+            }
+        });
+        if self.collapse_edits {
+            query["collapse"] = json!({ "field": "message_id" });
         }
+        self.fetch_messages(query).await
+    }
 
-        if must.is_empty() {
-            must.push(json!({ "match_all": {} }));
+    /// Run `query` and return the messages of its hits.
+    async fn fetch_messages(&self, query: Value) -> Result<Vec<ChatMessage>, AppError> {
+        if self.breaker.is_open() {
+            return Err(AppError::Unavailable);
         }
+        let response = self
+            .es
+            .search(SearchParts::Index(&[&self.index_name]))
+            .body(query)
+            .send()
+            .await?;
 
-        if let Some(uid) = params.user_id {
-            filter.push(json!({ "term": { "user_id": uid } }));
+        let status = response.status_code();
+        let body: Value = response.json().await?;
+        if !status.is_success() {
+            return Err(AppError::SearchFailure {
+                status_code: status.as_u16(),
+                details: body.to_string(),
+            });
         }
+        Ok(body["hits"]["hits"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|hit| serde_json::from_value(hit["_source"].clone()).ok())
+            .collect())
+    }
 
-        let mut range = serde_json::Map::new();
-        if let Some(from) = params.date_from {
-            range.insert("gte".into(), json!(from));
-        }
-        if let Some(to) = params.date_to {
-            range.insert("lte".into(), json!(to));
+    /// Show the generated query, the analyzed keyword and why the top hit matched.
+    pub async fn explain(&self, params: &SearchParams) -> Result<SearchExplanation, AppError> {
+        let params = &*self.resolve_senders(params).await;
+        let query = self.search_body(params);
+
+        let tokens = match params.keyword.as_deref().filter(|kw| !kw.is_empty()) {
+            Some(kw) => self.analyze(kw).await?,
+            None => Vec::new(),
+        };
+
+        let response = self
+            .es
+            .search(SearchParts::Index(&[&self.index_name]))
+            .size(1)
+            .explain(true)
+            .body(query.clone())
+            .send()
+            .await?;
+
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            return Err(AppError::SearchFailure {
+                status_code: status.as_u16(),
+                details: body.to_string(),
+            });
         }
-        if !range.is_empty() {
-            filter.push(json!({ "range": { "date": range } }));
+
+        let body: Value = response.json().await?;
+        let top_hit = body["hits"]["hits"].get(0).map(|hit| {
+            (
+                hit["_source"]["message_id"].as_i64().unwrap_or(0),
+                hit["_score"].as_f64().unwrap_or(0.0),
+                hit["_explanation"].clone(),
+            )
+        });
+
+        Ok(SearchExplanation {
+            query,
+            tokens,
+            top_hit,
+        })
+    }
+
+    async fn analyze(&self, text: &str) -> Result<Vec<String>, AppError> {
+        let response = self
+            .es
+            .indices()
+            .analyze(IndicesAnalyzeParts::Index(&self.index_name))
+            .body(json!({ "analyzer": "ik_smart", "text": text }))
+            .send()
+            .await?;
+
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            return Err(AppError::SearchFailure {
+                status_code: status.as_u16(),
+                details: body.to_string(),
+            });
         }
 
-        if let Some(ref mt) = params.message_type {
-            filter.push(json!({ "term": { "message_type": mt } }));
+        let body: Value = response.json().await?;
+        Ok(body["tokens"]
+            .as_array()
+            .map(|tokens| {
+                tokens
+                    .iter()
+                    .filter_map(|t| t["token"].as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Users `user_id` talks with most in the chat, for
+    /// [`SearchParams::boost_users`]; empty while the breaker is open.
+    pub async fn interlocutors(&self, chat_id: i64, user_id: i64) -> Arc<Vec<i64>> {
+        if self.breaker.is_open() {
+            return Arc::default();
         }
+        self.interlocutors.of(chat_id, user_id).await
+    }
 
-        json!({
-            "query": {
-                "bool": { "must": must, "filter": filter }
-            },
-            "sort": [
-                { "_score": { "order": "desc" } },
-                { "date": { "order": "desc" } }
-            ],
-            "highlight": {
-                "fields": {
-                    "text": {
-                        "pre_tags": ["<b>"],
-                        "post_tags": ["</b>"],
-                        "fragment_size": 100,
-                        "number_of_fragments": 1
-                    }
+    /// `params` with its `@username` resolved to the sender's id and the
+    /// senders its `from:` name belongs to, current or former, so messages
+    /// sent under other names match too. A failed lookup is logged and
+    /// leaves the filter to the names on the messages.
+    pub async fn resolve_senders<'a>(&self, params: &'a SearchParams) -> Cow<'a, SearchParams> {
+        let mut resolved = Cow::Borrowed(params);
+        if let Some(ref username) = params.username
+            && params.user_id.is_none()
+        {
+            match self.resolve_username(params.chat_id, username).await {
+                Ok(user_id) => resolved.to_mut().user_id = user_id,
+                Err(e) => {
+                    tracing::warn!("Failed to resolve @{username} in {}: {e}", params.chat_id);
                 }
             }
-        })
+        }
+        if let Some(ref from) = params.from
+            && params.from_users.is_empty()
+        {
+            match self.users.find(params.chat_id, from).await {
+                Ok(users) if !users.is_empty() => {
+                    resolved.to_mut().from_users = users.iter().map(|u| u.user_id).collect();
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::warn!("Failed to resolve from:{from} in {}: {e}", params.chat_id);
+                }
+            }
+        }
+        resolved
     }
 
-    fn parse_response(
-        &self,
-        body: &Value,
-        page: usize,
-        page_size: usize,
-    ) -> anyhow::Result<SearchResult> {
-        let total = body["hits"]["total"]["value"].as_u64().unwrap_or(0);
+    /// Sender who uses or used `username` in the chat, from the name history
+    /// or else the latest message carrying it.
+    async fn resolve_username(&self, chat_id: i64, username: &str) -> anyhow::Result<Option<i64>> {
+        if let Some(user_id) = self.users.resolve_username(chat_id, username).await? {
+            return Ok(Some(user_id));
+        }
+        latest_sender(&self.es, &self.index_name, chat_id, username).await
+    }
+
+    /// The Elasticsearch request body of a search, without paging and
+    /// timeout; see [`search_body`].
+    pub fn search_body(&self, params: &SearchParams) -> Value {
+        search_body(params, self.collapse_edits)
+    }
+
+    fn parse_response(&self, body: &Value, page: usize, page_size: usize) -> SearchResult {
+        let total = body["aggregations"]["messages"]["value"]
+            .as_u64()
+            .or_else(|| body["hits"]["total"]["value"].as_u64())
+            .unwrap_or(0);
         let total_pages = if total == 0 {
             0
         } else {
@@ -138,25 +791,400 @@ impl SearchClient {
             .unwrap_or_default()
             .iter()
             .filter_map(|hit| {
-                let message: ChatMessage =
-                    serde_json::from_value(hit["_source"].clone()).ok()?;
-                let highlight = hit["highlight"]["text"]
-                    .as_array()
-                    .and_then(|arr| arr.first())
-                    .and_then(|v| v.as_str())
-                    .map(String::from);
+                let hit = hit["inner_hits"]["latest"]["hits"]["hits"]
+                    .get(0)
+                    .unwrap_or(hit);
+                let message: ChatMessage = serde_json::from_value(hit["_source"].clone()).ok()?;
+                let fragments = |field: &str| -> Vec<&str> {
+                    hit["highlight"][field]
+                        .as_array()
+                        .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect())
+                        .unwrap_or_default()
+                };
+                let mut text = fragments("text");
+                if text.is_empty() {
+                    text = fragments("text.emoji");
+                }
+                // `caption:` matches highlight only the caption, which `text` repeats
+                if text.is_empty() {
+                    text = fragments("caption");
+                }
+                let snippet = join_fragments(&text, self.snippet_max_chars)
+                    .unwrap_or_else(|| truncate_snippet(&message.text, self.snippet_max_chars));
                 Some(SearchHit {
                     message,
-                    highlight,
+                    snippet,
+                    quote_highlight: fragments("quote_text").first().map(|f| f.to_string()),
                 })
             })
             .collect();
 
-        Ok(SearchResult {
+        SearchResult {
             total,
             messages,
             page,
+            page_size,
             total_pages,
-        })
+            facets: None,
+        }
+    }
+}
+
+/// Where the bot's searches run: [`SearchClient`] in production, an
+/// in-memory index in tests.
+pub trait SearchBackend: Send + Sync {
+    fn search<'a>(
+        &'a self,
+        params: &'a SearchParams,
+    ) -> BoxFuture<'a, Result<SearchResult, AppError>>;
+}
+
+impl SearchBackend for SearchClient {
+    fn search<'a>(
+        &'a self,
+        params: &'a SearchParams,
+    ) -> BoxFuture<'a, Result<SearchResult, AppError>> {
+        Box::pin(SearchClient::search(self, params))
+    }
+}
+
+/// Translate a structured search into the Elasticsearch request body the bot
+/// sends: a bool query of the keyword, caption and filters, sorted by
+/// [`SearchParams::sort`] and highlighted with [`HIGHLIGHT_START`] and
+/// [`HIGHLIGHT_END`]. Hits of [`SearchParams::boost_users`] have their score
+/// multiplied by [`PERSONAL_BOOST`]. With `collapse_edits` (`indexer.edit_versions`) hits
+/// are collapsed to one per message. Paging, timeout and facets are left to
+/// the caller, so integrations can run the body against the index
+/// themselves.
+pub fn search_body(params: &SearchParams, collapse_edits: bool) -> Value {
+    let mut bool_query = bool_query(params, true);
+    if !params.boost_users.is_empty() {
+        bool_query = json!({
+            "function_score": {
+                "query": bool_query,
+                "functions": [{
+                    "filter": { "terms": { "user_id": params.boost_users } },
+                    "weight": PERSONAL_BOOST
+                }],
+                "boost_mode": "multiply"
+            }
+        });
+    }
+    let mut query = json!({
+        "query": bool_query,
+        "sort": params.sort.clauses(),
+        "highlight": highlight()
+    });
+    if collapse_edits {
+        // Revisions share the message id: one hit per message, shown as
+        // its newest matching revision, and counted per message
+        query["collapse"] = json!({
+            "field": "message_id",
+            "inner_hits": {
+                "name": "latest",
+                "size": 1,
+                "sort": [newest_revision_first()],
+                "highlight": highlight()
+            }
+        });
+        query["aggs"] = json!({ "messages": message_count() });
+    }
+    query
+}
+
+/// The bool query of a search. Facet counts leave out the type and date
+/// filters (`with_facet_filters = false`) and apply them per bucket.
+fn bool_query(params: &SearchParams, with_facet_filters: bool) -> Value {
+    let mut must = vec![];
+    let mut filter = vec![json!({ "term": { "chat_id": params.chat_id } })];
+
+    if let Some(ref kw) = params.keyword
+        && !kw.is_empty()
+    {
+        let text_match = json!({
+            "multi_match": {
+                "query": kw,
+                "fields": ["text", "file_name", "quote_text"],
+                "analyzer": "ik_smart"
+            }
+        });
+        if contains_emoji(kw) {
+            // IK yields no tokens for emoji, so also match the emoji subfield
+            must.push(json!({
+                "bool": {
+                    "should": [text_match, { "match": { "text.emoji": kw } }],
+                    "minimum_should_match": 1
+                }
+            }));
+        } else {
+            must.push(text_match);
+        }
+    }
+
+    if let Some(ref caption) = params.caption {
+        must.push(json!({
+            "match": { "caption": { "query": caption, "analyzer": "ik_smart" } }
+        }));
+    }
+
+    if must.is_empty() {
+        must.push(json!({ "match_all": {} }));
+    }
+
+    if let Some(uid) = params.user_id {
+        filter.push(json!({ "term": { "user_id": uid } }));
+    } else if let Some(ref username) = params.username {
+        // No sender known by this name; only messages carrying it can match
+        filter.push(json!({ "term": { "username": username } }));
+    }
+
+    if let Some(ref from) = params.from {
+        let mut should = vec![
+            json!({ "term": { "username": from.trim_start_matches('@').to_lowercase() } }),
+            json!({ "match_phrase": { "display_name": from } }),
+        ];
+        if !params.from_users.is_empty() {
+            should.push(json!({ "terms": { "user_id": params.from_users } }));
+        }
+        filter.push(json!({
+            "bool": { "should": should, "minimum_should_match": 1 }
+        }));
+    }
+
+    if with_facet_filters {
+        filter.extend(date_filter(params.date_from, params.date_to));
+        filter.push(type_filter(params.message_type.as_deref()));
+    }
+
+    filter.extend(params.has.iter().map(|a| a.filter()));
+
+    if let Some(ref ext) = params.file_ext {
+        filter.push(json!({ "term": { "file_ext": ext } }));
+    }
+
+    if let Some(ref mime) = params.mime_type {
+        match mime.strip_suffix("/*") {
+            Some(prefix) => filter.push(json!({ "prefix": { "mime_type": format!("{prefix}/") } })),
+            None => filter.push(json!({ "term": { "mime_type": mime } })),
+        }
     }
+
+    if let Some(near) = params.near {
+        filter.push(json!({
+            "geo_distance": {
+                "distance": format!("{}m", near.radius_m),
+                "location": { "lat": near.lat, "lon": near.lon }
+            }
+        }));
+    }
+
+    if let Some(ref entity) = params.entity {
+        filter.push(json!({
+            "multi_match": {
+                "query": entity,
+                "fields": ["people", "orgs", "places"]
+            }
+        }));
+    }
+
+    if params.toxic {
+        filter.push(json!({ "range": { "toxicity": { "gte": TOXIC_THRESHOLD } } }));
+    }
+
+    json!({ "bool": { "must": must, "filter": filter } })
+}
+
+fn highlight() -> Value {
+    json!({
+        "pre_tags": [HIGHLIGHT_START.to_string()],
+        "post_tags": [HIGHLIGHT_END.to_string()],
+        "fields": {
+            "text": {
+                "fragment_size": 100,
+                "number_of_fragments": MAX_HIGHLIGHT_FRAGMENTS
+            },
+            "text.emoji": {
+                "fragment_size": 100,
+                "number_of_fragments": MAX_HIGHLIGHT_FRAGMENTS
+            },
+            "caption": {
+                "fragment_size": 100,
+                "number_of_fragments": MAX_HIGHLIGHT_FRAGMENTS
+            },
+            "quote_text": {
+                "fragment_size": 100,
+                "number_of_fragments": 1
+            }
+        }
+    })
+}
+
+/// Sort revisions of a message by edit time, newest first and the original
+/// last. Indexes that never stored an edit have no `edited_at` mapping.
+fn newest_revision_first() -> Value {
+    json!({
+        "edited_at": { "order": "desc", "missing": "_last", "unmapped_type": "long" }
+    })
+}
+
+/// Distinct messages among the hits, counting revisions once; exact below
+/// the precision threshold.
+fn message_count() -> Value {
+    json!({ "cardinality": { "field": "message_id", "precision_threshold": 40000 } })
+}
+
+fn parse_facets(body: &Value) -> FacetCounts {
+    let counts = |agg: &str| -> HashMap<String, u64> {
+        body["aggregations"][agg]["counts"]["buckets"]
+            .as_object()
+            .map(|buckets| {
+                buckets
+                    .iter()
+                    .map(|(key, b)| {
+                        // Per message when revisions are collapsed
+                        let count = b["messages"]["value"].as_u64().or(b["doc_count"].as_u64());
+                        (key.clone(), count.unwrap_or(0))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    FacetCounts {
+        types: counts("types"),
+        dates: counts("dates"),
+    }
+}
+
+/// Restrict to a message type. Pin records duplicate the pinned message, so
+/// only an explicit `type:pinned` sees them.
+fn type_filter(message_type: Option<&str>) -> Value {
+    match message_type {
+        Some(mt) => json!({ "term": { "message_type": mt } }),
+        None => json!({ "bool": { "must_not": { "term": { "message_type": "pinned" } } } }),
+    }
+}
+
+fn date_filter(from: Option<i64>, to: Option<i64>) -> Option<Value> {
+    let mut range = serde_json::Map::new();
+    if let Some(from) = from {
+        range.insert("gte".into(), json!(from));
+    }
+    if let Some(to) = to {
+        range.insert("lte".into(), json!(to));
+    }
+    (!range.is_empty()).then(|| json!({ "range": { "date": range } }))
+}
+
+/// Join highlight fragments as `… a … b …`, dropping trailing fragments that
+/// would push the snippet past `max_chars` graphemes. The first fragment is
+/// always kept, truncated if needed.
+fn join_fragments(fragments: &[&str], max_chars: usize) -> Option<String> {
+    let (first, rest) = fragments.split_first()?;
+    let first = truncate_snippet(first, max_chars);
+    let mut kept = vec![first.as_str()];
+    let mut len = snippet_len(&first);
+    for fragment in rest {
+        len += snippet_len(fragment) + 3;
+        if len > max_chars {
+            break;
+        }
+        kept.push(fragment);
+    }
+    if kept.len() == 1 {
+        return Some(first);
+    }
+    Some(format!("… {} …", kept.join(" … ")))
+}
+
+/// Whether a grapheme shows up in the rendered snippet, i.e. isn't a marker.
+fn is_visible(grapheme: &str) -> bool {
+    grapheme
+        .chars()
+        .any(|c| c != HIGHLIGHT_START && c != HIGHLIGHT_END)
+}
+
+fn snippet_len(s: &str) -> usize {
+    s.graphemes(true).filter(|g| is_visible(g)).count()
+}
+
+/// Cut a snippet to `max_chars` graphemes followed by `…`, never splitting a
+/// grapheme cluster and keeping highlight markers balanced.
+fn truncate_snippet(s: &str, max_chars: usize) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut shown = 0;
+    let mut open = false;
+    for grapheme in s.graphemes(true) {
+        let visible = is_visible(grapheme);
+        if visible && shown == max_chars {
+            if open && out.ends_with(HIGHLIGHT_START) {
+                out.pop();
+            } else if open {
+                out.push(HIGHLIGHT_END);
+            }
+            out.push('…');
+            return out;
+        }
+        shown += usize::from(visible);
+        for c in grapheme.chars() {
+            match c {
+                HIGHLIGHT_START => open = true,
+                HIGHLIGHT_END => open = false,
+                _ => {}
+            }
+        }
+        out.push_str(grapheme);
+    }
+    out
+}
+
+/// Run a search with profiling enabled and log the time spent on each shard.
+async fn profile_search(es: &Elasticsearch, index_name: &str, query: Value) -> anyhow::Result<()> {
+    let response = es
+        .search(SearchParts::Index(&[index_name]))
+        .size(0)
+        .body(query)
+        .send()
+        .await?;
+
+    let status = response.status_code();
+    if !status.is_success() {
+        let body: Value = response.json().await?;
+        anyhow::bail!("Profile search failed (status {status}): {body}");
+    }
+
+    let body: Value = response.json().await?;
+    let shards = body["profile"]["shards"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    for shard in &shards {
+        let nanos = |section: &str| -> u64 {
+            shard["searches"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .flat_map(|search| search[section].as_array().cloned().unwrap_or_default())
+                .filter_map(|node| node["time_in_nanos"].as_u64())
+                .sum()
+        };
+        tracing::warn!(
+            shard = shard["id"].as_str().unwrap_or_default(),
+            query_ms = nanos("query") as f64 / 1e6,
+            collector_ms = nanos("collector") as f64 / 1e6,
+            "Slow search profile"
+        );
+    }
+    Ok(())
+}
+
+/// Rough check for emoji code points (pictographs, dingbats, misc symbols).
+fn contains_emoji(s: &str) -> bool {
+    s.chars().any(is_emoji)
+}
+
+pub(crate) fn is_emoji(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2300..=0x23FF | 0x2B00..=0x2BFF
+    )
 }
@@ -0,0 +1,295 @@
+use dashmap::DashMap;
+use elasticsearch::params::Refresh;
+use elasticsearch::{Elasticsearch, GetParts, IndexParts, SearchParts};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::{Arc, OnceLock};
+
+/// Per-chat settings changed through admin commands.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatSettings {
+    pub chat_id: i64,
+    /// Senders whose messages are never indexed
+    #[serde(default)]
+    pub ignored: Vec<IgnoredSender>,
+    /// Rules recovering the real author of messages relayed by bridge bots
+    #[serde(default)]
+    pub bridge_rules: Vec<BridgeRule>,
+    /// Scheduled activity digest, off when `None`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<DigestSettings>,
+    /// Unix epoch seconds of the last weekly throwback, off when `None`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub throwback_last_sent: Option<i64>,
+    /// Link preview shown under search results
+    #[serde(default)]
+    pub link_preview: LinkPreviewMode,
+    /// Kinds of senders whose messages are indexed
+    #[serde(default)]
+    pub senders: IndexedSenders,
+    /// Language of reference texts such as `/syntax`
+    #[serde(default)]
+    pub language: Language,
+    /// Rank hits from the searcher's frequent reply partners higher
+    #[serde(default)]
+    pub personalize: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Language {
+    #[default]
+    Zh,
+    En,
+}
+
+/// Which senders are indexed, checked after the ignore list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedSenders {
+    /// Messages of people
+    #[serde(default = "default_true")]
+    pub humans: bool,
+    /// Messages of bots; `None` follows `recorder.ignore_bots`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bots: Option<bool>,
+    /// Bots indexed even when other bots aren't, e.g. an announcement bot
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bot_ids: Vec<i64>,
+}
+
+impl Default for IndexedSenders {
+    fn default() -> Self {
+        Self {
+            humans: true,
+            bots: None,
+            bot_ids: Vec::new(),
+        }
+    }
+}
+
+impl IndexedSenders {
+    /// Whether messages of the sender are indexed; `ignore_bots` is the
+    /// global default for bots.
+    pub fn indexes(&self, user_id: i64, is_bot: bool, ignore_bots: bool) -> bool {
+        if !is_bot {
+            return self.humans;
+        }
+        self.bot_ids.contains(&user_id) || self.bots.unwrap_or(!ignore_bots)
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkPreviewMode {
+    /// Let Telegram pick a link to preview
+    #[default]
+    Auto,
+    Off,
+    /// Preview the link to the top hit
+    TopHit,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestSettings {
+    pub period: DigestPeriod,
+    /// Pin each digest, unpinning the previous one
+    pub pin: bool,
+    /// Unix epoch seconds the last digest covered up to
+    pub last_sent: i64,
+    /// Message id of the last digest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_message_id: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestPeriod {
+    Daily,
+    Weekly,
+}
+
+impl DigestPeriod {
+    pub fn secs(self) -> i64 {
+        match self {
+            Self::Daily => 86400,
+            Self::Weekly => 7 * 86400,
+        }
+    }
+}
+
+/// A sender on the ignore list, matched by id or by username.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IgnoredSender {
+    Id(i64),
+    /// Lowercased, without the `@`
+    Username(String),
+}
+
+/// Extracts the relayed author from messages of one bridge bot, e.g.
+/// `^<(?P<name>[^>]+)> ` for IRC-style `<nick> message` lines.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgeRule {
+    /// Lowercased, without the `@`
+    pub bot_username: String,
+    /// Must contain a `name` capture group; the whole match is stripped
+    pub pattern: String,
+    #[serde(skip)]
+    compiled: OnceLock<Option<Regex>>,
+}
+
+impl BridgeRule {
+    pub fn new(bot_username: &str, pattern: &str) -> anyhow::Result<Self> {
+        let regex = Regex::new(pattern)?;
+        if !regex.capture_names().any(|name| name == Some("name")) {
+            anyhow::bail!("pattern has no `name` capture group");
+        }
+        Ok(Self {
+            bot_username: bot_username.trim_start_matches('@').to_lowercase(),
+            pattern: pattern.to_string(),
+            compiled: OnceLock::from(Some(regex)),
+        })
+    }
+
+    /// Split bridged text into the author name and the message itself.
+    pub fn split<'a>(&self, text: &'a str) -> Option<(&'a str, &'a str)> {
+        let regex = self
+            .compiled
+            .get_or_init(|| Regex::new(&self.pattern).ok())
+            .as_ref()?;
+        let caps = regex.captures(text)?;
+        let prefix = caps.get(0)?;
+        let name = caps.name("name")?.as_str().trim();
+        // Only a leading prefix is attribution; anything else is message text
+        if prefix.start() != 0 || name.is_empty() {
+            return None;
+        }
+        Some((name, text[prefix.end()..].trim_start()))
+    }
+}
+
+impl ChatSettings {
+    pub fn is_ignored(&self, user_id: i64, username: Option<&str>) -> bool {
+        self.ignored.iter().any(|entry| match entry {
+            IgnoredSender::Id(id) => *id == user_id,
+            IgnoredSender::Username(name) => username.is_some_and(|u| u.eq_ignore_ascii_case(name)),
+        })
+    }
+
+    /// The bridge rule for messages sent by `username`, if any.
+    pub fn bridge_rule(&self, username: Option<&str>) -> Option<&BridgeRule> {
+        let username = username?;
+        self.bridge_rules
+            .iter()
+            .find(|rule| rule.bot_username.eq_ignore_ascii_case(username))
+    }
+}
+
+/// Stores one settings document per chat, cached in memory since the
+/// recorder consults it for every message.
+pub struct SettingsStore {
+    es: Arc<Elasticsearch>,
+    index_name: String,
+    cache: DashMap<i64, Arc<ChatSettings>>,
+}
+
+impl SettingsStore {
+    pub fn new(es: Arc<Elasticsearch>, index_name: String) -> Self {
+        Self {
+            es,
+            index_name,
+            cache: DashMap::new(),
+        }
+    }
+
+    pub async fn get(&self, chat_id: i64) -> anyhow::Result<Arc<ChatSettings>> {
+        if let Some(settings) = self.cache.get(&chat_id) {
+            return Ok(Arc::clone(&settings));
+        }
+        let settings = Arc::new(self.load(chat_id).await?);
+        self.cache.insert(chat_id, Arc::clone(&settings));
+        Ok(settings)
+    }
+
+    /// Apply `change` to the chat's settings and persist the result.
+    pub async fn update(
+        &self,
+        chat_id: i64,
+        change: impl FnOnce(&mut ChatSettings),
+    ) -> anyhow::Result<Arc<ChatSettings>> {
+        let mut settings = self.load(chat_id).await?;
+        change(&mut settings);
+
+        let response = self
+            .es
+            .index(IndexParts::IndexId(&self.index_name, &chat_id.to_string()))
+            .refresh(Refresh::WaitFor)
+            .body(&settings)
+            .send()
+            .await?;
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Settings write failed (status {status}): {body}");
+        }
+
+        let settings = Arc::new(settings);
+        self.cache.insert(chat_id, Arc::clone(&settings));
+        Ok(settings)
+    }
+
+    /// Every chat that has stored settings.
+    pub async fn all(&self) -> anyhow::Result<Vec<ChatSettings>> {
+        let response = self
+            .es
+            .search(SearchParts::Index(&[&self.index_name]))
+            .size(1000)
+            .body(json!({ "query": { "match_all": {} } }))
+            .send()
+            .await?;
+
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Settings search failed (status {status}): {body}");
+        }
+
+        let body: Value = response.json().await?;
+        Ok(body["hits"]["hits"]
+            .as_array()
+            .map(|hits| {
+                hits.iter()
+                    .filter_map(|h| serde_json::from_value(h["_source"].clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn load(&self, chat_id: i64) -> anyhow::Result<ChatSettings> {
+        let response = self
+            .es
+            .get(GetParts::IndexId(&self.index_name, &chat_id.to_string()))
+            .send()
+            .await?;
+
+        let status = response.status_code();
+        if status.as_u16() == 404 {
+            return Ok(ChatSettings {
+                chat_id,
+                ..Default::default()
+            });
+        }
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Settings read failed (status {status}): {body}");
+        }
+
+        let body: Value = response.json().await?;
+        Ok(serde_json::from_value(body["_source"].clone())?)
+    }
+}
@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+use crate::es::indexer::IndexOp;
+
+/// Append-only JSON-lines file holding operations that couldn't be indexed
+/// while Elasticsearch was unavailable, replayed once it recovers. Lines
+/// written before partial updates existed are plain messages and still parse.
+pub struct Spool {
+    path: PathBuf,
+}
+
+impl Spool {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub async fn append(&self, ops: &[IndexOp]) -> anyhow::Result<()> {
+        if ops.is_empty() {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent()
+            && !dir.as_os_str().is_empty()
+        {
+            fs::create_dir_all(dir).await?;
+        }
+
+        let mut lines = String::new();
+        for op in ops {
+            lines.push_str(&serde_json::to_string(op)?);
+            lines.push('\n');
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(lines.as_bytes()).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        fs::metadata(&self.path)
+            .await
+            .map_or(true, |meta| meta.len() == 0)
+    }
+
+    /// Number of spooled operations.
+    pub async fn len(&self) -> anyhow::Result<usize> {
+        match fs::read_to_string(&self.path).await {
+            Ok(content) => Ok(content.lines().filter(|l| !l.trim().is_empty()).count()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Take every spooled operation, leaving the spool empty.
+    pub async fn drain(&self) -> anyhow::Result<Vec<IndexOp>> {
+        let content = match fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        fs::remove_file(&self.path).await?;
+
+        Ok(content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(op) => Some(op),
+                Err(e) => {
+                    tracing::warn!("Dropping unreadable spooled operation: {e}");
+                    None
+                }
+            })
+            .collect())
+    }
+}
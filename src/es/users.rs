@@ -0,0 +1,228 @@
+use dashmap::DashMap;
+use elasticsearch::{Elasticsearch, GetParts, SearchParts, UpdateParts};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Name changes kept per sender and chat, oldest dropped first.
+const MAX_NAME_HISTORY: usize = 50;
+/// Most senders returned by one name lookup.
+const MAX_MATCHES: i64 = 20;
+
+/// The names a sender has posted under in one chat, kept up to date by the
+/// recorder. Stored per chat so a lookup never reveals what a member was
+/// called somewhere else.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserInfo {
+    pub chat_id: i64,
+    pub user_id: i64,
+    /// Current username, lowercased and without the `@`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// Names the sender had, oldest first, the current ones last
+    #[serde(default)]
+    pub name_history: Vec<NameChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NameChange {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// Unix epoch seconds of the first message seen under these names
+    pub since: i64,
+}
+
+/// Stores one name history document per chat and sender.
+pub struct UserStore {
+    es: Arc<Elasticsearch>,
+    index_name: String,
+    /// Username and display name last written, keyed by chat and sender
+    written: DashMap<(i64, i64), (Option<String>, Option<String>)>,
+}
+
+impl UserStore {
+    pub fn new(es: Arc<Elasticsearch>, index_name: String) -> Self {
+        Self {
+            es,
+            index_name,
+            written: DashMap::new(),
+        }
+    }
+
+    /// Record that `user_id` posted in the chat at `date` under these names.
+    /// Only writes when a name changed since the last write.
+    pub async fn observe(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        username: Option<&str>,
+        display_name: Option<&str>,
+        date: i64,
+    ) -> anyhow::Result<()> {
+        let names = (
+            username.map(str::to_lowercase),
+            display_name.map(String::from),
+        );
+        if self
+            .written
+            .get(&(chat_id, user_id))
+            .is_some_and(|w| *w == names)
+        {
+            return Ok(());
+        }
+
+        let response = self
+            .es
+            .update(UpdateParts::IndexId(
+                &self.index_name,
+                &doc_id(chat_id, user_id),
+            ))
+            .retry_on_conflict(3)
+            .body(json!({
+                "scripted_upsert": true,
+                "upsert": {},
+                "script": {
+                    "source": OBSERVE_SCRIPT,
+                    "params": {
+                        "chat_id": chat_id,
+                        "user_id": user_id,
+                        "username": names.0,
+                        "display_name": names.1,
+                        "date": date,
+                        "max_names": MAX_NAME_HISTORY
+                    }
+                }
+            }))
+            .send()
+            .await?;
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Sender name write failed (status {status}): {body}");
+        }
+
+        self.written.insert((chat_id, user_id), names);
+        Ok(())
+    }
+
+    pub async fn get(&self, chat_id: i64, user_id: i64) -> anyhow::Result<Option<UserInfo>> {
+        let response = self
+            .es
+            .get(GetParts::IndexId(
+                &self.index_name,
+                &doc_id(chat_id, user_id),
+            ))
+            .send()
+            .await?;
+
+        let status = response.status_code();
+        if status.as_u16() == 404 {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Sender name read failed (status {status}): {body}");
+        }
+
+        let body: Value = response.json().await?;
+        Ok(Some(serde_json::from_value(body["_source"].clone())?))
+    }
+
+    /// Senders of the chat who use or once used `name` as their username
+    /// (with or without the `@`) or display name.
+    pub async fn find(&self, chat_id: i64, name: &str) -> anyhow::Result<Vec<UserInfo>> {
+        let name = name.trim();
+        let username = name.trim_start_matches('@').to_lowercase();
+        self.search(
+            chat_id,
+            json!([
+                { "term": { "username": username } },
+                { "term": { "name_history.username": username } },
+                { "term": { "display_name.keyword": name } },
+                { "term": { "name_history.display_name": name } }
+            ]),
+        )
+        .await
+    }
+
+    /// The sender of the chat known by `username` (lowercased, without the
+    /// `@`): whoever uses it now, else whoever used it before.
+    pub async fn resolve_username(
+        &self,
+        chat_id: i64,
+        username: &str,
+    ) -> anyhow::Result<Option<i64>> {
+        let found = self
+            .search(
+                chat_id,
+                json!([
+                    { "term": { "username": username } },
+                    { "term": { "name_history.username": username } }
+                ]),
+            )
+            .await?;
+        let current = found
+            .iter()
+            .find(|u| u.username.as_deref() == Some(username));
+        Ok(current.or(found.first()).map(|u| u.user_id))
+    }
+
+    /// Senders of the chat matching any of the `should` clauses.
+    async fn search(&self, chat_id: i64, should: Value) -> anyhow::Result<Vec<UserInfo>> {
+        let response = self
+            .es
+            .search(SearchParts::Index(&[&self.index_name]))
+            .size(MAX_MATCHES)
+            .body(json!({
+                "query": { "bool": {
+                    "filter": [{ "term": { "chat_id": chat_id } }],
+                    "should": should,
+                    "minimum_should_match": 1
+                } }
+            }))
+            .send()
+            .await?;
+
+        let status = response.status_code();
+        if !status.is_success() {
+            let body: Value = response.json().await?;
+            anyhow::bail!("Sender name search failed (status {status}): {body}");
+        }
+
+        let body: Value = response.json().await?;
+        Ok(body["hits"]["hits"]
+            .as_array()
+            .map(|hits| {
+                hits.iter()
+                    .filter_map(|h| serde_json::from_value(h["_source"].clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+fn doc_id(chat_id: i64, user_id: i64) -> String {
+    format!("{chat_id}_{user_id}")
+}
+
+/// Upsert of the recorder: append a name change and refresh the current names.
+const OBSERVE_SCRIPT: &str = "
+    def s = ctx._source;
+    s.chat_id = params.chat_id;
+    s.user_id = params.user_id;
+    if (s.name_history == null) { s.name_history = []; }
+    if (s.username != params.username || s.display_name != params.display_name) {
+        s.name_history.add([
+            'username': params.username,
+            'display_name': params.display_name,
+            'since': params.date
+        ]);
+        if (s.name_history.size() > params.max_names) { s.name_history.remove(0); }
+    }
+    s.username = params.username;
+    s.display_name = params.display_name;
+";
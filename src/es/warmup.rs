@@ -0,0 +1,53 @@
+use std::time::Instant;
+
+use crate::config::WarmupConfig;
+use crate::es::analytics::AnalyticsClient;
+use crate::es::search::{SearchClient, SearchParams};
+
+/// Window in which chats are ranked by activity.
+const ACTIVITY_WINDOW_SECS: i64 = 7 * 24 * 3600;
+
+/// Search the most active chats once unfiltered and once per configured
+/// keyword, so the segments real searches hit are in the file-system cache.
+/// Failures are only logged; a cold cache is no reason not to start.
+pub async fn warm_up(
+    config: &WarmupConfig,
+    search: &SearchClient,
+    analytics: &AnalyticsClient,
+    page_size: usize,
+) {
+    let started = Instant::now();
+    let since = chrono::Utc::now().timestamp() - ACTIVITY_WINDOW_SECS;
+    let chats = match analytics.most_active_chats(since, config.chats).await {
+        Ok(chats) => chats,
+        Err(e) => {
+            tracing::warn!("Warm-up skipped, could not rank chats: {e}");
+            return;
+        }
+    };
+
+    let keywords = std::iter::once(None).chain(config.keywords.iter().map(Some));
+    let queries: Vec<SearchParams> = keywords
+        .flat_map(|keyword| {
+            chats.iter().map(move |&chat_id| SearchParams {
+                chat_id,
+                keyword: keyword.cloned(),
+                page_size,
+                ..Default::default()
+            })
+        })
+        .collect();
+    let mut failed = 0;
+    for params in &queries {
+        if let Err(e) = search.search(params).await {
+            tracing::debug!("Warm-up search in chat {} failed: {e}", params.chat_id);
+            failed += 1;
+        }
+    }
+    tracing::info!(
+        "Warm-up ran {} searches over {} chats in {:?} ({failed} failed)",
+        queries.len(),
+        chats.len(),
+        started.elapsed()
+    );
+}
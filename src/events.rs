@@ -0,0 +1,231 @@
+//! Optional stream of changes to the message index, published to Kafka or
+//! NATS so analytics pipelines can follow chats without polling
+//! Elasticsearch. Events are queued in memory and dropped, not retried, when
+//! the broker falls behind; Elasticsearch stays the source of truth.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc;
+
+use crate::config::{EventBackend, EventsConfig};
+use crate::models::message::ChatMessage;
+
+/// A change that reached the message index.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum DocumentEvent {
+    /// A message was indexed or fully replaced
+    Indexed {
+        doc_id: String,
+        doc: Box<ChatMessage>,
+    },
+    /// Fields of an indexed message were merged into it
+    Updated {
+        doc_id: String,
+        chat_id: i64,
+        message_id: i64,
+        doc: Value,
+    },
+    /// The chat's messages matching `query` were deleted
+    Deleted {
+        chat_id: i64,
+        query: Value,
+        deleted: u64,
+    },
+}
+
+impl DocumentEvent {
+    pub fn chat_id(&self) -> i64 {
+        match self {
+            Self::Indexed { doc, .. } => doc.chat_id,
+            Self::Updated { chat_id, .. } | Self::Deleted { chat_id, .. } => *chat_id,
+        }
+    }
+}
+
+/// What goes on the wire: the event with the index it happened in.
+#[derive(Serialize)]
+struct Envelope<'a> {
+    index: &'a str,
+    /// Unix seconds when the change was published
+    timestamp: i64,
+    #[serde(flatten)]
+    event: &'a DocumentEvent,
+}
+
+pub struct EventPublisher {
+    sender: mpsc::Sender<(String, DocumentEvent)>,
+    dropped: AtomicU64,
+}
+
+impl EventPublisher {
+    /// Connect to the configured broker and spawn the publishing task, or
+    /// `None` when no backend is configured. Fails when the backend wasn't
+    /// compiled in.
+    pub async fn connect(config: &EventsConfig) -> anyhow::Result<Option<Self>> {
+        let output = match config.backend {
+            EventBackend::None => return Ok(None),
+            EventBackend::Kafka => Output::kafka(config)?,
+            EventBackend::Nats => Output::nats(config).await?,
+        };
+        tracing::info!(
+            "Publishing index events to {:?} at {} ({})",
+            config.backend,
+            config.url,
+            config.topic
+        );
+        let (tx, rx) = mpsc::channel(config.queue_size.max(1));
+        tokio::spawn(publish_loop(rx, output));
+        Ok(Some(Self {
+            sender: tx,
+            dropped: AtomicU64::new(0),
+        }))
+    }
+
+    /// Queue `event` without waiting; it is dropped if the queue is full.
+    pub fn publish(&self, index: &str, event: DocumentEvent) {
+        if self.sender.try_send((index.to_string(), event)).is_err() {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            if dropped.is_power_of_two() {
+                tracing::warn!("Event queue full, {dropped} events dropped so far");
+            }
+        }
+    }
+}
+
+enum Output {
+    #[cfg(feature = "kafka")]
+    Kafka {
+        producer: rdkafka::producer::FutureProducer,
+        topic: String,
+    },
+    #[cfg(feature = "nats")]
+    Nats {
+        client: async_nats::Client,
+        subject: String,
+    },
+}
+
+impl Output {
+    #[cfg(feature = "kafka")]
+    fn kafka(config: &EventsConfig) -> anyhow::Result<Self> {
+        let producer = rdkafka::ClientConfig::new()
+            .set("bootstrap.servers", &config.url)
+            .set("message.timeout.ms", "10000")
+            .create()?;
+        Ok(Self::Kafka {
+            producer,
+            topic: config.topic.clone(),
+        })
+    }
+
+    #[cfg(not(feature = "kafka"))]
+    fn kafka(_config: &EventsConfig) -> anyhow::Result<Self> {
+        anyhow::bail!("Kafka events need a build with `--features kafka`")
+    }
+
+    #[cfg(feature = "nats")]
+    async fn nats(config: &EventsConfig) -> anyhow::Result<Self> {
+        let client = async_nats::connect(&config.url).await?;
+        Ok(Self::Nats {
+            client,
+            subject: config.topic.clone(),
+        })
+    }
+
+    #[cfg(not(feature = "nats"))]
+    async fn nats(_config: &EventsConfig) -> anyhow::Result<Self> {
+        anyhow::bail!("NATS events need a build with `--features nats`")
+    }
+
+    /// Send one serialized event. Kafka messages are keyed by chat so each
+    /// chat's events stay ordered within a partition; NATS subjects end in
+    /// the chat id so subscribers can pick chats with wildcards.
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    async fn send(&self, chat_id: i64, payload: Vec<u8>) -> anyhow::Result<()> {
+        match *self {
+            #[cfg(feature = "kafka")]
+            Self::Kafka {
+                ref producer,
+                ref topic,
+            } => {
+                let key = chat_id.to_string();
+                let record = rdkafka::producer::FutureRecord::to(topic)
+                    .key(&key)
+                    .payload(&payload);
+                producer
+                    .send(record, std::time::Duration::from_secs(0))
+                    .await
+                    .map_err(|(e, _)| e)?;
+            }
+            #[cfg(feature = "nats")]
+            Self::Nats {
+                ref client,
+                ref subject,
+            } => {
+                client
+                    .publish(nats_subject(subject, chat_id), payload.into())
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Without a backend compiled in there is no output to send to.
+    #[cfg(not(any(feature = "kafka", feature = "nats")))]
+    async fn send(&self, _chat_id: i64, _payload: Vec<u8>) -> anyhow::Result<()> {
+        match *self {}
+    }
+}
+
+async fn publish_loop(mut rx: mpsc::Receiver<(String, DocumentEvent)>, output: Output) {
+    while let Some((index, event)) = rx.recv().await {
+        let payload = match serialize(&index, &event) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::error!("Failed to serialize event: {e}");
+                continue;
+            }
+        };
+        if let Err(e) = output.send(event.chat_id(), payload).await {
+            tracing::warn!("Failed to publish event: {e}");
+        }
+    }
+}
+
+fn serialize(index: &str, event: &DocumentEvent) -> serde_json::Result<Vec<u8>> {
+    serde_json::to_vec(&Envelope {
+        index,
+        timestamp: chrono::Utc::now().timestamp(),
+        event,
+    })
+}
+
+#[cfg_attr(not(feature = "nats"), allow(dead_code))]
+fn nats_subject(prefix: &str, chat_id: i64) -> String {
+    format!("{prefix}.{chat_id}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn events_are_flat_json_tagged_by_op() {
+        let event = DocumentEvent::Updated {
+            doc_id: "-100_7".into(),
+            chat_id: -100,
+            message_id: 7,
+            doc: json!({ "reactions": 3 }),
+        };
+        let value: Value = serde_json::from_slice(&serialize("messages", &event).unwrap()).unwrap();
+        assert_eq!(value["op"], "updated");
+        assert_eq!(value["index"], "messages");
+        assert_eq!(value["doc_id"], "-100_7");
+        assert_eq!(value["doc"]["reactions"], 3);
+        assert!(value["timestamp"].is_i64());
+        assert_eq!(nats_subject("search.events", -100), "search.events.-100");
+    }
+}
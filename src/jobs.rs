@@ -0,0 +1,315 @@
+//! Background jobs for operations that outlive the update that started
+//! them, such as chat exports and purges. Each job gets an id, a state and
+//! progress that `/jobs` lists, and a cancellation token it can poll. At
+//! most [`MAX_RUNNING`] jobs run at once; the rest wait in order.
+//!
+//! Jobs live in memory only: a restart forgets finished jobs and abandons
+//! running ones.
+
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
+
+/// Jobs running at the same time.
+pub const MAX_RUNNING: usize = 2;
+/// Finished jobs remembered for `/jobs`, oldest forgotten first.
+const MAX_FINISHED: usize = 50;
+
+pub type JobId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobKind {
+    Export,
+    Purge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobState {
+    /// Waiting for one of the running jobs to finish
+    Queued,
+    Running,
+    Succeeded,
+    /// Why the job failed
+    Failed(String),
+    Cancelled,
+}
+
+impl JobState {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, Self::Queued | Self::Running)
+    }
+}
+
+/// What `/jobs` shows of a job.
+#[derive(Debug, Clone)]
+pub struct JobInfo {
+    pub id: JobId,
+    pub kind: JobKind,
+    /// Chat the job works on
+    pub chat_id: i64,
+    /// User who started, or approved, the job
+    pub requested_by: i64,
+    /// What the job does, e.g. `2023-01-01 之前的消息`
+    pub description: String,
+    pub state: JobState,
+    /// Items processed so far, and of how many when known
+    pub done: u64,
+    pub total: Option<u64>,
+    /// Unix epoch seconds
+    pub created_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+struct Job {
+    info: JobInfo,
+    cancel: CancellationToken,
+}
+
+pub struct JobQueue {
+    next_id: AtomicU64,
+    jobs: DashMap<JobId, Job>,
+    slots: Semaphore,
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JobQueue {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            jobs: DashMap::new(),
+            slots: Semaphore::new(MAX_RUNNING),
+        }
+    }
+
+    /// Queue `run` as a background job and return its id right away. The
+    /// job's outcome becomes its state; an error is also logged.
+    pub fn spawn<F, Fut>(
+        self: &Arc<Self>,
+        kind: JobKind,
+        chat_id: i64,
+        requested_by: i64,
+        description: String,
+        run: F,
+    ) -> JobId
+    where
+        F: FnOnce(JobHandle) -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let cancel = CancellationToken::new();
+        let info = JobInfo {
+            id,
+            kind,
+            chat_id,
+            requested_by,
+            description,
+            state: JobState::Queued,
+            done: 0,
+            total: None,
+            created_at: chrono::Utc::now().timestamp(),
+            finished_at: None,
+        };
+        self.jobs.insert(
+            id,
+            Job {
+                info,
+                cancel: cancel.clone(),
+            },
+        );
+        self.forget_old();
+
+        let queue = Arc::clone(self);
+        tokio::spawn(async move {
+            let Ok(_slot) = queue.slots.acquire().await else {
+                return;
+            };
+            if cancel.is_cancelled() {
+                queue.finish(id, JobState::Cancelled);
+                return;
+            }
+            queue.update(id, |info| info.state = JobState::Running);
+            let handle = JobHandle {
+                id,
+                queue: Arc::clone(&queue),
+                cancel: cancel.clone(),
+            };
+            let state = match run(handle).await {
+                _ if cancel.is_cancelled() => JobState::Cancelled,
+                Ok(()) => JobState::Succeeded,
+                Err(e) => {
+                    tracing::error!("Job {id} ({kind:?} in {chat_id}) failed: {e}");
+                    JobState::Failed(e.to_string())
+                }
+            };
+            queue.finish(id, state);
+        });
+        id
+    }
+
+    pub fn get(&self, id: JobId) -> Option<JobInfo> {
+        self.jobs.get(&id).map(|job| job.info.clone())
+    }
+
+    /// Jobs of `chat_id`, or of every chat, newest first.
+    pub fn list(&self, chat_id: Option<i64>) -> Vec<JobInfo> {
+        let mut jobs: Vec<JobInfo> = self
+            .jobs
+            .iter()
+            .filter(|job| chat_id.is_none_or(|chat| job.info.chat_id == chat))
+            .map(|job| job.info.clone())
+            .collect();
+        jobs.sort_by_key(|info| std::cmp::Reverse(info.id));
+        jobs
+    }
+
+    /// Ask a queued or running job to stop. Queued jobs never start; running
+    /// ones stop at their next check. `false` if the job is unknown or done.
+    pub fn cancel(&self, id: JobId) -> bool {
+        match self.jobs.get(&id) {
+            Some(job) if !job.info.state.is_finished() => {
+                job.cancel.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn update(&self, id: JobId, f: impl FnOnce(&mut JobInfo)) {
+        if let Some(mut job) = self.jobs.get_mut(&id) {
+            f(&mut job.info);
+        }
+    }
+
+    fn finish(&self, id: JobId, state: JobState) {
+        self.update(id, |info| {
+            info.state = state;
+            info.finished_at = Some(chrono::Utc::now().timestamp());
+        });
+    }
+
+    /// Drop the oldest finished jobs beyond [`MAX_FINISHED`].
+    fn forget_old(&self) {
+        let mut finished: Vec<JobId> = self
+            .jobs
+            .iter()
+            .filter(|job| job.info.state.is_finished())
+            .map(|job| job.info.id)
+            .collect();
+        if finished.len() <= MAX_FINISHED {
+            return;
+        }
+        finished.sort_unstable();
+        for id in &finished[..finished.len() - MAX_FINISHED] {
+            self.jobs.remove(id);
+        }
+    }
+}
+
+/// Given to a running job to report progress and notice cancellation.
+#[derive(Clone)]
+pub struct JobHandle {
+    id: JobId,
+    queue: Arc<JobQueue>,
+    cancel: CancellationToken,
+}
+
+impl JobHandle {
+    pub fn id(&self) -> JobId {
+        self.id
+    }
+
+    pub fn set_progress(&self, done: u64, total: Option<u64>) {
+        self.queue.update(self.id, |info| {
+            info.done = done;
+            info.total = total;
+        });
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// The token cancelled when someone cancels the job, for operations
+    /// that can stop in the middle of a wait.
+    pub fn token(&self) -> &CancellationToken {
+        &self.cancel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::oneshot;
+
+    async fn wait_finished(queue: &JobQueue, id: JobId) -> JobInfo {
+        loop {
+            let info = queue.get(id).unwrap();
+            if info.state.is_finished() {
+                return info;
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn jobs_report_progress_and_outcome() {
+        let queue = Arc::new(JobQueue::new());
+        let (release, released) = oneshot::channel::<()>();
+        let id = queue.spawn(
+            JobKind::Export,
+            -100,
+            1,
+            "export".into(),
+            |job| async move {
+                job.set_progress(5, Some(10));
+                released.await?;
+                Ok(())
+            },
+        );
+        let failing = queue.spawn(JobKind::Purge, -200, 1, "purge".into(), |_| async {
+            anyhow::bail!("boom")
+        });
+
+        assert_eq!(
+            wait_finished(&queue, failing).await.state,
+            JobState::Failed("boom".into())
+        );
+        while queue.get(id).unwrap().done == 0 {
+            tokio::task::yield_now().await;
+        }
+        let running = queue.get(id).unwrap();
+        assert_eq!(
+            (running.state, running.total),
+            (JobState::Running, Some(10))
+        );
+        assert_eq!(queue.list(Some(-100)).len(), 1);
+
+        release.send(()).unwrap();
+        assert_eq!(wait_finished(&queue, id).await.state, JobState::Succeeded);
+        assert!(!queue.cancel(id));
+    }
+
+    #[tokio::test]
+    async fn cancelled_jobs_end_cancelled() {
+        let queue = Arc::new(JobQueue::new());
+        let id = queue.spawn(
+            JobKind::Export,
+            -100,
+            1,
+            "export".into(),
+            |job| async move {
+                job.token().cancelled().await;
+                anyhow::bail!("stopped")
+            },
+        );
+        assert!(queue.cancel(id));
+        assert_eq!(wait_finished(&queue, id).await.state, JobState::Cancelled);
+    }
+}
@@ -0,0 +1,12 @@
+//! Telegram group search bot backed by Elasticsearch. The modules are shared
+//! by the bot binary and the tools under `src/bin`.
+
+pub mod api;
+pub mod bot;
+pub mod config;
+pub mod error;
+pub mod es;
+pub mod events;
+pub mod jobs;
+pub mod models;
+pub mod nlp;
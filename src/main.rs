@@ -1,11 +1,5 @@
+use search_bot_rs::{api, bot, config, es, events};
 use std::sync::Arc;
-use teloxide::prelude::*;
-
-mod bot;
-mod config;
-mod error;
-mod es;
-mod models;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -19,8 +13,14 @@ async fn main() -> anyhow::Result<()> {
 
     tracing::info!("Starting search-bot-rs...");
 
-    // Load configuration (env vars override TOML)
-    let config = config::AppConfig::load()?;
+    // Load configuration (flags override env vars, which override TOML)
+    let config = config::AppConfig::load(config::CliArgs::parse(std::env::args().skip(1))?)?;
+    for entry in config.report() {
+        tracing::info!("Config {} = {} ({})", entry.key, entry.value, entry.source);
+    }
+    for warning in config.warnings() {
+        tracing::warn!("Config: {warning}");
+    }
     tracing::info!("Elasticsearch URL: {}", config.elasticsearch.url);
 
     if config.webhook.is_enabled() {
@@ -38,31 +38,154 @@ async fn main() -> anyhow::Result<()> {
     let es_client = es::client::create_client(&config).await?;
     tracing::info!("Elasticsearch client initialized");
 
+    // Circuit breaker shared by search and indexing (spawns background probe)
+    let probe_interval = std::time::Duration::from_secs(config.breaker.probe_interval_secs);
+    let breaker = Arc::new(es::breaker::CircuitBreaker::new(
+        config.breaker.failure_threshold,
+    ));
+    breaker.spawn_probe(es_client.clone(), probe_interval);
+
+    // Searches and reports go to the read node when one is configured, with
+    // their own breaker so an unhealthy read node doesn't spool writes
+    let (read_client, read_breaker) = match es::client::create_read_client(&config)? {
+        Some(client) => {
+            tracing::info!("Elasticsearch read URL: {}", config.elasticsearch.read_url);
+            let read_breaker = Arc::new(es::breaker::CircuitBreaker::new(
+                config.breaker.failure_threshold,
+            ));
+            read_breaker.spawn_probe(client.clone(), probe_interval);
+            (client, read_breaker)
+        }
+        None => (es_client.clone(), breaker.clone()),
+    };
+
+    // Connect the optional event stream of index changes
+    let events = events::EventPublisher::connect(&config.events)
+        .await?
+        .map(Arc::new);
+
     // Create batch indexer (spawns background flush task)
     let indexer = Arc::new(es::indexer::BatchIndexer::new(
         es_client.clone(),
         config.elasticsearch.index_name.clone(),
-        config.indexer.batch_size,
-        config.indexer.flush_interval_ms,
+        &config.indexer,
+        breaker.clone(),
+        events.clone(),
+    ));
+
+    // Create sender name history store, updated by the recorder and
+    // consulted by from: filters
+    let users = Arc::new(es::users::UserStore::new(
+        es_client.clone(),
+        config.users.index_name.clone(),
     ));
 
     // Create search client
     let search_client = Arc::new(es::search::SearchClient::new(
+        read_client.clone(),
+        config.elasticsearch.index_name.clone(),
+        &config.search,
+        &config.indexer,
+        read_breaker,
+        users.clone(),
+    ));
+
+    // Create analytics client for reporting commands
+    let analytics = Arc::new(es::analytics::AnalyticsClient::new(
+        read_client,
+        config.elasticsearch.index_name.clone(),
+    ));
+
+    // Create search audit log
+    let audit = Arc::new(es::audit::AuditLog::new(
+        es_client.clone(),
+        config.audit.index_name.clone(),
+        config.audit.enabled,
+    ));
+
+    // Create keyword alert store
+    let alerts = Arc::new(es::alerts::AlertStore::new(
+        es_client.clone(),
+        config.alerts.index_name.clone(),
+    ));
+
+    // Create per-chat settings store
+    let settings = Arc::new(es::settings::SettingsStore::new(
+        es_client.clone(),
+        config.settings.index_name.clone(),
+    ));
+
+    // Create chat metadata store, updated by the recorder
+    let chats = Arc::new(es::chats::ChatStore::new(
+        es_client.clone(),
+        config.chats.index_name.clone(),
+    ));
+
+    // Create personal bookmark store
+    let bookmarks = Arc::new(es::bookmarks::BookmarkStore::new(
+        es_client.clone(),
+        config.bookmarks.index_name.clone(),
+    ));
+
+    // Create digest rating store, filled by the buttons under digests
+    let feedback = Arc::new(es::feedback::DigestFeedbackStore::new(
+        es_client.clone(),
+        config.digest.feedback_index.clone(),
+    ));
+
+    // Create admin client for purge commands
+    let admin = Arc::new(es::admin::AdminClient::new(
         es_client,
-        config.elasticsearch.index_name,
+        config.elasticsearch.index_name.clone(),
+        events,
     ));
 
-    // Create bot and launch dispatcher
-    let bot = Bot::new(&config.telegram.bot_token);
+    // Prime caches so the first searches after a restart aren't slow
+    if config.warmup.enabled {
+        es::warmup::warm_up(
+            &config.warmup,
+            &search_client,
+            &analytics,
+            config.search.default_page_size,
+        )
+        .await;
+    }
+
+    // Serve the bulk import and search API next to the bot
+    if config.api.is_enabled() {
+        let api = api::serve(
+            config.api.clone(),
+            indexer.clone(),
+            search_client.clone(),
+            audit.clone(),
+            config.search.default_page_size,
+        );
+        tokio::spawn(async move {
+            if let Err(e) = api.await {
+                tracing::error!("HTTP API stopped: {e}");
+            }
+        });
+    }
+
+    // Create the bots and launch one dispatcher per bot
+    let shards = Arc::new(bot::shards::Shards::new(&config.telegram)?);
 
-    tracing::info!("Bot starting...");
+    tracing::info!("Bot starting ({} bots)...", shards.bots().len());
 
     bot::handler::run_bot(
-        bot,
+        shards,
+        Arc::new(config),
         indexer,
         search_client,
-        config.search.default_page_size,
-        config.webhook,
+        analytics,
+        audit,
+        alerts,
+        settings,
+        chats,
+        users,
+        bookmarks,
+        feedback,
+        admin,
     )
     .await?;
 
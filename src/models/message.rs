@@ -1,41 +1,313 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::config::IdStrategy;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub message_id: i64,
     pub chat_id: i64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user_id: Option<i64>,
+    /// Lowercased Telegram username of the sender, without the `@`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Sender's name, or the relayed author's name for bridged messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
     pub text: String,
+    /// Caption of a media message, which `text` also carries so plain
+    /// keyword searches find it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
     /// Unix epoch seconds
     pub date: i64,
     pub message_type: MessageType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to_message_id: Option<i64>,
+    /// Links found in the text or caption entities
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub urls: Vec<String>,
+    /// Lowercased hosts of `urls`, without a leading `www.`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub domains: Vec<String>,
+    /// Lowercased usernames @-mentioned in the text, without the `@`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mentions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    /// Lowercased extension of `file_name`, without the dot
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_ext: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    /// File size in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_size: Option<u64>,
+    /// Shared location or venue position
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<GeoPoint>,
+    /// Portion of the replied-to message quoted by this reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote_text: Option<String>,
+    /// Chat of a replied-to message that lives in another chat
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_reply_chat_id: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_reply_message_id: Option<i64>,
+    /// Message a `pinned` service message pinned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned_message_id: Option<i64>,
+    /// View count of a channel post, refreshed by partial updates since the
+    /// Bot API doesn't report it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub views: Option<u64>,
+    /// Forward count of a channel post, refreshed like `views`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forwards: Option<u64>,
+    /// Posted by Telegram on the sender's behalf, e.g. a scheduled message;
+    /// `date` is then the time the bot received it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub scheduled: bool,
+    /// Forwarded from another chat or user
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub forwarded: bool,
+    /// Named entities in the text, when entity extraction is enabled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub people: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub orgs: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub places: Vec<String>,
+    /// From -1 (negative) to 1 (positive), when classification is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sentiment: Option<f32>,
+    /// From 0 (harmless) to 1 (toxic), when classification is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toxicity: Option<f32>,
+    /// Unix epoch seconds of the edit this revision records, when edits are
+    /// indexed as revisions; `None` for the message as first sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edited_at: Option<i64>,
+    /// [`content_hash`] of the text, set by the indexer; equal for copies of
+    /// the same announcement
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+}
+
+impl ChatMessage {
+    /// Elasticsearch document id. Each revision of an edited message gets its
+    /// own document next to the original.
+    pub fn doc_id(&self, strategy: IdStrategy) -> String {
+        strategy.doc_id(self.chat_id, self.message_id, self.edited_at)
+    }
+}
+
+impl IdStrategy {
+    /// Document id of a message, or of its revision from `edited_at`.
+    pub fn doc_id(self, chat_id: i64, message_id: i64, edited_at: Option<i64>) -> String {
+        let simple = match edited_at {
+            Some(edited_at) => format!("{chat_id}_{message_id}_{edited_at}"),
+            None => format!("{chat_id}_{message_id}"),
+        };
+        match self {
+            Self::Simple => simple,
+            Self::Versioned => format!("{chat_id}_{message_id}_{}", edited_at.unwrap_or(0)),
+            Self::Hashed => format!("{:032x}", fnv1a_128(simple.as_bytes())),
+        }
+    }
+}
+
+/// Letters and digits a text needs to get a content hash; shorter texts
+/// ("ok", "哈哈哈") are posted everywhere by chance.
+const MIN_HASHED_CHARS: usize = 12;
+
+/// Hash of the lowercased letters and digits of `text`, so copies differing
+/// only in case, spacing, punctuation or emoji hash alike. `None` for texts
+/// too short to tell copies from coincidences.
+pub fn content_hash(text: &str) -> Option<String> {
+    let normalized: String = text
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+    (normalized.chars().count() >= MIN_HASHED_CHARS)
+        .then(|| format!("{:032x}", fnv1a_128(normalized.as_bytes())))
+}
+
+/// 128-bit FNV-1a. Hashed ids are stored, so unlike `std`'s hasher this must
+/// give the same result on every build.
+fn fnv1a_128(bytes: &[u8]) -> u128 {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+    bytes.iter().fold(OFFSET_BASIS, |hash, &b| {
+        (hash ^ u128::from(b)).wrapping_mul(PRIME)
+    })
+}
+
+/// Serialized in the `{ "lat": .., "lon": .. }` form ES accepts for `geo_point`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lon: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
+/// Stored as its snake_case name; names this build doesn't know, written by
+/// a newer one or classified from a Telegram kind without its own variant,
+/// are kept verbatim in [`MessageType::Unknown`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub enum MessageType {
     Text,
     Photo,
     Video,
+    /// Round video message
+    VideoNote,
     Document,
     Sticker,
     Voice,
+    /// Music file, as opposed to a recorded voice message
+    Audio,
     Animation,
+    Location,
+    Venue,
+    Contact,
+    Game,
+    Dice,
+    /// Story forwarded into the chat
+    Story,
+    /// A "message pinned" service message
+    Pinned,
+    /// Telegram kind without its own variant, e.g. `poll` or `giveaway`
+    Unknown(String),
+    #[default]
     Other,
 }
 
+impl MessageType {
+    /// Names of the types searchable with `type:`.
+    pub const NAMES: &[&str] = &[
+        "text",
+        "photo",
+        "video",
+        "video_note",
+        "document",
+        "sticker",
+        "voice",
+        "audio",
+        "animation",
+        "location",
+        "venue",
+        "contact",
+        "game",
+        "dice",
+        "story",
+        "pinned",
+    ];
+
+    /// The type named `name`, falling back to [`MessageType::Unknown`].
+    pub fn from_name(name: &str) -> Self {
+        name.parse()
+            .unwrap_or_else(|()| Self::Unknown(name.to_string()))
+    }
+}
+
+impl Serialize for MessageType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for MessageType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Self::from_name(&name))
+    }
+}
+
 impl std::fmt::Display for MessageType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Text => write!(f, "text"),
             Self::Photo => write!(f, "photo"),
             Self::Video => write!(f, "video"),
+            Self::VideoNote => write!(f, "video_note"),
             Self::Document => write!(f, "document"),
             Self::Sticker => write!(f, "sticker"),
             Self::Voice => write!(f, "voice"),
+            Self::Audio => write!(f, "audio"),
             Self::Animation => write!(f, "animation"),
+            Self::Location => write!(f, "location"),
+            Self::Venue => write!(f, "venue"),
+            Self::Contact => write!(f, "contact"),
+            Self::Game => write!(f, "game"),
+            Self::Dice => write!(f, "dice"),
+            Self::Story => write!(f, "story"),
+            Self::Pinned => write!(f, "pinned"),
+            Self::Unknown(name) => write!(f, "{name}"),
             Self::Other => write!(f, "other"),
         }
     }
 }
+
+impl std::str::FromStr for MessageType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "photo" => Ok(Self::Photo),
+            "video" => Ok(Self::Video),
+            "video_note" => Ok(Self::VideoNote),
+            "document" => Ok(Self::Document),
+            "sticker" => Ok(Self::Sticker),
+            "voice" => Ok(Self::Voice),
+            "audio" => Ok(Self::Audio),
+            "animation" => Ok(Self::Animation),
+            "location" => Ok(Self::Location),
+            "venue" => Ok(Self::Venue),
+            "contact" => Ok(Self::Contact),
+            "game" => Ok(Self::Game),
+            "dice" => Ok(Self::Dice),
+            "story" => Ok(Self::Story),
+            "pinned" => Ok(Self::Pinned),
+            "other" => Ok(Self::Other),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doc_ids_follow_strategy() {
+        let original = |s: IdStrategy| s.doc_id(-100123, 42, None);
+        let revision = |s: IdStrategy| s.doc_id(-100123, 42, Some(1_700_000_000));
+
+        assert_eq!(original(IdStrategy::Simple), "-100123_42");
+        assert_eq!(revision(IdStrategy::Simple), "-100123_42_1700000000");
+        assert_eq!(original(IdStrategy::Versioned), "-100123_42_0");
+        assert_eq!(revision(IdStrategy::Versioned), "-100123_42_1700000000");
+        assert_eq!(original(IdStrategy::Hashed).len(), 32);
+        assert_ne!(original(IdStrategy::Hashed), revision(IdStrategy::Hashed));
+    }
+
+    #[test]
+    fn copies_share_a_content_hash() {
+        let hash = content_hash("限时免费领取会员！点击 t.me/xxx");
+        assert!(hash.is_some());
+        assert_eq!(content_hash("限时免费领取会员 点击 T.ME/XXX 🎉"), hash);
+        assert_ne!(content_hash("限时免费领取会员！点击 t.me/yyy"), hash);
+        assert_eq!(content_hash("哈哈哈哈！"), None);
+    }
+
+    #[test]
+    fn hashed_ids_are_stable() {
+        // Ids already stored in an index depend on this exact value
+        assert_eq!(fnv1a_128(b"a"), 0xd228cb696f1a8caf78912b704e4a8964);
+        assert_eq!(
+            IdStrategy::Hashed.doc_id(-100123, 42, None),
+            format!("{:032x}", fnv1a_128(b"-100123_42"))
+        );
+    }
+}
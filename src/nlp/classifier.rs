@@ -0,0 +1,50 @@
+//! Sentiment and toxicity scoring through an external classification service.
+
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+use crate::config::ClassifierConfig;
+
+/// Scores of one message, as returned by the service.
+#[derive(Debug, Deserialize)]
+pub struct Scores {
+    /// From -1 (negative) to 1 (positive)
+    pub sentiment: f32,
+    /// From 0 (harmless) to 1 (toxic)
+    pub toxicity: f32,
+}
+
+pub struct MessageClassifier {
+    client: reqwest::Client,
+    endpoint: String,
+}
+
+impl MessageClassifier {
+    pub fn new(config: &ClassifierConfig) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()?;
+        Ok(Self {
+            client,
+            endpoint: config.endpoint.clone(),
+        })
+    }
+
+    /// POST the text to the service, which answers
+    /// `{"sentiment": 0.4, "toxicity": 0.02}`. Scores are clamped to their range.
+    pub async fn classify(&self, text: &str) -> anyhow::Result<Scores> {
+        let response = self
+            .client
+            .post(&self.endpoint)
+            .json(&json!({ "text": text }))
+            .send()
+            .await?
+            .error_for_status()?;
+        let scores: Scores = response.json().await?;
+        Ok(Scores {
+            sentiment: scores.sentiment.clamp(-1.0, 1.0),
+            toxicity: scores.toxicity.clamp(0.0, 1.0),
+        })
+    }
+}
@@ -0,0 +1,72 @@
+//! Named-entity extraction through an external NER service.
+
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+use crate::config::EntitiesConfig;
+
+/// Entities found in one message, deduplicated in order of appearance.
+#[derive(Debug, Default)]
+pub struct Entities {
+    pub people: Vec<String>,
+    pub orgs: Vec<String>,
+    pub places: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct NerResponse {
+    entities: Vec<NerEntity>,
+}
+
+#[derive(Deserialize)]
+struct NerEntity {
+    text: String,
+    label: String,
+}
+
+pub struct EntityExtractor {
+    client: reqwest::Client,
+    endpoint: String,
+}
+
+impl EntityExtractor {
+    pub fn new(config: &EntitiesConfig) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()?;
+        Ok(Self {
+            client,
+            endpoint: config.endpoint.clone(),
+        })
+    }
+
+    /// POST the text to the service, which answers
+    /// `{"entities": [{"text": "..", "label": "PER"}]}`. Labels other than
+    /// people, organizations and locations are dropped.
+    pub async fn extract(&self, text: &str) -> anyhow::Result<Entities> {
+        let response = self
+            .client
+            .post(&self.endpoint)
+            .json(&json!({ "text": text }))
+            .send()
+            .await?
+            .error_for_status()?;
+        let body: NerResponse = response.json().await?;
+
+        let mut entities = Entities::default();
+        for entity in body.entities {
+            let name = entity.text.trim();
+            let bucket = match entity.label.to_uppercase().as_str() {
+                "PER" | "PERSON" => &mut entities.people,
+                "ORG" | "ORGANIZATION" => &mut entities.orgs,
+                "LOC" | "LOCATION" | "GPE" => &mut entities.places,
+                _ => continue,
+            };
+            if !name.is_empty() && !bucket.iter().any(|n| n == name) {
+                bucket.push(name.to_string());
+            }
+        }
+        Ok(entities)
+    }
+}
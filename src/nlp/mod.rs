@@ -0,0 +1,2 @@
+pub mod classifier;
+pub mod entities;